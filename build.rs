@@ -0,0 +1,29 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    ffi_header::generate();
+}
+
+/// Writes the C header for the `ffi` module to `$OUT_DIR/b3mt.h`.
+#[cfg(feature = "ffi")]
+mod ffi_header {
+    use std::env;
+    use std::path::PathBuf;
+
+    pub fn generate() {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        // The FFI test compiles its C program for the same target
+        println!("cargo:rustc-env=B3MT_TARGET={}", env::var("TARGET").unwrap());
+
+        let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+        let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+        let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).expect("cbindgen.toml is invalid");
+        // Parsing just the module keeps cbindgen away from `cargo metadata`
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(crate_dir.join("src/ffi.rs"))
+            .generate()
+            .expect("Could not generate the C header for src/ffi.rs")
+            .write_to_file(out_dir.join("b3mt.h"));
+    }
+}
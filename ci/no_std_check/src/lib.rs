@@ -0,0 +1,39 @@
+//! Uses the core tree API from a `no_std` crate, so that building this crate for
+//! a bare-metal target fails if anything it needs starts depending on `std`.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Hash32, Output, FLAGS, IV};
+use merkle_tree::error::MerkleError;
+use merkle_tree::kary_merkle_tree::KaryMerkleTree;
+use merkle_tree::proof::MerkleProof;
+
+/// The BLAKE3 hash of `input`, through the Merkle tree.
+pub fn root_hash(input: &[u8]) -> Hash32 {
+    BinaryMerkleTree::from_input(input, IV, FLAGS).root_hash()
+}
+
+/// Replace one leaf and return the new root hash with a proof for that leaf.
+pub fn update_and_prove(input: &[u8], leaf_index: usize, leaf: Output) -> Result<(Hash32, MerkleProof), MerkleError> {
+    let mut tree = BinaryMerkleTree::from_input(input, IV, FLAGS);
+    tree.bulk_insert_leaves([leaf_index].into_iter(), [leaf].into_iter())?;
+    let proof = tree.generate_proof(leaf_index).ok_or(MerkleError::LeafIndexOutOfBounds {
+        leaf_index,
+        actual_leaves: tree.actual_leaves(),
+    })?;
+    Ok((tree.root_hash(), proof))
+}
+
+/// The pruned-tree witnesses for `keep`, serialized.
+pub fn pruned_bytes(input: &[u8], keep: &[usize]) -> Result<Vec<u8>, MerkleError> {
+    Ok(BinaryMerkleTree::from_input(input, IV, FLAGS).prune(keep)?.to_bytes())
+}
+
+/// The root hash of a k-ary tree over `input`.
+pub fn kary_root_hash(input: &[u8], fanout: usize) -> Result<Hash32, MerkleError> {
+    Ok(KaryMerkleTree::from_input(input, fanout, IV, FLAGS)?.root_hash())
+}
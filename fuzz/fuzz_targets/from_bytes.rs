@@ -0,0 +1,26 @@
+//! Untrusted bytes through every deserializer. Whatever loads must be usable and
+//! serialize back to something that loads the same way.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, FLAGS, IV};
+use merkle_tree::fuzzing::{check_pruned_serialization, check_serialization};
+use merkle_tree::proof::MerkleProof;
+use merkle_tree::pruned_tree::PrunedTree;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(tree) = BinaryMerkleTree::from_bytes(data) {
+        tree.verify_integrity();
+        tree.generate_proof(tree.actual_leaves() - 1).unwrap();
+        check_serialization(&tree).unwrap();
+    }
+    if let Ok(pruned) = PrunedTree::from_bytes(data) {
+        pruned.root_hash();
+        check_pruned_serialization(&pruned).unwrap();
+    }
+    if let Some(proof) = MerkleProof::from_path_bytes(data, &[], IV, FLAGS) {
+        proof.verify(&[0; 32], IV, FLAGS);
+        assert_eq!(proof.to_path_bytes(), data);
+    }
+});
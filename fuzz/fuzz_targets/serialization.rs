@@ -0,0 +1,14 @@
+//! Serialization round trips of arbitrary trees and pruned trees.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use merkle_tree::binary_merkle_tree::BinaryMerkleTree;
+use merkle_tree::fuzzing::{check_pruned_serialization, check_serialization};
+use merkle_tree::pruned_tree::PrunedTree;
+
+fuzz_target!(|case: (BinaryMerkleTree, PrunedTree)| {
+    let (tree, pruned) = case;
+    check_serialization(&tree).unwrap();
+    check_pruned_serialization(&pruned).unwrap();
+});
@@ -0,0 +1,13 @@
+//! Random operation sequences on a tree, checked against a shadow input buffer.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use merkle_tree::fuzzing::{check_ops, TreeOp};
+
+fuzz_target!(|case: (Vec<u8>, Option<[u8; 32]>, Vec<TreeOp>)| {
+    let (input, key, ops) = case;
+    if let Err(divergence) = check_ops(&input, key, &ops) {
+        panic!("{}", divergence);
+    }
+});
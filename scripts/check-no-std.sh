@@ -0,0 +1,19 @@
+#!/bin/sh
+# Check that the core crate builds without std, for a bare-metal target with
+# only `alloc`:
+#
+#     scripts/check-no-std.sh                          # thumbv7em-none-eabihf
+#     scripts/check-no-std.sh riscv32imac-unknown-none-elf
+#
+# Builds the library itself with `--no-default-features --features alloc`, then
+# ci/no_std_check, a `#![no_std]` crate that calls the tree API the way a
+# firmware user would.
+set -eu
+
+target="${1:-thumbv7em-none-eabihf}"
+
+cd "$(dirname "$0")/.."
+rustup target add "$target"
+cargo build --target "$target" --no-default-features --features alloc
+cargo build --target "$target" --no-default-features --features alloc,serde
+cargo build --target "$target" --manifest-path ci/no_std_check/Cargo.toml
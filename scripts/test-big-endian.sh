@@ -0,0 +1,28 @@
+#!/bin/sh
+# Run the test suite on a big-endian target, to check that every byte format
+# (serialized trees, Output encodings, outboard and bao encodings) decodes the
+# little-endian golden fixtures in tests/fixtures unchanged.
+#
+# Uses cross (https://github.com/cross-rs/cross), which runs the tests under QEMU
+# in a container, so Docker or Podman must be available:
+#
+#     cargo install cross
+#     scripts/test-big-endian.sh            # s390x-unknown-linux-gnu
+#     scripts/test-big-endian.sh powerpc    # powerpc-unknown-linux-gnu
+#
+# Extra arguments are passed to `cargo test`, e.g. `-- --include-ignored`.
+set -eu
+
+arch="${1:-s390x}"
+[ $# -gt 0 ] && shift
+case "$arch" in
+    s390x) target=s390x-unknown-linux-gnu ;;
+    powerpc) target=powerpc-unknown-linux-gnu ;;
+    *)
+        echo "usage: $0 [s390x|powerpc] [cargo test args...]" >&2
+        exit 2
+        ;;
+esac
+
+cd "$(dirname "$0")/.."
+exec cross test --target "$target" --all-features "$@"
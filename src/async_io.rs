@@ -0,0 +1,106 @@
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, ChunkState, CvStack, Output, TreeLeafBuilder, CHUNK_LEN};
+
+// Size of the intermediate buffer used when hashing from an async reader.
+const ASYNC_BUFFER_LEN: usize = 64 * CHUNK_LEN;
+
+/// Read from `reader` until `buffer` holds `len` bytes or EOF is reached.
+async fn read_batch<R: AsyncRead + Unpin>(reader: &mut R, buffer: &mut Vec<u8>, len: usize) -> io::Result<()> {
+    buffer.clear();
+    buffer.reserve(len);
+    while buffer.len() < len {
+        let mut limited = (&mut *reader).take((len - buffer.len()) as u64);
+        if limited.read_buf(buffer).await? == 0 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Hash a batch of whole chunks (only the final batch may end in a partial chunk),
+/// the first of which has index `first_chunk_counter`.
+fn batch_leaves(batch: &[u8], first_chunk_counter: u64, key_words: [u32; 8], flags: u32) -> Vec<Output> {
+    batch
+        .chunks(CHUNK_LEN)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut chunk_state = ChunkState::new(key_words, first_chunk_counter + i as u64, flags);
+            chunk_state.update(chunk);
+            chunk_state.output()
+        })
+        .collect()
+}
+
+impl<S: CvStack> Blake3Hasher<S> {
+    /// Feed everything `reader` yields until EOF into the hasher, without blocking
+    /// the async runtime on I/O. Returns the number of bytes hashed.
+    pub async fn update_async_reader<R: AsyncRead + Unpin>(&mut self, mut reader: R) -> io::Result<u64> {
+        let mut buffer = Vec::with_capacity(ASYNC_BUFFER_LEN);
+        let mut total = 0;
+        loop {
+            buffer.clear();
+            if reader.read_buf(&mut buffer).await? == 0 {
+                return Ok(total);
+            }
+            self.update(&buffer);
+            total += buffer.len() as u64;
+        }
+    }
+}
+
+impl BinaryMerkleTree {
+    /// Construct a new BinaryMerkleTree from everything an async reader yields until
+    /// EOF. Hashing runs inline on the calling task, between reads.
+    pub async fn from_async_reader<R: AsyncRead + Unpin>(mut reader: R, key_words: [u32; 8], flags: u32) -> io::Result<Self> {
+        let mut builder = TreeLeafBuilder::new(key_words, flags);
+        let mut buffer = Vec::with_capacity(ASYNC_BUFFER_LEN);
+        loop {
+            buffer.clear();
+            if reader.read_buf(&mut buffer).await? == 0 {
+                return Ok(builder.finalize_tree());
+            }
+            builder.update(&buffer);
+        }
+    }
+
+    /// Like `from_async_reader`, but the CPU-heavy chunk hashing is moved off the
+    /// async runtime with `spawn_blocking`, one batch of `batch_len` bytes at a time
+    /// (rounded up to a whole number of chunks). Must be called from within a tokio
+    /// runtime.
+    pub async fn from_async_reader_offloaded<R: AsyncRead + Unpin>(
+        mut reader: R,
+        key_words: [u32; 8],
+        flags: u32,
+        batch_len: usize,
+    ) -> io::Result<Self> {
+        let batch_len = batch_len.max(1).div_ceil(CHUNK_LEN) * CHUNK_LEN;
+        let mut leaves = Vec::new();
+        let mut input_len = 0;
+        loop {
+            let mut batch = Vec::new();
+            read_batch(&mut reader, &mut batch, batch_len).await?;
+            if batch.is_empty() {
+                break;
+            }
+            let is_last_batch = batch.len() < batch_len;
+            input_len += batch.len() as u64;
+            let first_chunk_counter = leaves.len() as u64;
+            let batch_outputs = tokio::task::spawn_blocking(move || batch_leaves(&batch, first_chunk_counter, key_words, flags))
+                .await
+                .map_err(io::Error::other)?;
+            leaves.extend(batch_outputs);
+            if is_last_batch {
+                break;
+            }
+        }
+
+        // Empty input still has one (empty) chunk
+        if leaves.is_empty() {
+            leaves.push(ChunkState::new(key_words, 0, flags).output());
+        }
+        Ok(Self::new_from_leaves(leaves, key_words, flags).with_input_len(input_len))
+    }
+}
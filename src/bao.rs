@@ -0,0 +1,564 @@
+//! The "bao" encodings: the chaining values needed to verify an input, in the
+//! layouts used by the bao tool and crate.
+//!
+//! Both start with the input length as an 8-byte little-endian integer, followed
+//! by the tree in pre-order: each parent node is the 32-byte chaining value of its
+//! left child followed by that of its right child (8 LE words each). The combined
+//! encoding also stores each leaf as the raw chunk bytes, so it is self-verifying;
+//! the outboard encoding stores only the parents and is used next to the input.
+//! The tree has BLAKE3's shape, so a reader can locate every node from the length
+//! alone and verify each chunk before using it.
+//!
+//! A slice is the part of either encoding needed to verify one byte range: the
+//! header, the parents on the way down to the covered chunks, and those chunks, in
+//! the same pre-order. Slices let a server answer range requests with data the
+//! client can verify against the root hash alone.
+//!
+//! Verifying a slice needs only `alloc`; encoding, extracting slices and the
+//! streaming `VerifiedDecoder` need `std`.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+use crate::binary_merkle_tree::{parent_output, ChunkState, Output, CHUNK_LEN, OUT_LEN, ROOT};
+#[cfg(feature = "std")]
+use crate::binary_merkle_tree::{cv_to_bytes, BinaryMerkleTree, FLAGS, IV};
+#[cfg(feature = "std")]
+use crate::error::MerkleError;
+use crate::le_bytes::read_words;
+
+const HEADER_LEN: usize = 8;
+const PARENT_LEN: usize = 2 * OUT_LEN;
+
+/// The number of chunks in the left subtree of a parent covering `chunk_count`
+/// chunks: the largest power of two below `chunk_count`.
+fn left_subtree_chunks(chunk_count: u64) -> u64 {
+    chunk_count.div_ceil(2).next_power_of_two()
+}
+
+#[cfg(feature = "std")]
+impl BinaryMerkleTree {
+    /// Same as `encode_combined`.
+    pub fn encode_bao(&self, input: &[u8]) -> Result<Vec<u8>, MerkleError> {
+        self.encode_combined(input)
+    }
+
+    /// Produce the combined bao encoding of `input`, taking every parent chaining
+    /// value from the tree instead of rehashing.
+    ///
+    /// `input` must be the bytes this tree was built from; only its chunk count is
+    /// checked, so passing other bytes of the same size yields an encoding that
+    /// will fail to decode. Returns `MerkleError::InputLengthMismatch` if the chunk
+    /// count does not match.
+    pub fn encode_combined(&self, input: &[u8]) -> Result<Vec<u8>, MerkleError> {
+        self.check_input_len(input.len() as u64)?;
+        Ok(self.encode(Some(input), input.len() as u64))
+    }
+
+    /// Produce the bao outboard encoding of this tree's `input_len`-byte input: the
+    /// length header and the pre-order parent pairs, without any chunk data.
+    ///
+    /// Returns `MerkleError::InputLengthMismatch` if `input_len` does not split into
+    /// this tree's number of leaves.
+    pub fn to_bao_outboard(&self, input_len: u64) -> Result<Vec<u8>, MerkleError> {
+        self.check_input_len(input_len)?;
+        Ok(self.encode(None, input_len))
+    }
+
+    fn check_input_len(&self, input_len: u64) -> Result<(), MerkleError> {
+        if input_len.div_ceil(CHUNK_LEN as u64).max(1) != self.actual_leaves() as u64 {
+            return Err(MerkleError::InputLengthMismatch {
+                input_len,
+                actual_leaves: self.actual_leaves(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The combined encoding of `input`, or the outboard encoding when `input` is
+    /// `None`, for an `input_len` already checked against the leaf count.
+    fn encode(&self, input: Option<&[u8]>, input_len: u64) -> Vec<u8> {
+        let parent_count = self.actual_leaves() - 1;
+        let data_len = input.map_or(0, <[u8]>::len);
+        let mut encoded = Vec::with_capacity(HEADER_LEN + parent_count * PARENT_LEN + data_len);
+        encoded.extend_from_slice(&input_len.to_le_bytes());
+        self.encode_subtree(input, 0, self.actual_leaves(), &mut encoded);
+        encoded
+    }
+
+    /// Append the pre-order encoding of a subtree, with the chunk bytes taken from
+    /// `input` for the combined encoding, or omitted when `input` is `None`.
+    fn encode_subtree(&self, input: Option<&[u8]>, first_chunk: usize, chunk_count: usize, encoded: &mut Vec<u8>) {
+        if chunk_count == 1 {
+            if let Some(input) = input {
+                let start = first_chunk * CHUNK_LEN;
+                let end = (start + CHUNK_LEN).min(input.len());
+                encoded.extend_from_slice(&input[start..end]);
+            }
+            return;
+        }
+        let left_count = left_subtree_chunks(chunk_count as u64) as usize;
+        let right_first = first_chunk + left_count;
+        let right_count = chunk_count - left_count;
+        encoded.extend_from_slice(&cv_to_bytes(self.subtree_chaining_value(first_chunk, left_count)));
+        encoded.extend_from_slice(&cv_to_bytes(self.subtree_chaining_value(right_first, right_count)));
+        self.encode_subtree(input, first_chunk, left_count, encoded);
+        self.encode_subtree(input, right_first, right_count, encoded);
+    }
+}
+
+/// Hash `input` with the regular hash function and return its combined bao
+/// encoding. The root hash to verify it against is `blake3::hash(input)`, or the
+/// `root_hash` of the same tree.
+#[cfg(feature = "std")]
+pub fn encode_combined(input: &[u8]) -> Vec<u8> {
+    BinaryMerkleTree::from_input(input, IV, FLAGS).encode(Some(input), input.len() as u64)
+}
+
+/// Hash `input` with the regular hash function and return its bao outboard
+/// encoding together with its hash.
+#[cfg(feature = "std")]
+pub fn encode_outboard_bao(input: &[u8]) -> (Vec<u8>, [u8; OUT_LEN]) {
+    let tree = BinaryMerkleTree::from_input(input, IV, FLAGS);
+    (tree.encode(None, input.len() as u64), tree.root_hash().0)
+}
+
+/// Why a slice, or a whole combined encoding, failed to verify.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SliceError {
+    /// The slice ends before all the nodes the range needs.
+    Truncated,
+    /// The slice has this many bytes left over after the nodes the range needs.
+    TrailingBytes(usize),
+    /// The parent over `chunk_count` chunks from `first_chunk` does not match the
+    /// chaining value above it, or the root hash.
+    ParentMismatch { first_chunk: u64, chunk_count: u64 },
+    /// Chunk `chunk_index` does not match the chaining value above it, or the root hash.
+    ChunkMismatch { chunk_index: u64 },
+}
+
+impl fmt::Display for SliceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SliceError::Truncated => write!(f, "slice ends before the nodes covering the range"),
+            SliceError::TrailingBytes(count) => write!(f, "{} trailing bytes after the slice", count),
+            SliceError::ParentMismatch { first_chunk, chunk_count } => write!(
+                f,
+                "parent of chunks {}..{} does not match the tree",
+                first_chunk,
+                first_chunk + chunk_count
+            ),
+            SliceError::ChunkMismatch { chunk_index } => write!(f, "chunk {} does not match the tree", chunk_index),
+        }
+    }
+}
+
+impl core::error::Error for SliceError {}
+
+#[cfg(feature = "std")]
+impl From<SliceError> for io::Error {
+    fn from(e: SliceError) -> Self {
+        let kind = match e {
+            SliceError::Truncated => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// The chunks a slice of `byte_range` carries for an input of `input_len` bytes:
+/// every chunk overlapping the range, and always at least one, so an empty range
+/// or one past the end still carries (and verifies) the chunk at its start or the
+/// final chunk, as in bao.
+fn covered_chunks(input_len: u64, byte_range: &Range<u64>) -> Range<u64> {
+    let chunk_count = input_len.div_ceil(CHUNK_LEN as u64).max(1);
+    let start = (byte_range.start / CHUNK_LEN as u64).min(chunk_count - 1);
+    let end = byte_range.end.min(input_len).div_ceil(CHUNK_LEN as u64).clamp(start + 1, chunk_count);
+    start..end
+}
+
+fn read_header(encoded: &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(encoded.get(..HEADER_LEN)?.try_into().unwrap()))
+}
+
+/// Copies out of a combined or outboard encoding the nodes a slice needs, skipping
+/// whole subtrees outside the covered chunks.
+#[cfg(feature = "std")]
+struct SliceExtractor<'a> {
+    encoded: &'a [u8],
+    position: usize,
+    // The input next to an outboard encoding; `None` for a combined encoding
+    input: Option<&'a [u8]>,
+    input_len: u64,
+    chunks: Range<u64>,
+    slice: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl<'a> SliceExtractor<'a> {
+    fn take(&mut self, len: u64) -> io::Result<&'a [u8]> {
+        let encoded = self.encoded;
+        let remaining = (encoded.len() - self.position) as u64;
+        if len > remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("encoding ends at byte {}", encoded.len()),
+            ));
+        }
+        let bytes = &encoded[self.position..self.position + len as usize];
+        self.position += len as usize;
+        Ok(bytes)
+    }
+
+    /// The number of bytes a subtree takes up in the encoding being read.
+    fn encoded_subtree_len(&self, first_chunk: u64, chunk_count: u64) -> u64 {
+        let parents_len = (chunk_count - 1) * PARENT_LEN as u64;
+        if self.input.is_some() {
+            return parents_len;
+        }
+        let start = first_chunk * CHUNK_LEN as u64;
+        let end = (first_chunk + chunk_count).saturating_mul(CHUNK_LEN as u64).min(self.input_len);
+        parents_len + end - start
+    }
+
+    fn extract_subtree(&mut self, first_chunk: u64, chunk_count: u64) -> io::Result<()> {
+        if first_chunk + chunk_count <= self.chunks.start || first_chunk >= self.chunks.end {
+            self.take(self.encoded_subtree_len(first_chunk, chunk_count))?;
+            return Ok(());
+        }
+        if chunk_count == 1 {
+            let start = first_chunk * CHUNK_LEN as u64;
+            let chunk_len = (self.input_len - start).min(CHUNK_LEN as u64);
+            let chunk = match self.input {
+                Some(input) => &input[start as usize..(start + chunk_len) as usize],
+                None => self.take(chunk_len)?,
+            };
+            self.slice.extend_from_slice(chunk);
+            return Ok(());
+        }
+
+        let node = self.take(PARENT_LEN as u64)?;
+        self.slice.extend_from_slice(node);
+        let left_count = left_subtree_chunks(chunk_count);
+        self.extract_subtree(first_chunk, left_count)?;
+        self.extract_subtree(first_chunk + left_count, chunk_count - left_count)
+    }
+}
+
+/// Extract from a combined encoding (`input` is `None`), or from an outboard
+/// encoding and the input it describes, the slice that lets a reader holding only
+/// the root hash verify the bytes in `byte_range`.
+///
+/// A slice is the length header followed by the pre-order nodes of the encoding
+/// that cover the range: every parent above a covered chunk, and the covered
+/// chunks themselves. Ranges not on chunk boundaries carry the whole boundary
+/// chunks, and ranges past the end are cut off at the end (see `verify_slice`).
+/// The encoding is not verified here; a malformed one is reported as
+/// `UnexpectedEof` if it is too short and `InvalidData` otherwise.
+#[cfg(feature = "std")]
+pub fn extract_slice(encoded: &[u8], input: Option<&[u8]>, byte_range: Range<u64>) -> io::Result<Vec<u8>> {
+    let input_len = read_header(encoded)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "encoding is shorter than its length header"))?;
+    if let Some(input) = input {
+        if input.len() as u64 != input_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("outboard encoding is for {} bytes but the input has {}", input_len, input.len()),
+            ));
+        }
+    }
+
+    let mut extractor = SliceExtractor {
+        encoded,
+        position: HEADER_LEN,
+        input,
+        input_len,
+        chunks: covered_chunks(input_len, &byte_range),
+        slice: encoded[..HEADER_LEN].to_vec(),
+    };
+    extractor.extract_subtree(0, input_len.div_ceil(CHUNK_LEN as u64).max(1))?;
+    if extractor.position != encoded.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} trailing bytes after the encoding", encoded.len() - extractor.position),
+        ));
+    }
+    Ok(extractor.slice)
+}
+
+/// What a node of the encoding must hash to: the root hash for the root node, and
+/// the chaining value given by its parent for every other node.
+#[derive(Clone, Copy)]
+enum Expected {
+    Root([u8; OUT_LEN]),
+    ChainingValue([u32; 8]),
+}
+
+impl Expected {
+    fn matches(self, output: Output) -> bool {
+        match self {
+            Expected::Root(root_hash) => {
+                let mut root = output;
+                root.flags |= ROOT;
+                let mut hash = [0; OUT_LEN];
+                root.root_output_bytes(&mut hash);
+                hash == root_hash
+            }
+            Expected::ChainingValue(cv) => output.chaining_value() == cv,
+        }
+    }
+}
+
+/// Verifies the pre-order nodes of a slice and collects the bytes of the range.
+/// A whole combined encoding is the slice of the whole input.
+struct Decoder<'a> {
+    encoded: &'a [u8],
+    position: usize,
+    input_len: u64,
+    key_words: [u32; 8],
+    flags: u32,
+    // The bytes to return, already cut off at the end of the input
+    byte_range: Range<u64>,
+    chunks: Range<u64>,
+    decoded: Vec<u8>,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SliceError> {
+        let encoded = self.encoded;
+        let bytes = encoded.get(self.position..self.position + len).ok_or(SliceError::Truncated)?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn decode_subtree(&mut self, first_chunk: u64, chunk_count: u64, expected: Expected) -> Result<(), SliceError> {
+        // Subtrees without covered chunks are not part of the slice
+        if first_chunk + chunk_count <= self.chunks.start || first_chunk >= self.chunks.end {
+            return Ok(());
+        }
+        if chunk_count == 1 {
+            let start = first_chunk * CHUNK_LEN as u64;
+            let end = start + (self.input_len - start).min(CHUNK_LEN as u64);
+            let chunk = self.take((end - start) as usize)?;
+            let mut chunk_state = ChunkState::new(self.key_words, first_chunk, self.flags);
+            chunk_state.update(chunk);
+            if !expected.matches(chunk_state.output()) {
+                return Err(SliceError::ChunkMismatch { chunk_index: first_chunk });
+            }
+            // Only release the verified bytes that fall inside the range
+            let from = self.byte_range.start.clamp(start, end) - start;
+            let to = self.byte_range.end.clamp(start, end) - start;
+            self.decoded.extend_from_slice(&chunk[from as usize..to as usize]);
+            return Ok(());
+        }
+
+        let node = self.take(PARENT_LEN)?;
+        let (left_cv, right_cv) = (read_words(&node[..OUT_LEN]), read_words(&node[OUT_LEN..]));
+        if !expected.matches(parent_output(left_cv, right_cv, self.key_words, self.flags)) {
+            return Err(SliceError::ParentMismatch { first_chunk, chunk_count });
+        }
+        let left_count = left_subtree_chunks(chunk_count);
+        self.decode_subtree(first_chunk, left_count, Expected::ChainingValue(left_cv))?;
+        self.decode_subtree(first_chunk + left_count, chunk_count - left_count, Expected::ChainingValue(right_cv))
+    }
+}
+
+/// Verify a slice produced by `extract_slice` for the same `byte_range` against
+/// `root_hash`, and return the input bytes in that range.
+///
+/// Every node is checked before any of its bytes are used, and boundary chunks are
+/// trimmed to the range only after they verify. The range is cut off at the end
+/// of the input, so a range past the end returns fewer bytes (none if it starts
+/// past the end). As in bao, the length in the header is only fully authenticated
+/// when the slice carries the final chunk. Use `IV` and `FLAGS` as the key and
+/// flags for slices of the regular hash.
+pub fn verify_slice(
+    slice: &[u8],
+    root_hash: &[u8; OUT_LEN],
+    key_words: [u32; 8],
+    flags: u32,
+    byte_range: Range<u64>,
+) -> Result<Vec<u8>, SliceError> {
+    let input_len = read_header(slice).ok_or(SliceError::Truncated)?;
+    let start = byte_range.start.min(input_len);
+    let end = byte_range.end.clamp(start, input_len);
+
+    let mut decoder = Decoder {
+        encoded: slice,
+        position: HEADER_LEN,
+        input_len,
+        key_words,
+        flags,
+        byte_range: start..end,
+        chunks: covered_chunks(input_len, &byte_range),
+        decoded: Vec::new(),
+    };
+    decoder.decode_subtree(0, input_len.div_ceil(CHUNK_LEN as u64).max(1), Expected::Root(*root_hash))?;
+    if decoder.position != slice.len() {
+        return Err(SliceError::TrailingBytes(slice.len() - decoder.position));
+    }
+    Ok(decoder.decoded)
+}
+
+/// Decode a combined bao encoding, verifying every node against `root_hash` before
+/// its bytes are accepted, and return the original input.
+///
+/// A node that does not match is reported as `io::ErrorKind::InvalidData`, a
+/// truncated encoding as `UnexpectedEof`, and bytes past the end as `InvalidData`.
+/// Use `IV` and `FLAGS` as the key and flags for encodings of the regular hash.
+#[cfg(feature = "std")]
+pub fn decode_and_verify(
+    encoded: &[u8],
+    root_hash: &[u8; OUT_LEN],
+    key_words: [u32; 8],
+    flags: u32,
+) -> io::Result<Vec<u8>> {
+    Ok(verify_slice(encoded, root_hash, key_words, flags, 0..u64::MAX)?)
+}
+
+/// Decodes a combined encoding read incrementally from `R`, releasing each chunk of
+/// plaintext through `Read` only once it and every parent above it have been
+/// verified against the root hash.
+///
+/// Besides the current chunk, it holds only the subtrees still to decode: each
+/// parent read replaces one pending subtree with its two children, so at most one
+/// per level of the tree is pending. The first mismatch fails with `InvalidData`
+/// naming the node and its byte offset in the encoding; a truncated encoding fails
+/// with `UnexpectedEof`, and bytes after the encoding with `InvalidData`. After an
+/// error, every later read fails too.
+#[cfg(feature = "std")]
+pub struct VerifiedDecoder<R: Read> {
+    reader: R,
+    root_hash: [u8; OUT_LEN],
+    key_words: [u32; 8],
+    flags: u32,
+    input_len: Option<u64>,
+    // Subtrees still to decode as (first chunk, chunk count, expected node), next on top
+    pending: Vec<(u64, u64, Expected)>,
+    chunk: Vec<u8>,
+    chunk_position: usize,
+    encoded_position: u64,
+    failed: bool,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> VerifiedDecoder<R> {
+    /// A decoder for the combined encoding in `reader` of an input with hash
+    /// `root_hash`. Use `IV` and `FLAGS` for encodings of the regular hash.
+    pub fn new(reader: R, root_hash: [u8; OUT_LEN], key_words: [u32; 8], flags: u32) -> Self {
+        VerifiedDecoder {
+            reader,
+            root_hash,
+            key_words,
+            flags,
+            input_len: None,
+            pending: Vec::new(),
+            chunk: Vec::with_capacity(CHUNK_LEN),
+            chunk_position: 0,
+            encoded_position: 0,
+            failed: false,
+        }
+    }
+
+    /// The input length from the header, once the first read has consumed it. Not
+    /// authenticated until the final chunk has been verified.
+    pub fn input_len(&self) -> Option<u64> {
+        self.input_len
+    }
+
+    fn read_node(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.reader.read_exact(buf)?;
+        self.encoded_position += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Verify nodes until the next chunk is ready in `self.chunk`. Returns false
+    /// once the whole encoding has been decoded.
+    fn next_chunk(&mut self) -> io::Result<bool> {
+        if self.input_len.is_none() {
+            let mut header = [0u8; HEADER_LEN];
+            self.read_node(&mut header)?;
+            let input_len = u64::from_le_bytes(header);
+            self.input_len = Some(input_len);
+            self.pending.push((0, input_len.div_ceil(CHUNK_LEN as u64).max(1), Expected::Root(self.root_hash)));
+        }
+        let input_len = self.input_len.unwrap();
+
+        while let Some((first_chunk, chunk_count, expected)) = self.pending.pop() {
+            let node_position = self.encoded_position;
+            if chunk_count == 1 {
+                let start = first_chunk * CHUNK_LEN as u64;
+                let chunk_len = (input_len - start).min(CHUNK_LEN as u64) as usize;
+                self.chunk.resize(chunk_len, 0);
+                let mut chunk = std::mem::take(&mut self.chunk);
+                let result = self.read_node(&mut chunk);
+                self.chunk = chunk;
+                result?;
+
+                let mut chunk_state = ChunkState::new(self.key_words, first_chunk, self.flags);
+                chunk_state.update(&self.chunk);
+                if !expected.matches(chunk_state.output()) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("chunk {} at byte {} of the encoding does not match the tree", first_chunk, node_position),
+                    ));
+                }
+                self.chunk_position = 0;
+                return Ok(true);
+            }
+
+            let mut node = [0u8; PARENT_LEN];
+            self.read_node(&mut node)?;
+            let (left_cv, right_cv) = (read_words(&node[..OUT_LEN]), read_words(&node[OUT_LEN..]));
+            if !expected.matches(parent_output(left_cv, right_cv, self.key_words, self.flags)) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "parent of chunks {}..{} at byte {} of the encoding does not match the tree",
+                        first_chunk,
+                        first_chunk + chunk_count,
+                        node_position
+                    ),
+                ));
+            }
+            let left_count = left_subtree_chunks(chunk_count);
+            self.pending.push((first_chunk + left_count, chunk_count - left_count, Expected::ChainingValue(right_cv)));
+            self.pending.push((first_chunk, left_count, Expected::ChainingValue(left_cv)));
+        }
+
+        if self.reader.read(&mut [0u8; 1])? != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("trailing bytes after the encoding at byte {}", self.encoded_position),
+            ));
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for VerifiedDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.failed {
+            return Err(io::Error::other("decoder stopped after an earlier error"));
+        }
+        // Loop, since the chunk of an empty input has no bytes to release
+        while self.chunk_position == self.chunk.len() {
+            match self.next_chunk() {
+                Ok(true) => {}
+                Ok(false) => return Ok(0),
+                Err(e) => {
+                    self.failed = true;
+                    return Err(e);
+                }
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.chunk_position);
+        buf[..len].copy_from_slice(&self.chunk[self.chunk_position..self.chunk_position + len]);
+        self.chunk_position += len;
+        Ok(len)
+    }
+}
@@ -0,0 +1,122 @@
+//! `b3mt`: hash files, write their trees, and make and check proofs for single
+//! chunks. Trees use the regular hash mode, so `b3mt hash` prints the same digest
+//! as `b3sum`.
+//!
+//! The exit status is 0 on success, 1 when `verify` rejects a chunk, and 2 for
+//! usage and I/O errors.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Hash32, FLAGS, IV, OUT_LEN};
+use merkle_tree::error::MerkleError;
+use merkle_tree::proof::MerkleProof;
+
+const EXIT_MISMATCH: u8 = 1;
+// Also what clap exits with for usage errors
+const EXIT_ERROR: u8 = 2;
+
+#[derive(Parser)]
+#[command(name = "b3mt", version, about = "BLAKE3 Merkle trees: hash files, and prove and verify single chunks")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the root hash of FILE in hex; "-" reads stdin
+    Hash { file: PathBuf },
+    /// Write the serialized tree of FILE, for `prove`; "-" reads stdin
+    Tree {
+        file: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Write the proof for chunk N of a serialized tree
+    Prove {
+        tree: PathBuf,
+        #[arg(long, value_name = "N")]
+        chunk: usize,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Check a chunk's bytes against a root hash, with a proof from `prove`
+    Verify {
+        #[arg(long, value_name = "HEX")]
+        root: String,
+        #[arg(long)]
+        proof: PathBuf,
+        #[arg(long)]
+        chunk_data: PathBuf,
+    },
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("b3mt: {}", e);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+fn run(command: Command) -> io::Result<ExitCode> {
+    match command {
+        Command::Hash { file } => {
+            println!("{}", build_tree(&file)?.root_hash().to_hex());
+        }
+        Command::Tree { file, output } => {
+            let tree = build_tree(&file)?;
+            let writer = File::create(&output).map_err(|e| with_path(&output, e))?;
+            tree.write_to(BufWriter::new(writer)).map_err(|e| with_path(&output, e))?;
+        }
+        Command::Prove { tree, chunk, output } => {
+            let tree_file = File::open(&tree).map_err(|e| with_path(&tree, e))?;
+            let merkle_tree = BinaryMerkleTree::read_from(io::BufReader::new(tree_file)).map_err(|e| with_path(&tree, e))?;
+            let proof = merkle_tree.generate_proof(chunk).ok_or(MerkleError::LeafIndexOutOfBounds {
+                leaf_index: chunk,
+                actual_leaves: merkle_tree.actual_leaves(),
+            })?;
+            fs::write(&output, proof.to_path_bytes()).map_err(|e| with_path(&output, e))?;
+        }
+        Command::Verify { root, proof, chunk_data } => {
+            let root_hash = parse_root(&root)?;
+            let proof_bytes = fs::read(&proof).map_err(|e| with_path(&proof, e))?;
+            let chunk = fs::read(&chunk_data).map_err(|e| with_path(&chunk_data, e))?;
+            let merkle_proof = MerkleProof::from_path_bytes(&proof_bytes, &chunk, IV, FLAGS).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is not a chunk proof, or the chunk is over 1024 bytes", proof.display()),
+                )
+            })?;
+            if !merkle_proof.verify(&root_hash, IV, FLAGS) {
+                eprintln!("b3mt: chunk {} does not match the root", merkle_proof.leaf_index);
+                return Ok(ExitCode::from(EXIT_MISMATCH));
+            }
+            println!("chunk {} OK", merkle_proof.leaf_index);
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The tree of the file at `path`, or of stdin for "-".
+fn build_tree(path: &Path) -> io::Result<BinaryMerkleTree> {
+    if path == Path::new("-") {
+        return BinaryMerkleTree::from_reader(io::stdin().lock(), IV, FLAGS);
+    }
+    BinaryMerkleTree::from_file(path, IV, FLAGS).map_err(|e| with_path(path, e))
+}
+
+fn with_path(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+fn parse_root(root: &str) -> io::Result<Hash32> {
+    root.parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("--root must be {} hex digits", 2 * OUT_LEN)))
+}
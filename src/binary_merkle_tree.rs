@@ -1,14 +1,66 @@
-use std::collections::VecDeque;
+use alloc::boxed::Box;
+use alloc::collections::btree_map::Entry;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
 use core::cmp::min;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use crate::error::MerkleError;
+use crate::le_bytes::{read_words, words_into};
+#[cfg(feature = "std")]
+use crate::le_bytes::{words_from, write_words};
+use crate::node_store::{NodeStore, SharedStore, VecStore};
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
 
 pub const OUT_LEN: usize = 32;
 pub const BLOCK_LEN: usize = 64;
 pub const CHUNK_LEN: usize = 1024;
-
-const CHUNK_START: u32 = 1 << 0;
-const CHUNK_END: u32 = 1 << 1;
-const PARENT: u32 = 1 << 2;
+/// The most chunks a BLAKE3 input can have: 2^64 bytes in 1024-byte chunks.
+pub const MAX_CHUNKS: u64 = 1 << 54;
+
+// Size of the intermediate buffer used when hashing from a reader.
+#[cfg(feature = "std")]
+const READ_BUFFER_LEN: usize = 64 * CHUNK_LEN;
+
+/// Size of an Output in its canonical byte encoding (see `Output::to_bytes`).
+pub const OUTPUT_BYTES_LEN: usize = 8 * 4 + 16 * 4 + 8 + 4 + 4;
+
+// Serialized tree format: magic, version, and the bits of the presence word.
+#[cfg(feature = "std")]
+const SERIALIZED_MAGIC: [u8; 4] = *b"B3MT";
+#[cfg(feature = "std")]
+const SERIALIZED_VERSION: u32 = 1;
+#[cfg(feature = "std")]
+const SERIALIZED_HAS_KEY: u32 = 1 << 0;
+#[cfg(feature = "std")]
+const SERIALIZED_HAS_INPUT_LEN: u32 = 1 << 1;
+#[cfg(feature = "std")]
+const SERIALIZED_LEAF_HASHES: u32 = 1 << 2;
+
+/// Set on the first block of each chunk.
+pub const CHUNK_START: u32 = 1 << 0;
+/// Set on the last block of each chunk, which is the block a chunk's Output holds.
+pub const CHUNK_END: u32 = 1 << 1;
+/// Set on every parent node.
+pub const PARENT: u32 = 1 << 2;
 pub const ROOT: u32 = 1 << 3;
+/// The flags of BLAKE3's keyed hash mode, used with the key as `key_words`.
+pub const KEYED_HASH: u32 = 1 << 4;
+/// The flags for hashing a derive_key context string, used with `IV`.
+pub const DERIVE_KEY_CONTEXT: u32 = 1 << 5;
+/// The flags for hashing derive_key key material, used with the context key.
+pub const DERIVE_KEY_MATERIAL: u32 = 1 << 6;
+pub const KEY_LEN: usize = 32;
+const ALL_FLAGS: u32 = CHUNK_START | CHUNK_END | PARENT | ROOT | KEYED_HASH | DERIVE_KEY_CONTEXT | DERIVE_KEY_MATERIAL;
 
 pub const IV: [u32; 8] = [
     0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
@@ -21,16 +73,40 @@ pub const FLAGS: u32 = 0;
 // Each chunk or parent node can produce either an 8-word chaining value or, by
 // setting the ROOT flag, any number of final output bytes. The Output struct
 // captures the state just prior to choosing between those two possibilities.
-#[derive(Debug, Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Output {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::words"))]
     pub input_chaining_value: [u32; 8],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::words"))]
     pub block_words: [u32; 16],
     pub counter: u64,
     pub block_len: u32,
     pub flags: u32,
 }
 
+/// A chaining value as the 32 bytes BLAKE3 writes it: each word little-endian,
+/// first word first.
+#[inline]
+pub fn cv_to_bytes(cv: [u32; 8]) -> [u8; OUT_LEN] {
+    let mut bytes = [0; OUT_LEN];
+    words_into(&cv, &mut bytes);
+    bytes
+}
+
+/// The chaining value `cv_to_bytes` wrote as `bytes`.
+#[inline]
+pub fn cv_from_bytes(bytes: [u8; OUT_LEN]) -> [u32; 8] {
+    read_words(&bytes)
+}
+
 impl Output {
+    /// `chaining_value` as bytes, with `cv_to_bytes`. With the ROOT flag set, as on
+    /// `root()`, these are the root hash.
+    pub fn cv_bytes(&self) -> [u8; OUT_LEN] {
+        cv_to_bytes(self.chaining_value())
+    }
+
     pub fn chaining_value(&self) -> [u32; 8] {
         first_8_words(compress(
             &self.input_chaining_value,
@@ -42,12 +118,11 @@ impl Output {
     }
 
     pub fn root_output_bytes(&self, out_slice: &mut [u8]) {
-        let mut output_block_counter = 0;
-        for out_block in out_slice.chunks_mut(2 * OUT_LEN) {
+        for (output_block_counter, out_block) in out_slice.chunks_mut(2 * OUT_LEN).enumerate() {
             let words = compress(
                 &self.input_chaining_value,
                 &self.block_words,
-                output_block_counter,
+                output_block_counter as u64,
                 self.block_len,
                 self.flags | ROOT,
             );
@@ -55,9 +130,111 @@ impl Output {
             for (word, out_word) in words.iter().zip(out_block.chunks_mut(4)) {
                 out_word.copy_from_slice(&word.to_le_bytes()[..out_word.len()]);
             }
-            output_block_counter += 1;
         }
     }
+
+    /// `root_output_bytes` starting `byte_offset` bytes into the output stream, so
+    /// the bytes at any offset cost only the 64-byte blocks they fall in: the first
+    /// block is `byte_offset / 64`, and an unaligned start uses the tail of it.
+    /// Bytes past the end of the stream, at `u64::MAX`, are left as they were.
+    pub fn root_output_bytes_at(&self, byte_offset: u64, out_slice: &mut [u8]) {
+        let mut reader = OutputReader::new(*self);
+        reader.set_position(byte_offset);
+        reader.fill(out_slice);
+    }
+}
+
+// The flags `Output::flag_names` and `Debug` know, in bit order
+const FLAG_NAMES: [(u32, &str); 7] = [
+    (CHUNK_START, "CHUNK_START"),
+    (CHUNK_END, "CHUNK_END"),
+    (PARENT, "PARENT"),
+    (ROOT, "ROOT"),
+    (KEYED_HASH, "KEYED_HASH"),
+    (DERIVE_KEY_CONTEXT, "DERIVE_KEY_CONTEXT"),
+    (DERIVE_KEY_MATERIAL, "DERIVE_KEY_MATERIAL"),
+];
+
+impl Output {
+    pub fn is_root(&self) -> bool {
+        self.flags & ROOT != 0
+    }
+
+    /// Whether the node is a parent. The leaves of a tree of leaf hashes carry
+    /// PARENT as part of their marker, but are not parents.
+    pub fn is_parent(&self) -> bool {
+        self.flags & PARENT != 0 && !self.is_leaf_hash()
+    }
+
+    pub fn is_chunk_start(&self) -> bool {
+        self.flags & CHUNK_START != 0
+    }
+
+    pub fn is_chunk_end(&self) -> bool {
+        self.flags & CHUNK_END != 0
+    }
+
+    /// Whether the node belongs to a keyed hash.
+    pub fn is_keyed(&self) -> bool {
+        self.flags & KEYED_HASH != 0
+    }
+
+    /// Whether the node belongs to either stage of derive_key.
+    pub fn is_derive_key(&self) -> bool {
+        self.flags & (DERIVE_KEY_CONTEXT | DERIVE_KEY_MATERIAL) != 0
+    }
+
+    /// The names of the flags set, in bit order, as the constants are named. Bits
+    /// that are not BLAKE3 flags are skipped.
+    pub fn flag_names(&self) -> impl Iterator<Item = &'static str> {
+        let flags = self.flags;
+        FLAG_NAMES.into_iter().filter(move |&(flag, _)| flags & flag != 0).map(|(_, name)| name)
+    }
+}
+
+/// Writes flags as their names joined by `|`, any unknown bits in hex, and `0` for none.
+struct FlagsDebug(u32);
+
+impl core::fmt::Debug for FlagsDebug {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut separator = "";
+        for (flag, name) in FLAG_NAMES {
+            if self.0 & flag != 0 {
+                write!(f, "{}{}", separator, name)?;
+                separator = "|";
+            }
+        }
+        match self.0 & !ALL_FLAGS {
+            0 if separator.is_empty() => f.write_str("0"),
+            0 => Ok(()),
+            unknown => write!(f, "{}{:#x}", separator, unknown),
+        }
+    }
+}
+
+/// Writes the first two words of `words` as the hex of their little-endian bytes,
+/// followed by `…`.
+struct WordsPreview<'a>(&'a [u32]);
+
+impl core::fmt::Debug for WordsPreview<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:08x}{:08x}…", self.0[0].swap_bytes(), self.0[1].swap_bytes())
+    }
+}
+
+/// Prints the flags by name and a short hex preview of the words; the chunk of
+/// `b"abc"` is `Output { input_chaining_value: 67e6096a85ae67bb…, block_words:
+/// 6162630000000000…, counter: 0, block_len: 3, flags: CHUNK_START|CHUNK_END }`.
+impl core::fmt::Debug for Output {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Output")
+            .field("input_chaining_value", &WordsPreview(&self.input_chaining_value))
+            .field("block_words", &WordsPreview(&self.block_words))
+            .field("counter", &self.counter)
+            .field("block_len", &self.block_len)
+            .field("flags", &FlagsDebug(self.flags))
+            .finish()
+    }
 }
 
 pub fn parent_output(
@@ -66,19 +243,82 @@ pub fn parent_output(
     key_words: [u32; 8],
     flags: u32,
 ) -> Output {
-    let mut block_words = [0; 16];
-    block_words[..8].copy_from_slice(&left_child_cv);
-    block_words[8..].copy_from_slice(&right_child_cv);
-    let output = Output {
+    let mut output = Output {
         input_chaining_value: key_words,
-        block_words,
-        counter: 0,                  // Always 0 for parent nodes.
-        block_len: BLOCK_LEN as u32, // Always BLOCK_LEN (64) for parent nodes.
-        flags: PARENT | flags,
+        block_words: [0; 16],
+        counter: 0,
+        block_len: 0,
+        flags: 0,
     };
+    write_parent_output(&mut output, left_child_cv, right_child_cv, key_words, flags);
     output
 }
 
+/// Overwrite every field of `slot` with `parent_output`'s result, so a parent can
+/// be hashed straight into its place in the tree without a temporary Output.
+#[inline]
+pub(crate) fn write_parent_output(
+    slot: &mut Output,
+    left_child_cv: [u32; 8],
+    right_child_cv: [u32; 8],
+    key_words: [u32; 8],
+    flags: u32,
+) {
+    #[cfg(feature = "metrics")]
+    crate::metrics::count(|metrics| metrics.parents += 1);
+    slot.input_chaining_value = key_words;
+    slot.block_words[..8].copy_from_slice(&left_child_cv);
+    slot.block_words[8..].copy_from_slice(&right_child_cv);
+    slot.counter = 0; // Always 0 for parent nodes.
+    slot.block_len = BLOCK_LEN as u32; // Always BLOCK_LEN (64) for parent nodes.
+    slot.flags = PARENT | flags;
+}
+
+/// The Output filling the heap positions past the real nodes of each level, up to
+/// the next power of two. The tree never folds these: a node without a right
+/// sibling is promoted instead, and the root is always written. In case that ever
+/// breaks, padding has a counter no chunk reaches and PARENT together with
+/// CHUNK_START, a combination hashing never produces and `validate_flags` rejects,
+/// so a leaked padding node cannot pass for a real one.
+pub(crate) fn padding_output(key_words: [u32; 8], flags: u32) -> Output {
+    Output {
+        input_chaining_value: key_words,
+        block_words: [0; 16],
+        counter: u64::MAX,
+        block_len: 0,
+        flags: PARENT | CHUNK_START | flags,
+    }
+}
+
+impl Output {
+    pub(crate) fn is_padding(&self) -> bool {
+        self.counter == u64::MAX && self.flags & (PARENT | CHUNK_START) == PARENT | CHUNK_START
+    }
+}
+
+/// How a tree built by `from_leaf_hashes` stores a leaf hash. No Output compresses
+/// to a chosen chaining value, so the hash is kept as the input chaining value of
+/// an Output that is never compressed, and such a tree reads that field back as
+/// the chaining value. Like padding, it is marked by PARENT together with a chunk
+/// flag, here CHUNK_END, so it can be told apart wherever promotion copies it and
+/// never passes `validate_flags`. `is_parent`, `LeafCv`, `ParentCv` and `NodeCv`
+/// all check the marker and classify it as a leaf.
+pub(crate) fn leaf_hash_output(hash: &[u8; OUT_LEN], flags: u32) -> Output {
+    Output {
+        input_chaining_value: cv_from_bytes(*hash),
+        block_words: [0; 16],
+        counter: 0,
+        block_len: 0,
+        flags: PARENT | CHUNK_END | flags,
+    }
+}
+
+impl Output {
+    pub(crate) fn is_leaf_hash(&self) -> bool {
+        self.flags & (PARENT | CHUNK_END) == PARENT | CHUNK_END
+    }
+}
+
 fn first_8_words(compression_output: [u32; 16]) -> [u32; 8] {
     compression_output[0..8].try_into().unwrap()
 }
@@ -90,6 +330,8 @@ fn compress(
     block_len: u32,
     flags: u32,
 ) -> [u32; 16] {
+    #[cfg(feature = "metrics")]
+    crate::metrics::count(|metrics| metrics.compressions += 1);
     let counter_low = counter as u32;
     let counter_high = (counter >> 32) as u32;
     #[rustfmt::skip]
@@ -122,7 +364,7 @@ fn compress(
     state
 }
 
-const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+pub(crate) const MSG_PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
 
 fn permute(m: &mut [u32; 16]) {
     let mut permuted = [0; 16];
@@ -192,6 +434,10 @@ impl ChunkState {
         BLOCK_LEN * self.blocks_compressed as usize + self.block_len as usize
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn start_flag(&self) -> u32 {
         if self.blocks_compressed == 0 {
             CHUNK_START
@@ -201,6 +447,8 @@ impl ChunkState {
     }
 
     pub fn update(&mut self, mut input: &[u8]) {
+        #[cfg(feature = "metrics")]
+        crate::metrics::count(|metrics| metrics.bytes += input.len() as u64);
         while !input.is_empty() {
             // If the block buffer is full, compress it and clear it. More
             // input is coming, so this compression is not CHUNK_END.
@@ -212,7 +460,7 @@ impl ChunkState {
                     &block_words,
                     self.chunk_counter,
                     BLOCK_LEN as u32,
-                    self.flags | self.start_flag() as u32,
+                    self.flags | self.start_flag(),
                 ));
                 self.blocks_compressed += 1;
                 self.block = [0; BLOCK_LEN];
@@ -229,16 +477,17 @@ impl ChunkState {
     }
 
     pub fn output(&self) -> Output {
+        #[cfg(feature = "metrics")]
+        crate::metrics::count(|metrics| metrics.chunks += 1);
         let mut block_words = [0; 16];
         words_from_little_endian_bytes(&self.block, &mut block_words);
-        let output = Output {
+        Output {
             input_chaining_value: self.chaining_value,
             block_words,
             counter: self.chunk_counter,
             block_len: self.block_len as u32,
-            flags: self.flags | self.start_flag() as u32 | CHUNK_END as u32,
-        };
-        output
+            flags: self.flags | self.start_flag() | CHUNK_END,
+        }
     }
 }
 
@@ -251,42 +500,502 @@ pub fn parent_cv(
     parent_output(left_child_cv, right_child_cv, key_words, flags).chaining_value()
 }
 
+/// The non-root chaining value of a single chunk of the regular hash function,
+/// i.e. the per-chunk intermediate value from the BLAKE3 spec. `counter` is the
+/// chunk's index within the whole input.
+///
+/// Returns `MerkleError::ChunkTooLong` if `data` is longer than `CHUNK_LEN`.
+pub fn chunk_chaining_value(data: &[u8], counter: u64) -> Result<[u32; 8], MerkleError> {
+    if data.len() > CHUNK_LEN {
+        return Err(MerkleError::ChunkTooLong { len: data.len() });
+    }
+    let mut chunk_state = ChunkState::new(IV, counter, 0);
+    chunk_state.update(data);
+    Ok(chunk_state.output().chaining_value())
+}
+
+/// The leaf Output of `chunk_bytes` as chunk `chunk_index` of an input, in one
+/// call rather than `ChunkState::new`, `update` and `output`. `key_words` and
+/// `flags` are those of the tree, so keyed and derive-key trees get their leaves
+/// the same way; pass `IV` and `FLAGS` for the regular hash.
+///
+/// Returns `MerkleError::ChunkTooLong` if `chunk_bytes` is longer than `CHUNK_LEN`,
+/// the same error `chunk_chaining_value` and `chunk_digest` return, rather than an
+/// error type of its own.
+pub fn chunk_output(chunk_bytes: &[u8], chunk_index: u64, key_words: [u32; 8], flags: u32) -> Result<Output, MerkleError> {
+    if chunk_bytes.len() > CHUNK_LEN {
+        return Err(MerkleError::ChunkTooLong { len: chunk_bytes.len() });
+    }
+    let mut chunk_state = ChunkState::new(key_words, chunk_index, flags);
+    chunk_state.update(chunk_bytes);
+    Ok(chunk_state.output())
+}
+
+/// The leaf Output of chunk `chunk_index` of the whole `input`, the last chunk
+/// possibly partial, as `BinaryMerkleTree::from_input` hashes it. An empty input
+/// has one empty chunk. Takes the tree's `key_words` and `flags` like `chunk_output`.
+///
+/// Returns `MerkleError::LeafIndexOutOfBounds` if the input has no such chunk.
+pub fn leaf_for_input_chunk(input: &[u8], chunk_index: usize, key_words: [u32; 8], flags: u32) -> Result<Output, MerkleError> {
+    let actual_leaves = chunk_count(input.len());
+    if chunk_index >= actual_leaves {
+        return Err(MerkleError::LeafIndexOutOfBounds { leaf_index: chunk_index, actual_leaves });
+    }
+    let start = chunk_index * CHUNK_LEN;
+    let chunk_bytes = &input[start..min(start + CHUNK_LEN, input.len())];
+    chunk_output(chunk_bytes, chunk_index as u64, key_words, flags)
+}
+
+fn digest_of(chunk_state: &ChunkState) -> Hash32 {
+    let mut digest = [0; OUT_LEN];
+    chunk_state.output().root_output_bytes(&mut digest);
+    Hash32(digest)
+}
+
+/// The BLAKE3 hash of `data` as a whole input of at most one chunk, i.e. its chunk
+/// hashed as the root. Meant for content-defined chunking, where each
+/// variable-length piece is hashed on its own. Longer pieces span several chunks
+/// and need the full tree (`BinaryMerkleTree::from_input`) or `Blake3Hasher`.
+///
+/// Returns `MerkleError::ChunkTooLong` if `data` is longer than `CHUNK_LEN`.
+pub fn chunk_digest(data: &[u8]) -> Result<[u8; OUT_LEN], MerkleError> {
+    if data.len() > CHUNK_LEN {
+        return Err(MerkleError::ChunkTooLong { len: data.len() });
+    }
+    let mut chunk_state = ChunkState::new(IV, 0, 0);
+    chunk_state.update(data);
+    Ok(digest_of(&chunk_state).0)
+}
+
+/// `leaf_count` as a usize, if a tree can have that many leaves: at most
+/// `MAX_CHUNKS`, and few enough that the padded heap of `2 * leaf_count.next_power_of_two()`
+/// nodes is addressable. Returns `MerkleError::TooManyChunks` otherwise.
+pub fn check_leaf_count(leaf_count: u64) -> Result<usize, MerkleError> {
+    usize::try_from(leaf_count)
+        .ok()
+        .filter(|_| leaf_count <= MAX_CHUNKS)
+        .filter(|count| count.checked_next_power_of_two().and_then(|leaves| leaves.checked_mul(2)).is_some())
+        .ok_or(MerkleError::TooManyChunks { chunks: leaf_count })
+}
+
+/// The number of chunks, and so of tree leaves, an input of `input_len` bytes
+/// produces. Empty input still yields one (empty) chunk, as in `from_input`.
+pub fn chunk_count(input_len: usize) -> usize {
+    input_len.div_ceil(CHUNK_LEN).max(1)
+}
+
+/// A 32-byte root hash, as returned by `root_hash`. It dereferences to the byte
+/// array and compares equal to one, so it can be used wherever `[u8; OUT_LEN]`
+/// was. With the `blake3-interop` feature it also compares with, and converts to
+/// and from, `blake3::Hash` (see `blake3_interop`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hash32(pub [u8; OUT_LEN]);
+
+impl Hash32 {
+    pub fn as_bytes(&self) -> &[u8; OUT_LEN] {
+        &self.0
+    }
+
+    /// The hash as 64 lowercase hex digits.
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// The hash as 64 uppercase hex digits.
+    pub fn to_hex_upper(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02X}", byte)).collect()
+    }
+}
+
+/// Why a string is not a hex-encoded `Hash32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidHex {
+    /// The string is not exactly 64 bytes long.
+    Length(usize),
+    /// The byte at this offset is not a hex digit.
+    Digit(usize),
+}
+
+impl core::fmt::Display for InvalidHex {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            InvalidHex::Length(len) => write!(f, "expected {} hex digits, got {}", 2 * OUT_LEN, len),
+            InvalidHex::Digit(offset) => write!(f, "invalid hex digit at offset {}", offset),
+        }
+    }
+}
+
+impl core::error::Error for InvalidHex {}
+
+/// Parses 64 hex digits in either case, as written by `to_hex` and `to_hex_upper`.
+impl core::str::FromStr for Hash32 {
+    type Err = InvalidHex;
+
+    fn from_str(hex: &str) -> Result<Self, InvalidHex> {
+        if hex.len() != 2 * OUT_LEN {
+            return Err(InvalidHex::Length(hex.len()));
+        }
+        let digit = |offset: usize| {
+            (hex.as_bytes()[offset] as char)
+                .to_digit(16)
+                .map(|value| value as u8)
+                .ok_or(InvalidHex::Digit(offset))
+        };
+        let mut hash = [0; OUT_LEN];
+        for (i, byte) in hash.iter_mut().enumerate() {
+            *byte = digit(2 * i)? << 4 | digit(2 * i + 1)?;
+        }
+        Ok(Hash32(hash))
+    }
+}
+
+impl core::ops::Deref for Hash32 {
+    type Target = [u8; OUT_LEN];
+
+    fn deref(&self) -> &[u8; OUT_LEN] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Hash32 {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<[u8; OUT_LEN]> for Hash32 {
+    fn from(bytes: [u8; OUT_LEN]) -> Self {
+        Hash32(bytes)
+    }
+}
+
+impl From<Hash32> for [u8; OUT_LEN] {
+    fn from(hash: Hash32) -> Self {
+        hash.0
+    }
+}
+
+impl PartialEq<[u8; OUT_LEN]> for Hash32 {
+    fn eq(&self, other: &[u8; OUT_LEN]) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<Hash32> for [u8; OUT_LEN] {
+    fn eq(&self, other: &Hash32) -> bool {
+        *self == other.0
+    }
+}
+
+/// Why a byte slice is not a valid encoded Output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidOutputBytes {
+    /// The slice is not exactly `OUTPUT_BYTES_LEN` bytes long.
+    Length(usize),
+    /// The encoded block_len exceeds `BLOCK_LEN`.
+    BlockLen(u32),
+}
+
+impl core::fmt::Display for InvalidOutputBytes {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            InvalidOutputBytes::Length(len) => {
+                write!(f, "encoded Output must be {} bytes, got {}", OUTPUT_BYTES_LEN, len)
+            }
+            InvalidOutputBytes::BlockLen(block_len) => {
+                write!(f, "encoded Output has block_len {} > {}", block_len, BLOCK_LEN)
+            }
+        }
+    }
+}
+
+impl core::error::Error for InvalidOutputBytes {}
+
+impl Output {
+    /// The canonical `OUTPUT_BYTES_LEN`-byte encoding, little-endian throughout:
+    ///
+    /// | bytes    | field                                 |
+    /// |----------|---------------------------------------|
+    /// | 0..32    | input_chaining_value (8 u32 words)    |
+    /// | 32..96   | block_words (16 u32 words)            |
+    /// | 96..104  | counter (u64)                         |
+    /// | 104..108 | block_len (u32)                       |
+    /// | 108..112 | flags (u32)                           |
+    ///
+    /// Decode with `Output::try_from(&bytes[..])`.
+    pub fn to_bytes(self) -> [u8; OUTPUT_BYTES_LEN] {
+        let mut bytes = [0u8; OUTPUT_BYTES_LEN];
+        words_into(&self.input_chaining_value, &mut bytes[..32]);
+        words_into(&self.block_words, &mut bytes[32..96]);
+        bytes[96..104].copy_from_slice(&self.counter.to_le_bytes());
+        bytes[104..108].copy_from_slice(&self.block_len.to_le_bytes());
+        bytes[108..112].copy_from_slice(&self.flags.to_le_bytes());
+        bytes
+    }
+
+    /// Check that the flags could come from hashing: no bits outside BLAKE3's flags,
+    /// a parent without the chunk flags, and at most one of the keyed and derive_key
+    /// mode flags. Returns `MerkleError::InvalidFlags` naming the conflicting bits.
+    /// Outputs decoded with `try_from`, deserialized, or passed in over FFI are not
+    /// otherwise checked.
+    pub fn validate_flags(&self) -> Result<(), MerkleError> {
+        let invalid = |conflicting| Err(MerkleError::InvalidFlags { flags: self.flags, conflicting });
+        let unknown = self.flags & !ALL_FLAGS;
+        if unknown != 0 {
+            return invalid(unknown);
+        }
+        let chunk = self.flags & (CHUNK_START | CHUNK_END);
+        if self.flags & PARENT != 0 && chunk != 0 {
+            return invalid(PARENT | chunk);
+        }
+        let modes = self.flags & (KEYED_HASH | DERIVE_KEY_CONTEXT | DERIVE_KEY_MATERIAL);
+        if !modes.is_power_of_two() && modes != 0 {
+            return invalid(modes);
+        }
+        Ok(())
+    }
+
+    fn from_bytes_unchecked(bytes: &[u8; OUTPUT_BYTES_LEN]) -> Self {
+        Output {
+            input_chaining_value: read_words(&bytes[..32]),
+            block_words: read_words(&bytes[32..96]),
+            counter: u64::from_le_bytes(bytes[96..104].try_into().unwrap()),
+            block_len: u32::from_le_bytes(bytes[104..108].try_into().unwrap()),
+            flags: u32::from_le_bytes(bytes[108..112].try_into().unwrap()),
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for Output {
+    type Error = InvalidOutputBytes;
+
+    /// Decode the encoding produced by `Output::to_bytes`, rejecting slices of the
+    /// wrong length and block lengths no real block can have.
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let bytes: &[u8; OUTPUT_BYTES_LEN] = bytes.try_into().map_err(|_| InvalidOutputBytes::Length(bytes.len()))?;
+        let output = Output::from_bytes_unchecked(bytes);
+        if output.block_len > BLOCK_LEN as u32 {
+            return Err(InvalidOutputBytes::BlockLen(output.block_len));
+        }
+        Ok(output)
+    }
+}
+
+/// Streams the extended output of a root node: a byte sequence whose first
+/// `OUT_LEN` bytes are the digest, ending only where u64 positions run out at
+/// `u64::MAX`. Any prefix equals `root_output_bytes` over a buffer of that length.
+/// Each 64-byte output block costs one compression.
+#[derive(Debug, Clone)]
+pub struct OutputReader {
+    output: Output,
+    position: u64,
+}
+
+impl OutputReader {
+    /// A reader over the extended output of `output`, starting at byte 0. The ROOT
+    /// flag is applied to every block, whether or not `output` already carries it.
+    pub fn new(output: Output) -> Self {
+        OutputReader { output, position: 0 }
+    }
+
+    /// Fill `buf` with the next output bytes and advance past them. Returns the
+    /// number of bytes filled, which is short only where the stream ends: positions
+    /// are u64s, so the last byte is the one before `u64::MAX`, and the rest of `buf`
+    /// is left as it was.
+    pub fn fill(&mut self, buf: &mut [u8]) -> usize {
+        let block_len = 2 * OUT_LEN;
+        let len = min(buf.len() as u64, u64::MAX - self.position) as usize;
+        let mut buf = &mut buf[..len];
+        while !buf.is_empty() {
+            let words = compress(
+                &self.output.input_chaining_value,
+                &self.output.block_words,
+                self.position / block_len as u64,
+                self.output.block_len,
+                self.output.flags | ROOT,
+            );
+            let mut block = [0u8; 2 * OUT_LEN];
+            words_into(&words, &mut block);
+            let offset = (self.position % block_len as u64) as usize;
+            let take = min(block_len - offset, buf.len());
+            buf[..take].copy_from_slice(&block[offset..offset + take]);
+            buf = &mut buf[take..];
+            self.position += take as u64;
+        }
+        len
+    }
+
+    /// The offset of the next byte `fill` returns.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Move to byte `position` of the output stream.
+    pub fn set_position(&mut self, position: u64) {
+        self.position = position;
+    }
+}
+
+#[cfg(feature = "std")]
+impl Read for OutputReader {
+    /// Never fails, and returns short only at the end of the stream, at `u64::MAX`.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(self.fill(buf))
+    }
+}
+
+#[cfg(feature = "std")]
+impl Seek for OutputReader {
+    /// Moves without computing the skipped bytes. `SeekFrom::End` fails with
+    /// `InvalidInput`, since the stream has no practical end, as does a seek before byte 0
+    /// or past `u64::MAX`.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "cannot seek from the end of an output stream: it is unbounded",
+                ))
+            }
+        };
+        let position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a position before byte 0 or past u64::MAX")
+        })?;
+        self.set_position(position);
+        Ok(position)
+    }
+}
+
+/// The `Read + Seek` stream over a tree's root output, from `BinaryMerkleTree::root_reader`.
+#[cfg(feature = "std")]
+pub type RootReader = OutputReader;
+
 // =============================================
 // COPIED DIRECTLY FROM BLAKE3 reference_impl.rs
 // =============================================
-/// An incremental hasher that can accept any number of writes.
-pub struct Blake3Hasher {
-    chunk_state: ChunkState,
-    key_words: [u32; 8],
+/// Storage for the hasher's stack of completed-subtree chaining values.
+pub trait CvStack {
+    fn push(&mut self, cv: [u32; 8]);
+    fn pop(&mut self) -> [u32; 8];
+    /// The stacked CVs, bottom (leftmost subtree) first.
+    fn as_slice(&self) -> &[[u32; 8]];
+}
+
+/// The default fixed-size CV stack, stored inline in the hasher.
+#[derive(Debug, Clone)]
+pub struct ArrayCvStack {
     cv_stack: [[u32; 8]; 54], // Space for 54 subtree chaining values:
     cv_stack_len: u8,         // 2^54 * CHUNK_LEN = 2^64
-    flags: u32,
 }
 
-impl Blake3Hasher {
-    fn new_internal(key_words: [u32; 8], flags: u32) -> Self {
+impl Default for ArrayCvStack {
+    fn default() -> Self {
         Self {
-            chunk_state: ChunkState::new(key_words, 0, flags),
-            key_words,
             cv_stack: [[0; 8]; 54],
             cv_stack_len: 0,
-            flags,
         }
     }
+}
+
+impl CvStack for ArrayCvStack {
+    fn push(&mut self, cv: [u32; 8]) {
+        self.cv_stack[self.cv_stack_len as usize] = cv;
+        self.cv_stack_len += 1;
+    }
+
+    fn pop(&mut self) -> [u32; 8] {
+        self.cv_stack_len -= 1;
+        self.cv_stack[self.cv_stack_len as usize]
+    }
+
+    fn as_slice(&self) -> &[[u32; 8]] {
+        &self.cv_stack[..self.cv_stack_len as usize]
+    }
+}
+
+/// A heap-backed CV stack that only grows as deep as the input requires
+/// (one entry per set bit of the chunk count).
+impl CvStack for Vec<[u32; 8]> {
+    fn push(&mut self, cv: [u32; 8]) {
+        Vec::push(self, cv);
+    }
+
+    fn pop(&mut self) -> [u32; 8] {
+        Vec::pop(self).expect("CV stack underflow")
+    }
+
+    fn as_slice(&self) -> &[[u32; 8]] {
+        self
+    }
+}
+
+/// An incremental hasher that can accept any number of writes.
+///
+/// By default the CV stack is a fixed inline array (about 1.7KB). Use
+/// `Blake3Hasher::new_heap` where stack space matters more than an allocation.
+pub struct Blake3Hasher<S: CvStack = ArrayCvStack> {
+    chunk_state: ChunkState,
+    key_words: [u32; 8],
+    cv_stack: S,
+    flags: u32,
+}
+
+impl Blake3Hasher {
+    fn new_internal(key_words: [u32; 8], flags: u32) -> Self {
+        Blake3Hasher::with_cv_stack(key_words, flags, ArrayCvStack::default())
+    }
 
     /// Construct a new `Hasher` for the regular hash function.
     pub fn new() -> Self {
         Self::new_internal(IV, 0)
     }
 
+    /// Construct a new `Hasher` for the keyed hash function.
+    pub fn new_keyed(key: &[u8; KEY_LEN]) -> Self {
+        let mut key_words = [0; 8];
+        words_from_little_endian_bytes(key, &mut key_words);
+        Self::new_internal(key_words, KEYED_HASH)
+    }
+
+    /// Construct a new `Hasher` for the key derivation function. The context
+    /// string should be hardcoded, globally unique, and application-specific.
+    pub fn new_derive_key(context: &str) -> Self {
+        let mut context_hasher = Self::new_internal(IV, DERIVE_KEY_CONTEXT);
+        context_hasher.update(context.as_bytes());
+        let mut context_key = [0; KEY_LEN];
+        context_hasher.finalize(&mut context_key);
+        let mut context_key_words = [0; 8];
+        words_from_little_endian_bytes(&context_key, &mut context_key_words);
+        Self::new_internal(context_key_words, DERIVE_KEY_MATERIAL)
+    }
+}
+
+impl Blake3Hasher<Vec<[u32; 8]>> {
+    /// Construct a new `Hasher` for the regular hash function whose CV stack lives
+    /// on the heap, keeping the hasher itself small. Digests are identical to `new`.
+    pub fn new_heap() -> Self {
+        Blake3Hasher::with_cv_stack(IV, 0, Vec::new())
+    }
+}
+
+impl<S: CvStack> Blake3Hasher<S> {
+    fn with_cv_stack(key_words: [u32; 8], flags: u32, cv_stack: S) -> Self {
+        Self {
+            chunk_state: ChunkState::new(key_words, 0, flags),
+            key_words,
+            cv_stack,
+            flags,
+        }
+    }
+
     fn push_stack(&mut self, cv: [u32; 8]) {
-        self.cv_stack[self.cv_stack_len as usize] = cv;
-        self.cv_stack_len += 1;
+        self.cv_stack.push(cv);
     }
 
     fn pop_stack(&mut self) -> [u32; 8] {
-        self.cv_stack_len -= 1;
-        self.cv_stack[self.cv_stack_len as usize]
+        self.cv_stack.pop()
     }
 
     // Section 5.1.2 of the BLAKE3 spec explains this algorithm in more detail.
@@ -325,17 +1034,55 @@ impl Blake3Hasher {
         }
     }
 
+    /// Add `value`'s bytes to the hash state, as `Update` defines them, so strings
+    /// and byte containers need no `.as_bytes()` or slicing first.
+    pub fn update_typed<T: Update + ?Sized>(&mut self, value: &T) {
+        value.update_hasher(self);
+    }
+
+    /// The chaining values of the complete subtrees over every complete chunk seen
+    /// so far, leftmost (largest) first: one per set bit of the complete chunk
+    /// count, each the root of a power-of-two run of chunks. Together they are a
+    /// compact commitment to the input so far, short of any trailing partial chunk.
+    ///
+    /// A full chunk stays in the chunk state until more input arrives, since it may
+    /// turn out to be the root; it is merged here as it would be then.
+    pub fn peaks(&self) -> Vec<[u32; 8]> {
+        let mut peaks = self.cv_stack.as_slice().to_vec();
+        if self.chunk_state.len() == CHUNK_LEN {
+            let mut new_cv = self.chunk_state.output().chaining_value();
+            let mut total_chunks = self.chunk_state.chunk_counter + 1;
+            while total_chunks & 1 == 0 {
+                new_cv = parent_cv(peaks.pop().expect("CV stack underflow"), new_cv, self.key_words, self.flags);
+                total_chunks >>= 1;
+            }
+            peaks.push(new_cv);
+        }
+        peaks
+    }
+
+    /// The peaks bagged into one commitment, as `mmr::bag_peaks`: the chaining value
+    /// of the root of the tree over every complete chunk seen so far. `None` before
+    /// the first complete chunk.
+    pub fn mmr_bag(&self) -> Option<[u32; 8]> {
+        crate::mmr::bag_peaks(&self.peaks(), self.key_words, self.flags)
+    }
+
     /// Finalize the hash and write any number of output bytes.
+    ///
+    /// This does not modify the hasher: it may be called mid-stream to get the hash
+    /// of the input so far, and `update` may continue afterwards.
     pub fn finalize(&self, out_slice: &mut [u8]) {
         // Starting with the Output from the current chunk, compute all the
         // parent chaining values along the right edge of the tree, until we
         // have the root Output.
         let mut output = self.chunk_state.output();
-        let mut parent_nodes_remaining = self.cv_stack_len as usize;
+        let cv_stack = self.cv_stack.as_slice();
+        let mut parent_nodes_remaining = cv_stack.len();
         while parent_nodes_remaining > 0 {
             parent_nodes_remaining -= 1;
             output = parent_output(
-                self.cv_stack[parent_nodes_remaining],
+                cv_stack[parent_nodes_remaining],
                 output.chaining_value(),
                 self.key_words,
                 self.flags,
@@ -343,51 +1090,742 @@ impl Blake3Hasher {
         }
         output.root_output_bytes(out_slice);
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct BinaryMerkleTree {
-    tree: Vec<Output>,
-    actual_leaves: usize,
-    number_of_leaves: usize,
-    leaf_start_index: usize,
-    key_words: [u32; 8],
-    flags: u32,
+    /// Finalize the hash into exactly `N` output bytes, without an out-parameter or
+    /// heap allocation. Useful when the output size is known at compile time.
+    pub fn finalize_n<const N: usize>(&self) -> [u8; N] {
+        let mut out = [0; N];
+        self.finalize(&mut out);
+        out
+    }
+
+    /// Finalize the hash into the default 32-byte digest.
+    pub fn finalize_array(&self) -> [u8; OUT_LEN] {
+        self.finalize_n::<OUT_LEN>()
+    }
+
+    /// The number of input bytes added so far.
+    pub fn count(&self) -> u64 {
+        self.chunk_state.chunk_counter * CHUNK_LEN as u64 + self.chunk_state.len() as u64
+    }
 }
 
+impl Default for Blake3Hasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Values with a canonical byte representation for `Blake3Hasher::update_typed`.
+/// The bytes are hashed as they are, with no length or type prefix, so `"abc"`,
+/// `b"abc"` and `vec![b'a', b'b', b'c']` all hash alike, and hashing two values in
+/// a row is the same as hashing their concatenation.
+pub trait Update {
+    fn update_hasher<S: CvStack>(&self, hasher: &mut Blake3Hasher<S>);
+}
+
+impl Update for [u8] {
+    fn update_hasher<S: CvStack>(&self, hasher: &mut Blake3Hasher<S>) {
+        hasher.update(self);
+    }
+}
+
+impl<const N: usize> Update for [u8; N] {
+    fn update_hasher<S: CvStack>(&self, hasher: &mut Blake3Hasher<S>) {
+        hasher.update(self);
+    }
+}
+
+impl Update for Vec<u8> {
+    fn update_hasher<S: CvStack>(&self, hasher: &mut Blake3Hasher<S>) {
+        hasher.update(self);
+    }
+}
+
+/// The UTF-8 bytes of the string.
+impl Update for str {
+    fn update_hasher<S: CvStack>(&self, hasher: &mut Blake3Hasher<S>) {
+        hasher.update(self.as_bytes());
+    }
+}
+
+impl Update for String {
+    fn update_hasher<S: CvStack>(&self, hasher: &mut Blake3Hasher<S>) {
+        hasher.update(self.as_bytes());
+    }
+}
+
+impl<T: Update + ?Sized> Update for &T {
+    fn update_hasher<S: CvStack>(&self, hasher: &mut Blake3Hasher<S>) {
+        (**self).update_hasher(hasher);
+    }
+}
+
+/// `Blake3Hasher` as a `core::hash::Hasher`, so it can hash keys for `HashMap` and
+/// other users of `Hash`. `finish` keeps only the first 8 bytes of the digest, as
+/// a little-endian u64, and finalizes on every call, so this is far slower than
+/// the default SipHash; use it where keys must hash the same everywhere or a keyed
+/// BLAKE3 is wanted, not for speed.
+#[derive(Default)]
+pub struct Blake3StdHasher(Blake3Hasher);
+
+impl core::hash::Hasher for Blake3StdHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        u64::from_le_bytes(self.0.finalize_n())
+    }
+}
+
+/// Builds a `Blake3StdHasher` per key, for `HashMap::with_hasher`. `new_keyed`
+/// makes the hashes unpredictable to anyone without the key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blake3BuildHasher {
+    key_words: [u32; 8],
+    flags: u32,
+}
+
+impl Blake3BuildHasher {
+    pub fn new() -> Self {
+        Blake3BuildHasher { key_words: IV, flags: FLAGS }
+    }
+
+    pub fn new_keyed(key: &[u8; KEY_LEN]) -> Self {
+        Blake3BuildHasher { key_words: read_words(key), flags: KEYED_HASH }
+    }
+}
+
+impl Default for Blake3BuildHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::hash::BuildHasher for Blake3BuildHasher {
+    type Hasher = Blake3StdHasher;
+
+    fn build_hasher(&self) -> Blake3StdHasher {
+        Blake3StdHasher(Blake3Hasher::new_internal(self.key_words, self.flags))
+    }
+}
+
+/// The root bytes a tree over `input` must have, computed through `Blake3Hasher`
+/// (the reference implementation) rather than through a tree. This is the
+/// authoritative value to validate a BinaryMerkleTree against.
+pub fn expected_root_bytes(input: &[u8], key_words: [u32; 8], flags: u32) -> [u8; OUT_LEN] {
+    let mut hasher = Blake3Hasher::new_internal(key_words, flags);
+    hasher.update(input);
+    hasher.finalize_array()
+}
+
+/// Incrementally splits a byte stream into chunks and collects the leaf Output of
+/// each completed chunk, so a tree can be built from a stream without buffering it.
+/// Like `Blake3Hasher`, the current chunk is carried across `update` calls.
+#[derive(Debug, Clone)]
+pub struct TreeLeafBuilder {
+    chunk_state: ChunkState,
+    leaves: Vec<Output>,
+    key_words: [u32; 8],
+    flags: u32,
+    // The `chunk_digest` of each completed chunk and the state computing the
+    // current one, when enabled with `with_content_hashes`. The state is boxed so
+    // builders without content hashes stay small.
+    content_hashes: Option<(Vec<Hash32>, Box<ChunkState>)>,
+}
+
+impl TreeLeafBuilder {
+    pub fn new(key_words: [u32; 8], flags: u32) -> Self {
+        Self {
+            chunk_state: ChunkState::new(key_words, 0, flags),
+            leaves: Vec::new(),
+            key_words,
+            flags,
+            content_hashes: None,
+        }
+    }
+
+    /// Also record each chunk's `chunk_digest`, its plain BLAKE3 hash on its own,
+    /// which depends only on the chunk's bytes and not on its position, key or
+    /// flags. `finalize_tree` attaches them to the tree for `BinaryMerkleTree::content_hash`
+    /// and `dedupe::dedupe_report`. Every chunk is hashed twice.
+    pub fn with_content_hashes(mut self) -> Self {
+        self.content_hashes = Some((Vec::new(), Box::new(ChunkState::new(IV, 0, 0))));
+        self
+    }
+
+    /// Add input to the builder. This can be called any number of times.
+    pub fn update(&mut self, mut input: &[u8]) {
+        while !input.is_empty() {
+            // If the current chunk is complete, finalize it and reset the
+            // chunk state. More input is coming, so this chunk is not ROOT.
+            if self.chunk_state.len() == CHUNK_LEN {
+                self.leaves.push(self.chunk_state.output());
+                let total_chunks = self.chunk_state.chunk_counter + 1;
+                self.chunk_state = ChunkState::new(self.key_words, total_chunks, self.flags);
+                if let Some((hashes, digest_state)) = &mut self.content_hashes {
+                    hashes.push(digest_of(digest_state));
+                    **digest_state = ChunkState::new(IV, 0, 0);
+                }
+            }
+
+            // Compress input bytes into the current chunk state.
+            let want = CHUNK_LEN - self.chunk_state.len();
+            let take = min(want, input.len());
+            self.chunk_state.update(&input[..take]);
+            if let Some((_, digest_state)) = &mut self.content_hashes {
+                digest_state.update(&input[..take]);
+            }
+            input = &input[take..];
+        }
+    }
+
+    /// The leaves of all chunks completed so far. The chunk currently being filled
+    /// is not included, since more input may still extend it.
+    pub fn completed_leaves(&self) -> &[Output] {
+        &self.leaves
+    }
+
+    /// Flush the final chunk and return the leaf Outputs of the whole stream.
+    /// Empty input yields a single empty-chunk leaf, as in `BinaryMerkleTree::from_input`.
+    pub fn finalize(mut self) -> Vec<Output> {
+        // Add the final chunk if it's not empty, or the empty chunk if nothing was added
+        if !self.chunk_state.is_empty() || self.leaves.is_empty() {
+            self.leaves.push(self.chunk_state.output());
+        }
+        self.leaves
+    }
+
+    /// Flush the final chunk and build the tree over the whole stream.
+    pub fn finalize_tree(mut self) -> BinaryMerkleTree {
+        let (key_words, flags) = (self.key_words, self.flags);
+        let input_len = (self.leaves.len() * CHUNK_LEN + self.chunk_state.len()) as u64;
+        let content_hashes = self.content_hashes.take().map(|(mut hashes, digest_state)| {
+            // The same final chunk `finalize` flushes
+            if !self.chunk_state.is_empty() || self.leaves.is_empty() {
+                hashes.push(digest_of(&digest_state));
+            }
+            hashes
+        });
+        let mut tree = BinaryMerkleTree::new_from_leaves(self.finalize(), key_words, flags).with_input_len(input_len);
+        tree.content_hashes = content_hashes;
+        tree
+    }
+}
+
+/// A 0-based position among the leaves, as used by `insert_leaf`, `leaf` and the
+/// other leaf-level methods. Plain `usize` values convert into it, so existing
+/// callers keep working, but a `HeapIndex` does not: mixing the two up is a type
+/// error.
+///
+/// ```compile_fail
+/// # use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, HeapIndex, IV, FLAGS};
+/// let tree = BinaryMerkleTree::from_input(&[0; 4096], IV, FLAGS);
+/// tree.leaf(HeapIndex(4)); // a heap index is not a leaf index
+/// ```
+///
+/// Convert with `BinaryMerkleTree::heap_index` and `BinaryMerkleTree::leaf_index`,
+/// which know the tree's leaf offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LeafIndex(pub usize);
+
+impl From<usize> for LeafIndex {
+    fn from(leaf_index: usize) -> Self {
+        LeafIndex(leaf_index)
+    }
+}
+
+/// A 1-based position in the node heap: 1 is the root, the children of `i` are
+/// `2i` and `2i + 1`, and leaf `l` sits at `num_leaves() + l`. Deliberately not
+/// convertible from `usize` or `LeafIndex`, so the offset is never applied
+/// implicitly.
+///
+/// ```compile_fail
+/// # use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, HeapIndex, LeafIndex, IV, FLAGS};
+/// let tree = BinaryMerkleTree::from_input(&[0; 4096], IV, FLAGS);
+/// let heap_index: HeapIndex = LeafIndex(2).into(); // needs the tree's leaf offset
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HeapIndex(pub usize);
+
+/// The chaining value of a leaf (a chunk). BLAKE3 separates leaves from parents
+/// with the CHUNK_START/CHUNK_END and PARENT flags, but a bare `[u32; 8]` no
+/// longer says which it came from. APIs that need a leaf take this type, so a
+/// parent's chaining value cannot be passed by mistake:
+///
+/// ```compile_fail
+/// # use merkle_tree::binary_merkle_tree::{fold_leaf_path, BinaryMerkleTree, HeapIndex, IV, FLAGS};
+/// let tree = BinaryMerkleTree::from_input(&[0; 4096], IV, FLAGS);
+/// let parent = tree.parent_cv(HeapIndex(2)).unwrap();
+/// let siblings = tree.update_path_siblings(0).unwrap();
+/// fold_leaf_path(parent, 0, 4, &siblings, IV, FLAGS); // a parent is not a leaf
+/// ```
+///
+/// Get one from `LeafCv::from_output` or `GenericMerkleTree::leaf_cv`.
+/// `LeafCv::assume_leaf` wraps raw words the caller vouches for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LeafCv([u32; 8]);
+
+/// The chaining value of a parent node, from `ParentCv::join`. Like `LeafCv` it
+/// only converts from raw words through an explicitly named function:
+///
+/// ```compile_fail
+/// # use merkle_tree::binary_merkle_tree::{LeafCv, ParentCv};
+/// let leaf: LeafCv = ParentCv::assume_parent([0; 8]).into();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParentCv([u32; 8]);
+
+/// The chaining value of either kind of node, for the places where both are
+/// allowed: the children of a parent, and the root of a tree that may have one leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeCv {
+    Leaf(LeafCv),
+    Parent(ParentCv),
+}
+
+impl LeafCv {
+    /// The chaining value of a chunk's Output, or the hash a leaf of a tree of leaf
+    /// hashes holds, or `None` if `output` is a parent.
+    pub fn from_output(output: &Output) -> Option<Self> {
+        if output.is_leaf_hash() {
+            return Some(LeafCv(output.input_chaining_value));
+        }
+        (!output.is_parent()).then(|| LeafCv(output.chaining_value()))
+    }
+
+    /// Treat `words` as a leaf's chaining value without checking where they came from.
+    pub fn assume_leaf(words: [u32; 8]) -> Self {
+        LeafCv(words)
+    }
+
+    pub fn words(&self) -> [u32; 8] {
+        self.0
+    }
+}
+
+impl ParentCv {
+    /// The chaining value of the parent of `left` and `right`. Either child may be a
+    /// leaf or a parent.
+    pub fn join(left: impl Into<NodeCv>, right: impl Into<NodeCv>, key_words: [u32; 8], flags: u32) -> Self {
+        ParentCv(parent_cv(left.into().words(), right.into().words(), key_words, flags))
+    }
+
+    /// The chaining value of a parent's Output, or `None` if `output` is a chunk
+    /// or a leaf hash.
+    pub fn from_output(output: &Output) -> Option<Self> {
+        output.is_parent().then(|| ParentCv(output.chaining_value()))
+    }
+
+    /// Treat `words` as a parent's chaining value without checking where they came from.
+    pub fn assume_parent(words: [u32; 8]) -> Self {
+        ParentCv(words)
+    }
+
+    pub fn words(&self) -> [u32; 8] {
+        self.0
+    }
+}
+
+impl NodeCv {
+    /// The chaining value of `output`, as the kind its flags say it is.
+    pub fn from_output(output: &Output) -> Self {
+        match LeafCv::from_output(output) {
+            Some(leaf) => NodeCv::Leaf(leaf),
+            None => NodeCv::Parent(ParentCv(output.chaining_value())),
+        }
+    }
+
+    pub fn words(&self) -> [u32; 8] {
+        match self {
+            NodeCv::Leaf(leaf) => leaf.0,
+            NodeCv::Parent(parent) => parent.0,
+        }
+    }
+}
+
+impl From<LeafCv> for NodeCv {
+    fn from(leaf: LeafCv) -> Self {
+        NodeCv::Leaf(leaf)
+    }
+}
+
+impl From<ParentCv> for NodeCv {
+    fn from(parent: ParentCv) -> Self {
+        NodeCv::Parent(parent)
+    }
+}
+
+impl From<LeafCv> for [u32; 8] {
+    fn from(leaf: LeafCv) -> Self {
+        leaf.0
+    }
+}
+
+impl From<ParentCv> for [u32; 8] {
+    fn from(parent: ParentCv) -> Self {
+        parent.0
+    }
+}
+
+/// Fold `leaf`, the new chaining value of leaf `leaf_index` in a tree of
+/// `actual_leaves` leaves, up through `siblings` from `update_path_siblings` to
+/// the chaining value of the root. This is the root's chaining value, not the root
+/// hash, and is the leaf itself for a one-leaf tree. Returns `None` if the index
+/// is out of bounds or there are not exactly as many siblings as the path needs.
+pub fn fold_leaf_path(
+    leaf: LeafCv,
+    leaf_index: usize,
+    actual_leaves: usize,
+    siblings: &[[u32; 8]],
+    key_words: [u32; 8],
+    flags: u32,
+) -> Option<NodeCv> {
+    if leaf_index >= actual_leaves {
+        return None;
+    }
+    let mut siblings = siblings.iter();
+    let mut node = NodeCv::Leaf(leaf);
+    let mut position = leaf_index;
+    let mut nodes_in_this_level = actual_leaves;
+    while nodes_in_this_level > 1 {
+        // A lone left child is promoted unchanged
+        if position ^ 1 < nodes_in_this_level {
+            // Siblings may be leaves or parents; only their words are needed
+            let sibling = *siblings.next()?;
+            let parent = if position.is_multiple_of(2) {
+                parent_cv(node.words(), sibling, key_words, flags)
+            } else {
+                parent_cv(sibling, node.words(), key_words, flags)
+            };
+            node = NodeCv::Parent(ParentCv(parent));
+        }
+        position /= 2;
+        nodes_in_this_level = nodes_in_this_level.div_ceil(2);
+    }
+    siblings.next().is_none().then_some(node)
+}
+
+/// A BLAKE3 Merkle tree whose nodes live in `S` (see `NodeStore`). Every
+/// operation on nodes goes through the store, so roots and proofs do not depend
+/// on where the nodes are kept.
+///
+/// Reads such as `root`, `leaf`, `generate_proof` and `verify_chunk` take `&self`
+/// and the tree keeps no caches of its own, so it is `Sync` whenever its store is:
+/// every store in this crate is, and a `BinaryMerkleTree` behind `&` or `Arc` can
+/// serve proofs from many threads at once. Changes take `&mut self`, so the borrow
+/// checker keeps them apart from readers.
+#[derive(Debug, Clone)]
+pub struct GenericMerkleTree<S: NodeStore> {
+    nodes: S,
+    actual_leaves: usize,
+    number_of_leaves: usize,
+    leaf_start_index: usize,
+    key_words: [u32; 8],
+    flags: u32,
+    // Total input length, when the tree was built from bytes rather than leaves
+    input_len: Option<u64>,
+    // Whether the leaves are application-defined hashes (see `from_leaf_hashes`)
+    leaf_hashes: bool,
+    // Leaves marked by `remove_leaf`, which still hash into the root until `compact`
+    removed: BTreeSet<usize>,
+    // The `chunk_digest` of every leaf's chunk, when built with
+    // `TreeLeafBuilder::with_content_hashes` and no leaf has been replaced since
+    content_hashes: Option<Vec<Hash32>>,
+    // Internal nodes rewritten by the last `insert_leaf` or `bulk_insert_leaves`
+    nodes_recomputed: usize,
+    // Open checkpoints, outermost first; changes are journaled in the last one
+    checkpoints: Vec<Journal>,
+    // The id of the next checkpoint, so a closed one is never mistaken for an open one
+    next_checkpoint: u64,
+}
+
+/// A handle to the tree state saved by `checkpoint`, consumed by `rollback` or
+/// `discard`.
+#[derive(Debug)]
+pub struct Checkpoint {
+    id: u64,
+}
+
+/// What a checkpoint needs to undo the changes made since it was taken.
+#[derive(Debug, Clone)]
+struct Journal {
+    id: u64,
+    // The value each node had at the checkpoint, saved when it is first overwritten
+    nodes: BTreeMap<usize, Output>,
+    input_len: Option<u64>,
+    removed: BTreeSet<usize>,
+    nodes_recomputed: usize,
+    // What the content hashes were, once a change has forgotten them
+    content_hashes: Option<Option<Vec<Hash32>>>,
+}
+
+/// The tree with its nodes in a `VecStore`. Node storage is shared copy-on-write:
+/// cloning a tree (see `snapshot`) is O(1) and the nodes are only copied the first
+/// time either copy is modified.
+pub type BinaryMerkleTree = GenericMerkleTree<VecStore>;
+
+/// The tree with its nodes in a `SharedStore`, for keeping many versions of one
+/// file. Versions share every node except those on the paths that changed between
+/// them, so each single-leaf update costs O(log n) nodes, where a `BinaryMerkleTree`
+/// snapshot copies all of them on its first change. Reads go through more pointers.
+pub type SnapshotTree = GenericMerkleTree<SharedStore>;
+
 impl BinaryMerkleTree {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(leaves = leaves.len())))]
     pub fn new_from_leaves(leaves: Vec<Output>, key_words: [u32; 8], flags: u32) -> Self {
-        let actual_leaves = leaves.len();
         // Calculate the next power of two to allocate enough space
         let number_of_leaves = leaves.len().next_power_of_two();
-        let nodes = vec![Output {
-            input_chaining_value: key_words,
-            block_words: [0; 16],
-            counter: 0,
-            block_len: 64,
-            flags,
-        }; 2 * number_of_leaves];
+        let nodes = VecStore::new(2 * number_of_leaves, padding_output(key_words, flags));
+        Self::new_from_leaves_in(nodes, leaves, key_words, flags)
+    }
 
+    /// `new_from_leaves` for leaves from an untrusted source, such as deserialized
+    /// or FFI Outputs. Each leaf must pass `Output::validate_flags` and be a finished
+    /// chunk hashed with `flags`: CHUNK_END set, PARENT and ROOT clear, and the same
+    /// mode bits. Returns `NoLeaves` for no leaves, and the first failure otherwise.
+    pub fn new_from_leaves_checked(leaves: Vec<Output>, key_words: [u32; 8], flags: u32) -> Result<Self, MerkleError> {
+        if leaves.is_empty() {
+            return Err(MerkleError::NoLeaves);
+        }
+        for (leaf_index, leaf) in leaves.iter().enumerate() {
+            leaf.validate_flags()?;
+            let is_chunk = leaf.flags & (CHUNK_END | PARENT | ROOT) == CHUNK_END;
+            if !is_chunk || leaf.flags & !(CHUNK_START | CHUNK_END) != flags {
+                return Err(MerkleError::InvalidLeaf { leaf_index, flags: leaf.flags });
+            }
+        }
+        Ok(Self::new_from_leaves(leaves, key_words, flags))
+    }
+
+    /// Take a cheap, independent copy of the tree. The snapshot shares node storage
+    /// with `self` until one of them is modified, so keeping many versions costs
+    /// nothing until they diverge.
+    pub fn snapshot(&self) -> BinaryMerkleTree {
+        self.clone()
+    }
+
+    /// The memory the tree holds, in bytes: the struct itself, the node heap at
+    /// `OUTPUT_BYTES_LEN` bytes or so per node, and the content hashes if kept. The
+    /// heap has `2 * num_leaves()` positions, so this is about 224 bytes per leaf,
+    /// against 64 for the chaining values of a `FlatTree`. Nodes shared with a
+    /// snapshot are counted in full by each tree sharing them.
+    pub fn heap_size_bytes(&self) -> usize {
+        size_of::<Self>()
+            + self.nodes.capacity() * size_of::<Output>()
+            + self.content_hashes.as_ref().map_or(0, |hashes| hashes.capacity() * size_of::<Hash32>())
+    }
+
+    /// Mutable access to the nodes, copying them first if they are shared with a snapshot.
+    #[cfg(test)]
+    fn nodes_mut(&mut self) -> &mut Vec<Output> {
+        self.nodes.nodes_mut()
+    }
+
+    /// Rebuild the tree over the chunks of `input` that were not removed, in order,
+    /// and return for each old leaf index its new index, or `None` if it was removed.
+    ///
+    /// Compaction changes the data the tree describes, so the root changes too: the
+    /// result is exactly `from_input` over the surviving chunks. Those chunks move to
+    /// new positions, and a leaf's Output commits to its chunk counter, so surviving
+    /// leaves cannot be reused and are rehashed from `input`, the bytes this tree was
+    /// built from. Any open checkpoints are closed, and rolling one back afterwards
+    /// returns `MerkleError::UnknownCheckpoint`. Returns
+    /// `MerkleError::InputLengthMismatch`, leaving the tree unchanged, if `input`
+    /// does not split into this tree's number of leaves, or
+    /// `MerkleError::WrongLeafKind` if the leaves are leaf hashes, which have no
+    /// input to rehash.
+    pub fn compact(&mut self, input: &[u8]) -> Result<Vec<Option<usize>>, MerkleError> {
+        self.check_leaf_kind(false)?;
+        if chunk_count(input.len()) != self.actual_leaves {
+            return Err(MerkleError::InputLengthMismatch {
+                input_len: input.len() as u64,
+                actual_leaves: self.actual_leaves,
+            });
+        }
+
+        let mut kept = Vec::with_capacity(input.len());
+        let mut mapping = Vec::with_capacity(self.actual_leaves);
+        let mut new_index = 0;
+        for leaf_index in 0..self.actual_leaves {
+            if self.removed.contains(&leaf_index) {
+                mapping.push(None);
+                continue;
+            }
+            let start = leaf_index * CHUNK_LEN;
+            kept.extend_from_slice(&input[start..min(start + CHUNK_LEN, input.len())]);
+            mapping.push(Some(new_index));
+            new_index += 1;
+        }
+        // Open checkpoints are dropped with the old nodes, but ids keep counting so
+        // none of them can match a checkpoint taken afterwards
+        let next_checkpoint = self.next_checkpoint;
+        *self = Self::from_input(&kept, self.key_words, self.flags);
+        self.next_checkpoint = next_checkpoint;
+        Ok(mapping)
+    }
+}
+
+impl SnapshotTree {
+    /// `BinaryMerkleTree::from_input` into a `SharedStore`.
+    pub fn from_input(input: &[u8], key_words: [u32; 8], flags: u32) -> Self {
+        Self::from_input_in(SharedStore::new(), input, key_words, flags)
+    }
+
+    /// `BinaryMerkleTree::new_from_leaves` into a `SharedStore`.
+    pub fn new_from_leaves(leaves: Vec<Output>, key_words: [u32; 8], flags: u32) -> Self {
+        Self::new_from_leaves_in(SharedStore::new(), leaves, key_words, flags)
+    }
+
+    /// Take an O(1) copy of this version. Changing either copy leaves the other as
+    /// it was and shares all the nodes the change did not touch.
+    pub fn snapshot(&self) -> SnapshotTree {
+        self.clone()
+    }
+
+    /// The next version: this tree with the leaf at `leaf_index` replaced, as
+    /// `insert_leaf` would, sharing everything off its path with `self`.
+    pub fn with_leaf(&self, leaf_index: impl Into<LeafIndex>, leaf_output: Output) -> Result<SnapshotTree, MerkleError> {
+        let mut next = self.snapshot();
+        next.insert_leaf(leaf_index, leaf_output)?;
+        Ok(next)
+    }
+}
+
+impl<S: NodeStore> GenericMerkleTree<S> {
+    /// Build a tree over `leaves` with its nodes in `nodes`, which must accept
+    /// every id below `2 * leaves.len().next_power_of_two()`.
+    pub fn new_from_leaves_in(nodes: S, leaves: Vec<Output>, key_words: [u32; 8], flags: u32) -> Self {
         // Create a new tree with the actual number of leaves
-        let mut binary_tree = BinaryMerkleTree { 
-            tree: nodes,
+        let mut binary_tree = Self::with_shape(nodes, leaves.len(), key_words, flags);
+        binary_tree.create_tree_from_leaves(leaves);
+        binary_tree
+    }
+
+    /// Build a tree over `input` with its nodes in `nodes`, as `from_input` does.
+    /// Each chunk is written to the store as soon as it is hashed and parents are
+    /// built from the store, so beyond the store itself this holds O(1) nodes in
+    /// memory, which keeps a disk-backed store bounded by its cache.
+    pub fn from_input_in(nodes: S, input: &[u8], key_words: [u32; 8], flags: u32) -> Self {
+        let mut binary_tree = Self::with_shape(nodes, chunk_count(input.len()), key_words, flags);
+        for leaf_index in 0..binary_tree.actual_leaves {
+            let start = leaf_index * CHUNK_LEN;
+            let mut chunk_state = ChunkState::new(key_words, leaf_index as u64, flags);
+            chunk_state.update(&input[start..min(start + CHUNK_LEN, input.len())]);
+            binary_tree.set_node(binary_tree.leaf_start_index + leaf_index, chunk_state.output());
+        }
+        binary_tree.build_parents();
+        binary_tree.with_input_len(input.len() as u64)
+    }
+
+    /// Attach to `nodes`, a store already holding every node of a tree with
+    /// `actual_leaves` leaves built with `key_words` and `flags`, such as a reopened
+    /// `FileStore`. Nothing is read or checked; call `verify_integrity` when the
+    /// store is not trusted. Returns `MerkleError::NoLeaves` if `actual_leaves` is zero
+    /// and `MerkleError::TooManyChunks` if it fails `check_leaf_count`.
+    pub fn open_in(nodes: S, actual_leaves: usize, key_words: [u32; 8], flags: u32) -> Result<Self, MerkleError> {
+        if actual_leaves == 0 {
+            return Err(MerkleError::NoLeaves);
+        }
+        check_leaf_count(actual_leaves as u64)?;
+        Ok(Self::with_shape(nodes, actual_leaves, key_words, flags))
+    }
+
+    fn with_shape(nodes: S, actual_leaves: usize, key_words: [u32; 8], flags: u32) -> Self {
+        let number_of_leaves = actual_leaves.next_power_of_two();
+        GenericMerkleTree {
+            nodes,
             actual_leaves,
             number_of_leaves,
             leaf_start_index: number_of_leaves,
             key_words,
             flags,
-        };
-        binary_tree.create_tree_from_leaves(leaves);
-        binary_tree
+            input_len: None,
+            leaf_hashes: false,
+            removed: BTreeSet::new(),
+            content_hashes: None,
+            nodes_recomputed: 0,
+            checkpoints: Vec::new(),
+            next_checkpoint: 0,
+        }
+    }
+
+    /// The store holding this tree's nodes.
+    pub fn store(&self) -> &S {
+        &self.nodes
+    }
+
+    /// Mutable access to the store, for maintenance such as flushing it. Nodes
+    /// written through this bypass the tree and must keep it consistent.
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.nodes
     }
 
     pub fn root(&self) -> Output {
-        let mut root = self.tree[1];
+        let mut root = self.node(1);
         // Apply ROOT flag to the final root output
         root.flags |= ROOT;
         root
     }
 
+    /// Returns the 32-byte BLAKE3 digest of the whole input, i.e. the ROOT-flagged
+    /// output bytes of `tree[1]`. This also holds for empty input, where the tree is
+    /// a single empty chunk and the digest equals the official `blake3::hash(b"")`.
+    pub fn root_hash(&self) -> Hash32 {
+        let mut hash = [0; OUT_LEN];
+        self.root().root_output_bytes(&mut hash);
+        Hash32(hash)
+    }
+
+    /// The root hash as `prefix` followed by 64 uppercase hex digits, for tooling that
+    /// expects tagged digests such as `blake3:AF1349B9...`. `root_hash().to_hex()`
+    /// gives the plain lowercase form.
+    pub fn root_hash_tagged(&self, prefix: &str) -> String {
+        format!("{}{}", prefix, self.root_hash().to_hex_upper())
+    }
+
+    /// A reader streaming any number of output bytes from the ROOT output of
+    /// `tree[1]`; its first `OUT_LEN` bytes are `root_hash`.
+    pub fn output_reader(&self) -> OutputReader {
+        OutputReader::new(self.root())
+    }
+
+    /// `output_reader` as a `Read + Seek` stream, for using the root as an
+    /// arbitrarily long deterministic byte source: keystreams, test data, padding.
+    /// Seeking costs nothing; only the blocks actually read are computed.
+    #[cfg(feature = "std")]
+    pub fn root_reader(&self) -> RootReader {
+        self.output_reader()
+    }
+
+    /// Like `output_reader`, but consumes the tree, for "build, then stream N bytes".
+    pub fn into_output_reader(self) -> OutputReader {
+        self.output_reader()
+    }
+
+    /// Both faces of the root node: its non-root chaining value, which is what a
+    /// parent would consume if this tree were composed as a subtree of a larger one,
+    /// and the 32-byte ROOT-flagged digest. These differ, because the ROOT flag
+    /// changes the compression. `root().chaining_value()` is not the former: it is
+    /// the ROOT-flagged compression, i.e. just the digest read back as words.
+    pub fn root_cv_and_bytes(&self) -> ([u32; 8], Hash32) {
+        (self.node(1).chaining_value(), self.root_hash())
+    }
+
+    /// Panic unless the root matches `expected_root_bytes` over `input` with this
+    /// tree's key and flags. A debugging aid for tests after construction or updates.
+    #[track_caller]
+    pub fn assert_matches_reference(&self, input: &[u8]) {
+        let expected = expected_root_bytes(input, self.key_words, self.flags);
+        let actual = self.root_hash();
+        assert!(
+            actual == expected,
+            "Tree root {:02x?} does not match reference root {:02x?} for {} bytes in {} leaves",
+            actual, expected, input.len(), self.actual_leaves
+        );
+    }
+
     pub fn num_leaves(&self) -> usize {
         self.number_of_leaves
     }
@@ -396,6 +1834,124 @@ impl BinaryMerkleTree {
         self.actual_leaves
     }
 
+    pub fn key_words(&self) -> [u32; 8] {
+        self.key_words
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// The total length of the input, if known. It is known for trees built from
+    /// bytes, unknown for trees built with `new_from_leaves`, and forgotten once the
+    /// final leaf is replaced, since the new leaf may cover a different length.
+    pub fn input_len(&self) -> Option<u64> {
+        self.input_len
+    }
+
+    /// The `chunk_digest` of the chunk at `leaf_index`, if the tree was built by a
+    /// `TreeLeafBuilder` using `with_content_hashes`. Unlike the leaf's chaining
+    /// value it does not depend on the chunk's position, so equal chunks anywhere in
+    /// any trees have equal content hashes. Replacing or moving any leaf (`insert_leaf`,
+    /// `bulk_insert_leaves`, `swap_leaves`) forgets them all; they are not serialized.
+    pub fn content_hash(&self, leaf_index: impl Into<LeafIndex>) -> Option<Hash32> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        self.content_hashes.as_ref()?.get(leaf_index).copied()
+    }
+
+    pub(crate) fn with_input_len(mut self, input_len: u64) -> Self {
+        self.input_len = Some(input_len);
+        self
+    }
+
+    /// Restore a known input length after undoing a change to the final leaf.
+    #[cfg(feature = "std")]
+    pub(crate) fn set_input_len(&mut self, input_len: Option<u64>) {
+        self.input_len = input_len;
+    }
+
+    /// The heap position of leaf `leaf_index`, or `None` if out of bounds.
+    pub fn heap_index(&self, leaf_index: impl Into<LeafIndex>) -> Option<HeapIndex> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        (leaf_index < self.actual_leaves).then_some(HeapIndex(self.leaf_start_index + leaf_index))
+    }
+
+    /// The leaf at heap position `heap_index`, or `None` if that node is not a leaf.
+    pub fn leaf_index(&self, heap_index: HeapIndex) -> Option<LeafIndex> {
+        let leaf_index = heap_index.0.checked_sub(self.leaf_start_index)?;
+        (leaf_index < self.actual_leaves).then_some(LeafIndex(leaf_index))
+    }
+
+    /// The node at heap position `heap_index`, or `None` for index 0 and for the
+    /// unused padding positions past the real nodes of each level.
+    pub fn node_at(&self, heap_index: HeapIndex) -> Option<Output> {
+        let HeapIndex(index) = heap_index;
+        if index == 0 || index >= 2 * self.number_of_leaves {
+            return None;
+        }
+        let level = self.leaf_start_index.ilog2() - index.ilog2();
+        let position = index - (self.leaf_start_index >> level);
+        let nodes_in_level = self.actual_leaves.div_ceil(1 << level);
+        (position < nodes_in_level).then(|| self.node(index))
+    }
+
+    /// Returns the stored Output of the leaf at `leaf_index`, or `None` if out of bounds.
+    pub fn leaf(&self, leaf_index: impl Into<LeafIndex>) -> Option<Output> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        if leaf_index >= self.actual_leaves {
+            return None;
+        }
+        Some(self.node(self.leaf_start_index + leaf_index))
+    }
+
+    /// The chaining value of the leaf at `leaf_index`, or `None` if out of bounds.
+    pub fn leaf_cv(&self, leaf_index: impl Into<LeafIndex>) -> Option<LeafCv> {
+        self.leaf(leaf_index).map(|leaf| LeafCv(self.stored_cv(&leaf)))
+    }
+
+    /// Whether the leaves are application-defined hashes (see `from_leaf_hashes`).
+    pub fn has_leaf_hashes(&self) -> bool {
+        self.leaf_hashes
+    }
+
+    /// The hash at `leaf_index` of a tree of leaf hashes, or `None` if out of bounds
+    /// or the leaves are chunks.
+    pub fn leaf_hash(&self, leaf_index: impl Into<LeafIndex>) -> Option<Hash32> {
+        if !self.leaf_hashes {
+            return None;
+        }
+        Some(Hash32(cv_to_bytes(self.leaf(leaf_index)?.input_chaining_value)))
+    }
+
+    /// The chaining value of the parent node at `heap_index`, or `None` if there is
+    /// no such node or it is a leaf, including a lone leaf promoted to a higher level.
+    pub fn parent_cv(&self, heap_index: HeapIndex) -> Option<ParentCv> {
+        if heap_index.0 >= self.leaf_start_index {
+            return None;
+        }
+        self.node_at(heap_index).and_then(|node| ParentCv::from_output(&node))
+    }
+
+    /// Check that `chunk_bytes` is exactly the data of chunk `chunk_index`, by hashing
+    /// it with that chunk's counter and comparing against the stored leaf CV. The
+    /// CV commits to the chunk length, so a truncated or extended final chunk fails.
+    pub fn verify_chunk(&self, chunk_index: impl Into<LeafIndex>, chunk_bytes: &[u8]) -> bool {
+        let LeafIndex(chunk_index) = chunk_index.into();
+        let Some(leaf) = self.leaf(chunk_index) else {
+            return false;
+        };
+        let verified = chunk_bytes.len() <= CHUNK_LEN && {
+            let mut chunk_state = ChunkState::new(self.key_words, chunk_index as u64, self.flags);
+            chunk_state.update(chunk_bytes);
+            chunk_state.output().chaining_value() == leaf.chaining_value()
+        };
+        #[cfg(feature = "tracing")]
+        if !verified {
+            tracing::debug!(chunk_index, len = chunk_bytes.len(), "chunk failed verification");
+        }
+        verified
+    }
+
     fn get_sibling_index(index: usize) -> usize {
         // Bit-wise XOR to get the sibling index
         // Example: Sibling of index 4(0b100) is 5(0b101) and sibling of index 5(0b101) is 4(0b100)
@@ -404,7 +1960,7 @@ impl BinaryMerkleTree {
 
     fn is_left(index: usize) -> bool {
         // All left-children have an even node index
-        index % 2 == 0
+        index.is_multiple_of(2)
     }
 
     // The parent of a node is always at node_index / 2
@@ -414,32 +1970,50 @@ impl BinaryMerkleTree {
 
     /// Given an index of the current node, identify its direct sibling,
     /// identify which node is left, which is right, and return them.
+    ///
+    /// Parent hashing is NOT commutative: `parent_output` puts the left CV in
+    /// `block_words[..8]` and the right CV in `block_words[8..]`, so
+    /// `parent_cv(a, b) != parent_cv(b, a)`. A left/right swap here would silently
+    /// produce a root that no longer matches BLAKE3, so the result is checked to be an
+    /// even (left) index followed by its odd (right) sibling in debug builds.
     fn get_left_and_right_node_indices_from_index(&self, current_index: usize) -> (usize, usize) {
-        let sibling_index = BinaryMerkleTree::get_sibling_index(current_index);
+        let sibling_index = Self::get_sibling_index(current_index);
 
         // Use boolean indexing to avoid if statement branching
         let node_pair = [current_index, sibling_index]; // Stack allocation
 
         // If the sibling is the left child, is_left returns 1 and gets the sibling
         // If the sibling is the right child, is_left returns 0 and gets the node to update (the left child)
-        let left_node_index = node_pair[BinaryMerkleTree::is_left(sibling_index) as usize];
+        let left_node_index = node_pair[Self::is_left(sibling_index) as usize];
 
         // If the node to update is the left child, is_left returns 1 and gets the sibling (the right child)
         // If the node to update is the right child, is_left returns 0 and gets the node to update
-        let right_node_index = node_pair[BinaryMerkleTree::is_left(current_index) as usize];
+        let right_node_index = node_pair[Self::is_left(current_index) as usize];
 
+        debug_assert!(
+            Self::is_left(left_node_index) && right_node_index == left_node_index + 1,
+            "Left/right order inverted for node {}: got ({}, {})",
+            current_index, left_node_index, right_node_index
+        );
         (left_node_index, right_node_index)
     }
 
     fn create_tree_from_leaves(&mut self, leaves: Vec<Output>) {
         // Copy the actual leaves into the end of the tree
-        for (i, leaf) in leaves.into_iter().enumerate() {
-            self.tree[self.leaf_start_index + i] = leaf;
-        }
+        let leaf_nodes = leaves
+            .into_iter()
+            .enumerate()
+            .map(|(i, leaf)| (HeapIndex(self.leaf_start_index + i), leaf))
+            .collect::<Vec<_>>();
+        self.nodes.put_batch(&leaf_nodes);
+        self.build_parents();
+    }
 
+    /// Compute every internal node from the leaves already in the store.
+    fn build_parents(&mut self) {
         // If there is only one leaf, the tree is simply that leaf
         if self.actual_leaves == 1 {
-            self.tree[1] = self.tree[self.leaf_start_index];
+            self.set_node(1, self.node(self.leaf_start_index));
             return;
         }
 
@@ -449,25 +2023,20 @@ impl BinaryMerkleTree {
         
         while current_level_start > 1 {
             let parent_level_start = current_level_start / 2;
-            let nodes_in_parent_level = (nodes_at_current_level + 1) / 2;
+            let nodes_in_parent_level = nodes_at_current_level.div_ceil(2);
 
             for i in 0..nodes_in_parent_level {
                 let left_index = current_level_start + 2 * i;
-                let right_index = left_index + 1;
                 let parent_index = parent_level_start + i;
 
                 // For the last node in a level, if it doesn't have a right sibling,
                 // promote the left node directly to be the parent
-                if 2 * i + 1 >= nodes_at_current_level {
-                    self.tree[parent_index] = self.tree[left_index];
+                if 2 * i + 1 < nodes_at_current_level {
+                    self.save_node(parent_index);
+                    let (left_cv, right_cv) = (self.node_cv(left_index), self.node_cv(left_index + 1));
+                    self.nodes.put_parent(HeapIndex(parent_index), left_cv, right_cv, self.key_words, self.flags);
                 } else {
-                    // If we have both left and right children, create a parent node
-                    self.tree[parent_index] = parent_output(
-                        self.tree[left_index].chaining_value(),
-                        self.tree[right_index].chaining_value(),
-                        self.key_words,
-                        self.flags,
-                    );
+                    self.set_node(parent_index, self.node(left_index));
                 }
             }
             current_level_start = parent_level_start;
@@ -475,68 +2044,364 @@ impl BinaryMerkleTree {
         }
     }
 
-    pub fn insert_leaf(&mut self, leaf_index: usize, leaf_output: Output) {
+    /// Compute the node that belongs above `left_index`: the parent of it and its
+    /// right sibling, or the left node itself if it has no right sibling (promotion).
+    fn recompute_parent(&self, left_index: usize, has_right_sibling: bool) -> Output {
+        if has_right_sibling {
+            parent_output(
+                self.node_cv(left_index),
+                self.node_cv(left_index + 1),
+                self.key_words,
+                self.flags,
+            )
+        } else {
+            self.node(left_index)
+        }
+    }
+
+    /// The non-root chaining value of the subtree over `chunk_count` chunks starting at
+    /// `first_chunk`, where the range is a subtree of BLAKE3's tree shape (a left
+    /// subtree always holds a power of two chunks). Such a subtree is a single node
+    /// at level ceil(log2(chunk_count)), possibly promoted from further down.
+    #[cfg(feature = "std")]
+    pub(crate) fn subtree_chaining_value(&self, first_chunk: usize, chunk_count: usize) -> [u32; 8] {
+        let level = chunk_count.next_power_of_two().trailing_zeros();
+        self.node_cv((self.leaf_start_index + first_chunk) >> level)
+    }
+
+    /// The node at heap index `index`: 1 is the root and the leaves start at `num_leaves`.
+    pub(crate) fn node(&self, index: usize) -> Output {
+        let node = self.nodes.get(HeapIndex(index));
+        debug_assert!(!node.is_padding(), "read padding at heap index {}", index);
+        node
+    }
+
+    /// The chaining value of the node at heap index `index`.
+    pub(crate) fn node_cv(&self, index: usize) -> [u32; 8] {
+        self.stored_cv(&self.node(index))
+    }
+
+    /// The chaining value of a stored node: its compression, except for the leaves
+    /// of a tree of leaf hashes and their promoted copies, which hold theirs as is.
+    pub(crate) fn stored_cv(&self, node: &Output) -> [u32; 8] {
+        if self.leaf_hashes && node.is_leaf_hash() {
+            node.input_chaining_value
+        } else {
+            node.chaining_value()
+        }
+    }
+
+    fn set_node(&mut self, index: usize, output: Output) {
+        self.save_node(index);
+        self.nodes.put(HeapIndex(index), output);
+    }
+
+    /// Journal the node at `index` in the innermost checkpoint, if it has not been
+    /// since that checkpoint was taken.
+    fn save_node(&mut self, index: usize) {
+        if let Some(journal) = self.checkpoints.last_mut() {
+            if let Entry::Vacant(entry) = journal.nodes.entry(index) {
+                entry.insert(self.nodes.get(HeapIndex(index)));
+            }
+        }
+    }
+
+    fn forget_content_hashes(&mut self) {
+        match self.checkpoints.last_mut() {
+            Some(journal) if journal.content_hashes.is_none() => journal.content_hashes = Some(self.content_hashes.take()),
+            _ => self.content_hashes = None,
+        }
+    }
+
+    /// Whether `other` describes the same data: the same number of leaves, key,
+    /// flags and root chaining value, which takes O(1) hashing. With `full`, trees
+    /// that pass are then compared node by node, every real node at every level,
+    /// which also catches stored nodes that no longer hash into the root, such as
+    /// after a corrupted store; that takes O(n) reads and no hashing.
+    pub fn deep_equal<T: NodeStore>(&self, other: &GenericMerkleTree<T>, full: bool) -> bool {
+        if self.actual_leaves != other.actual_leaves
+            || self.leaf_hashes != other.leaf_hashes
+            || self.key_words != other.key_words
+            || self.flags != other.flags
+            || self.root().chaining_value() != other.root().chaining_value()
+        {
+            return false;
+        }
+        if !full {
+            return true;
+        }
+        let mut level_start = self.leaf_start_index;
+        for nodes_in_level in Self::level_sizes(self.actual_leaves) {
+            for index in level_start..level_start + nodes_in_level {
+                if self.node(index) != other.node(index) {
+                    return false;
+                }
+            }
+            level_start /= 2;
+        }
+        true
+    }
+
+    /// Recompute every internal node from its children, respecting promotion of
+    /// lone left children, and check it matches the stored node all the way up to
+    /// the root. Runs in O(n); useful after deserialization to detect tampering.
+    pub fn verify_integrity(&self) -> bool {
+        let mut current_level_start = self.leaf_start_index;
+        let mut nodes_at_current_level = self.actual_leaves;
+
+        while current_level_start > 1 {
+            let parent_level_start = current_level_start / 2;
+            let nodes_in_parent_level = nodes_at_current_level.div_ceil(2);
+
+            for i in 0..nodes_in_parent_level {
+                let left_index = current_level_start + 2 * i;
+                let has_right_sibling = 2 * i + 1 < nodes_at_current_level;
+                if self.node(parent_level_start + i) != self.recompute_parent(left_index, has_right_sibling) {
+                    return false;
+                }
+            }
+            current_level_start = parent_level_start;
+            nodes_at_current_level = nodes_in_parent_level;
+        }
+        true
+    }
+
+    /// Like `verify_integrity`, but the parents of each level are recomputed in
+    /// parallel on the rayon thread pool. Levels are still checked bottom-up, one at
+    /// a time, and the walk stops at the first level containing a mismatch.
+    #[cfg(feature = "rayon")]
+    pub fn verify_integrity_parallel(&self) -> bool
+    where
+        S: Sync,
+    {
+        use rayon::prelude::*;
+
+        let mut current_level_start = self.leaf_start_index;
+        let mut nodes_at_current_level = self.actual_leaves;
+
+        while current_level_start > 1 {
+            let parent_level_start = current_level_start / 2;
+            let nodes_in_parent_level = nodes_at_current_level.div_ceil(2);
+
+            let level_matches = (0..nodes_in_parent_level).into_par_iter().all(|i| {
+                let left_index = current_level_start + 2 * i;
+                let has_right_sibling = 2 * i + 1 < nodes_at_current_level;
+                self.node(parent_level_start + i) == self.recompute_parent(left_index, has_right_sibling)
+            });
+            if !level_matches {
+                return false;
+            }
+            current_level_start = parent_level_start;
+            nodes_at_current_level = nodes_in_parent_level;
+        }
+        true
+    }
+
+    /// The chaining values of the siblings `insert_leaf` reads when updating
+    /// `leaf_index`, ordered from the leaf level up to just below the root. Returns
+    /// `None` if the leaf index is out of bounds.
+    ///
+    /// Levels where the path node is a lone left child have no sibling (the node is
+    /// promoted unchanged) and contribute nothing. At every other level the sibling
+    /// is on the left exactly when the path node's position in that level is odd.
+    /// Both facts follow from `leaf_index` and `actual_leaves`, so a client holding
+    /// only this path can fold a new leaf up to the new root.
+    pub fn update_path_siblings(&self, leaf_index: impl Into<LeafIndex>) -> Option<Vec<[u32; 8]>> {
+        let LeafIndex(leaf_index) = leaf_index.into();
         if leaf_index >= self.actual_leaves {
-            panic!("Leaf index {} is out of bounds for tree with {} leaves", leaf_index, self.actual_leaves);
+            return None;
+        }
+
+        // Collect the whole path first, so the store is asked for it in one batch
+        let mut sibling_ids = Vec::new();
+        let mut current_index = self.leaf_start_index + leaf_index;
+        let mut position = leaf_index;
+        let mut nodes_in_this_level = self.actual_leaves;
+        while nodes_in_this_level > 1 {
+            if Self::get_sibling_index(position) < nodes_in_this_level {
+                sibling_ids.push(HeapIndex(Self::get_sibling_index(current_index)));
+            }
+            current_index = Self::get_parent_index(current_index);
+            position = Self::get_parent_index(position);
+            nodes_in_this_level = nodes_in_this_level.div_ceil(2);
         }
+        let siblings = self.nodes.get_batch(&sibling_ids);
+        Some(siblings.iter().map(|sibling| self.stored_cv(sibling)).collect())
+    }
+
+    /// The number of internal nodes the last successful `insert_leaf` or
+    /// `bulk_insert_leaves` rewrote: one per level for a single leaf, and each shared
+    /// ancestor once for a batch. Promoted lone children count, as they are copied up.
+    pub fn nodes_recomputed(&self) -> usize {
+        self.nodes_recomputed
+    }
 
+    /// Replace the leaf at `leaf_index` and recompute its ancestors. Returns
+    /// `MerkleError::LeafIndexOutOfBounds` if there is no such leaf and
+    /// `MerkleError::WrongLeafKind` on a tree of leaf hashes (see
+    /// `insert_leaf_hash`), leaving the tree unchanged.
+    pub fn insert_leaf(&mut self, leaf_index: impl Into<LeafIndex>, leaf_output: Output) -> Result<(), MerkleError> {
+        self.check_leaf_kind(false)?;
+        self.replace_leaf(leaf_index.into().0, leaf_output)
+    }
+
+    /// `insert_leaf` without the check that the tree's leaves are chunks.
+    fn replace_leaf(&mut self, leaf_index: usize, leaf_output: Output) -> Result<(), MerkleError> {
+        self.check_leaf_index(leaf_index)?;
+
+        if leaf_index == self.actual_leaves - 1 {
+            self.input_len = None;
+        }
+        self.forget_content_hashes();
         let real_leaf_index = leaf_index + self.leaf_start_index;
         // First, update the leaf node
-        self.tree[real_leaf_index] = leaf_output;
+        self.set_node(real_leaf_index, leaf_output);
         
         // Then propagate changes up the tree
         let mut nodes_in_this_level = self.actual_leaves;
         let mut current_index = real_leaf_index;
+        self.nodes_recomputed = 0;
         
         while nodes_in_this_level > 1 {
-            let nodes_parent_level = (nodes_in_this_level + 1) / 2;
+            let nodes_parent_level = nodes_in_this_level.div_ceil(2);
 
             let (left_node_index, right_node_index, parent_index, has_right_sibling) = self.get_parent_and_validate_right(current_index);  
             if has_right_sibling {
                 let parent_output = parent_output(
-                    self.tree[left_node_index].chaining_value(),
-                    self.tree[right_node_index].chaining_value(),
+                    self.node_cv(left_node_index),
+                    self.node_cv(right_node_index),
                     self.key_words,
                     self.flags,
                 );
                 
-                self.tree[parent_index] = parent_output;
+                self.set_node(parent_index, parent_output);
             } else {
-                self.tree[parent_index] = self.tree[left_node_index];
+                self.set_node(parent_index, self.node(left_node_index));
             }
             
+            self.nodes_recomputed += 1;
             current_index = parent_index;
             nodes_in_this_level = nodes_parent_level;
         }
+        Ok(())
     }
 
+    /// The number of real (non-padding) nodes at each level, from the leaves up.
+    pub(crate) fn level_sizes(actual_leaves: usize) -> Vec<usize> {
+        let mut sizes = vec![actual_leaves];
+        while sizes[sizes.len() - 1] > 1 {
+            sizes.push(sizes[sizes.len() - 1].div_ceil(2));
+        }
+        sizes
+    }
+
+    /// Fail with `MerkleError::WrongLeafKind` unless the tree's leaves are leaf
+    /// hashes exactly when `leaf_hashes` is set.
+    fn check_leaf_kind(&self, leaf_hashes: bool) -> Result<(), MerkleError> {
+        if self.leaf_hashes != leaf_hashes {
+            return Err(MerkleError::WrongLeafKind { leaf_hashes: self.leaf_hashes });
+        }
+        Ok(())
+    }
+
+    fn check_leaf_index(&self, leaf_index: usize) -> Result<(), MerkleError> {
+        if leaf_index >= self.actual_leaves {
+            return Err(MerkleError::LeafIndexOutOfBounds {
+                leaf_index,
+                actual_leaves: self.actual_leaves,
+            });
+        }
+        Ok(())
+    }
+
+    /// Mark the leaf at `leaf_index` as deleted. This is a logical delete: the leaf
+    /// keeps its place and its hash, so the root is unchanged until `compact`.
+    /// Returns false if the leaf was already marked. Marks are not serialized.
+    pub fn remove_leaf(&mut self, leaf_index: impl Into<LeafIndex>) -> Result<bool, MerkleError> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        self.check_leaf_index(leaf_index)?;
+        Ok(self.removed.insert(leaf_index))
+    }
+
+    /// Whether `remove_leaf` has marked the leaf at `leaf_index`.
+    pub fn is_removed(&self, leaf_index: impl Into<LeafIndex>) -> bool {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        self.removed.contains(&leaf_index)
+    }
+
+    /// Replace several leaves at once and recompute each affected ancestor once.
+    /// `leaf_indices_iter` must be strictly increasing, in bounds, and yield exactly
+    /// as many items as `leaf_hashes_iter`; otherwise the tree is left unchanged.
+    /// Returns `MerkleError::WrongLeafKind` on a tree of leaf hashes (see
+    /// `bulk_insert_leaf_hashes`).
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub fn bulk_insert_leaves<I, J>(
         &mut self,
         leaf_indices_iter: I,
         leaf_hashes_iter: J,
-    ) -> Option<()>
+    ) -> Result<(), MerkleError>
+    where
+        I: Iterator<Item = usize>,
+        J: Iterator<Item = Output>,
+    {
+        self.check_leaf_kind(false)?;
+        self.replace_leaves(leaf_indices_iter, leaf_hashes_iter)
+    }
+
+    /// `bulk_insert_leaves` without the check that the tree's leaves are chunks.
+    fn replace_leaves<I, J>(&mut self, leaf_indices_iter: I, leaf_hashes_iter: J) -> Result<(), MerkleError>
     where
         I: Iterator<Item = usize>,
         J: Iterator<Item = Output>,
     {
+        let mut leaf_indices = leaf_indices_iter.collect::<Vec<_>>();
+        // Zipping would silently ignore extra indices or extra hashes
+        let leaf_hashes = leaf_hashes_iter.collect::<Vec<_>>();
+        if leaf_indices.len() != leaf_hashes.len() {
+            return Err(MerkleError::LengthMismatch {
+                indices: leaf_indices.len(),
+                hashes: leaf_hashes.len(),
+            });
+        }
+        if leaf_indices.is_empty() {
+            self.nodes_recomputed = 0;
+            return Ok(());
+        }
+
         // Check if sorted
-        let leaf_offset = self.num_leaves();
-        let leaf_indices = leaf_indices_iter
-            .map(|input_index| input_index + leaf_offset)
-            .collect::<Vec<_>>();
 
         // In-line our own sort checker because Rust's is_sorted is not yet stable.
         fn is_sorted(leaf_indices: &[usize]) -> bool {
             (0..leaf_indices.len() - 1).all(|i| leaf_indices[i] < leaf_indices[i + 1])
         }
         if !is_sorted(&leaf_indices) {
-            return None;
+            return Err(MerkleError::UnsortedIndices);
+        }
+        // Sorted, so only the last index can be out of bounds
+        self.check_leaf_index(leaf_indices[leaf_indices.len() - 1])?;
+        for leaf_index in &mut leaf_indices {
+            *leaf_index += self.leaf_start_index;
+        }
+
+        if leaf_indices.last() == Some(&(self.leaf_start_index + self.actual_leaves - 1)) {
+            self.input_len = None;
         }
+        self.forget_content_hashes();
 
         // Insert all leaf nodes
-        for (leaf_index, updated_leaf_hash) in leaf_indices.iter().zip(leaf_hashes_iter) {
-            self.tree[*leaf_index] = updated_leaf_hash;
+        let leaf_nodes = leaf_indices
+            .iter()
+            .zip(leaf_hashes)
+            .map(|(&leaf_index, updated_leaf_hash)| (HeapIndex(leaf_index), updated_leaf_hash))
+            .collect::<Vec<_>>();
+        for &(HeapIndex(index), _) in &leaf_nodes {
+            self.save_node(index);
         }
+        self.nodes.put_batch(&leaf_nodes);
+
+        #[cfg(feature = "tracing")]
+        let leaves = leaf_indices.len();
+        self.nodes_recomputed = 0;
 
         // Update ancestors based on sorted leaf indices
         let mut update_queue = VecDeque::from(leaf_indices);
@@ -548,7 +2413,7 @@ impl BinaryMerkleTree {
 
             // If the next ancestor to update is the sibling's, pop it from the queue
             // since it will have the same parent as the current node
-            let sibling_index = BinaryMerkleTree::get_sibling_index(current_index);
+            let sibling_index = Self::get_sibling_index(current_index);
             if let Some(&next_index) = update_queue.front() {
                 if next_index == sibling_index {
                     update_queue.pop_front();
@@ -558,19 +2423,138 @@ impl BinaryMerkleTree {
             let (left_node_index, right_node_index, parent_index, has_right_sibling) = self.get_parent_and_validate_right(current_index); 
             if has_right_sibling {
                 let parent_output = parent_output(
-                    self.tree[left_node_index].chaining_value(),
-                    self.tree[right_node_index].chaining_value(),
+                    self.node_cv(left_node_index),
+                    self.node_cv(right_node_index),
                     self.key_words,
                     self.flags,
                 );
-                self.tree[parent_index] = parent_output;
+                self.set_node(parent_index, parent_output);
             } else {
-                self.tree[parent_index] = self.tree[left_node_index];
+                self.set_node(parent_index, self.node(left_node_index));
             }
             update_queue.push_back(parent_index);
+            self.nodes_recomputed += 1;
         }
 
-        Some(())
+        #[cfg(feature = "tracing")]
+        tracing::debug!(leaves, nodes_recomputed = self.nodes_recomputed, "bulk insert done");
+        Ok(())
+    }
+
+    /// `insert_leaf` for a tree of leaf hashes: replace the hash at `leaf_index` and
+    /// recompute its ancestors. Returns `MerkleError::LeafIndexOutOfBounds` if there
+    /// is no such leaf and `MerkleError::WrongLeafKind` on a tree of chunks, leaving
+    /// the tree unchanged.
+    pub fn insert_leaf_hash(&mut self, leaf_index: impl Into<LeafIndex>, hash: [u8; OUT_LEN]) -> Result<(), MerkleError> {
+        self.check_leaf_kind(true)?;
+        self.replace_leaf(leaf_index.into().0, leaf_hash_output(&hash, self.flags))
+    }
+
+    /// `bulk_insert_leaves` for a tree of leaf hashes, with the same requirements on
+    /// the indices. Returns `MerkleError::WrongLeafKind` on a tree of chunks.
+    pub fn bulk_insert_leaf_hashes<I, J>(&mut self, leaf_indices_iter: I, hashes_iter: J) -> Result<(), MerkleError>
+    where
+        I: Iterator<Item = usize>,
+        J: Iterator<Item = [u8; OUT_LEN]>,
+    {
+        self.check_leaf_kind(true)?;
+        let flags = self.flags;
+        self.replace_leaves(leaf_indices_iter, hashes_iter.map(|hash| leaf_hash_output(&hash, flags)))
+    }
+
+    /// Swap the leaves at `i` and `j` and recompute the ancestors on both paths, each
+    /// shared one once, as `bulk_insert_leaves` does. Returns
+    /// `MerkleError::LeafIndexOutOfBounds`, leaving the tree unchanged, if either
+    /// index is out of bounds.
+    ///
+    /// A leaf Output keeps the chunk counter it was hashed with, so the result
+    /// equals `new_from_leaves` over the reordered Outputs, not `from_input` over
+    /// the reordered bytes, unless the two chunks are rehashed at their new positions.
+    pub fn swap_leaves(&mut self, i: usize, j: usize) -> Result<(), MerkleError> {
+        self.check_leaf_index(i)?;
+        self.check_leaf_index(j)?;
+        let (low, high) = (i.min(j), i.max(j));
+        if low == high {
+            self.nodes_recomputed = 0;
+            return Ok(());
+        }
+        let low_leaf = self.node(self.leaf_start_index + low);
+        let high_leaf = self.node(self.leaf_start_index + high);
+        self.replace_leaves([low, high].into_iter(), [high_leaf, low_leaf].into_iter())
+    }
+
+    /// Save the tree's state so `rollback` can return to it. Nothing is copied up
+    /// front: afterwards each node is saved the first time it is overwritten, so
+    /// the cost is the number of distinct nodes changed. Checkpoints nest; changes
+    /// are journaled in the innermost one only.
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        let id = self.next_checkpoint;
+        self.next_checkpoint += 1;
+        self.checkpoints.push(Journal {
+            id,
+            nodes: BTreeMap::new(),
+            input_len: self.input_len,
+            removed: self.removed.clone(),
+            nodes_recomputed: self.nodes_recomputed,
+            content_hashes: None,
+        });
+        Checkpoint { id }
+    }
+
+    /// Undo every change since `checkpoint` was taken, restoring the nodes and
+    /// leaf marks exactly. Checkpoints taken after it are rolled back with it.
+    /// Returns `MerkleError::UnknownCheckpoint`, leaving the tree unchanged, if
+    /// `checkpoint` is no longer open: already rolled back or discarded, directly
+    /// or with an outer one, or dropped when `compact` rebuilt the tree.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) -> Result<(), MerkleError> {
+        let position = self.checkpoint_position(&checkpoint)?;
+        // Innermost first, so each older journal has the last word
+        while self.checkpoints.len() > position {
+            let journal = self.checkpoints.pop().unwrap();
+            for (index, output) in journal.nodes {
+                self.nodes.put(HeapIndex(index), output);
+            }
+            self.input_len = journal.input_len;
+            self.removed = journal.removed;
+            self.nodes_recomputed = journal.nodes_recomputed;
+            if let Some(content_hashes) = journal.content_hashes {
+                self.content_hashes = content_hashes;
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep every change since `checkpoint` was taken and close it, along with any
+    /// checkpoint taken after it. An enclosing checkpoint can still roll them back.
+    /// Returns `MerkleError::UnknownCheckpoint` if `checkpoint` is no longer open.
+    pub fn discard(&mut self, checkpoint: Checkpoint) -> Result<(), MerkleError> {
+        let position = self.checkpoint_position(&checkpoint)?;
+        let closed = self.checkpoints.split_off(position);
+        if let Some(outer) = self.checkpoints.last_mut() {
+            // Outermost first: the first value saved for a node is the one the
+            // enclosing checkpoint saw
+            for journal in closed {
+                for (index, output) in journal.nodes {
+                    outer.nodes.entry(index).or_insert(output);
+                }
+                if outer.content_hashes.is_none() {
+                    outer.content_hashes = journal.content_hashes;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of open checkpoints.
+    pub fn open_checkpoints(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    fn checkpoint_position(&self, checkpoint: &Checkpoint) -> Result<usize, MerkleError> {
+        self.checkpoints
+            .iter()
+            .position(|journal| journal.id == checkpoint.id)
+            .ok_or(MerkleError::UnknownCheckpoint)
     }
 
     /// Given a node index, calculates its parent node index and validates if it has a right sibling.
@@ -592,7 +2576,7 @@ impl BinaryMerkleTree {
             
             // Calculate level by counting down from root
             while nodes_in_level > 1 {
-                nodes_in_level = (nodes_in_level + 1) / 2;
+                nodes_in_level = nodes_in_level.div_ceil(2);
                 if current_index >= (self.leaf_start_index >> level) {
                     break;
                 }
@@ -608,7 +2592,7 @@ impl BinaryMerkleTree {
         } else {
             let mut nodes = self.actual_leaves;
             for _ in 0..current_level {
-                nodes = (nodes + 1) / 2;
+                nodes = nodes.div_ceil(2);
             }
             nodes
         };
@@ -617,60 +2601,440 @@ impl BinaryMerkleTree {
         let (left_index, right_index) =
                 self.get_left_and_right_node_indices_from_index(current_index);
         // Calculate parent index
-        let parent_index = BinaryMerkleTree::get_parent_index(current_index);
+        let parent_index = Self::get_parent_index(current_index);
 
         // Check if right sibling is valid
         let has_right_sibling = right_index < level_start + nodes_in_level;
 
         (left_index, right_index, parent_index, has_right_sibling)
     }
+}
 
+impl BinaryMerkleTree {
     /// Process arbitrary input bytes into a vector of Output structs.
     /// This function:
     /// 1. Splits input into chunks of 1024 bytes
     /// 2. For each chunk, splits into blocks of 64 bytes
     /// 3. Creates a ChunkState for each chunk and processes its blocks
     /// 4. Returns a vector of Output structs ready for Merkle tree construction
-    fn process_input_to_chunks(input: &[u8], key_words: [u32; 8], flags: u32) -> Vec<Output> {
-        let mut outputs = Vec::new();
-        let mut chunk_state = ChunkState::new(key_words, 0, flags);
-        let mut input = input;
+    pub(crate) fn process_input_to_chunks(input: &[u8], key_words: [u32; 8], flags: u32) -> Vec<Output> {
+        let mut builder = TreeLeafBuilder::new(key_words, flags);
+        builder.update(input);
+        builder.finalize()
+    }
 
-        while !input.is_empty() {
-            // If the current chunk is complete, finalize it and reset the
-            // chunk state. More input is coming, so this chunk is not ROOT.
-            if chunk_state.len() == CHUNK_LEN {
-                let chunk_output = chunk_state.output();
-                outputs.push(chunk_output);
-                let total_chunks = chunk_state.chunk_counter + 1;
-                chunk_state = ChunkState::new(key_words, total_chunks, flags);
+    /// `from_input`, checking first that the input's chunk count is within
+    /// `check_leaf_count`. An in-memory slice always is on 64-bit targets; the check
+    /// matters where lengths come from elsewhere and on smaller targets.
+    pub fn try_from_input(input: &[u8], key_words: [u32; 8], flags: u32) -> Result<Self, MerkleError> {
+        check_leaf_count(chunk_count(input.len()) as u64)?;
+        Ok(Self::from_input(input, key_words, flags))
+    }
+
+    /// Construct a new BinaryMerkleTree directly from arbitrary raw bytes input.
+    /// This method is equivalent to collecting the leaves with `TreeLeafBuilder` and then
+    /// calling new_from_leaves.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(bytes = input.len())))]
+    pub fn from_input(input: &[u8], key_words: [u32; 8], flags: u32) -> Self {
+        let chunk_outputs = Self::process_input_to_chunks(input, key_words, flags);
+        Self::new_from_leaves(chunk_outputs, key_words, flags).with_input_len(input.len() as u64)
+    }
+
+    /// Build a tree with one leaf per item, for leaves that are not byte chunks,
+    /// such as records whose digests come from another system. `leaf_fn` maps each
+    /// item to a 32-byte digest as 8 words. An Output cannot be made to have a given
+    /// chaining value, so each digest is hashed as the content of chunk `i` for item
+    /// `i` (its 32 little-endian bytes); this also binds every digest to its
+    /// position. Returns `MerkleError::NoLeaves` if `items` is empty.
+    pub fn from_leaf_data<T>(
+        items: &[T],
+        leaf_fn: impl Fn(&T) -> [u32; 8],
+        key_words: [u32; 8],
+        flags: u32,
+    ) -> Result<Self, MerkleError> {
+        if items.is_empty() {
+            return Err(MerkleError::NoLeaves);
+        }
+        let leaves = items
+            .iter()
+            .enumerate()
+            .map(|(leaf_index, item)| {
+                let mut chunk_state = ChunkState::new(key_words, leaf_index as u64, flags);
+                chunk_state.update(&cv_to_bytes(leaf_fn(item)));
+                chunk_state.output()
+            })
+            .collect();
+        Ok(Self::new_from_leaves(leaves, key_words, flags))
+    }
+
+    /// Build a tree whose leaves are application-defined 32-byte hashes, such as
+    /// digests of records, used as leaf chaining values directly. Parents are
+    /// `parent_cv` of their children as usual and the root carries the ROOT flag,
+    /// but this is a different domain from hashing bytes: the root is not the
+    /// BLAKE3 hash of any byte string, the concatenated hashes included, and must
+    /// not be compared with one. Unlike `from_leaf_data`, a hash is not bound to
+    /// its position unless the application puts the position into it.
+    ///
+    /// Replace leaves with `insert_leaf_hash` or `bulk_insert_leaf_hashes`, and
+    /// prove them with `generate_leaf_hash_proof`. Methods that take or return
+    /// chunk Outputs as leaves do not apply: `insert_leaf` and `bulk_insert_leaves`
+    /// return `MerkleError::WrongLeafKind`, `generate_proof` returns `None` and
+    /// `verify_chunk` returns false. Returns `MerkleError::TooFewLeafHashes` for
+    /// fewer than two hashes, since a lone leaf would have no node to finalize.
+    ///
+    /// `key_words` and `flags` select the mode the parents are hashed in, as for
+    /// `from_input`: `IV` and `FLAGS` for the regular hash, or a key with
+    /// `KEYED_HASH`, or a derived key with `DERIVE_KEY_MATERIAL`.
+    pub fn from_leaf_hashes(hashes: &[[u8; OUT_LEN]], key_words: [u32; 8], flags: u32) -> Result<Self, MerkleError> {
+        if hashes.len() < 2 {
+            return Err(MerkleError::TooFewLeafHashes { count: hashes.len() });
+        }
+        let mut tree = Self::with_shape(
+            VecStore::new(2 * hashes.len().next_power_of_two(), padding_output(key_words, flags)),
+            hashes.len(),
+            key_words,
+            flags,
+        );
+        tree.leaf_hashes = true;
+        tree.create_tree_from_leaves(hashes.iter().map(|hash| leaf_hash_output(hash, flags)).collect());
+        Ok(tree)
+    }
+}
+
+#[cfg(feature = "std")]
+impl BinaryMerkleTree {
+    /// Construct a new BinaryMerkleTree from everything `reader` yields until EOF.
+    /// Input is consumed in bounded reads, so only the leaf Outputs are held in memory.
+    pub fn from_reader<R: Read>(reader: R, key_words: [u32; 8], flags: u32) -> io::Result<Self> {
+        Self::from_reader_buffered(reader, READ_BUFFER_LEN, key_words, flags)
+    }
+
+    /// Like `from_reader`, with a read buffer of `buf_size` bytes rounded down to a
+    /// multiple of `CHUNK_LEN`. The buffer is filled completely before it is hashed,
+    /// so every increment but the last covers whole chunks; larger buffers mean
+    /// fewer reads at the cost of memory. A `buf_size` below `CHUNK_LEN` is
+    /// rejected with `io::ErrorKind::InvalidInput`.
+    pub fn from_reader_buffered<R: Read>(mut reader: R, buf_size: usize, key_words: [u32; 8], flags: u32) -> io::Result<Self> {
+        if buf_size < CHUNK_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("buffer size {} is smaller than a chunk ({} bytes)", buf_size, CHUNK_LEN),
+            ));
+        }
+        let mut builder = TreeLeafBuilder::new(key_words, flags);
+        let mut buffer = vec![0u8; buf_size - buf_size % CHUNK_LEN];
+        loop {
+            let mut filled = 0;
+            while filled < buffer.len() {
+                match reader.read(&mut buffer[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            builder.update(&buffer[..filled]);
+            if filled < buffer.len() {
+                break;
             }
+        }
+        Ok(builder.finalize_tree())
+    }
 
-            // Compress input bytes into the current chunk state.
-            let want = CHUNK_LEN - chunk_state.len();
-            let take = min(want, input.len());
-            chunk_state.update(&input[..take]);
-            input = &input[take..];
+    /// Construct a new BinaryMerkleTree from the contents of the file at `path`.
+    pub fn from_file<P: AsRef<Path>>(path: P, key_words: [u32; 8], flags: u32) -> io::Result<Self> {
+        Self::from_reader(File::open(path)?, key_words, flags)
+    }
+
+    /// Construct a new BinaryMerkleTree by memory-mapping the file at `path` and
+    /// hashing the mapping as one contiguous slice.
+    ///
+    /// The file must not be modified (by this or any other process) while the tree
+    /// is being built: the mapping would observe the change mid-hash and the result
+    /// would describe neither the old nor the new contents. Zero-length files cannot
+    /// be mapped portably, so they fall back to hashing the empty input.
+    #[cfg(feature = "mmap")]
+    #[allow(unsafe_code)]
+    pub fn from_file_mmap<P: AsRef<Path>>(path: P, key_words: [u32; 8], flags: u32) -> io::Result<Self> {
+        let file = File::open(path)?;
+        if file.metadata()?.len() == 0 {
+            return Ok(Self::from_input(&[], key_words, flags));
         }
+        // SAFETY: the mapping is only read for the duration of this call; the
+        // caller is responsible for not modifying the file concurrently (see above).
+        let mapping = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(Self::from_input(&mapping, key_words, flags))
+    }
 
-        // Add the final chunk if it's not empty
-        if chunk_state.len() > 0 {
-            let chunk_output = chunk_state.output();
-            outputs.push(chunk_output);
+    /// Serialize the tree so it can be reloaded with `from_bytes` or `read_from`
+    /// without rehashing the input. See `write_to` for the layout.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes).expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    /// Write the serialized tree to `writer`. All integers are little-endian:
+    ///
+    /// - magic `b"B3MT"`, then the format version (u32, currently 1)
+    /// - presence bits (u32): bit 0 if a key follows, bit 1 if the input length is
+    ///   known, bit 2 if the leaves are leaf hashes (see `from_leaf_hashes`)
+    /// - flags (u32), leaf count (u64), input length (u64, 0 if unknown)
+    /// - the key as 8 u32 words, only if it differs from `IV`
+    /// - node count (u64), then every real node level by level from the root down,
+    ///   left to right, each as a 112-byte Output (see `Output::to_bytes`)
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let has_key = self.key_words != IV;
+        let mut presence = 0;
+        if has_key {
+            presence |= SERIALIZED_HAS_KEY;
+        }
+        if self.input_len.is_some() {
+            presence |= SERIALIZED_HAS_INPUT_LEN;
+        }
+        if self.leaf_hashes {
+            presence |= SERIALIZED_LEAF_HASHES;
         }
 
-        // If no chunks were produced, add a dummy chunk with the initial chaining value
-        if outputs.is_empty() {
-            outputs.push(ChunkState::new(key_words, 0, flags).output());
+        writer.write_all(&SERIALIZED_MAGIC)?;
+        writer.write_all(&SERIALIZED_VERSION.to_le_bytes())?;
+        writer.write_all(&presence.to_le_bytes())?;
+        writer.write_all(&self.flags.to_le_bytes())?;
+        writer.write_all(&(self.actual_leaves as u64).to_le_bytes())?;
+        writer.write_all(&self.input_len.unwrap_or(0).to_le_bytes())?;
+        if has_key {
+            write_words(&mut writer, &self.key_words)?;
         }
-        
-        outputs
+
+        let level_sizes = Self::level_sizes(self.actual_leaves);
+        writer.write_all(&(level_sizes.iter().sum::<usize>() as u64).to_le_bytes())?;
+        for (level, &size) in level_sizes.iter().enumerate().rev() {
+            let level_start = self.leaf_start_index >> level;
+            for index in level_start..level_start + size {
+                writer.write_all(&self.node(index).to_bytes())?;
+            }
+        }
+        writer.flush()
     }
 
-    /// Construct a new BinaryMerkleTree directly from arbitrary raw bytes input.
-    /// This method is equivalent to calling process_input_to_chunks and then new_from_leaves.
-    pub fn from_input(input: &[u8], key_words: [u32; 8], flags: u32) -> Self {
-        let chunk_outputs = Self::process_input_to_chunks(input, key_words, flags);
-        Self::new_from_leaves(chunk_outputs, key_words, flags)
+    /// Load a tree serialized by `to_bytes`, rejecting trailing data.
+    /// See `read_from` for what is validated.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = bytes;
+        let tree = Self::read_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(invalid_data(format!("{} trailing bytes after the serialized tree", reader.len())));
+        }
+        Ok(tree)
+    }
+
+    /// Load a tree written by `write_to`. The structure is validated (magic,
+    /// version, node count against leaf count, leaf counters, input length against
+    /// leaf count) and malformed data is reported as `io::ErrorKind::InvalidData`.
+    /// The header's counts are not used to size any allocation until that many
+    /// nodes have been read, so a header claiming more than the reader holds fails
+    /// with `io::ErrorKind::UnexpectedEof`. Node hashes are not recomputed; call
+    /// `verify_integrity` on the result when the source is not trusted.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        fn read_array<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+            let mut bytes = [0u8; N];
+            reader.read_exact(&mut bytes)?;
+            Ok(bytes)
+        }
+
+        if read_array::<_, 4>(&mut reader)? != SERIALIZED_MAGIC {
+            return Err(invalid_data("not a serialized BinaryMerkleTree".to_string()));
+        }
+        let version = u32::from_le_bytes(read_array(&mut reader)?);
+        if version != SERIALIZED_VERSION {
+            return Err(invalid_data(format!("unsupported format version {}", version)));
+        }
+        let presence = u32::from_le_bytes(read_array(&mut reader)?);
+        if presence & !(SERIALIZED_HAS_KEY | SERIALIZED_HAS_INPUT_LEN | SERIALIZED_LEAF_HASHES) != 0 {
+            return Err(invalid_data(format!("unknown presence bits {:#x}", presence)));
+        }
+        let flags = u32::from_le_bytes(read_array(&mut reader)?);
+        let actual_leaves = u64::from_le_bytes(read_array(&mut reader)?);
+        let input_len = u64::from_le_bytes(read_array(&mut reader)?);
+        let mut key_words = IV;
+        if presence & SERIALIZED_HAS_KEY != 0 {
+            words_from(&read_array::<_, 32>(&mut reader)?, &mut key_words);
+        }
+
+        let actual_leaves = check_leaf_count(actual_leaves)
+            .ok()
+            .filter(|&leaves| leaves > 0)
+            .ok_or_else(|| invalid_data(format!("invalid leaf count {}", actual_leaves)))?;
+        let input_len = (presence & SERIALIZED_HAS_INPUT_LEN != 0).then_some(input_len);
+        if let Some(input_len) = input_len {
+            if input_len.div_ceil(CHUNK_LEN as u64).max(1) != actual_leaves as u64 {
+                return Err(invalid_data(format!("input length {} does not fit {} leaves", input_len, actual_leaves)));
+            }
+        }
+        let level_sizes = Self::level_sizes(actual_leaves);
+        let node_count = u64::from_le_bytes(read_array(&mut reader)?);
+        if node_count != level_sizes.iter().sum::<usize>() as u64 {
+            return Err(invalid_data(format!("{} nodes do not fit {} leaves", node_count, actual_leaves)));
+        }
+
+        // The counts are untrusted, so read every node before allocating the heap:
+        // a header claiming more nodes than the input holds fails at its end instead
+        // of asking for an allocation of that size
+        let mut read_nodes = Vec::new();
+        for _ in 0..node_count {
+            let bytes: [u8; OUTPUT_BYTES_LEN] = read_array(&mut reader)?;
+            read_nodes.push(Output::try_from(&bytes[..]).map_err(|e| invalid_data(e.to_string()))?);
+        }
+
+        let number_of_leaves = actual_leaves.next_power_of_two();
+        let mut nodes = vec![padding_output(key_words, flags); 2 * number_of_leaves];
+        let mut read_nodes = read_nodes.into_iter();
+        for (level, &size) in level_sizes.iter().enumerate().rev() {
+            let level_start = number_of_leaves >> level;
+            for (node, read_node) in nodes[level_start..level_start + size].iter_mut().zip(&mut read_nodes) {
+                *node = read_node;
+            }
+        }
+        // Leaf hashes have no chunk counter
+        let leaf_hashes = presence & SERIALIZED_LEAF_HASHES != 0;
+        for (leaf_index, leaf) in nodes[number_of_leaves..number_of_leaves + actual_leaves].iter().enumerate() {
+            if !leaf_hashes && leaf.counter != leaf_index as u64 {
+                return Err(invalid_data(format!("leaf {} has counter {}", leaf_index, leaf.counter)));
+            }
+        }
+
+        Ok(BinaryMerkleTree {
+            nodes: VecStore::from_nodes(nodes),
+            actual_leaves,
+            number_of_leaves,
+            leaf_start_index: number_of_leaves,
+            key_words,
+            flags,
+            input_len,
+            leaf_hashes,
+            removed: BTreeSet::new(),
+            content_hashes: None,
+            nodes_recomputed: 0,
+            checkpoints: Vec::new(),
+            next_checkpoint: 0,
+        })
+    }
+}
+
+// =============================================
+// Wiping secret material
+// =============================================
+// Key words live on in every node's `input_chaining_value` and in the hasher,
+// and buffered input in the chunk state. These impls wipe the copies a value
+// owns. Copies the compiler made on the stack while hashing (the arguments and
+// state arrays of `compress`, returned chaining values) and values the caller
+// copied out, such as from `key_words()` or `leaf()`, are out of their reach.
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for Output {
+    fn zeroize(&mut self) {
+        self.input_chaining_value.zeroize();
+        self.block_words.zeroize();
+        self.counter.zeroize();
+        self.block_len.zeroize();
+        self.flags.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for ChunkState {
+    fn zeroize(&mut self) {
+        self.chaining_value.zeroize();
+        self.chunk_counter.zeroize();
+        self.block.zeroize();
+        self.block_len.zeroize();
+        self.blocks_compressed.zeroize();
+        self.flags.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for ArrayCvStack {
+    fn zeroize(&mut self) {
+        self.cv_stack.zeroize();
+        self.cv_stack_len.zeroize();
+    }
+}
+
+/// Wipes the key words, the buffered chunk and the CV stack. Afterwards the hasher
+/// holds no input and an all-zero key, so start over with a new one rather than
+/// reuse it. Wrap it in `zeroize::Zeroizing` to wipe it on drop.
+#[cfg(feature = "zeroize")]
+impl<S: CvStack + Zeroize> Zeroize for Blake3Hasher<S> {
+    fn zeroize(&mut self) {
+        self.chunk_state.zeroize();
+        self.key_words.zeroize();
+        self.cv_stack.zeroize();
+        self.flags.zeroize();
+    }
+}
+
+/// Wipes the key words and every node. Snapshots sharing the nodes keep their
+/// copy (this tree lets go of it instead), so zeroize those too. Afterwards the
+/// tree has its old shape but meaningless nodes and an all-zero key. Wrap it in
+/// `zeroize::Zeroizing` to wipe it on drop.
+#[cfg(feature = "zeroize")]
+impl Zeroize for BinaryMerkleTree {
+    fn zeroize(&mut self) {
+        self.nodes.zeroize();
+        self.key_words.zeroize();
+        for journal in &mut self.checkpoints {
+            journal.nodes.values_mut().for_each(Zeroize::zeroize);
+        }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "std")]
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_tree(chunks: usize) -> BinaryMerkleTree {
+        let input: Vec<u8> = (0..chunks * CHUNK_LEN).map(|i| (i % 251) as u8).collect();
+        BinaryMerkleTree::from_input(&input, IV, FLAGS)
+    }
+
+    #[test]
+    fn verify_integrity_detects_corrupted_internal_node() {
+        for chunks in [1, 2, 5, 8, 13] {
+            let tree = sample_tree(chunks);
+            assert!(tree.verify_integrity(), "untouched tree with {} chunks", chunks);
+        }
+
+        let mut tree = sample_tree(13);
+        // Node 3 is the right child of the root and always an internal node here
+        tree.nodes_mut()[3].block_words[0] ^= 1;
+        assert!(!tree.verify_integrity());
+
+        // Corrupting a promoted node breaks its equality with the promoted child
+        let mut tree = sample_tree(5);
+        let promoted_index = tree.leaf_start_index / 2 + 2;
+        tree.nodes_mut()[promoted_index].counter ^= 1;
+        assert!(!tree.verify_integrity());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn verify_integrity_parallel_matches_serial() {
+        // 4099 chunks: wide lower levels and a lone right edge promoted at several levels
+        let tree = sample_tree(4099);
+        assert!(tree.verify_integrity_parallel());
+
+        let last_leaf = tree.leaf_start_index + tree.actual_leaves - 1;
+        for corrupted_index in [1, 2, 3, tree.leaf_start_index / 2 + 7, tree.leaf_start_index, last_leaf / 2, last_leaf] {
+            let mut corrupted = tree.clone();
+            corrupted.nodes_mut()[corrupted_index].block_words[3] ^= 1 << 9;
+            assert!(!corrupted.verify_integrity(), "serial check missed node {}", corrupted_index);
+            assert!(!corrupted.verify_integrity_parallel(), "parallel check missed node {}", corrupted_index);
+        }
+    }
+}
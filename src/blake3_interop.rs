@@ -0,0 +1,88 @@
+//! Interop with the official `blake3` crate: comparing and converting root hashes
+//! with `blake3::Hash`, and hashing leaves with its optimized implementation.
+//!
+//! Roots of trees built with `IV` and `FLAGS` equal `blake3::hash`, trees keyed
+//! with `KEYED_HASH` equal `blake3::keyed_hash`, and trees over derive_key
+//! material equal `blake3::derive_key`, so values move freely in both directions.
+//!
+//! `blake3` only hands out a chunk's finished chaining value, not the state
+//! before its last block that a leaf `Output` holds, so `leaf_cvs` returns typed
+//! chaining values. Those are what a verifier compares against `leaf_cv`, flat
+//! trees, MMR proofs and `fold_leaf_path`.
+
+use alloc::vec::Vec;
+
+use blake3::hazmat::{ChainingValue, HasherExt};
+
+use crate::binary_merkle_tree::{ChunkState, Hash32, LeafCv, DERIVE_KEY_MATERIAL, FLAGS, IV, KEYED_HASH, KEY_LEN, CHUNK_LEN};
+use crate::le_bytes::{read_words, words_into};
+
+// blake3::Hash compares in constant time; these comparisons go through it
+impl PartialEq<blake3::Hash> for Hash32 {
+    fn eq(&self, other: &blake3::Hash) -> bool {
+        *other == self.0
+    }
+}
+
+impl PartialEq<Hash32> for blake3::Hash {
+    fn eq(&self, other: &Hash32) -> bool {
+        *self == other.0
+    }
+}
+
+impl From<blake3::Hash> for Hash32 {
+    fn from(hash: blake3::Hash) -> Self {
+        Hash32(*hash.as_bytes())
+    }
+}
+
+impl From<Hash32> for blake3::Hash {
+    fn from(hash: Hash32) -> Self {
+        blake3::Hash::from_bytes(hash.0)
+    }
+}
+
+/// The `blake3` hasher for the mode `key_words` and `flags` select, if it has one.
+fn blake3_hasher(key_words: [u32; 8], flags: u32) -> Option<blake3::Hasher> {
+    let mut key = [0; KEY_LEN];
+    words_into(&key_words, &mut key);
+    match flags {
+        FLAGS if key_words == IV => Some(blake3::Hasher::new()),
+        KEYED_HASH => Some(blake3::Hasher::new_keyed(&key)),
+        DERIVE_KEY_MATERIAL => Some(blake3::Hasher::new_from_context_key(&key)),
+        _ => None,
+    }
+}
+
+/// The chaining value of every chunk of `input`, as the leaves of
+/// `BinaryMerkleTree::from_input(input, key_words, flags)` have. Chunks are hashed
+/// by `blake3` in the regular, keyed and derive_key modes; other flags, and the
+/// single empty chunk of empty input, fall back to this crate's `ChunkState`.
+pub fn leaf_cvs(input: &[u8], key_words: [u32; 8], flags: u32) -> Vec<LeafCv> {
+    if input.is_empty() {
+        return Vec::from([LeafCv::assume_leaf(ChunkState::new(key_words, 0, flags).output().chaining_value())]);
+    }
+    let hasher = blake3_hasher(key_words, flags);
+    input
+        .chunks(CHUNK_LEN)
+        .enumerate()
+        .map(|(chunk_index, chunk)| {
+            let cv = match &hasher {
+                Some(hasher) => {
+                    let cv: ChainingValue = hasher
+                        .clone()
+                        .set_input_offset((chunk_index * CHUNK_LEN) as u64)
+                        .update(chunk)
+                        .finalize_non_root();
+                    read_words(&cv)
+                }
+                None => {
+                    let mut chunk_state = ChunkState::new(key_words, chunk_index as u64, flags);
+                    chunk_state.update(chunk);
+                    chunk_state.output().chaining_value()
+                }
+            };
+            LeafCv::assume_leaf(cv)
+        })
+        .collect()
+}
@@ -0,0 +1,195 @@
+//! A tree that keeps full Outputs only for its leaves, for when memory matters more
+//! than the internal nodes' Outputs.
+//!
+//! Folding a tree needs only each child's 8-word chaining value, but a
+//! `BinaryMerkleTree` stores every internal node as a whole `Output`, block words
+//! included. `CompactMerkleTree` stores the internal nodes as chaining values in
+//! the same heap layout and computes parents with `parent_cv`, so a node costs 32
+//! bytes instead of `OUTPUT_BYTES_LEN`. The root's Output is rebuilt from its two
+//! children when asked for, and a leaf's chaining value is recomputed from its
+//! Output whenever its parent is.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::binary_merkle_tree::{parent_cv, parent_output, BinaryMerkleTree, Hash32, LeafIndex, Output, OUT_LEN, ROOT};
+use crate::error::MerkleError;
+use crate::proof::MerkleProof;
+
+/// A BLAKE3 Merkle tree with the same leaves, root and proofs as a
+/// `BinaryMerkleTree`, storing chaining values rather than Outputs for its
+/// internal nodes: about 144 bytes per leaf rather than 224.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactMerkleTree {
+    leaves: Vec<Output>,
+    // Chaining values of the internal nodes by heap index: 1 is the root and the
+    // leaves would start at `num_leaves`, where this ends; index 0 is unused
+    cvs: Vec<[u32; 8]>,
+    key_words: [u32; 8],
+    flags: u32,
+}
+
+impl CompactMerkleTree {
+    /// Build a tree over the chunks of `input`, as `BinaryMerkleTree::from_input` does.
+    pub fn from_input(input: &[u8], key_words: [u32; 8], flags: u32) -> Self {
+        Self::build(BinaryMerkleTree::process_input_to_chunks(input, key_words, flags), key_words, flags)
+    }
+
+    /// Build a tree over `leaves`. Returns `MerkleError::NoLeaves` if there are none.
+    pub fn new_from_leaves(leaves: Vec<Output>, key_words: [u32; 8], flags: u32) -> Result<Self, MerkleError> {
+        if leaves.is_empty() {
+            return Err(MerkleError::NoLeaves);
+        }
+        Ok(Self::build(leaves, key_words, flags))
+    }
+
+    fn build(leaves: Vec<Output>, key_words: [u32; 8], flags: u32) -> Self {
+        let number_of_leaves = leaves.len().next_power_of_two();
+        let mut tree = CompactMerkleTree {
+            cvs: vec![[0; 8]; number_of_leaves],
+            leaves,
+            key_words,
+            flags,
+        };
+        // Each level's chaining values, folded into the next, so every node is hashed once
+        let mut level: Vec<[u32; 8]> = tree.leaves.iter().map(Output::chaining_value).collect();
+        let mut parent_level_start = number_of_leaves / 2;
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match *pair {
+                    [left, right] => parent_cv(left, right, key_words, flags),
+                    // A lone left child is promoted
+                    _ => pair[0],
+                })
+                .collect();
+            tree.cvs[parent_level_start..parent_level_start + level.len()].copy_from_slice(&level);
+            parent_level_start /= 2;
+        }
+        tree
+    }
+
+    pub fn num_leaves(&self) -> usize {
+        self.cvs.len()
+    }
+
+    pub fn actual_leaves(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn key_words(&self) -> [u32; 8] {
+        self.key_words
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// The chaining value of the node at heap index `index`.
+    fn cv(&self, index: usize) -> [u32; 8] {
+        match index.checked_sub(self.num_leaves()) {
+            Some(leaf_index) => self.leaves[leaf_index].chaining_value(),
+            None => self.cvs[index],
+        }
+    }
+
+    /// The root node with the ROOT flag applied: the only leaf of a one-leaf tree,
+    /// and otherwise the parent of the two nodes below the root, which every tree
+    /// of two or more leaves has.
+    pub fn root(&self) -> Output {
+        let mut root = match self.actual_leaves() {
+            1 => self.leaves[0],
+            _ => parent_output(self.cv(2), self.cv(3), self.key_words, self.flags),
+        };
+        root.flags |= ROOT;
+        root
+    }
+
+    /// The 32-byte root hash, equal to a `BinaryMerkleTree`'s over the same leaves.
+    pub fn root_hash(&self) -> Hash32 {
+        let mut hash = [0; OUT_LEN];
+        self.root().root_output_bytes(&mut hash);
+        Hash32(hash)
+    }
+
+    /// Returns the stored Output of the leaf at `leaf_index`, or `None` if out of bounds.
+    pub fn leaf(&self, leaf_index: impl Into<LeafIndex>) -> Option<Output> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        self.leaves.get(leaf_index).copied()
+    }
+
+    /// Replace the leaf at `leaf_index` and recompute its ancestors. Returns
+    /// `MerkleError::LeafIndexOutOfBounds`, leaving the tree unchanged, if there is
+    /// no such leaf.
+    pub fn insert_leaf(&mut self, leaf_index: impl Into<LeafIndex>, leaf_output: Output) -> Result<(), MerkleError> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        if leaf_index >= self.actual_leaves() {
+            return Err(MerkleError::LeafIndexOutOfBounds {
+                leaf_index,
+                actual_leaves: self.actual_leaves(),
+            });
+        }
+        self.leaves[leaf_index] = leaf_output;
+
+        let mut node_cv = leaf_output.chaining_value();
+        let mut index = self.num_leaves() + leaf_index;
+        let mut position = leaf_index;
+        let mut nodes_in_level = self.actual_leaves();
+        while nodes_in_level > 1 {
+            if (position ^ 1) < nodes_in_level {
+                let sibling_cv = self.cv(index ^ 1);
+                node_cv = if position.is_multiple_of(2) {
+                    parent_cv(node_cv, sibling_cv, self.key_words, self.flags)
+                } else {
+                    parent_cv(sibling_cv, node_cv, self.key_words, self.flags)
+                };
+            }
+            index /= 2;
+            position /= 2;
+            nodes_in_level = nodes_in_level.div_ceil(2);
+            self.cvs[index] = node_cv;
+        }
+        Ok(())
+    }
+
+    /// The chaining values of the siblings on the path from `leaf_index` to the
+    /// root, as `BinaryMerkleTree::update_path_siblings` returns them. Returns
+    /// `None` if the leaf index is out of bounds.
+    pub fn update_path_siblings(&self, leaf_index: impl Into<LeafIndex>) -> Option<Vec<[u32; 8]>> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        if leaf_index >= self.actual_leaves() {
+            return None;
+        }
+        let mut siblings = Vec::new();
+        let mut index = self.num_leaves() + leaf_index;
+        let mut position = leaf_index;
+        let mut nodes_in_level = self.actual_leaves();
+        while nodes_in_level > 1 {
+            if (position ^ 1) < nodes_in_level {
+                siblings.push(self.cv(index ^ 1));
+            }
+            index /= 2;
+            position /= 2;
+            nodes_in_level = nodes_in_level.div_ceil(2);
+        }
+        Some(siblings)
+    }
+
+    /// The inclusion proof for the leaf at `leaf_index`, or `None` if out of bounds.
+    /// It equals `BinaryMerkleTree::generate_proof` over the same leaves.
+    pub fn generate_proof(&self, leaf_index: impl Into<LeafIndex>) -> Option<MerkleProof> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        Some(MerkleProof {
+            leaf_index,
+            actual_leaves: self.actual_leaves(),
+            leaf: self.leaf(leaf_index)?,
+            siblings: self.update_path_siblings(leaf_index)?,
+        })
+    }
+
+    /// The memory the tree holds, in bytes, to compare with
+    /// `BinaryMerkleTree::heap_size_bytes`.
+    pub fn heap_size_bytes(&self) -> usize {
+        size_of::<Self>() + self.leaves.capacity() * size_of::<Output>() + self.cvs.capacity() * size_of::<[u32; 8]>()
+    }
+}
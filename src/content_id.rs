@@ -0,0 +1,119 @@
+//! Content identifiers binding a root hash to the input length.
+//!
+//! A root alone already determines the input, but a key that also states the
+//! length lets a store size and bound a fetch before verifying anything, and
+//! keeps a verified prefix of a blob from being mistaken for the whole blob by
+//! protocols layered on top. A `ContentId` is both, and is usable directly as a
+//! map key.
+//!
+//! The string form is `b3mt:<64 lowercase hex digits>:<decimal length>`; the byte
+//! form is the root followed by the length as a little-endian u64.
+
+use core::fmt;
+use core::str::FromStr;
+
+use crate::binary_merkle_tree::{Blake3Hasher, CvStack, GenericMerkleTree, Hash32, InvalidHex, OUT_LEN};
+use crate::node_store::NodeStore;
+
+/// The prefix of the string form.
+pub const CONTENT_ID_PREFIX: &str = "b3mt:";
+
+/// The length of the byte form.
+pub const CONTENT_ID_LEN: usize = OUT_LEN + 8;
+
+/// A root hash and the length of the input it covers. Ordered by root, then length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ContentId {
+    pub root: Hash32,
+    pub len: u64,
+}
+
+/// Why a string is not a `ContentId`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidContentId {
+    /// The string does not start with `b3mt:`.
+    Prefix,
+    /// There is no `:` between the root and the length.
+    MissingLength,
+    /// The root is not 64 hex digits.
+    Root(InvalidHex),
+    /// The length is not a decimal u64 without sign or leading zeros.
+    Length,
+}
+
+impl fmt::Display for InvalidContentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidContentId::Prefix => write!(f, "content id does not start with {:?}", CONTENT_ID_PREFIX),
+            InvalidContentId::MissingLength => write!(f, "content id has no length"),
+            InvalidContentId::Root(e) => write!(f, "invalid content id root: {}", e),
+            InvalidContentId::Length => write!(f, "invalid content id length"),
+        }
+    }
+}
+
+impl core::error::Error for InvalidContentId {}
+
+impl ContentId {
+    pub fn new(root: Hash32, len: u64) -> Self {
+        ContentId { root, len }
+    }
+
+    /// The id of everything `hasher` has seen so far.
+    pub fn from_hasher<S: CvStack>(hasher: &Blake3Hasher<S>) -> Self {
+        ContentId { root: Hash32(hasher.finalize_array()), len: hasher.count() }
+    }
+
+    /// The root followed by the length as a little-endian u64.
+    pub fn to_bytes(&self) -> [u8; CONTENT_ID_LEN] {
+        let mut bytes = [0; CONTENT_ID_LEN];
+        bytes[..OUT_LEN].copy_from_slice(&self.root.0);
+        bytes[OUT_LEN..].copy_from_slice(&self.len.to_le_bytes());
+        bytes
+    }
+
+    /// Decode the output of `to_bytes`.
+    pub fn from_bytes(bytes: &[u8; CONTENT_ID_LEN]) -> Self {
+        ContentId {
+            root: Hash32(bytes[..OUT_LEN].try_into().unwrap()),
+            len: u64::from_le_bytes(bytes[OUT_LEN..].try_into().unwrap()),
+        }
+    }
+}
+
+/// Writes `b3mt:<hex root>:<len>`, with the root in lowercase.
+impl fmt::Display for ContentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(CONTENT_ID_PREFIX)?;
+        for byte in self.root.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ":{}", self.len)
+    }
+}
+
+/// Parses the `Display` form. The root may be in either case; the length must be
+/// plain decimal digits, so each length has one spelling.
+impl FromStr for ContentId {
+    type Err = InvalidContentId;
+
+    fn from_str(s: &str) -> Result<Self, InvalidContentId> {
+        let rest = s.strip_prefix(CONTENT_ID_PREFIX).ok_or(InvalidContentId::Prefix)?;
+        let (root, len) = rest.split_once(':').ok_or(InvalidContentId::MissingLength)?;
+        let root = root.parse().map_err(InvalidContentId::Root)?;
+        let canonical = !len.is_empty() && len.bytes().all(|b| b.is_ascii_digit()) && (len == "0" || !len.starts_with('0'));
+        if !canonical {
+            return Err(InvalidContentId::Length);
+        }
+        let len = len.parse().map_err(|_| InvalidContentId::Length)?;
+        Ok(ContentId { root, len })
+    }
+}
+
+impl<S: NodeStore> GenericMerkleTree<S> {
+    /// The tree's root hash and input length, or `None` if the length is unknown
+    /// (see `input_len`).
+    pub fn content_id(&self) -> Option<ContentId> {
+        Some(ContentId { root: self.root_hash(), len: self.input_len()? })
+    }
+}
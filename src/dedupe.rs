@@ -0,0 +1,67 @@
+//! Finding which chunks of a new file are already stored as chunks of older ones.
+//!
+//! A content-addressed store keeps each distinct chunk once. Leaf chaining values
+//! cannot tell it which chunks it already has, since they commit to the chunk's
+//! position, so chunks are matched by the position-independent content hashes that
+//! `TreeLeafBuilder::with_content_hashes` records, and a chunk moved to another
+//! offset or another file still matches.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, Hash32, CHUNK_LEN};
+
+/// Where a chunk of the new file can be found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkMatch {
+    /// The same bytes are chunk `index` of `existing[file]`.
+    Duplicate { file: usize, index: usize },
+    /// No existing chunk has the same bytes.
+    Unique,
+}
+
+/// The result of `dedupe_report`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DedupeReport {
+    /// One entry per chunk of the new file, in order.
+    pub chunks: Vec<ChunkMatch>,
+    /// The total length of the duplicate chunks, which need not be uploaded.
+    pub reusable_bytes: u64,
+}
+
+/// Match each chunk of `new` against the chunks of `existing`. A chunk with several
+/// matches is reported at the first: the lowest file, then the lowest index.
+/// Chunks marked by `remove_leaf` are not offered as matches, since `compact` drops
+/// them.
+///
+/// Only trees carrying content hashes (see `BinaryMerkleTree::content_hash`) take
+/// part. If `new` has none every chunk is `Unique`, and an existing tree without
+/// them matches nothing, so a missing hash costs an upload rather than a lost chunk.
+pub fn dedupe_report(new: &BinaryMerkleTree, existing: &[&BinaryMerkleTree]) -> DedupeReport {
+    let mut stored: BTreeMap<Hash32, (usize, usize)> = BTreeMap::new();
+    for (file, tree) in existing.iter().enumerate() {
+        for index in 0..tree.actual_leaves() {
+            if tree.is_removed(index) {
+                continue;
+            }
+            if let Some(hash) = tree.content_hash(index) {
+                stored.entry(hash).or_insert((file, index));
+            }
+        }
+    }
+
+    // A tree with content hashes was built from bytes, so its length is known
+    let input_len = new.input_len().unwrap_or(0);
+    let mut reusable_bytes = 0;
+    let chunks = (0..new.actual_leaves())
+        .map(|index| match new.content_hash(index).and_then(|hash| stored.get(&hash)) {
+            Some(&(file, existing_index)) => {
+                let start = (index * CHUNK_LEN) as u64;
+                reusable_bytes += input_len.saturating_sub(start).min(CHUNK_LEN as u64);
+                ChunkMatch::Duplicate { file, index: existing_index }
+            }
+            None => ChunkMatch::Unique,
+        })
+        .collect();
+    DedupeReport { chunks, reusable_bytes }
+}
@@ -0,0 +1,115 @@
+//! Verifying a download whose chunks arrive in any order, as from a swarm of
+//! peers.
+//!
+//! Each chunk is checked against a trusted tree on its own, with `verify_chunk`,
+//! so a bad chunk is caught as soon as it arrives and only that chunk needs
+//! fetching again. Accepted chunks can be written straight to their offset in the
+//! output file.
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, CHUNK_LEN};
+use crate::io::VerifyError;
+
+/// What `DownloadVerifier::submit_chunk` did with a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkStatus {
+    /// The chunk verified and was recorded, and written to the sink if there is one.
+    Accepted,
+    /// The chunk had already been accepted; the new copy was ignored unchecked.
+    Duplicate,
+    /// The bytes are not the chunk's, including a final chunk of the wrong length,
+    /// and must be fetched again.
+    Rejected,
+}
+
+/// Tracks which chunks of a download have arrived and verified, in any order.
+///
+/// Built from a trusted tree, such as one deserialized from a signed `to_bytes`.
+/// With `with_sink`, accepted chunks are written at `chunk_index * CHUNK_LEN` of
+/// the sink, so once `is_complete` the sink holds exactly the original input.
+#[derive(Debug)]
+pub struct DownloadVerifier<W = io::Empty> {
+    tree: BinaryMerkleTree,
+    received: Vec<bool>,
+    chunks_received: usize,
+    bytes_written: u64,
+    sink: W,
+}
+
+impl DownloadVerifier {
+    /// A verifier for the input `tree` describes that keeps no data.
+    pub fn new(tree: &BinaryMerkleTree) -> Self {
+        DownloadVerifier::with_sink(tree, io::empty())
+    }
+}
+
+impl<W: Write + Seek> DownloadVerifier<W> {
+    /// A verifier that writes each accepted chunk to `sink` at its offset.
+    pub fn with_sink(tree: &BinaryMerkleTree, sink: W) -> Self {
+        DownloadVerifier {
+            tree: tree.snapshot(),
+            received: vec![false; tree.actual_leaves()],
+            chunks_received: 0,
+            bytes_written: 0,
+            sink,
+        }
+    }
+
+    /// Check `bytes` against chunk `chunk_index` and record it if it verifies.
+    ///
+    /// Returns a `VerifyError` for an index outside the tree (`InvalidInput`) or a
+    /// failed write to the sink. Either way the chunk is not recorded, so it can be
+    /// submitted again; after a failed write the sink may hold part of it.
+    pub fn submit_chunk(&mut self, chunk_index: usize, bytes: &[u8]) -> Result<ChunkStatus, VerifyError> {
+        let fail = |error| VerifyError { chunk_index, bytes_written: self.bytes_written, error };
+        let Some(&received) = self.received.get(chunk_index) else {
+            let message = format!("chunk {} is outside a tree of {} chunks", chunk_index, self.received.len());
+            return Err(fail(io::Error::new(io::ErrorKind::InvalidInput, message)));
+        };
+        if received {
+            return Ok(ChunkStatus::Duplicate);
+        }
+        if !self.tree.verify_chunk(chunk_index, bytes) {
+            return Ok(ChunkStatus::Rejected);
+        }
+        let written = self
+            .sink
+            .seek(SeekFrom::Start((chunk_index * CHUNK_LEN) as u64))
+            .and_then(|_| self.sink.write_all(bytes));
+        if let Err(error) = written {
+            return Err(fail(error));
+        }
+        self.received[chunk_index] = true;
+        self.chunks_received += 1;
+        self.bytes_written += bytes.len() as u64;
+        Ok(ChunkStatus::Accepted)
+    }
+}
+
+impl<W> DownloadVerifier<W> {
+    /// The chunks not yet accepted, ascending.
+    pub fn missing_chunks(&self) -> Vec<usize> {
+        (0..self.received.len()).filter(|&chunk_index| !self.received[chunk_index]).collect()
+    }
+
+    /// The fraction of chunks accepted, from 0.0 to 1.0.
+    pub fn progress(&self) -> f64 {
+        self.chunks_received as f64 / self.received.len() as f64
+    }
+
+    /// The total length of the accepted chunks.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Whether every chunk has been accepted.
+    pub fn is_complete(&self) -> bool {
+        self.chunks_received == self.received.len()
+    }
+
+    /// The sink, to flush or read back once the download is complete.
+    pub fn into_sink(self) -> W {
+        self.sink
+    }
+}
@@ -0,0 +1,110 @@
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+/// Errors from operations on trees.
+///
+/// Every public method that takes a leaf index, a leaf count, an input length or a
+/// fanout from the caller reports bad values with one of these instead of
+/// panicking, so sizes and indices from untrusted sources can be passed straight
+/// through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MerkleError {
+    /// `bulk_insert_leaves` was given a different number of leaf indices and leaf hashes.
+    LengthMismatch { indices: usize, hashes: usize },
+    /// `bulk_insert_leaves` requires strictly increasing leaf indices.
+    UnsortedIndices,
+    /// The leaf was not kept when the tree was pruned, so a `PrunedTree` cannot check it.
+    LeafNotKept { leaf_index: usize },
+    /// A leaf index at or past the number of leaves in the tree.
+    LeafIndexOutOfBounds { leaf_index: usize, actual_leaves: usize },
+    /// Input of `input_len` bytes does not split into the tree's number of leaves,
+    /// so it cannot be the input the tree was built from.
+    InputLengthMismatch { input_len: u64, actual_leaves: usize },
+    /// A tree, or the kept part of a pruned tree, needs at least one leaf.
+    NoLeaves,
+    /// A k-ary fanout, or the number of children of a k-ary parent, outside
+    /// 2..=`MAX_FANOUT`.
+    InvalidFanout { fanout: usize },
+    /// A single chunk longer than `CHUNK_LEN`.
+    ChunkTooLong { len: usize },
+    /// `FlatTree::from_flat_cvs` needs exactly one chaining value per node of a
+    /// tree with `actual_leaves` leaves.
+    CvCountMismatch { cvs: usize, actual_leaves: usize },
+    /// An Output's flags combine bits that hashing never sets together, or bits
+    /// that are not BLAKE3 flags; `conflicting` holds the offending bits.
+    InvalidFlags { flags: u32, conflicting: u32 },
+    /// `new_from_leaves_checked` was given a leaf that is not a finished chunk
+    /// hashed with the tree's flags.
+    InvalidLeaf { leaf_index: usize, flags: u32 },
+    /// More chunks than `MAX_CHUNKS`, or than a tree on this target can address.
+    TooManyChunks { chunks: u64 },
+    /// A `Checkpoint` that is no longer open on the tree.
+    UnknownCheckpoint,
+    /// A `GroupedTree` group size that is not a power of two, or whose groups'
+    /// byte length overflows.
+    InvalidGroupChunks { group_chunks: usize },
+    /// `len` bytes do not fill group `group_index` of a `GroupedTree`: every group
+    /// but the last takes exactly its full chunks, and the last its current number
+    /// of chunks.
+    GroupLengthMismatch { group_index: usize, len: usize },
+    /// A tree of leaf hashes needs at least two, so that its root is a parent node.
+    TooFewLeafHashes { count: usize },
+    /// A method for chunk leaves was called on a tree of leaf hashes, or one for
+    /// leaf hashes on a tree of chunks; `leaf_hashes` is the tree's kind.
+    WrongLeafKind { leaf_hashes: bool },
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleError::LengthMismatch { indices, hashes } => {
+                write!(f, "got {} leaf indices but {} leaf hashes", indices, hashes)
+            }
+            MerkleError::UnsortedIndices => write!(f, "leaf indices are not strictly increasing"),
+            MerkleError::LeafNotKept { leaf_index } => write!(f, "leaf {} was not kept in the pruned tree", leaf_index),
+            MerkleError::LeafIndexOutOfBounds { leaf_index, actual_leaves } => {
+                write!(f, "Leaf index {} is out of bounds for tree with {} leaves", leaf_index, actual_leaves)
+            }
+            MerkleError::InputLengthMismatch { input_len, actual_leaves } => {
+                write!(f, "input of {} bytes does not match a tree of {} leaves", input_len, actual_leaves)
+            }
+            MerkleError::NoLeaves => write!(f, "a tree needs at least one leaf"),
+            MerkleError::InvalidFanout { fanout } => {
+                write!(f, "fanout must be between 2 and {}, got {}", crate::kary_merkle_tree::MAX_FANOUT, fanout)
+            }
+            MerkleError::ChunkTooLong { len } => write!(f, "chunk of {} bytes exceeds CHUNK_LEN", len),
+            MerkleError::CvCountMismatch { cvs, actual_leaves } => {
+                write!(f, "{} chaining values do not fit a tree of {} leaves", cvs, actual_leaves)
+            }
+            MerkleError::InvalidFlags { flags, conflicting } => {
+                write!(f, "flags {:#x} have conflicting or unknown bits {:#x}", flags, conflicting)
+            }
+            MerkleError::InvalidLeaf { leaf_index, flags } => {
+                write!(f, "leaf {} with flags {:#x} is not a chunk hashed with the tree's flags", leaf_index, flags)
+            }
+            MerkleError::TooManyChunks { chunks } => write!(f, "{} chunks are more than a tree can hold", chunks),
+            MerkleError::UnknownCheckpoint => write!(f, "the checkpoint is no longer open on this tree"),
+            MerkleError::InvalidGroupChunks { group_chunks } => {
+                write!(f, "a group must be a power of two of chunks, got {}", group_chunks)
+            }
+            MerkleError::GroupLengthMismatch { group_index, len } => {
+                write!(f, "{} bytes do not fill group {}", len, group_index)
+            }
+            MerkleError::TooFewLeafHashes { count } => write!(f, "a tree of leaf hashes needs at least 2, got {}", count),
+            MerkleError::WrongLeafKind { leaf_hashes: true } => write!(f, "the tree's leaves are leaf hashes, not chunks"),
+            MerkleError::WrongLeafKind { leaf_hashes: false } => write!(f, "the tree's leaves are chunks, not leaf hashes"),
+        }
+    }
+}
+
+impl core::error::Error for MerkleError {}
+
+#[cfg(feature = "std")]
+/// For methods that already return `io::Result`: every `MerkleError` is a bad
+/// argument, so it becomes `InvalidInput`.
+impl From<MerkleError> for io::Error {
+    fn from(e: MerkleError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
@@ -0,0 +1,211 @@
+//! C bindings: build a tree from a buffer, read its root, replace leaves with new
+//! chunk bytes, and generate and verify inclusion proofs, through an opaque
+//! `b3mt_tree_t`. Trees use the regular hash mode (`IV` and `FLAGS`), so roots
+//! equal the plain BLAKE3 hash of the input.
+//!
+//! Every function returns a `B3MT_*` status code and catches panics, so no Rust
+//! unwind crosses into C. The build script writes the header to `$OUT_DIR/b3mt.h`.
+//! Cargo cannot switch crate types per feature, so build the C library with
+//! `cargo rustc --release --features ffi --crate-type cdylib` (or `staticlib`).
+//!
+//! Proofs are exchanged in the layout of `MerkleProof::to_path_bytes`.
+#![allow(unsafe_code)]
+
+use std::os::raw::c_int;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::{ptr, slice};
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, ChunkState, CHUNK_LEN, FLAGS, IV, OUT_LEN};
+use crate::proof::MerkleProof;
+
+/// Success.
+pub const B3MT_OK: c_int = 0;
+/// A required pointer was null.
+pub const B3MT_NULL_POINTER: c_int = -1;
+/// A leaf index was out of bounds, chunk bytes were longer than a chunk, or a proof
+/// had a malformed length.
+pub const B3MT_INVALID_ARGUMENT: c_int = -2;
+/// The output buffer was too small; the size needed was stored in `*written`.
+pub const B3MT_BUFFER_TOO_SMALL: c_int = -3;
+/// The proof does not lead from the chunk to the root.
+pub const B3MT_PROOF_MISMATCH: c_int = -4;
+/// A Rust panic was caught. This is a bug in the library.
+pub const B3MT_PANIC: c_int = -99;
+
+/// A tree owned by C code. Create it with `b3mt_tree_new`, release it with
+/// `b3mt_tree_free`.
+#[allow(non_camel_case_types)]
+pub struct b3mt_tree_t {
+    tree: BinaryMerkleTree,
+}
+
+/// Run `body`, turning a panic into `B3MT_PANIC`.
+fn guard(body: impl FnOnce() -> c_int) -> c_int {
+    catch_unwind(AssertUnwindSafe(body)).unwrap_or(B3MT_PANIC)
+}
+
+/// The `len` bytes at `data`, or `None` if `data` is null and `len` is not 0.
+unsafe fn input_bytes<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if len == 0 {
+        Some(&[])
+    } else if data.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(data, len))
+    }
+}
+
+/// Hash `len` bytes at `data` into a new tree and store it in `*out`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes (it may be null if `len` is 0), and
+/// `out` must be a valid pointer to write a tree pointer to.
+#[no_mangle]
+pub unsafe extern "C" fn b3mt_tree_new(data: *const u8, len: usize, out: *mut *mut b3mt_tree_t) -> c_int {
+    guard(|| {
+        if out.is_null() {
+            return B3MT_NULL_POINTER;
+        }
+        let Some(input) = input_bytes(data, len) else {
+            return B3MT_NULL_POINTER;
+        };
+        let tree = BinaryMerkleTree::from_input(input, IV, FLAGS);
+        *out = Box::into_raw(Box::new(b3mt_tree_t { tree }));
+        B3MT_OK
+    })
+}
+
+/// Write the 32-byte root hash of `tree` to `out`.
+///
+/// # Safety
+///
+/// `tree` must come from `b3mt_tree_new` and not be freed, and `out` must point to
+/// 32 writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn b3mt_tree_root(tree: *const b3mt_tree_t, out: *mut u8) -> c_int {
+    guard(|| {
+        if tree.is_null() || out.is_null() {
+            return B3MT_NULL_POINTER;
+        }
+        let root = (*tree).tree.root_hash();
+        ptr::copy_nonoverlapping(root.as_bytes().as_ptr(), out, OUT_LEN);
+        B3MT_OK
+    })
+}
+
+/// Replace leaf `leaf_index` with the hash of `chunk_len` bytes at `chunk` and
+/// recompute the root. At most one chunk (1024 bytes) may be given.
+///
+/// # Safety
+///
+/// `tree` must come from `b3mt_tree_new` and not be freed, and `chunk` must point to
+/// `chunk_len` readable bytes (it may be null if `chunk_len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn b3mt_tree_update_leaf(
+    tree: *mut b3mt_tree_t,
+    leaf_index: usize,
+    chunk: *const u8,
+    chunk_len: usize,
+) -> c_int {
+    guard(|| {
+        if tree.is_null() {
+            return B3MT_NULL_POINTER;
+        }
+        let Some(chunk_bytes) = input_bytes(chunk, chunk_len) else {
+            return B3MT_NULL_POINTER;
+        };
+        if chunk_bytes.len() > CHUNK_LEN {
+            return B3MT_INVALID_ARGUMENT;
+        }
+        let mut chunk_state = ChunkState::new(IV, leaf_index as u64, FLAGS);
+        chunk_state.update(chunk_bytes);
+        match (*tree).tree.insert_leaf(leaf_index, chunk_state.output()) {
+            Ok(()) => B3MT_OK,
+            Err(_) => B3MT_INVALID_ARGUMENT,
+        }
+    })
+}
+
+/// Write the inclusion proof for leaf `leaf_index` to the `out_len` bytes at `out`,
+/// and its length to `*written`. If the buffer is too small, returns
+/// `B3MT_BUFFER_TOO_SMALL` with the length needed in `*written`; passing a null
+/// `out` with `out_len` 0 queries the length.
+///
+/// # Safety
+///
+/// `tree` must come from `b3mt_tree_new` and not be freed, `out` must point to
+/// `out_len` writable bytes, and `written` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn b3mt_tree_proof(
+    tree: *const b3mt_tree_t,
+    leaf_index: usize,
+    out: *mut u8,
+    out_len: usize,
+    written: *mut usize,
+) -> c_int {
+    guard(|| {
+        if tree.is_null() || written.is_null() || (out.is_null() && out_len != 0) {
+            return B3MT_NULL_POINTER;
+        }
+        let Some(proof) = (*tree).tree.generate_proof(leaf_index) else {
+            return B3MT_INVALID_ARGUMENT;
+        };
+        let encoded = proof.to_path_bytes();
+        *written = encoded.len();
+        if encoded.len() > out_len {
+            return B3MT_BUFFER_TOO_SMALL;
+        }
+        ptr::copy_nonoverlapping(encoded.as_ptr(), out, encoded.len());
+        B3MT_OK
+    })
+}
+
+/// Check that the `chunk_len` bytes at `chunk` are the leaf a proof from
+/// `b3mt_tree_proof` names, under the 32-byte `root`. Returns `B3MT_OK` if so and
+/// `B3MT_PROOF_MISMATCH` if not.
+///
+/// # Safety
+///
+/// `root` must point to 32 readable bytes, `chunk` to `chunk_len` readable bytes and
+/// `proof` to `proof_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn b3mt_verify_proof(
+    root: *const u8,
+    chunk: *const u8,
+    chunk_len: usize,
+    proof: *const u8,
+    proof_len: usize,
+) -> c_int {
+    guard(|| {
+        if root.is_null() {
+            return B3MT_NULL_POINTER;
+        }
+        let (Some(chunk_bytes), Some(encoded)) = (input_bytes(chunk, chunk_len), input_bytes(proof, proof_len)) else {
+            return B3MT_NULL_POINTER;
+        };
+        let Some(proof) = MerkleProof::from_path_bytes(encoded, chunk_bytes, IV, FLAGS) else {
+            return B3MT_INVALID_ARGUMENT;
+        };
+        let root: &[u8; OUT_LEN] = &*root.cast();
+        if proof.verify(root, IV, FLAGS) {
+            B3MT_OK
+        } else {
+            B3MT_PROOF_MISMATCH
+        }
+    })
+}
+
+/// Free a tree from `b3mt_tree_new`. Null is ignored.
+///
+/// # Safety
+///
+/// `tree` must be null or come from `b3mt_tree_new`, and must not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn b3mt_tree_free(tree: *mut b3mt_tree_t) {
+    if !tree.is_null() {
+        // Dropping a tree only frees memory, but even so keep a panic from
+        // unwinding into C
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(tree))));
+    }
+}
@@ -0,0 +1,207 @@
+//! A `NodeStore` on disk, for trees too large to keep in memory.
+//!
+//! Node `id` lives at byte offset `id * OUTPUT_BYTES_LEN` of the file, in the
+//! canonical encoding of `Output::to_bytes`. Padding positions are never written,
+//! so the file may be sparse. A bounded LRU cache of nodes fronts the file; dirty
+//! nodes are written back when evicted, on `flush` and on drop.
+//!
+//! Written-back nodes go out in decreasing heap index order, so every leaf is
+//! written before the parents above it: after a crash, a parent on disk was
+//! always written after the children it was computed from.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::binary_merkle_tree::{Output, OUTPUT_BYTES_LEN};
+use crate::node_store::{NodeId, NodeStore};
+
+#[derive(Debug)]
+struct CachedNode {
+    output: Output,
+    dirty: bool,
+    last_used: u64,
+}
+
+#[derive(Debug)]
+struct PagedNodes {
+    file: File,
+    capacity: usize,
+    // Node positions in the file, including unwritten padding
+    len: usize,
+    cache: HashMap<usize, CachedNode>,
+    // Cached node ids by the tick they were last used, least recent first
+    by_use: BTreeMap<u64, usize>,
+    clock: u64,
+}
+
+impl PagedNodes {
+    fn offset(id: usize) -> u64 {
+        id as u64 * OUTPUT_BYTES_LEN as u64
+    }
+
+    fn read(&mut self, id: usize) -> io::Result<Output> {
+        let mut bytes = [0u8; OUTPUT_BYTES_LEN];
+        self.file.seek(SeekFrom::Start(Self::offset(id)))?;
+        self.file.read_exact(&mut bytes)?;
+        Output::try_from(&bytes[..]).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Mark `id` as the most recently used node.
+    fn touch(&mut self, id: usize) {
+        self.clock += 1;
+        let node = self.cache.get_mut(&id).unwrap();
+        self.by_use.remove(&node.last_used);
+        node.last_used = self.clock;
+        self.by_use.insert(self.clock, id);
+    }
+
+    fn insert(&mut self, id: usize, output: Output, dirty: bool) -> io::Result<()> {
+        if let Some(node) = self.cache.get_mut(&id) {
+            node.output = output;
+            node.dirty |= dirty;
+        } else {
+            if self.cache.len() == self.capacity {
+                self.evict()?;
+            }
+            self.cache.insert(id, CachedNode { output, dirty, last_used: 0 });
+        }
+        self.len = self.len.max(id + 1);
+        self.touch(id);
+        Ok(())
+    }
+
+    /// Drop the least recently used node. If it is dirty, every dirty node is
+    /// written back first, so write order stays leaves-before-parents.
+    fn evict(&mut self) -> io::Result<()> {
+        let (&tick, &id) = self.by_use.iter().next().expect("the cache is full");
+        if self.cache[&id].dirty {
+            self.write_back()?;
+        }
+        self.by_use.remove(&tick);
+        self.cache.remove(&id);
+        Ok(())
+    }
+
+    fn write_back(&mut self) -> io::Result<()> {
+        let mut dirty: Vec<usize> = self.cache.iter().filter(|(_, node)| node.dirty).map(|(&id, _)| id).collect();
+        dirty.sort_unstable_by(|a, b| b.cmp(a));
+        for id in dirty {
+            self.file.seek(SeekFrom::Start(Self::offset(id)))?;
+            self.file.write_all(&self.cache[&id].output.to_bytes())?;
+            self.cache.get_mut(&id).unwrap().dirty = false;
+        }
+        Ok(())
+    }
+}
+
+fn check_capacity(cache_capacity: usize) -> io::Result<()> {
+    if cache_capacity == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the node cache must hold at least one node"));
+    }
+    Ok(())
+}
+
+/// Nodes in a file, fronted by an LRU cache of at most `cache_capacity` nodes.
+///
+/// `NodeStore` has no error channel, so `get` and `put` panic if the file cannot
+/// be read or written, or holds an invalid node. `flush` reports write errors as
+/// an `io::Result` instead.
+///
+/// Even a read updates the cache, so the cache sits behind a `Mutex`: the store is
+/// `Sync` and a tree over it can be shared between threads, but concurrent reads
+/// take turns.
+#[derive(Debug)]
+pub struct FileStore {
+    paged: Mutex<PagedNodes>,
+}
+
+impl FileStore {
+    /// Create (or truncate) the file at `path` for a new tree. A `cache_capacity`
+    /// of zero is rejected with `io::ErrorKind::InvalidInput`, before the file is
+    /// touched.
+    pub fn create<P: AsRef<Path>>(path: P, cache_capacity: usize) -> io::Result<Self> {
+        check_capacity(cache_capacity)?;
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        Ok(Self::with_file(file, 0, cache_capacity))
+    }
+
+    /// Open a file written by a previous `FileStore`, with a cold cache. A
+    /// `cache_capacity` of zero is rejected as for `create`.
+    pub fn open<P: AsRef<Path>>(path: P, cache_capacity: usize) -> io::Result<Self> {
+        check_capacity(cache_capacity)?;
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let len = file.metadata()?.len().div_ceil(OUTPUT_BYTES_LEN as u64);
+        let len = usize::try_from(len).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "node file too large"))?;
+        Ok(Self::with_file(file, len, cache_capacity))
+    }
+
+    fn with_file(file: File, len: usize, cache_capacity: usize) -> Self {
+        FileStore {
+            paged: Mutex::new(PagedNodes {
+                file,
+                capacity: cache_capacity,
+                len,
+                cache: HashMap::with_capacity(cache_capacity),
+                by_use: BTreeMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+
+    /// The number of nodes currently cached.
+    pub fn cached_nodes(&self) -> usize {
+        self.paged().cache.len()
+    }
+
+    /// Write every dirty cached node back, leaves before parents, and sync the
+    /// file to disk.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let paged = self.paged_mut();
+        paged.write_back()?;
+        paged.file.sync_data()
+    }
+
+    // A panic while the cache is held, such as a failed read in `get`, leaves it
+    // consistent, so a poisoned lock is used as is
+    fn paged(&self) -> MutexGuard<'_, PagedNodes> {
+        self.paged.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn paged_mut(&mut self) -> &mut PagedNodes {
+        self.paged.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl NodeStore for FileStore {
+    fn get(&self, id: NodeId) -> Output {
+        let mut paged = self.paged();
+        if paged.cache.contains_key(&id.0) {
+            paged.touch(id.0);
+            return paged.cache[&id.0].output;
+        }
+        paged
+            .read(id.0)
+            .and_then(|output| paged.insert(id.0, output, false).map(|_| output))
+            .unwrap_or_else(|e| panic!("failed to read node {} from the node file: {}", id.0, e))
+    }
+
+    fn put(&mut self, id: NodeId, output: Output) {
+        self.paged_mut()
+            .insert(id.0, output, true)
+            .unwrap_or_else(|e| panic!("failed to write back nodes to the node file: {}", e));
+    }
+
+    fn len(&self) -> usize {
+        self.paged().len
+    }
+}
+
+impl Drop for FileStore {
+    fn drop(&mut self) {
+        // Like BufWriter: write back what we can, ignoring errors; call `flush` to see them
+        let _ = self.paged_mut().write_back();
+    }
+}
@@ -0,0 +1,152 @@
+//! A tree as a flat array of chaining values, for handing to C code or a GPU
+//! kernel, and `FlatTree`, the read-only tree rebuilt from such an array.
+//!
+//! The layout is the node order of `write_to`: every real (non-padding) node level
+//! by level from the root down, each level left to right, one 32-byte block per
+//! node holding its chaining value as 8 little-endian u32 words. Level sizes follow
+//! from the leaf count alone: the leaf level has `actual_leaves` nodes and each
+//! level above has half as many, rounded up, so the node at `position` of a level
+//! has its children at `2 * position` and `2 * position + 1` of the level below.
+//! A lone left child is promoted, so its parent repeats its chaining value.
+
+use alloc::vec::Vec;
+
+use crate::binary_merkle_tree::{
+    cv_from_bytes, parent_output, BinaryMerkleTree, GenericMerkleTree, Hash32, LeafIndex, Output, OUT_LEN, ROOT,
+};
+use crate::error::MerkleError;
+use crate::node_store::NodeStore;
+use crate::proof::MerkleProof;
+
+impl<S: NodeStore> GenericMerkleTree<S> {
+    /// Every real node's chaining value in the layout described in the module docs.
+    /// The root's entry is its non-root chaining value, not the root hash.
+    pub fn to_flat_cvs(&self) -> Vec<[u8; OUT_LEN]> {
+        let level_sizes = BinaryMerkleTree::level_sizes(self.actual_leaves());
+        let mut cvs = Vec::with_capacity(level_sizes.iter().sum());
+        let leaf_start_index = self.num_leaves();
+        for (level, &size) in level_sizes.iter().enumerate().rev() {
+            let level_start = leaf_start_index >> level;
+            for index in level_start..level_start + size {
+                cvs.push(self.node(index).cv_bytes());
+            }
+        }
+        cvs
+    }
+}
+
+/// A read-only tree rebuilt from `to_flat_cvs`. It holds only chaining values, so
+/// it can give the root hash and proof paths but not leaf Outputs: those commit to
+/// the chunk bytes, which a verifier hashes itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlatTree {
+    cvs: Vec<[u32; 8]>,
+    /// Index into `cvs` of the first node of each level, from the leaves up.
+    level_starts: Vec<usize>,
+    actual_leaves: usize,
+    key_words: [u32; 8],
+    flags: u32,
+}
+
+impl FlatTree {
+    /// Rebuild the tree of `actual_leaves` leaves whose `to_flat_cvs` is `cvs`. The
+    /// chaining values are not checked against each other. Returns `NoLeaves` for
+    /// 0 leaves, and `CvCountMismatch` if `cvs` is not exactly one per node.
+    pub fn from_flat_cvs(cvs: &[[u8; OUT_LEN]], actual_leaves: usize, key_words: [u32; 8], flags: u32) -> Result<Self, MerkleError> {
+        if actual_leaves == 0 {
+            return Err(MerkleError::NoLeaves);
+        }
+        let level_sizes = BinaryMerkleTree::level_sizes(actual_leaves);
+        if cvs.len() != level_sizes.iter().sum::<usize>() {
+            return Err(MerkleError::CvCountMismatch { cvs: cvs.len(), actual_leaves });
+        }
+        let mut level_starts = Vec::with_capacity(level_sizes.len());
+        let mut start = cvs.len();
+        for &size in &level_sizes {
+            start -= size;
+            level_starts.push(start);
+        }
+        Ok(FlatTree {
+            cvs: cvs.iter().map(|&cv| cv_from_bytes(cv)).collect(),
+            level_starts,
+            actual_leaves,
+            key_words,
+            flags,
+        })
+    }
+
+    /// The memory the tree holds, in bytes, to compare with
+    /// `BinaryMerkleTree::heap_size_bytes`: one chaining value per real node.
+    pub fn heap_size_bytes(&self) -> usize {
+        size_of::<Self>() + self.cvs.capacity() * size_of::<[u32; 8]>() + self.level_starts.capacity() * size_of::<usize>()
+    }
+
+    pub fn actual_leaves(&self) -> usize {
+        self.actual_leaves
+    }
+
+    /// The chaining value of the node at `position` in `level`, counting from the leaves.
+    fn cv(&self, level: usize, position: usize) -> [u32; 8] {
+        self.cvs[self.level_starts[level] + position]
+    }
+
+    /// The root hash, or `None` for a one-leaf tree: its root is the chunk itself,
+    /// whose hash needs the chunk's last block rather than its chaining value.
+    pub fn root_hash(&self) -> Option<Hash32> {
+        if self.actual_leaves == 1 {
+            return None;
+        }
+        // The level below the root always holds exactly two nodes
+        let below_root = self.level_starts.len() - 2;
+        let mut root = parent_output(self.cv(below_root, 0), self.cv(below_root, 1), self.key_words, self.flags);
+        root.flags |= ROOT;
+        let mut hash = [0; OUT_LEN];
+        root.root_output_bytes(&mut hash);
+        Some(Hash32(hash))
+    }
+
+    /// The chaining value of the leaf at `leaf_index`, or `None` if out of bounds.
+    pub fn leaf_cv(&self, leaf_index: impl Into<LeafIndex>) -> Option<[u32; 8]> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        (leaf_index < self.actual_leaves).then(|| self.cv(0, leaf_index))
+    }
+
+    /// The sibling chaining values on the path from `leaf_index` to the root, as
+    /// `BinaryMerkleTree::update_path_siblings` returns them, or `None` if out of
+    /// bounds.
+    pub fn update_path_siblings(&self, leaf_index: impl Into<LeafIndex>) -> Option<Vec<[u32; 8]>> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        if leaf_index >= self.actual_leaves {
+            return None;
+        }
+        let mut siblings = Vec::new();
+        let mut position = leaf_index;
+        let mut nodes_in_this_level = self.actual_leaves;
+        let mut level = 0;
+        while nodes_in_this_level > 1 {
+            if position ^ 1 < nodes_in_this_level {
+                siblings.push(self.cv(level, position ^ 1));
+            }
+            position /= 2;
+            nodes_in_this_level = nodes_in_this_level.div_ceil(2);
+            level += 1;
+        }
+        Some(siblings)
+    }
+
+    /// The inclusion proof for the leaf at `leaf_index`, given that leaf's Output
+    /// (from hashing the chunk with `ChunkState`). Returns `None` if the index is out
+    /// of bounds or `leaf` does not have the stored chaining value.
+    pub fn generate_proof(&self, leaf_index: impl Into<LeafIndex>, leaf: Output) -> Option<MerkleProof> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        if self.leaf_cv(leaf_index)? != leaf.chaining_value() {
+            return None;
+        }
+        Some(MerkleProof {
+            leaf_index,
+            actual_leaves: self.actual_leaves,
+            leaf,
+            siblings: self.update_path_siblings(leaf_index)?,
+        })
+    }
+}
@@ -0,0 +1,359 @@
+//! Structured fuzzing support: `Arbitrary` trees, pruned trees and tree
+//! operations, and a shadow model that applies operations to a tree and to a
+//! plain copy of its input side by side.
+//!
+//! After every operation the model checks that the tree's root is still the
+//! BLAKE3 hash of the shadow input, that the tree's own nodes are consistent,
+//! and that it survives a serialization round trip. Operations carry unchecked
+//! leaf indices, so the index validation of `insert_leaf` and
+//! `bulk_insert_leaves` is checked against the errors the model predicts.
+//!
+//! `ShadowModel::apply` reports a divergence as an error rather than panicking,
+//! so the same harness runs under `cargo fuzz` (see `fuzz/`), proptest, or a
+//! plain test loop.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use arbitrary::{Arbitrary, Unstructured};
+
+use crate::binary_merkle_tree::{
+    chunk_count, BinaryMerkleTree, Blake3Hasher, ChunkState, Hash32, Output, CHUNK_LEN, FLAGS, IV, KEYED_HASH, KEY_LEN,
+};
+use crate::error::MerkleError;
+use crate::le_bytes::read_words;
+use crate::proof::MerkleProof;
+use crate::pruned_tree::PrunedTree;
+
+/// A leaf index that is in range for small trees about as often as not.
+fn leaf_index(u: &mut Unstructured<'_>) -> arbitrary::Result<usize> {
+    if u.arbitrary()? {
+        u.int_in_range(0..=15)
+    } else {
+        u.arbitrary()
+    }
+}
+
+fn leaf_indices(u: &mut Unstructured<'_>) -> arbitrary::Result<Vec<usize>> {
+    let mut indices = Vec::new();
+    while u.arbitrary()? {
+        indices.push(leaf_index(u)?);
+    }
+    Ok(indices)
+}
+
+/// One step applied to both the tree and the shadow input.
+#[derive(Debug, Clone, PartialEq, Eq, Arbitrary)]
+pub enum TreeOp {
+    /// Replace chunk `leaf_index` with `insert_leaf`. `bytes` are fitted to the
+    /// chunk: cut or zero-padded to a full chunk, except that the last chunk may
+    /// be shorter (but only empty when it is the only one).
+    Insert {
+        #[arbitrary(with = leaf_index)]
+        leaf_index: usize,
+        bytes: Vec<u8>,
+    },
+    /// Replace several chunks with `bulk_insert_leaves`, fitted as for `Insert`.
+    /// The indices are passed through as they are, sorted or not.
+    BulkInsert {
+        #[arbitrary(with = leaf_indices)]
+        leaf_indices: Vec<usize>,
+        chunks: Vec<Vec<u8>>,
+    },
+    /// Append bytes to the input. A tree cannot grow in place, so this rebuilds
+    /// it with `from_input`.
+    Append { bytes: Vec<u8> },
+    /// Cut the input to `len % (input length + 1)` bytes, by removing the chunks
+    /// past the end, compacting, and rehashing a final partial chunk.
+    Truncate { len: usize },
+    /// Generate a proof for `leaf_index`, send it through `to_path_bytes` and
+    /// `from_path_bytes`, and verify it against the root.
+    ProofRoundTrip {
+        #[arbitrary(with = leaf_index)]
+        leaf_index: usize,
+    },
+}
+
+/// A tree and its shadow input parting ways.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The number of operations applied before this one.
+    pub step: usize,
+    pub op: Option<TreeOp>,
+    pub what: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.op {
+            Some(op) => write!(f, "step {} ({:?}): {}", self.step, op, self.what),
+            None => write!(f, "step {}: {}", self.step, self.what),
+        }
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// A tree checked against a plain copy of the input it describes.
+#[derive(Debug, Clone)]
+pub struct ShadowModel {
+    tree: BinaryMerkleTree,
+    input: Vec<u8>,
+    key: Option<[u8; KEY_LEN]>,
+    steps: usize,
+}
+
+impl ShadowModel {
+    /// Start from a tree over `input`, keyed with `key` if there is one.
+    pub fn new(input: &[u8], key: Option<[u8; KEY_LEN]>) -> Result<Self, Divergence> {
+        let (key_words, flags) = mode(key);
+        let model = ShadowModel {
+            tree: BinaryMerkleTree::from_input(input, key_words, flags),
+            input: input.to_vec(),
+            key,
+            steps: 0,
+        };
+        model.check(None)?;
+        Ok(model)
+    }
+
+    pub fn tree(&self) -> &BinaryMerkleTree {
+        &self.tree
+    }
+
+    /// The input the tree should describe.
+    pub fn input(&self) -> &[u8] {
+        &self.input
+    }
+
+    /// Apply `op` to the tree and the shadow input, then check they still agree.
+    pub fn apply(&mut self, op: &TreeOp) -> Result<(), Divergence> {
+        let result = self.apply_unchecked(op);
+        let checked = result.map_err(|what| self.divergence(Some(op), what)).and_then(|()| self.check(Some(op)));
+        self.steps += 1;
+        checked
+    }
+
+    fn apply_unchecked(&mut self, op: &TreeOp) -> Result<(), String> {
+        let (key_words, flags) = mode(self.key);
+        match op {
+            TreeOp::Insert { leaf_index, bytes } => {
+                let expected = self.check_index(*leaf_index).map(|()| self.fit_chunk(*leaf_index, bytes));
+                let actual = match &expected {
+                    Ok(chunk) => self.tree.insert_leaf(*leaf_index, self.leaf(*leaf_index, chunk)),
+                    Err(_) => self.tree.insert_leaf(*leaf_index, self.leaf(0, &[])),
+                };
+                expect_result(&actual, &expected.as_ref().map(|_| ()).map_err(Clone::clone))?;
+                if let Ok(chunk) = expected {
+                    self.replace_chunk(*leaf_index, &chunk);
+                }
+            }
+            TreeOp::BulkInsert { leaf_indices, chunks } => {
+                let expected = self.check_bulk(leaf_indices, chunks.len());
+                let fitted: Vec<Vec<u8>> = match expected {
+                    Ok(()) => leaf_indices.iter().zip(chunks).map(|(&index, bytes)| self.fit_chunk(index, bytes)).collect(),
+                    Err(_) => chunks.clone(),
+                };
+                let leaves = leaf_indices
+                    .iter()
+                    .zip(&fitted)
+                    .map(|(&index, chunk)| self.leaf(index, chunk))
+                    .chain(fitted.iter().skip(leaf_indices.len()).map(|chunk| self.leaf(0, chunk)))
+                    .collect::<Vec<_>>();
+                let actual = self.tree.bulk_insert_leaves(leaf_indices.iter().copied(), leaves.into_iter());
+                expect_result(&actual, &expected)?;
+                if expected.is_ok() {
+                    for (&index, chunk) in leaf_indices.iter().zip(&fitted) {
+                        self.replace_chunk(index, chunk);
+                    }
+                }
+            }
+            TreeOp::Append { bytes } => {
+                self.input.extend_from_slice(bytes);
+                self.tree = BinaryMerkleTree::from_input(&self.input, key_words, flags);
+            }
+            TreeOp::Truncate { len } => {
+                let len = len % (self.input.len() + 1);
+                let kept_chunks = chunk_count(len);
+                for leaf_index in kept_chunks..self.tree.actual_leaves() {
+                    self.tree.remove_leaf(leaf_index).map_err(|error| format!("remove_leaf({}) failed: {}", leaf_index, error))?;
+                }
+                // Even when no chunk is removed, the last kept one may shrink
+                if len < self.input.len() {
+                    self.tree.compact(&self.input).map_err(|error| format!("compact failed: {}", error))?;
+                    self.input.truncate(len);
+                    let last = kept_chunks - 1;
+                    let chunk = self.input[last * CHUNK_LEN..].to_vec();
+                    self.tree
+                        .insert_leaf(last, self.leaf(last, &chunk))
+                        .map_err(|error| format!("insert_leaf({}) after compacting failed: {}", last, error))?;
+                }
+            }
+            TreeOp::ProofRoundTrip { leaf_index } => {
+                let proof = self.tree.generate_proof(*leaf_index);
+                let Some(proof) = proof else {
+                    return match self.check_index(*leaf_index) {
+                        Ok(()) => Err(format!("no proof for leaf {}", leaf_index)),
+                        Err(_) => Ok(()),
+                    };
+                };
+                self.check_index(*leaf_index).map_err(|error| format!("proof for a missing leaf: {}", error))?;
+                let start = leaf_index * CHUNK_LEN;
+                let chunk = &self.input[start..(start + CHUNK_LEN).min(self.input.len())];
+                let decoded = MerkleProof::from_path_bytes(&proof.to_path_bytes(), chunk, key_words, flags);
+                if decoded.as_ref() != Some(&proof) {
+                    return Err(format!("proof decoded as {:?}, expected {:?}", decoded, proof));
+                }
+                if !proof.verify(&self.tree.root_hash().0, key_words, flags) {
+                    return Err(format!("proof for leaf {} does not verify", leaf_index));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The leaf for `chunk` at `leaf_index`, in this model's mode.
+    fn leaf(&self, leaf_index: usize, chunk: &[u8]) -> Output {
+        let (key_words, flags) = mode(self.key);
+        let mut chunk_state = ChunkState::new(key_words, leaf_index as u64, flags);
+        chunk_state.update(&chunk[..chunk.len().min(CHUNK_LEN)]);
+        chunk_state.output()
+    }
+
+    fn leaf_count(&self) -> usize {
+        chunk_count(self.input.len())
+    }
+
+    fn check_index(&self, leaf_index: usize) -> Result<(), MerkleError> {
+        if leaf_index >= self.leaf_count() {
+            return Err(MerkleError::LeafIndexOutOfBounds { leaf_index, actual_leaves: self.leaf_count() });
+        }
+        Ok(())
+    }
+
+    /// The error `bulk_insert_leaves` should return, in the order it checks.
+    fn check_bulk(&self, leaf_indices: &[usize], hashes: usize) -> Result<(), MerkleError> {
+        if leaf_indices.len() != hashes {
+            return Err(MerkleError::LengthMismatch { indices: leaf_indices.len(), hashes });
+        }
+        if leaf_indices.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(MerkleError::UnsortedIndices);
+        }
+        match leaf_indices.last() {
+            Some(&last) => self.check_index(last),
+            None => Ok(()),
+        }
+    }
+
+    fn fit_chunk(&self, leaf_index: usize, bytes: &[u8]) -> Vec<u8> {
+        let mut chunk = bytes[..bytes.len().min(CHUNK_LEN)].to_vec();
+        if leaf_index + 1 < self.leaf_count() {
+            chunk.resize(CHUNK_LEN, 0);
+        } else if chunk.is_empty() && leaf_index > 0 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn replace_chunk(&mut self, leaf_index: usize, chunk: &[u8]) {
+        let start = leaf_index * CHUNK_LEN;
+        let end = (start + CHUNK_LEN).min(self.input.len());
+        self.input.splice(start..end, chunk.iter().copied());
+    }
+
+    /// Check the tree against the shadow input.
+    fn check(&self, op: Option<&TreeOp>) -> Result<(), Divergence> {
+        let mut hasher = match &self.key {
+            Some(key) => Blake3Hasher::new_keyed(key),
+            None => Blake3Hasher::new(),
+        };
+        hasher.update(&self.input);
+        let expected = Hash32(hasher.finalize_array());
+        if self.tree.root_hash() != expected {
+            return Err(self.divergence(op, format!("root is {}, the shadow input hashes to {}", self.tree.root_hash().to_hex(), expected.to_hex())));
+        }
+        if self.tree.actual_leaves() != self.leaf_count() {
+            return Err(self.divergence(
+                op,
+                format!("{} leaves for {} input bytes", self.tree.actual_leaves(), self.input.len()),
+            ));
+        }
+        if !self.tree.verify_integrity() {
+            return Err(self.divergence(op, String::from("internal nodes do not match their children")));
+        }
+        check_serialization(&self.tree).map_err(|what| self.divergence(op, what))
+    }
+
+    fn divergence(&self, op: Option<&TreeOp>, what: String) -> Divergence {
+        Divergence { step: self.steps, op: op.cloned(), what }
+    }
+}
+
+/// Build a model over `input` and apply every op, stopping at the first divergence.
+pub fn check_ops(input: &[u8], key: Option<[u8; KEY_LEN]>, ops: &[TreeOp]) -> Result<ShadowModel, Divergence> {
+    let mut model = ShadowModel::new(input, key)?;
+    for op in ops {
+        model.apply(op)?;
+    }
+    Ok(model)
+}
+
+/// Check that `tree` reloads from `to_bytes` with the same bytes and root.
+pub fn check_serialization(tree: &BinaryMerkleTree) -> Result<(), String> {
+    let bytes = tree.to_bytes();
+    let reloaded = BinaryMerkleTree::from_bytes(&bytes).map_err(|error| format!("from_bytes(to_bytes) failed: {}", error))?;
+    if reloaded.to_bytes() != bytes || reloaded.root_hash() != tree.root_hash() {
+        return Err(String::from("from_bytes(to_bytes) changed the tree"));
+    }
+    Ok(())
+}
+
+/// Check that `pruned` reloads from `to_bytes` unchanged.
+pub fn check_pruned_serialization(pruned: &PrunedTree) -> Result<(), String> {
+    match PrunedTree::from_bytes(&pruned.to_bytes()) {
+        Ok(reloaded) if reloaded == *pruned => Ok(()),
+        Ok(_) => Err(String::from("from_bytes(to_bytes) changed the pruned tree")),
+        Err(error) => Err(format!("from_bytes(to_bytes) failed: {}", error)),
+    }
+}
+
+fn mode(key: Option<[u8; KEY_LEN]>) -> ([u32; 8], u32) {
+    match key {
+        Some(key) => (read_words(&key), KEYED_HASH),
+        None => (IV, FLAGS),
+    }
+}
+
+fn expect_result(actual: &Result<(), MerkleError>, expected: &Result<(), MerkleError>) -> Result<(), String> {
+    if actual != expected {
+        return Err(format!("returned {:?}, expected {:?}", actual, expected));
+    }
+    Ok(())
+}
+
+/// A tree over arbitrary input, keyed or not.
+impl<'a> Arbitrary<'a> for BinaryMerkleTree {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let key: Option<[u8; KEY_LEN]> = u.arbitrary()?;
+        let input: Vec<u8> = u.arbitrary()?;
+        let (key_words, flags) = mode(key);
+        Ok(BinaryMerkleTree::from_input(&input, key_words, flags))
+    }
+}
+
+/// An arbitrary tree pruned to an arbitrary non-empty set of its leaves.
+impl<'a> Arbitrary<'a> for PrunedTree {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let tree: BinaryMerkleTree = u.arbitrary()?;
+        let mut keep = Vec::new();
+        for item in u.arbitrary_iter::<usize>()? {
+            keep.push(item? % tree.actual_leaves());
+        }
+        if keep.is_empty() {
+            keep.push(0);
+        }
+        tree.prune(&keep).map_err(|_| arbitrary::Error::IncorrectFormat)
+    }
+}
@@ -0,0 +1,196 @@
+//! Trees with one leaf per group of chunks, for inputs too large for a leaf per chunk.
+//!
+//! A group of `2^k` chunks starting at a multiple of `2^k` is a complete subtree of
+//! BLAKE3's tree, so its node can stand in for its chunks: the tree over the group
+//! nodes is the top of the tree over the chunks, `2^k` times smaller, with the same
+//! root. Only the last group may be short, and its node is the subtree over the
+//! chunks it has, as in the full tree.
+
+use alloc::vec::Vec;
+use core::cmp::min;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+use crate::binary_merkle_tree::{chunk_count, BinaryMerkleTree, ChunkState, Hash32, Output, CHUNK_LEN};
+use crate::error::MerkleError;
+use crate::proof::{subtree_node, MerkleProof};
+
+/// The node over the chunks of `group_bytes`, the first of which is chunk
+/// `first_chunk` of the whole input.
+fn group_output(group_bytes: &[u8], first_chunk: usize, key_words: [u32; 8], flags: u32) -> Output {
+    let leaves: Vec<Output> = (0..chunk_count(group_bytes.len()))
+        .map(|i| {
+            let mut chunk_state = ChunkState::new(key_words, (first_chunk + i) as u64, flags);
+            chunk_state.update(&group_bytes[i * CHUNK_LEN..min((i + 1) * CHUNK_LEN, group_bytes.len())]);
+            chunk_state.output()
+        })
+        .collect();
+    subtree_node(&leaves, key_words, flags)
+}
+
+/// A BLAKE3 Merkle tree whose leaves are the nodes of groups of `group_chunks`
+/// chunks. Its root hash is the BLAKE3 hash of the input, as for `BinaryMerkleTree`.
+#[derive(Debug, Clone)]
+pub struct GroupedTree {
+    // The tree over the group nodes; its leaf indices are group indices
+    groups: BinaryMerkleTree,
+    group_chunks: usize,
+    input_len: u64,
+}
+
+impl GroupedTree {
+    /// Build a tree over `input` with one leaf per `group_chunks` chunks. Returns
+    /// `MerkleError::InvalidGroupChunks` unless `group_chunks` is a power of two
+    /// whose groups' byte length fits a `usize`; 1 gives the same leaves as
+    /// `BinaryMerkleTree::from_input`.
+    pub fn from_input(input: &[u8], group_chunks: usize, key_words: [u32; 8], flags: u32) -> Result<Self, MerkleError> {
+        check_group_chunks(group_chunks)?;
+        let group_len = group_chunks * CHUNK_LEN;
+        let leaves = if input.is_empty() {
+            Vec::from([group_output(&[], 0, key_words, flags)])
+        } else {
+            input
+                .chunks(group_len)
+                .enumerate()
+                .map(|(group_index, group)| group_output(group, group_index * group_chunks, key_words, flags))
+                .collect()
+        };
+        Ok(Self::from_groups(leaves, group_chunks, input.len() as u64, key_words, flags))
+    }
+
+    fn from_groups(leaves: Vec<Output>, group_chunks: usize, input_len: u64, key_words: [u32; 8], flags: u32) -> Self {
+        GroupedTree {
+            groups: BinaryMerkleTree::new_from_leaves(leaves, key_words, flags),
+            group_chunks,
+            input_len,
+        }
+    }
+
+    /// The root node, with the ROOT flag applied.
+    pub fn root(&self) -> Output {
+        self.groups.root()
+    }
+
+    /// The BLAKE3 hash of the input.
+    pub fn root_hash(&self) -> Hash32 {
+        self.groups.root_hash()
+    }
+
+    /// The number of chunks each leaf covers.
+    pub fn group_chunks(&self) -> usize {
+        self.group_chunks
+    }
+
+    /// The number of groups, and so of leaves.
+    pub fn actual_groups(&self) -> usize {
+        self.groups.actual_leaves()
+    }
+
+    /// The total length of the input.
+    pub fn input_len(&self) -> u64 {
+        self.input_len
+    }
+
+    /// The node of group `group_index`, or `None` if out of bounds.
+    pub fn group(&self, group_index: usize) -> Option<Output> {
+        self.groups.leaf(group_index)
+    }
+
+    /// The proof that group `group_index`'s node is in the tree, with the group as
+    /// its leaf. It verifies against the root hash with `MerkleProof::verify`.
+    pub fn group_proof(&self, group_index: usize) -> Option<MerkleProof> {
+        self.groups.generate_proof(group_index)
+    }
+
+    /// The number of chunks group `group_index` must have: `group_chunks` for every
+    /// group but the last, which has the rest.
+    fn chunks_in_group(&self, group_index: usize) -> usize {
+        let total_chunks = chunk_count(self.input_len as usize);
+        min(self.group_chunks, total_chunks - group_index * self.group_chunks)
+    }
+
+    /// Whether `group_bytes` are the bytes of group `group_index`. Returns false for
+    /// a group that does not exist or bytes of the wrong length.
+    pub fn verify_group(&self, group_index: usize, group_bytes: &[u8]) -> bool {
+        if group_index >= self.actual_groups() || !self.fits_group(group_index, group_bytes.len()) {
+            return false;
+        }
+        let (key_words, flags) = (self.groups.key_words(), self.groups.flags());
+        let node = group_output(group_bytes, group_index * self.group_chunks, key_words, flags);
+        Some(node.chaining_value()) == self.group(group_index).map(|group| group.chaining_value())
+    }
+
+    /// Whether `len` bytes fill group `group_index`: exactly `group_chunks` full
+    /// chunks for every group but the last, and for the last, the number of chunks
+    /// it has now, the final one possibly partial.
+    fn fits_group(&self, group_index: usize, len: usize) -> bool {
+        if group_index + 1 < self.actual_groups() {
+            len == self.group_chunks * CHUNK_LEN
+        } else {
+            chunk_count(len) == self.chunks_in_group(group_index)
+        }
+    }
+
+    /// Replace group `group_index` with the group hashed from `group_bytes` and
+    /// recompute its ancestors. Changing the length of the last group changes
+    /// `input_len`.
+    ///
+    /// Returns `MerkleError::LeafIndexOutOfBounds` for a group that does not exist
+    /// and `MerkleError::GroupLengthMismatch` if `group_bytes` do not fill the group
+    /// (see `verify_group`), leaving the tree unchanged.
+    pub fn insert_group(&mut self, group_index: usize, group_bytes: &[u8]) -> Result<(), MerkleError> {
+        if group_index >= self.actual_groups() {
+            return Err(MerkleError::LeafIndexOutOfBounds {
+                leaf_index: group_index,
+                actual_leaves: self.actual_groups(),
+            });
+        }
+        if !self.fits_group(group_index, group_bytes.len()) {
+            return Err(MerkleError::GroupLengthMismatch { group_index, len: group_bytes.len() });
+        }
+        let (key_words, flags) = (self.groups.key_words(), self.groups.flags());
+        let node = group_output(group_bytes, group_index * self.group_chunks, key_words, flags);
+        self.groups.insert_leaf(group_index, node)?;
+        if group_index + 1 == self.actual_groups() {
+            self.input_len = (group_index * self.group_chunks * CHUNK_LEN + group_bytes.len()) as u64;
+        }
+        Ok(())
+    }
+}
+
+fn check_group_chunks(group_chunks: usize) -> Result<(), MerkleError> {
+    if !group_chunks.is_power_of_two() || group_chunks.checked_mul(CHUNK_LEN).is_none() {
+        return Err(MerkleError::InvalidGroupChunks { group_chunks });
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+impl GroupedTree {
+    /// `from_input` over a reader, holding one group of input at a time. Returns
+    /// `io::ErrorKind::InvalidInput` wrapping `MerkleError::InvalidGroupChunks` for
+    /// a bad `group_chunks`, and the reader's own errors.
+    pub fn from_reader<R: Read>(mut reader: R, group_chunks: usize, key_words: [u32; 8], flags: u32) -> io::Result<Self> {
+        check_group_chunks(group_chunks)?;
+        let group_len = group_chunks * CHUNK_LEN;
+        let mut leaves = Vec::new();
+        let mut input_len = 0;
+        let mut group = Vec::with_capacity(group_len);
+        loop {
+            group.clear();
+            reader.by_ref().take(group_len as u64).read_to_end(&mut group)?;
+            if group.is_empty() {
+                break;
+            }
+            leaves.push(group_output(&group, leaves.len() * group_chunks, key_words, flags));
+            input_len += group.len() as u64;
+            if group.len() < group_len {
+                break;
+            }
+        }
+        if leaves.is_empty() {
+            leaves.push(group_output(&[], 0, key_words, flags));
+        }
+        Ok(Self::from_groups(leaves, group_chunks, input_len, key_words, flags))
+    }
+}
@@ -0,0 +1,871 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+
+use crate::binary_merkle_tree::{
+    check_leaf_count, cv_from_bytes, parent_output, BinaryMerkleTree, Blake3Hasher, ChunkState, Output, TreeLeafBuilder, BLOCK_LEN, CHUNK_LEN,
+    OUTPUT_BYTES_LEN, OUT_LEN, ROOT,
+};
+use crate::le_bytes::{read_words, write_words};
+
+/// What a hashing wrapper does with the bytes passing through it: either fold them
+/// into a single digest, or keep every chunk Output so a tree can be built.
+/// The hasher is boxed because its fixed CV stack dwarfs the leaf builder.
+enum HashingState {
+    Hasher(Box<Blake3Hasher>),
+    Tree(TreeLeafBuilder),
+}
+
+impl HashingState {
+    fn update(&mut self, input: &[u8]) {
+        match self {
+            HashingState::Hasher(hasher) => hasher.update(input),
+            HashingState::Tree(builder) => builder.update(input),
+        }
+    }
+
+    fn finalize(self) -> [u8; OUT_LEN] {
+        match self {
+            HashingState::Hasher(hasher) => hasher.finalize_array(),
+            HashingState::Tree(builder) => builder.finalize_tree().root_hash().0,
+        }
+    }
+
+    fn finalize_tree(self) -> Option<BinaryMerkleTree> {
+        match self {
+            HashingState::Hasher(_) => None,
+            HashingState::Tree(builder) => Some(builder.finalize_tree()),
+        }
+    }
+}
+
+/// A `Read` adapter that hashes every byte it hands out to the caller.
+///
+/// Bytes are hashed exactly as they are returned from the inner reader, so short
+/// reads and empty reads never shift chunk boundaries.
+pub struct HashingReader<R> {
+    inner: R,
+    state: HashingState,
+}
+
+impl<R: Read> HashingReader<R> {
+    /// Wrap `inner`, computing the regular BLAKE3 hash of everything read through it.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            state: HashingState::Hasher(Box::default()),
+        }
+    }
+
+    /// Wrap `inner`, collecting chunk Outputs so `finalize_tree` can return a full
+    /// BinaryMerkleTree of everything read through it.
+    pub fn new_tree(inner: R, key_words: [u32; 8], flags: u32) -> Self {
+        Self {
+            inner,
+            state: HashingState::Tree(TreeLeafBuilder::new(key_words, flags)),
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Return the 32-byte digest of all bytes read so far, along with the inner reader.
+    pub fn finalize(self) -> ([u8; OUT_LEN], R) {
+        (self.state.finalize(), self.inner)
+    }
+
+    /// Return the tree of all bytes read so far, along with the inner reader.
+    /// Returns `None` if the reader was not created with `new_tree`.
+    pub fn finalize_tree(self) -> Option<(BinaryMerkleTree, R)> {
+        let inner = self.inner;
+        self.state.finalize_tree().map(|tree| (tree, inner))
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.state.update(&buf[..bytes_read]);
+        Ok(bytes_read)
+    }
+}
+
+/// A `Write` adapter that hashes every byte the inner writer accepts.
+///
+/// Only the bytes the inner writer reports as written are hashed, so partial writes
+/// keep the digest in sync with what actually reached the destination.
+pub struct HashingWriter<W> {
+    inner: W,
+    state: HashingState,
+}
+
+impl<W: Write> HashingWriter<W> {
+    /// Wrap `inner`, computing the regular BLAKE3 hash of everything written through it.
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            state: HashingState::Hasher(Box::default()),
+        }
+    }
+
+    /// Wrap `inner`, collecting chunk Outputs so `finalize_tree` can return a full
+    /// BinaryMerkleTree of everything written through it.
+    pub fn new_tree(inner: W, key_words: [u32; 8], flags: u32) -> Self {
+        Self {
+            inner,
+            state: HashingState::Tree(TreeLeafBuilder::new(key_words, flags)),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Return the 32-byte digest of all bytes written so far, along with the inner writer.
+    pub fn finalize(self) -> ([u8; OUT_LEN], W) {
+        (self.state.finalize(), self.inner)
+    }
+
+    /// Return the tree of all bytes written so far, along with the inner writer.
+    /// Returns `None` if the writer was not created with `new_tree`.
+    pub fn finalize_tree(self) -> Option<(BinaryMerkleTree, W)> {
+        let inner = self.inner;
+        self.state.finalize_tree().map(|tree| (tree, inner))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes_written = self.inner.write(buf)?;
+        self.state.update(&buf[..bytes_written]);
+        Ok(bytes_written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A `Write` sink that builds a full BinaryMerkleTree from everything written to it.
+///
+/// Unlike `Blake3Hasher`, which collapses subtrees as it goes, this keeps the Output of
+/// every completed chunk, so memory grows with the number of leaves rather than with
+/// the number of bytes written.
+#[derive(Debug, Clone)]
+pub struct StreamingTreeBuilder {
+    leaves: TreeLeafBuilder,
+}
+
+impl StreamingTreeBuilder {
+    pub fn new(key_words: [u32; 8], flags: u32) -> Self {
+        Self {
+            leaves: TreeLeafBuilder::new(key_words, flags),
+        }
+    }
+
+    /// Build the tree over all bytes written so far.
+    pub fn finalize(self) -> BinaryMerkleTree {
+        self.leaves.finalize_tree()
+    }
+}
+
+impl Write for StreamingTreeBuilder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.leaves.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Read from `reader` until `buf` is full or EOF, retrying interrupted reads.
+/// Returns the number of bytes read.
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Read chunk `chunk_index` from `reader` into `buf` (at least `CHUNK_LEN` long) and
+/// verify it against `tree`, returning its length. Whatever follows the final chunk
+/// is left unread.
+fn read_chunk_unbounded<R: Read>(
+    tree: &BinaryMerkleTree,
+    reader: &mut R,
+    buf: &mut [u8],
+    chunk_index: usize,
+) -> io::Result<usize> {
+    let chunk_len = read_up_to(reader, &mut buf[..CHUNK_LEN])?;
+    if chunk_len < CHUNK_LEN && chunk_index + 1 != tree.actual_leaves() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("input ended inside chunk {} of {}", chunk_index, tree.actual_leaves()),
+        ));
+    }
+    if !tree.verify_chunk(chunk_index, &buf[..chunk_len]) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("chunk {} does not match the tree", chunk_index),
+        ));
+    }
+    Ok(chunk_len)
+}
+
+/// `read_chunk_unbounded`, also checking that the reader has nothing left after the
+/// final chunk.
+fn read_verified_chunk<R: Read>(
+    tree: &BinaryMerkleTree,
+    reader: &mut R,
+    buf: &mut [u8],
+    chunk_index: usize,
+) -> io::Result<usize> {
+    let chunk_len = read_chunk_unbounded(tree, reader, buf, chunk_index)?;
+    if chunk_index + 1 == tree.actual_leaves() && read_up_to(reader, &mut [0u8; 1])? != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("input continues past the final chunk {}", chunk_index),
+        ));
+    }
+    Ok(chunk_len)
+}
+
+/// A `Read` adapter that only releases data after it has been verified against a
+/// trusted tree.
+///
+/// The inner reader is consumed one chunk at a time; each chunk is checked against
+/// its stored leaf before any of its bytes reach the caller. A corrupted chunk is
+/// reported as `io::ErrorKind::InvalidData`, a stream that ends early as
+/// `io::ErrorKind::UnexpectedEof`, and data past the final chunk as `InvalidData`.
+pub struct VerifiedReader<'a, R> {
+    tree: &'a BinaryMerkleTree,
+    inner: R,
+    chunk: Vec<u8>,
+    chunk_len: usize,
+    position: usize,
+    next_chunk_index: usize,
+    failed: bool,
+}
+
+impl<'a, R: Read> VerifiedReader<'a, R> {
+    pub fn new(tree: &'a BinaryMerkleTree, inner: R) -> Self {
+        Self {
+            tree,
+            inner,
+            chunk: vec![0; CHUNK_LEN],
+            chunk_len: 0,
+            position: 0,
+            next_chunk_index: 0,
+            failed: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read and verify the next chunk into the internal buffer.
+    fn fill_chunk(&mut self) -> io::Result<()> {
+        let chunk_len = read_verified_chunk(self.tree, &mut self.inner, &mut self.chunk, self.next_chunk_index)?;
+        self.chunk_len = chunk_len;
+        self.position = 0;
+        self.next_chunk_index += 1;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for VerifiedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // The inner reader is mid-chunk after a failure, so never resume from it
+        if self.failed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("verification already failed at chunk {}", self.next_chunk_index),
+            ));
+        }
+        // Only pull the next chunk once the current one has been fully released
+        while self.position == self.chunk_len {
+            if self.next_chunk_index == self.tree.actual_leaves() {
+                return Ok(0);
+            }
+            if let Err(e) = self.fill_chunk() {
+                self.failed = true;
+                return Err(e);
+            }
+        }
+        let take = (self.chunk_len - self.position).min(buf.len());
+        buf[..take].copy_from_slice(&self.chunk[self.position..self.position + take]);
+        self.position += take;
+        Ok(take)
+    }
+}
+
+/// A `Write` adapter that only passes data on to its sink after it has been
+/// verified against a trusted tree.
+///
+/// Incoming bytes are accumulated one chunk at a time; a chunk reaches the sink
+/// only once it matches its stored leaf, so a corrupted chunk (and everything after
+/// it) is never written. Call `finish` at the end of the stream to verify the final
+/// chunk and confirm that no chunks are missing.
+pub struct VerifiedWriter<'a, W: Write> {
+    tree: &'a BinaryMerkleTree,
+    sink: W,
+    chunk: Vec<u8>,
+    chunk_index: usize,
+    // Bytes of a verified chunk already handed to the sink, if one is pending
+    pending_written: Option<usize>,
+    failed: bool,
+}
+
+impl<'a, W: Write> VerifiedWriter<'a, W> {
+    pub fn new(tree: &'a BinaryMerkleTree, sink: W) -> Self {
+        Self {
+            tree,
+            sink,
+            chunk: Vec::with_capacity(CHUNK_LEN),
+            chunk_index: 0,
+            pending_written: None,
+            failed: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.sink
+    }
+
+    /// Verify the buffered chunk and queue it for the sink, poisoning the writer on
+    /// a mismatch so nothing further is ever written.
+    fn verify_chunk(&mut self) -> io::Result<()> {
+        if !self.tree.verify_chunk(self.chunk_index, &self.chunk) {
+            self.failed = true;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("chunk {} does not match the tree", self.chunk_index),
+            ));
+        }
+        self.pending_written = Some(0);
+        Ok(())
+    }
+
+    /// Hand the pending verified chunk to the sink. On a sink error the progress is
+    /// kept, so a later call resumes where the sink stopped instead of rewriting.
+    fn drain_pending(&mut self) -> io::Result<()> {
+        while let Some(written) = self.pending_written {
+            if written == self.chunk.len() {
+                self.pending_written = None;
+                self.chunk.clear();
+                self.chunk_index += 1;
+                break;
+            }
+            match self.sink.write(&self.chunk[written..]) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        format!("sink stopped accepting data in chunk {}", self.chunk_index),
+                    ))
+                }
+                Ok(n) => self.pending_written = Some(written + n),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn check_not_failed(&self) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("verification already failed at chunk {}", self.chunk_index),
+            ));
+        }
+        Ok(())
+    }
+
+    /// End the stream: verify and write the final chunk, and fail with
+    /// `io::ErrorKind::UnexpectedEof` if the stream stopped before the last chunk.
+    /// Returns the sink once everything has been verified and flushed.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.check_not_failed()?;
+        self.drain_pending()?;
+        let total_chunks = self.tree.actual_leaves();
+        if self.chunk_index + 1 < total_chunks {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "stream ended in chunk {}; chunks {}..{} are missing",
+                    self.chunk_index, self.chunk_index, total_chunks
+                ),
+            ));
+        }
+        if self.chunk_index + 1 == total_chunks {
+            self.verify_chunk()?;
+            self.drain_pending()?;
+        }
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+}
+
+impl<W: Write> Write for VerifiedWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_not_failed()?;
+        self.drain_pending()?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.chunk_index >= self.tree.actual_leaves() {
+            self.failed = true;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream continues past the final chunk",
+            ));
+        }
+
+        let take = (CHUNK_LEN - self.chunk.len()).min(buf.len());
+        self.chunk.extend_from_slice(&buf[..take]);
+        if self.chunk.len() == CHUNK_LEN {
+            self.verify_chunk()?;
+            // The bytes are accepted either way; a sink error resurfaces on the next call
+            let _ = self.drain_pending();
+        }
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.check_not_failed()?;
+        self.drain_pending()?;
+        self.sink.flush()
+    }
+}
+
+/// Why `copy_verified` stopped, and how far it got.
+///
+/// Everything before `chunk_index` was verified and written to the destination;
+/// `bytes_written` counts exactly those bytes. From
+/// `DownloadVerifier::submit_chunk`, where chunks arrive in any order, it counts
+/// the bytes of every chunk accepted so far instead, and from
+/// `BinaryMerkleTree::verify_regions`, which writes nothing, the bytes of the region
+/// that verified.
+#[derive(Debug)]
+pub struct VerifyError {
+    /// The chunk that failed verification, or whose read or write failed.
+    pub chunk_index: usize,
+    /// Bytes of verified chunks already written to the destination.
+    pub bytes_written: u64,
+    /// The underlying cause: `InvalidData` for a mismatched chunk or trailing data,
+    /// `UnexpectedEof` for a truncated source, or the source or destination's own error.
+    pub error: io::Error,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "verified copy stopped at chunk {} after {} bytes: {}",
+            self.chunk_index, self.bytes_written, self.error
+        )
+    }
+}
+
+impl std::error::Error for VerifyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<VerifyError> for io::Error {
+    fn from(e: VerifyError) -> Self {
+        io::Error::new(e.error.kind(), e)
+    }
+}
+
+/// Copy `src` to `dst` one chunk at a time, verifying each chunk against `tree`
+/// before any of it is written. Returns the number of bytes copied.
+///
+/// Only a single chunk-sized buffer is allocated. On failure `dst` holds exactly the
+/// chunks before the failing one, as reported by the returned `VerifyError`.
+pub fn copy_verified<R: Read, W: Write>(
+    mut src: R,
+    mut dst: W,
+    tree: &BinaryMerkleTree,
+) -> Result<u64, VerifyError> {
+    let mut chunk = vec![0u8; CHUNK_LEN];
+    let mut bytes_written = 0u64;
+    for chunk_index in 0..tree.actual_leaves() {
+        let result = read_verified_chunk(tree, &mut src, &mut chunk, chunk_index)
+            .and_then(|chunk_len| dst.write_all(&chunk[..chunk_len]).map(|_| chunk_len));
+        match result {
+            Ok(chunk_len) => bytes_written += chunk_len as u64,
+            Err(error) => return Err(VerifyError { chunk_index, bytes_written, error }),
+        }
+    }
+    Ok(bytes_written)
+}
+
+impl BinaryMerkleTree {
+    /// Verify byte ranges of an input, such as the members listed in an archive's
+    /// offset table, reading only the chunks they cover. Each region is an
+    /// `(offset, len)` pair; the result for each is `Ok` if every chunk it touches
+    /// verifies.
+    ///
+    /// The covered chunks are read in ascending order, each at most once however many
+    /// regions share it, and runs of adjacent chunks are read without seeking in
+    /// between. An empty region reads nothing and always verifies. A failed region's
+    /// `VerifyError` names the first of its chunks that failed, with `bytes_written`
+    /// the number of the region's bytes that verified before it. A region reaching
+    /// past the end of the input fails with `io::ErrorKind::UnexpectedEof` at the
+    /// final chunk.
+    pub fn verify_regions<R: Read + Seek>(&self, mut input_source: R, regions: &[(u64, u64)]) -> Vec<Result<(), VerifyError>> {
+        let chunk_ranges: Vec<_> = regions.iter().map(|&(offset, len)| region_chunks(offset, len)).collect();
+        let needed: BTreeSet<usize> = chunk_ranges
+            .iter()
+            .flat_map(|chunks| chunks.start..chunks.end.min(self.actual_leaves()))
+            .collect();
+
+        // Each needed chunk's length once verified, or why it failed
+        let mut chunk_results = BTreeMap::new();
+        let mut chunk = vec![0u8; CHUNK_LEN];
+        let mut position = None;
+        for chunk_index in needed {
+            let chunk_start = (chunk_index * CHUNK_LEN) as u64;
+            let result = match position {
+                Some(position) if position == chunk_start => Ok(()),
+                _ => input_source.seek(SeekFrom::Start(chunk_start)).map(drop),
+            }
+            .and_then(|_| read_chunk_unbounded(self, &mut input_source, &mut chunk, chunk_index));
+            position = result.as_ref().ok().map(|&chunk_len| chunk_start + chunk_len as u64);
+            chunk_results.insert(chunk_index, result);
+        }
+
+        let last_chunk = self.actual_leaves() - 1;
+        regions
+            .iter()
+            .zip(chunk_ranges)
+            .map(|(&(offset, len), chunks)| {
+                let fail = |chunk_index: usize, verified_end: u64, error| VerifyError {
+                    chunk_index,
+                    bytes_written: verified_end.saturating_sub(offset),
+                    error,
+                };
+                for chunk_index in chunks.start..chunks.end.min(last_chunk + 1) {
+                    if let Err(error) = &chunk_results[&chunk_index] {
+                        let error = io::Error::new(error.kind(), error.to_string());
+                        return Err(fail(chunk_index, (chunk_index * CHUNK_LEN) as u64, error));
+                    }
+                }
+                if chunks.end > last_chunk {
+                    // The final chunk was read and verified if the region starts at
+                    // or before it; otherwise the region starts past its end anyway
+                    let input_end = match chunk_results.get(&last_chunk) {
+                        Some(Ok(chunk_len)) => (last_chunk * CHUNK_LEN + chunk_len) as u64,
+                        _ => ((last_chunk + 1) * CHUNK_LEN) as u64,
+                    };
+                    let end = offset.saturating_add(len);
+                    if end > input_end {
+                        let message = format!("region {}..{} runs past the end of the input at {}", offset, end, input_end);
+                        return Err(fail(last_chunk, input_end, io::Error::new(io::ErrorKind::UnexpectedEof, message)));
+                    }
+                }
+                Ok(())
+            })
+            .collect()
+    }
+}
+
+/// The chunks covering `len` bytes at `offset`, none for an empty region.
+fn region_chunks(offset: u64, len: u64) -> core::ops::Range<usize> {
+    if len == 0 {
+        return 0..0;
+    }
+    let chunk_len = CHUNK_LEN as u64;
+    let end = offset.saturating_add(len);
+    let to_index = |chunk: u64| usize::try_from(chunk).unwrap_or(usize::MAX);
+    to_index(offset / chunk_len)..to_index(end.div_ceil(chunk_len))
+}
+
+// =============================================
+// Outboard encoding
+// =============================================
+// An outboard holds every node of the tree, in post-order (left subtree, right
+// subtree, parent), without the input bytes themselves. The shape of the tree, and
+// therefore which record comes next, is fully determined by the input length.
+//
+// Leaf record (`LEAF_RECORD_LEN` bytes): the chunk's Output, i.e. the input chaining
+// value (8 words), the last block's words (16 words), all little-endian u32s,
+// followed by the counter (u64 LE), block_len (u32 LE) and flags (u32 LE).
+// Parent record (`OUT_LEN` bytes): the parent's non-root chaining value as 8 LE words.
+
+const LEAF_RECORD_LEN: usize = OUTPUT_BYTES_LEN;
+
+fn write_leaf_record<W: Write>(out: &mut W, leaf: &Output) -> io::Result<()> {
+    out.write_all(&leaf.to_bytes())
+}
+
+fn read_leaf_record<R: Read>(reader: &mut R) -> io::Result<Output> {
+    let mut record = [0u8; LEAF_RECORD_LEN];
+    reader.read_exact(&mut record)?;
+    Output::try_from(&record[..]).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn read_parent_record<R: Read>(reader: &mut R) -> io::Result<[u32; 8]> {
+    let mut record = [0u8; OUT_LEN];
+    reader.read_exact(&mut record)?;
+    Ok(cv_from_bytes(record))
+}
+
+/// Stream `input` and write the outboard of its tree to `out` as the nodes are
+/// computed, returning the root hash. See the outboard encoding notes above for the
+/// record layout and order.
+///
+/// Like `Blake3Hasher`, only the current chunk and a stack of one chaining value per
+/// tree level are kept, so memory stays O(log n) however large the input is.
+pub fn write_outboard<R: Read, W: Write>(
+    mut input: R,
+    mut out: W,
+    key_words: [u32; 8],
+    flags: u32,
+) -> io::Result<[u8; OUT_LEN]> {
+    let mut chunk_state = ChunkState::new(key_words, 0, flags);
+    let mut cv_stack: Vec<[u32; 8]> = Vec::new();
+    let mut buffer = vec![0u8; CHUNK_LEN];
+    loop {
+        let bytes_read = read_up_to(&mut input, &mut buffer)?;
+        let mut remaining = &buffer[..bytes_read];
+        while !remaining.is_empty() {
+            // A full chunk followed by more input is final and not the root: emit it,
+            // then every parent it completes, exactly as Blake3Hasher merges them.
+            if chunk_state.len() == CHUNK_LEN {
+                let leaf = chunk_state.output();
+                write_leaf_record(&mut out, &leaf)?;
+                let mut new_cv = leaf.chaining_value();
+                let mut total_chunks = chunk_state.chunk_counter + 1;
+                while total_chunks & 1 == 0 {
+                    let parent = parent_output(cv_stack.pop().unwrap(), new_cv, key_words, flags);
+                    new_cv = parent.chaining_value();
+                    write_words(&mut out, &new_cv)?;
+                    total_chunks >>= 1;
+                }
+                cv_stack.push(new_cv);
+                chunk_state = ChunkState::new(key_words, chunk_state.chunk_counter + 1, flags);
+            }
+            let take = (CHUNK_LEN - chunk_state.len()).min(remaining.len());
+            chunk_state.update(&remaining[..take]);
+            remaining = &remaining[take..];
+        }
+        if bytes_read < buffer.len() {
+            break;
+        }
+    }
+
+    // The last chunk, then the parents along the right edge from the bottom up
+    let mut output = chunk_state.output();
+    write_leaf_record(&mut out, &output)?;
+    while let Some(left_cv) = cv_stack.pop() {
+        output = parent_output(left_cv, output.chaining_value(), key_words, flags);
+        write_words(&mut out, &output.chaining_value())?;
+    }
+    out.flush()?;
+
+    let mut root = output;
+    root.flags |= ROOT;
+    let mut hash = [0; OUT_LEN];
+    root.root_output_bytes(&mut hash);
+    Ok(hash)
+}
+
+/// The `block_len` of the last block in a chunk holding `chunk_len` bytes.
+fn last_block_len(chunk_len: usize) -> u32 {
+    match chunk_len % BLOCK_LEN {
+        0 if chunk_len > 0 => BLOCK_LEN as u32,
+        partial => partial as u32,
+    }
+}
+
+/// Read the outboard of the subtree covering `chunk_count` chunks starting at
+/// `first_chunk`, appending its leaves and returning its chaining value. Every
+/// stored parent is checked against the one recomputed from its children.
+fn read_outboard_subtree<R: Read>(
+    reader: &mut R,
+    first_chunk: u64,
+    chunk_count: u64,
+    key_words: [u32; 8],
+    flags: u32,
+    leaves: &mut Vec<Output>,
+) -> io::Result<[u32; 8]> {
+    if chunk_count == 1 {
+        let leaf = read_leaf_record(reader)?;
+        if leaf.counter != first_chunk {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("leaf record for chunk {} has counter {}", first_chunk, leaf.counter),
+            ));
+        }
+        leaves.push(leaf);
+        return Ok(leaf.chaining_value());
+    }
+    // As in BLAKE3, the left subtree holds the largest power of two chunks below chunk_count
+    let left_count = (chunk_count / 2 + chunk_count % 2).next_power_of_two();
+    let left_cv = read_outboard_subtree(reader, first_chunk, left_count, key_words, flags, leaves)?;
+    let right_cv = read_outboard_subtree(
+        reader,
+        first_chunk + left_count,
+        chunk_count - left_count,
+        key_words,
+        flags,
+        leaves,
+    )?;
+    let stored_cv = read_parent_record(reader)?;
+    let parent_cv = parent_output(left_cv, right_cv, key_words, flags).chaining_value();
+    if stored_cv != parent_cv {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("parent of chunks {}..{} does not match its children", first_chunk, first_chunk + chunk_count),
+        ));
+    }
+    Ok(parent_cv)
+}
+
+impl BinaryMerkleTree {
+    /// Rebuild the tree of an `input_len`-byte input from an outboard written by
+    /// `write_outboard` with the same `key_words` and `flags`. The input itself is not
+    /// needed. Returns `io::ErrorKind::InvalidData` if a stored parent disagrees with
+    /// its children or the final leaf does not fit `input_len`, and
+    /// `io::ErrorKind::InvalidInput` wrapping `MerkleError::TooManyChunks` if
+    /// `input_len` has more chunks than a tree can hold.
+    pub fn from_outboard<R: Read>(mut reader: R, input_len: u64, key_words: [u32; 8], flags: u32) -> io::Result<Self> {
+        let chunk_count = input_len.div_ceil(CHUNK_LEN as u64).max(1);
+        check_leaf_count(chunk_count)?;
+        // Grown as records are read, so a false `input_len` costs no more than the
+        // outboard actually holds
+        let mut leaves = Vec::new();
+        read_outboard_subtree(&mut reader, 0, chunk_count, key_words, flags, &mut leaves)?;
+
+        let final_chunk_len = (input_len - (chunk_count - 1) * CHUNK_LEN as u64) as usize;
+        let final_leaf = leaves[leaves.len() - 1];
+        if final_leaf.counter != chunk_count - 1
+            || !final_leaf.is_chunk_end()
+            || final_leaf.block_len != last_block_len(final_chunk_len)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("final leaf does not match an input of {} bytes", input_len),
+            ));
+        }
+        Ok(Self::new_from_leaves(leaves, key_words, flags).with_input_len(input_len))
+    }
+}
+
+// =============================================
+// Two-pass hashing for inputs larger than memory
+// =============================================
+
+// Chaining values read from scratch per step of the second pass (an even number,
+// so a window never splits a pair of siblings).
+const HUGE_WINDOW_CVS: usize = 4096;
+
+/// Hash an input too large to hold even its leaf Outputs in memory, using `scratch`
+/// (typically a temporary file) for the intermediate levels of the tree.
+///
+/// Pass one streams `input` and writes the chaining value of every chunk to
+/// `scratch`. Pass two reads each level back in bounded windows and appends the
+/// level above it, promoting a lone last node, until only the root is left. Memory
+/// use is a fixed window regardless of input size. When this returns, `scratch`
+/// holds every level from the leaves up, each as consecutive 32-byte LE chaining
+/// values, with the two children of the root last. The levels start at the
+/// position `scratch` is at when this is called, so anything before it is kept.
+pub fn hash_huge<R: Read, S: Read + Write + Seek>(
+    mut input: R,
+    mut scratch: S,
+    key_words: [u32; 8],
+    flags: u32,
+) -> io::Result<[u8; OUT_LEN]> {
+    // Pass one: chunk CVs. The final chunk is kept as an Output, since it is the
+    // root when the input is a single chunk.
+    let mut chunk_state = ChunkState::new(key_words, 0, flags);
+    let mut leaf_count = 0u64;
+    let scratch_start = scratch.stream_position()?;
+    {
+        let mut leaf_writer = BufWriter::new(&mut scratch);
+        let mut buffer = vec![0u8; HUGE_WINDOW_CVS * OUT_LEN];
+        loop {
+            let bytes_read = read_up_to(&mut input, &mut buffer)?;
+            let mut remaining = &buffer[..bytes_read];
+            while !remaining.is_empty() {
+                if chunk_state.len() == CHUNK_LEN {
+                    write_words(&mut leaf_writer, &chunk_state.output().chaining_value())?;
+                    leaf_count += 1;
+                    chunk_state = ChunkState::new(key_words, leaf_count, flags);
+                }
+                let take = (CHUNK_LEN - chunk_state.len()).min(remaining.len());
+                chunk_state.update(&remaining[..take]);
+                remaining = &remaining[take..];
+            }
+            if bytes_read < buffer.len() {
+                break;
+            }
+        }
+        if leaf_count > 0 {
+            write_words(&mut leaf_writer, &chunk_state.output().chaining_value())?;
+            leaf_count += 1;
+        }
+        leaf_writer.flush()?;
+    }
+
+    let mut root = if leaf_count == 0 {
+        chunk_state.output()
+    } else {
+        // Pass two: build each parent level from the one below it
+        let mut level_start = 0u64;
+        let mut level_len = leaf_count;
+        let mut window = vec![0u8; HUGE_WINDOW_CVS * OUT_LEN];
+        let mut parents = Vec::with_capacity(HUGE_WINDOW_CVS / 2 * OUT_LEN);
+        while level_len > 2 {
+            let mut write_pos = level_start + level_len;
+            for window_start in (0..level_len).step_by(HUGE_WINDOW_CVS) {
+                let window_len = (level_len - window_start).min(HUGE_WINDOW_CVS as u64) as usize;
+                scratch.seek(SeekFrom::Start(scratch_start + (level_start + window_start) * OUT_LEN as u64))?;
+                scratch.read_exact(&mut window[..window_len * OUT_LEN])?;
+
+                parents.clear();
+                for pair in window[..window_len * OUT_LEN].chunks(2 * OUT_LEN) {
+                    if pair.len() == 2 * OUT_LEN {
+                        let parent = parent_output(read_words(&pair[..OUT_LEN]), read_words(&pair[OUT_LEN..]), key_words, flags);
+                        write_words(&mut parents, &parent.chaining_value())?;
+                    } else {
+                        // A lone last node is promoted unchanged
+                        parents.extend_from_slice(pair);
+                    }
+                }
+                scratch.seek(SeekFrom::Start(scratch_start + write_pos * OUT_LEN as u64))?;
+                scratch.write_all(&parents)?;
+                write_pos += (parents.len() / OUT_LEN) as u64;
+            }
+            level_start += level_len;
+            level_len = level_len.div_ceil(2);
+        }
+
+        let mut children = [0u8; 2 * OUT_LEN];
+        scratch.seek(SeekFrom::Start(scratch_start + level_start * OUT_LEN as u64))?;
+        scratch.read_exact(&mut children)?;
+        scratch.flush()?;
+        parent_output(read_words(&children[..OUT_LEN]), read_words(&children[OUT_LEN..]), key_words, flags)
+    };
+
+    root.flags |= ROOT;
+    let mut hash = [0; OUT_LEN];
+    root.root_output_bytes(&mut hash);
+    Ok(hash)
+}
@@ -0,0 +1,147 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, ChunkState, Hash32, Output, CHUNK_LEN, OUT_LEN, PARENT, ROOT};
+use crate::error::MerkleError;
+
+/// The largest supported fanout: the children's chaining values must fit in one chunk.
+pub const MAX_FANOUT: usize = CHUNK_LEN / OUT_LEN;
+
+/// Hash up to `MAX_FANOUT` child chaining values into a k-ary parent node.
+///
+/// The concatenated child CVs are compressed like the blocks of a chunk, with the
+/// PARENT flag added on top of the chunk flags. BLAKE3 never combines PARENT with
+/// CHUNK_START or CHUNK_END, so these nodes are domain-separated from every BLAKE3
+/// node. The number of children is committed to through the message length.
+///
+/// Returns `MerkleError::InvalidFanout` unless there are 2 to `MAX_FANOUT` children.
+pub fn kary_parent_output(child_cvs: &[[u32; 8]], key_words: [u32; 8], flags: u32) -> Result<Output, MerkleError> {
+    if !(2..=MAX_FANOUT).contains(&child_cvs.len()) {
+        return Err(MerkleError::InvalidFanout { fanout: child_cvs.len() });
+    }
+    Ok(parent_node(child_cvs, key_words, flags))
+}
+
+/// `kary_parent_output` for a child count already known to be valid.
+fn parent_node(child_cvs: &[[u32; 8]], key_words: [u32; 8], flags: u32) -> Output {
+    let mut chunk_state = ChunkState::new(key_words, 0, flags | PARENT);
+    for cv in child_cvs {
+        for word in cv {
+            chunk_state.update(&word.to_le_bytes());
+        }
+    }
+    chunk_state.output()
+}
+
+/// A Merkle tree whose parents hash up to `fanout` children, for shallower trees
+/// than `BinaryMerkleTree`.
+///
+/// Leaves are ordinary BLAKE3 chunk Outputs, but parents use `kary_parent_output`, so
+/// the root is NOT the BLAKE3 hash of the input, even for a fanout of 2. As in the
+/// binary tree, a lone last node at any level is promoted unchanged; a shorter final
+/// group of two or more children is hashed as a parent of that many children.
+#[derive(Debug, Clone)]
+pub struct KaryMerkleTree {
+    // levels[0] holds the leaves and the last level holds only the root node
+    levels: Vec<Vec<Output>>,
+    fanout: usize,
+    key_words: [u32; 8],
+    flags: u32,
+}
+
+impl KaryMerkleTree {
+    /// Build a tree over `leaves`. Returns `MerkleError::NoLeaves` if there are no
+    /// leaves, or `MerkleError::InvalidFanout` if `fanout` is not between 2 and
+    /// `MAX_FANOUT`.
+    pub fn new_from_leaves(leaves: Vec<Output>, fanout: usize, key_words: [u32; 8], flags: u32) -> Result<Self, MerkleError> {
+        if !(2..=MAX_FANOUT).contains(&fanout) {
+            return Err(MerkleError::InvalidFanout { fanout });
+        }
+        if leaves.is_empty() {
+            return Err(MerkleError::NoLeaves);
+        }
+
+        let mut tree = KaryMerkleTree {
+            levels: vec![leaves],
+            fanout,
+            key_words,
+            flags,
+        };
+        while tree.levels[tree.levels.len() - 1].len() > 1 {
+            let level = tree.levels.len() - 1;
+            let parents = (0..tree.levels[level].len().div_ceil(fanout))
+                .map(|parent_index| tree.compute_parent(level, parent_index))
+                .collect();
+            tree.levels.push(parents);
+        }
+        Ok(tree)
+    }
+
+    /// Build a tree directly from raw bytes, split into chunks as for `BinaryMerkleTree`.
+    /// Returns `MerkleError::InvalidFanout` for a fanout outside 2..=`MAX_FANOUT`.
+    pub fn from_input(input: &[u8], fanout: usize, key_words: [u32; 8], flags: u32) -> Result<Self, MerkleError> {
+        let leaves = BinaryMerkleTree::process_input_to_chunks(input, key_words, flags);
+        Self::new_from_leaves(leaves, fanout, key_words, flags)
+    }
+
+    /// The node at `parent_index` of level `level + 1`, from its children in `level`.
+    fn compute_parent(&self, level: usize, parent_index: usize) -> Output {
+        let children = &self.levels[level];
+        let first_child = parent_index * self.fanout;
+        let group = &children[first_child..(first_child + self.fanout).min(children.len())];
+        if group.len() == 1 {
+            return group[0];
+        }
+        let child_cvs: Vec<[u32; 8]> = group.iter().map(Output::chaining_value).collect();
+        parent_node(&child_cvs, self.key_words, self.flags)
+    }
+
+    pub fn root(&self) -> Output {
+        let mut root = self.levels[self.levels.len() - 1][0];
+        root.flags |= ROOT;
+        root
+    }
+
+    pub fn root_hash(&self) -> Hash32 {
+        let mut hash = [0; OUT_LEN];
+        self.root().root_output_bytes(&mut hash);
+        Hash32(hash)
+    }
+
+    pub fn fanout(&self) -> usize {
+        self.fanout
+    }
+
+    pub fn actual_leaves(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// The number of levels, including the leaves and the root.
+    pub fn depth(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn leaf(&self, leaf_index: usize) -> Option<Output> {
+        self.levels[0].get(leaf_index).copied()
+    }
+
+    /// Replace one leaf and recompute its ancestors, one parent per level. Returns
+    /// `MerkleError::LeafIndexOutOfBounds`, leaving the tree unchanged, if there is
+    /// no such leaf.
+    pub fn insert_leaf(&mut self, leaf_index: usize, leaf_output: Output) -> Result<(), MerkleError> {
+        if leaf_index >= self.actual_leaves() {
+            return Err(MerkleError::LeafIndexOutOfBounds {
+                leaf_index,
+                actual_leaves: self.actual_leaves(),
+            });
+        }
+        self.levels[0][leaf_index] = leaf_output;
+
+        let mut node_index = leaf_index;
+        for level in 0..self.levels.len() - 1 {
+            node_index /= self.fanout;
+            self.levels[level + 1][node_index] = self.compute_parent(level, node_index);
+        }
+        Ok(())
+    }
+}
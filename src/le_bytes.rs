@@ -0,0 +1,59 @@
+//! Little-endian conversions of word arrays for every byte format the crate
+//! reads or writes: `Output::to_bytes`, the serialized tree, the outboard and bao
+//! encodings, and serde's hex strings. These formats are little-endian on every
+//! host, so a file written on x86_64 loads unchanged on a big-endian target.
+//!
+//! Word arrays are only converted here, one `to_le_bytes`/`from_le_bytes` per
+//! word, and never reinterpreted in place: the crate denies `unsafe_code`, which
+//! rules out transmutes and pointer casts between `[u32; N]` and bytes. Scalar
+//! fields call `to_le_bytes`/`from_le_bytes` directly where they are encoded.
+//! Chaining values go through the public `cv_to_bytes` and `cv_from_bytes`, which
+//! call these.
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+/// Write `words` into the first `4 * words.len()` bytes of `out`.
+#[inline]
+pub(crate) fn words_into(words: &[u32], out: &mut [u8]) {
+    assert!(out.len() >= 4 * words.len(), "{} bytes cannot hold {} words", out.len(), words.len());
+    for (word, dest) in words.iter().zip(out.chunks_exact_mut(4)) {
+        dest.copy_from_slice(&word.to_le_bytes());
+    }
+}
+
+/// Fill `words` from the first `4 * words.len()` bytes of `bytes`.
+#[inline]
+pub(crate) fn words_from(bytes: &[u8], words: &mut [u32]) {
+    assert!(bytes.len() >= 4 * words.len(), "{} bytes cannot fill {} words", bytes.len(), words.len());
+    for (word, src) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes(src.try_into().unwrap());
+    }
+}
+
+/// Read `N` words from the first `4 * N` bytes of `bytes`.
+#[inline]
+pub(crate) fn read_words<const N: usize>(bytes: &[u8]) -> [u32; N] {
+    let mut words = [0; N];
+    words_from(bytes, &mut words);
+    words
+}
+
+/// Append `words` to `encoded`.
+pub(crate) fn push_words(encoded: &mut Vec<u8>, words: &[u32]) {
+    let start = encoded.len();
+    encoded.resize(start + 4 * words.len(), 0);
+    words_into(words, &mut encoded[start..]);
+}
+
+/// Write `words` to `out`.
+#[cfg(feature = "std")]
+pub(crate) fn write_words<W: Write>(out: &mut W, words: &[u32]) -> io::Result<()> {
+    let mut bytes = [0u8; 16 * 4];
+    for batch in words.chunks(16) {
+        words_into(batch, &mut bytes);
+        out.write_all(&bytes[..4 * batch.len()])?;
+    }
+    Ok(())
+}
@@ -1 +1,54 @@
-pub mod binary_merkle_tree;
\ No newline at end of file
+#![cfg_attr(not(feature = "std"), no_std)]
+#![deny(unsafe_code)]
+
+#[cfg(not(feature = "alloc"))]
+compile_error!("merkle_tree needs the `alloc` feature (enabled by the default `std` feature)");
+
+extern crate alloc;
+
+pub mod bao;
+pub mod binary_merkle_tree;
+#[cfg(feature = "blake3-interop")]
+pub mod blake3_interop;
+pub mod compact_tree;
+pub mod content_id;
+pub mod dedupe;
+#[cfg(feature = "std")]
+pub mod download;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod file_store;
+pub mod flat_tree;
+pub mod grouped_tree;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+#[cfg(feature = "std")]
+pub mod io;
+pub mod kary_merkle_tree;
+mod le_bytes;
+#[cfg(feature = "std")]
+pub mod manifest;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod mmr;
+#[cfg(feature = "multihash")]
+pub mod multihash;
+pub mod node_store;
+pub mod proof;
+pub mod pruned_tree;
+#[cfg(feature = "test-vectors")]
+pub mod self_test;
+pub mod shallow_tree;
+#[cfg(feature = "simd")]
+pub mod simd;
+pub mod sync;
+#[cfg(feature = "std")]
+pub mod update_log;
+#[cfg(feature = "tokio")]
+pub mod async_io;
+#[cfg(feature = "serde")]
+mod serde_support;
+#[cfg(feature = "wasm")]
+pub mod wasm;
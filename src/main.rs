@@ -1,9 +1,7 @@
-mod binary_merkle_tree;
-
 use rand::Rng;
 use std::time::Instant;
 use std::collections::HashMap;
-use crate::binary_merkle_tree::{BinaryMerkleTree, ChunkState, Blake3Hasher, CHUNK_LEN, IV, FLAGS};
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, ChunkState, Blake3Hasher, CHUNK_LEN, IV, FLAGS};
 
 const INPUT_SIZE: usize = 10000000; // ~10MB
 const MUTATION_COUNTS: [usize; 8] = [5, 10, 50, 100, 500, 1000, 5000, 10000]; // Different numbers of mutations to test
@@ -42,7 +40,7 @@ fn main() {
             
             let chunk_index = pos / CHUNK_LEN;
             chunk_updates.entry(chunk_index)
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(pos);
         }
         
@@ -67,7 +65,8 @@ fn main() {
         
         // Time the Merkle tree bulk update
         let merkle_start = Instant::now();
-        tree.bulk_insert_leaves(chunk_indices.into_iter(), chunk_outputs.into_iter());
+        tree.bulk_insert_leaves(chunk_indices.into_iter(), chunk_outputs.into_iter())
+            .expect("Bulk insert failed");
         let mutated_root = tree.root().chaining_value();
         let merkle_duration = merkle_start.elapsed();
         
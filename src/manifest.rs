@@ -0,0 +1,237 @@
+//! Directory manifests: one root committing to every file under a directory.
+//!
+//! Each file gets its own tree, and a manifest tree has one leaf per file, in
+//! order of relative path. A leaf commits to the file's relative path, the root
+//! of its tree and its length, so renaming, changing, adding or removing any file
+//! changes the manifest root, and a `FileProof` shows that one file belongs to a
+//! manifest without the others.
+//!
+//! # Leaf derivation
+//!
+//! The digest of an entry is BLAKE3 in derive_key mode with the context
+//! `MANIFEST_CONTEXT`, over
+//!
+//! - the path length in bytes (u64, little-endian), then the path as UTF-8, with
+//!   components separated by `/`
+//! - the file's root hash (32 bytes)
+//! - the file length (u64, little-endian)
+//!
+//! The context keeps entry digests apart from every other use of BLAKE3, and the
+//! length prefix keeps the path from running into the root. Entry `i` is then
+//! leaf `i` of `BinaryMerkleTree::from_leaf_data` (regular hash mode), which
+//! hashes the digest as the content of chunk `i`.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, ChunkState, Hash32, FLAGS, IV, OUT_LEN};
+use crate::le_bytes::read_words;
+use crate::proof::MerkleProof;
+
+/// The derive_key context for manifest entry digests.
+pub const MANIFEST_CONTEXT: &str = "merkle_tree 2026-10-16 directory manifest entry v1";
+
+// Serialized manifest format: magic and version.
+const MANIFEST_MAGIC: [u8; 4] = *b"B3MF";
+const MANIFEST_VERSION: u32 = 1;
+
+/// One file of a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// The path relative to the manifest's directory, `/`-separated.
+    pub path: String,
+    /// The root hash of the file's tree (its BLAKE3 hash).
+    pub root: Hash32,
+    /// The file length in bytes.
+    pub len: u64,
+}
+
+impl ManifestEntry {
+    /// The entry's digest; see the module documentation.
+    pub fn digest(&self) -> Hash32 {
+        entry_digest(&self.path, &self.root, self.len)
+    }
+}
+
+fn entry_digest(path: &str, root: &Hash32, len: u64) -> Hash32 {
+    let mut hasher = Blake3Hasher::new_derive_key(MANIFEST_CONTEXT);
+    hasher.update(&(path.len() as u64).to_le_bytes());
+    hasher.update(path.as_bytes());
+    hasher.update(root.as_bytes());
+    hasher.update(&len.to_le_bytes());
+    Hash32(hasher.finalize_array())
+}
+
+/// A manifest over a set of files, sorted by path.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    entries: Vec<ManifestEntry>,
+    tree: BinaryMerkleTree,
+}
+
+impl Manifest {
+    /// Build a manifest over `entries`, in any order. Returns
+    /// `io::ErrorKind::InvalidInput` if there are none or two share a path.
+    pub fn from_entries(mut entries: Vec<ManifestEntry>) -> io::Result<Self> {
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        if let Some(pair) = entries.windows(2).find(|pair| pair[0].path == pair[1].path) {
+            return Err(invalid_input(format!("duplicate path {:?}", pair[0].path)));
+        }
+        let tree = BinaryMerkleTree::from_leaf_data(&entries, |entry| read_words(entry.digest().as_bytes()), IV, FLAGS)?;
+        Ok(Manifest { entries, tree })
+    }
+
+    /// Hash every file under `dir`, recursively. Paths are sorted by their
+    /// `/`-separated relative form, so the root does not depend on the order the
+    /// file system lists them in. Symbolic links are skipped, and a name that is
+    /// not UTF-8 is `io::ErrorKind::InvalidData`. A directory with no files is
+    /// `io::ErrorKind::InvalidInput`.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        collect_files(dir.as_ref(), "", &mut entries)?;
+        Self::from_entries(entries)
+    }
+
+    /// The root committing to every entry.
+    pub fn root(&self) -> Hash32 {
+        self.tree.root_hash()
+    }
+
+    /// The entries, sorted by path.
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+
+    /// The entry for `path`, if the manifest has one.
+    pub fn entry(&self, path: &str) -> Option<&ManifestEntry> {
+        self.position(path).map(|index| &self.entries[index])
+    }
+
+    fn position(&self, path: &str) -> Option<usize> {
+        self.entries.binary_search_by(|entry| entry.path.as_str().cmp(path)).ok()
+    }
+
+    /// The proof that the file at `path` is in this manifest, or `None` if it is not.
+    pub fn prove_file(&self, path: &str) -> Option<FileProof> {
+        let index = self.position(path)?;
+        Some(FileProof { file_len: self.entries[index].len, proof: self.tree.generate_proof(index)? })
+    }
+
+    /// Whether `proof` shows a file at `path` with root `file_root` is in this manifest.
+    pub fn verify_file(&self, path: &str, file_root: &Hash32, proof: &FileProof) -> bool {
+        proof.verify(&self.root(), path, file_root)
+    }
+
+    /// Serialize the manifest, little-endian throughout: magic `b"B3MF"`, version
+    /// (u32, currently 1) and entry count (u64), then per entry in path order the
+    /// path length (u32), the UTF-8 path, the file root (32 bytes) and the file
+    /// length (u64). The tree is rebuilt on load rather than stored.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MANIFEST_MAGIC);
+        bytes.extend_from_slice(&MANIFEST_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+        for entry in &self.entries {
+            bytes.extend_from_slice(&(entry.path.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(entry.path.as_bytes());
+            bytes.extend_from_slice(entry.root.as_bytes());
+            bytes.extend_from_slice(&entry.len.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Load a manifest written by `to_bytes`. Entries must be in strictly
+    /// increasing path order. Malformed input is `io::ErrorKind::InvalidData` and
+    /// truncated input `UnexpectedEof`.
+    pub fn from_bytes(mut bytes: &[u8]) -> io::Result<Self> {
+        fn read_array<const N: usize>(reader: &mut &[u8]) -> io::Result<[u8; N]> {
+            let mut array = [0u8; N];
+            reader.read_exact(&mut array)?;
+            Ok(array)
+        }
+
+        if read_array::<4>(&mut bytes)? != MANIFEST_MAGIC {
+            return Err(invalid_data("not a serialized manifest".to_string()));
+        }
+        let version = u32::from_le_bytes(read_array(&mut bytes)?);
+        if version != MANIFEST_VERSION {
+            return Err(invalid_data(format!("unsupported manifest version {}", version)));
+        }
+        let count = u64::from_le_bytes(read_array(&mut bytes)?);
+        // Every entry takes at least 44 bytes, so the count is bounded before allocating
+        if count == 0 || count > (bytes.len() / (4 + OUT_LEN + 8)) as u64 {
+            return Err(invalid_data(format!("invalid entry count {} for {} bytes", count, bytes.len())));
+        }
+
+        let mut entries: Vec<ManifestEntry> = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let path_len = u32::from_le_bytes(read_array(&mut bytes)?) as usize;
+            if path_len > bytes.len() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "path runs past the end"));
+            }
+            let (path, rest) = bytes.split_at(path_len);
+            bytes = rest;
+            let path = String::from_utf8(path.to_vec()).map_err(|_| invalid_data("path is not UTF-8".to_string()))?;
+            if entries.last().is_some_and(|previous| previous.path >= path) {
+                return Err(invalid_data(format!("path {:?} is out of order or repeated", path)));
+            }
+            let root = Hash32(read_array(&mut bytes)?);
+            let len = u64::from_le_bytes(read_array(&mut bytes)?);
+            entries.push(ManifestEntry { path, root, len });
+        }
+        if !bytes.is_empty() {
+            return Err(invalid_data(format!("{} trailing bytes after the manifest", bytes.len())));
+        }
+        Self::from_entries(entries)
+    }
+}
+
+/// Proof that a file belongs to a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileProof {
+    /// The file length the manifest records.
+    pub file_len: u64,
+    /// The inclusion proof of the file's entry in the manifest tree.
+    pub proof: MerkleProof,
+}
+
+impl FileProof {
+    /// Whether the proof shows a file at `path` with root `file_root` is in the
+    /// manifest with root `manifest_root`. The file's length comes from the proof;
+    /// check it against the file too when that matters.
+    pub fn verify(&self, manifest_root: &Hash32, path: &str, file_root: &Hash32) -> bool {
+        let digest = entry_digest(path, file_root, self.file_len);
+        let mut chunk_state = ChunkState::new(IV, self.proof.leaf_index as u64, FLAGS);
+        chunk_state.update(digest.as_bytes());
+        chunk_state.output() == self.proof.leaf && self.proof.verify(manifest_root.as_bytes(), IV, FLAGS)
+    }
+}
+
+fn collect_files(dir: &Path, prefix: &str, entries: &mut Vec<ManifestEntry>) -> io::Result<()> {
+    for dir_entry in fs::read_dir(dir)? {
+        let dir_entry = dir_entry?;
+        let name = dir_entry.file_name();
+        let name = name
+            .to_str()
+            .ok_or_else(|| invalid_data(format!("file name {:?} is not UTF-8", name)))?;
+        let path = format!("{}{}", prefix, name);
+        let file_type = dir_entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&dir_entry.path(), &format!("{}/", path), entries)?;
+        } else if file_type.is_file() {
+            let tree = BinaryMerkleTree::from_file(dir_entry.path(), IV, FLAGS)?;
+            let len = tree.input_len().expect("trees read from files know their length");
+            entries.push(ManifestEntry { path, root: tree.root_hash(), len });
+        }
+    }
+    Ok(())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
@@ -0,0 +1,61 @@
+//! Opt-in counters of the hashing work done on the current thread, for checking
+//! that a change to an incremental path does as much work as expected.
+//!
+//! Counters are thread-local and only ever grow. `measure` reports what a single
+//! closure did; work that rayon runs on its worker threads is counted on those
+//! threads, not the caller's.
+
+use core::ops::Sub;
+use std::cell::Cell;
+
+/// Counts of hashing work.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Calls to the compression function, for chunk blocks, parents and root output alike.
+    pub compressions: u64,
+    /// Chunk outputs produced, once per chunk hashed.
+    pub chunks: u64,
+    /// Parent nodes built from two child chaining values.
+    pub parents: u64,
+    /// Input bytes fed to chunk states.
+    pub bytes: u64,
+}
+
+impl Sub for Metrics {
+    type Output = Metrics;
+
+    fn sub(self, earlier: Metrics) -> Metrics {
+        Metrics {
+            compressions: self.compressions - earlier.compressions,
+            chunks: self.chunks - earlier.chunks,
+            parents: self.parents - earlier.parents,
+            bytes: self.bytes - earlier.bytes,
+        }
+    }
+}
+
+thread_local! {
+    static COUNTERS: Cell<Metrics> = const {
+        Cell::new(Metrics { compressions: 0, chunks: 0, parents: 0, bytes: 0 })
+    };
+}
+
+pub(crate) fn count(update: impl FnOnce(&mut Metrics)) {
+    COUNTERS.with(|counters| {
+        let mut metrics = counters.get();
+        update(&mut metrics);
+        counters.set(metrics);
+    });
+}
+
+/// Everything counted on this thread so far.
+pub fn snapshot() -> Metrics {
+    COUNTERS.with(Cell::get)
+}
+
+/// Run `f` and return its result with the work it did on this thread.
+pub fn measure<R>(f: impl FnOnce() -> R) -> (R, Metrics) {
+    let before = snapshot();
+    let result = f();
+    (result, snapshot() - before)
+}
@@ -0,0 +1,178 @@
+//! The tree as a Merkle Mountain Range, for append-only logs.
+//!
+//! Appending leaves one at a time, as `Blake3Hasher` does with chunks, leaves a
+//! list of peaks: the roots of the complete power-of-two subtrees, one per set bit
+//! of the leaf count, largest (leftmost) first. Bagging them folds the list right
+//! to left with parent nodes, which is exactly how BLAKE3 joins those subtrees, so
+//! the bag is the chaining value of the root of the tree over the same leaves.
+//!
+//! A proof for a leaf is its path up to its own peak plus every other peak. The
+//! hasher keeps only the peaks, so proofs come from a `GenericMerkleTree` over the
+//! same leaves and verify against either one's commitment.
+//!
+//! `StreamingRoot` keeps the same peaks over leaves that arrive as Outputs, and
+//! folds the latest leaf into them on demand to give the root of every prefix.
+
+use alloc::vec::Vec;
+
+use crate::binary_merkle_tree::{
+    parent_cv, parent_output, GenericMerkleTree, Hash32, LeafCv, LeafIndex, Output, OUT_LEN, ROOT,
+};
+use crate::node_store::NodeStore;
+
+/// Fold `peaks` right to left into one chaining value, or `None` if there are none.
+pub fn bag_peaks(peaks: &[[u32; 8]], key_words: [u32; 8], flags: u32) -> Option<[u32; 8]> {
+    let (&last, rest) = peaks.split_last()?;
+    Some(rest.iter().rev().fold(last, |bag, &peak| parent_cv(peak, bag, key_words, flags)))
+}
+
+/// The first leaf and the height of each peak of a range of `leaf_count` leaves,
+/// leftmost first. A peak of height `h` covers `2^h` leaves.
+fn peak_spans(leaf_count: usize) -> impl Iterator<Item = (usize, u32)> {
+    let mut start = 0;
+    (0..usize::BITS).rev().filter(move |&height| leaf_count >> height & 1 == 1).map(move |height| {
+        let span = (start, height);
+        start += 1 << height;
+        span
+    })
+}
+
+/// Proof that `leaf` is leaf `leaf_index` of a range of `leaf_count` leaves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MmrProof {
+    pub leaf_index: usize,
+    pub leaf_count: usize,
+    pub leaf: LeafCv,
+    /// Sibling chaining values from the leaf up to just below its peak.
+    pub siblings: Vec<[u32; 8]>,
+    /// Every peak but the leaf's own, leftmost first.
+    pub other_peaks: Vec<[u32; 8]>,
+}
+
+impl MmrProof {
+    /// The bag this proof leads to, or `None` if it has the wrong number of
+    /// siblings or peaks for its position.
+    pub fn bag(&self, key_words: [u32; 8], flags: u32) -> Option<[u32; 8]> {
+        let (peak_number, (peak_start, height)) = peak_spans(self.leaf_count)
+            .enumerate()
+            .find(|&(_, (start, height))| self.leaf_index >= start && self.leaf_index - start < 1 << height)?;
+        if self.siblings.len() != height as usize || self.other_peaks.len() + 1 != self.leaf_count.count_ones() as usize {
+            return None;
+        }
+
+        let mut node = self.leaf.words();
+        let mut position = self.leaf_index - peak_start;
+        for &sibling in &self.siblings {
+            node = if position.is_multiple_of(2) {
+                parent_cv(node, sibling, key_words, flags)
+            } else {
+                parent_cv(sibling, node, key_words, flags)
+            };
+            position /= 2;
+        }
+
+        let mut peaks = self.other_peaks.clone();
+        peaks.insert(peak_number, node);
+        bag_peaks(&peaks, key_words, flags)
+    }
+
+    /// Whether the proof leads to `bag`, as from `Blake3Hasher::mmr_bag`.
+    pub fn verify(&self, bag: [u32; 8], key_words: [u32; 8], flags: u32) -> bool {
+        self.bag(key_words, flags) == Some(bag)
+    }
+}
+
+impl<S: NodeStore> GenericMerkleTree<S> {
+    /// The tree's peaks as a Merkle Mountain Range over its leaves, leftmost first.
+    /// Each complete power-of-two run of leaves is a node of the tree.
+    pub fn mmr_peaks(&self) -> Vec<[u32; 8]> {
+        let leaf_start_index = self.num_leaves();
+        peak_spans(self.actual_leaves())
+            .map(|(start, height)| self.node((leaf_start_index + start) >> height).chaining_value())
+            .collect()
+    }
+
+    /// The MMR proof for the leaf at `leaf_index`, or `None` if out of bounds.
+    /// It verifies against `root_cv_and_bytes().0`, and against the `mmr_bag` of a
+    /// hasher that has seen the same complete chunks.
+    pub fn mmr_proof(&self, leaf_index: impl Into<LeafIndex>) -> Option<MmrProof> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        let leaf = self.leaf_cv(leaf_index)?;
+        let leaf_start_index = self.num_leaves();
+        let mut siblings = Vec::new();
+        let mut other_peaks = Vec::new();
+        for (start, height) in peak_spans(self.actual_leaves()) {
+            if leaf_index >= start && leaf_index - start < 1 << height {
+                let mut index = leaf_start_index + leaf_index;
+                for _ in 0..height {
+                    siblings.push(self.node(index ^ 1).chaining_value());
+                    index /= 2;
+                }
+            } else {
+                other_peaks.push(self.node((leaf_start_index + start) >> height).chaining_value());
+            }
+        }
+        Some(MmrProof { leaf_index, leaf_count: self.actual_leaves(), leaf, siblings, other_peaks })
+    }
+}
+
+/// The root over a stream of leaves, available after every leaf: after `n` chunk
+/// Outputs, as `Blake3Hasher::finalize` gives it after the same `n` chunks.
+///
+/// Like the hasher, it keeps one peak per set bit of the leaf count, over every
+/// leaf but the latest. The latest is held as an Output, since it is the root of a
+/// one-leaf stream, and the root is its fold with the peaks along the right edge,
+/// `O(log n)` parent compressions each time it is asked for.
+#[derive(Debug, Clone)]
+pub struct StreamingRoot {
+    peaks: Vec<[u32; 8]>,
+    latest: Option<Output>,
+    leaf_count: usize,
+    key_words: [u32; 8],
+    flags: u32,
+}
+
+impl StreamingRoot {
+    pub fn new(key_words: [u32; 8], flags: u32) -> Self {
+        StreamingRoot { peaks: Vec::new(), latest: None, leaf_count: 0, key_words, flags }
+    }
+
+    /// Append the next leaf and return the root hash over every leaf so far.
+    pub fn push(&mut self, leaf: Output) -> Hash32 {
+        if let Some(previous) = self.latest.replace(leaf) {
+            // As `Blake3Hasher` merges a chunk: one parent per trailing zero bit of
+            // the count of leaves now in the peaks
+            let mut new_cv = previous.chaining_value();
+            let mut total_leaves = self.leaf_count;
+            while total_leaves & 1 == 0 {
+                new_cv = parent_cv(self.peaks.pop().expect("peak stack underflow"), new_cv, self.key_words, self.flags);
+                total_leaves >>= 1;
+            }
+            self.peaks.push(new_cv);
+        }
+        self.leaf_count += 1;
+        self.root_hash().expect("a leaf was just pushed")
+    }
+
+    /// The number of leaves pushed.
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// The root node over the leaves so far, with the ROOT flag applied, or `None`
+    /// before the first leaf.
+    pub fn root(&self) -> Option<Output> {
+        let mut root = self.peaks.iter().rev().fold(self.latest?, |right, &left| {
+            parent_output(left, right.chaining_value(), self.key_words, self.flags)
+        });
+        root.flags |= ROOT;
+        Some(root)
+    }
+
+    /// The 32-byte root hash over the leaves so far, or `None` before the first leaf.
+    pub fn root_hash(&self) -> Option<Hash32> {
+        let mut hash = [0; OUT_LEN];
+        self.root()?.root_output_bytes(&mut hash);
+        Some(Hash32(hash))
+    }
+}
@@ -0,0 +1,125 @@
+//! Multihash and multibase encodings of root hashes, for content-addressed
+//! systems such as IPFS that name data by self-describing digests.
+//!
+//! A multihash is the hash function's code and the digest length, each as an
+//! unsigned varint (LEB128, at most 9 bytes, minimally encoded), followed by the
+//! digest. For a BLAKE3 root that is `0x1e 0x20` and the 32 bytes. A multibase
+//! string is a one-character base prefix followed by the multihash in that base,
+//! such as `b` for lowercase base32 or `z` for base58btc.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+pub use multibase::Base;
+
+use crate::binary_merkle_tree::{Hash32, OUT_LEN};
+
+/// The multicodec table's code for BLAKE3.
+pub const BLAKE3_MULTIHASH_CODE: u64 = 0x1e;
+
+/// The longest unsigned varint the multiformats spec allows.
+const MAX_VARINT_LEN: usize = 9;
+
+/// Why bytes or a string are not the multihash of a BLAKE3 root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MultihashError {
+    /// The bytes end inside a varint or the digest.
+    Truncated,
+    /// A varint longer than 9 bytes, or not minimally encoded.
+    InvalidVarint,
+    /// The multihash is for a hash function other than BLAKE3.
+    WrongCode { code: u64 },
+    /// The digest is not 32 bytes long.
+    WrongLength { len: u64 },
+    /// This many bytes follow the digest.
+    TrailingBytes(usize),
+    /// The string has an unknown base prefix or characters outside its base.
+    Multibase(multibase::Error),
+}
+
+impl fmt::Display for MultihashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultihashError::Truncated => write!(f, "multihash ends before its digest"),
+            MultihashError::InvalidVarint => write!(f, "multihash varint is too long or not minimally encoded"),
+            MultihashError::WrongCode { code } => {
+                write!(f, "multihash code {:#x} is not BLAKE3 ({:#x})", code, BLAKE3_MULTIHASH_CODE)
+            }
+            MultihashError::WrongLength { len } => write!(f, "multihash digest is {} bytes, not {}", len, OUT_LEN),
+            MultihashError::TrailingBytes(count) => write!(f, "{} trailing bytes after the multihash", count),
+            MultihashError::Multibase(e) => write!(f, "invalid multibase string: {}", e),
+        }
+    }
+}
+
+impl core::error::Error for MultihashError {}
+
+fn push_varint(encoded: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        encoded.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    encoded.push(value as u8);
+}
+
+/// Split an unsigned varint off the front of `bytes`.
+fn read_varint(bytes: &[u8]) -> Result<(u64, &[u8]), MultihashError> {
+    let mut value = 0;
+    for (i, &byte) in bytes.iter().enumerate().take(MAX_VARINT_LEN) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            // A zero final byte after others means the value fit in fewer bytes
+            if byte == 0 && i > 0 {
+                return Err(MultihashError::InvalidVarint);
+            }
+            return Ok((value, &bytes[i + 1..]));
+        }
+    }
+    if bytes.len() < MAX_VARINT_LEN {
+        Err(MultihashError::Truncated)
+    } else {
+        Err(MultihashError::InvalidVarint)
+    }
+}
+
+impl Hash32 {
+    /// The multihash of this root: code `0x1e`, length 32, then the digest.
+    pub fn to_multihash(&self) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(2 + OUT_LEN);
+        push_varint(&mut encoded, BLAKE3_MULTIHASH_CODE);
+        push_varint(&mut encoded, OUT_LEN as u64);
+        encoded.extend_from_slice(&self.0);
+        encoded
+    }
+
+    /// Parse a multihash, which must be exactly a 32-byte BLAKE3 digest.
+    pub fn from_multihash(bytes: &[u8]) -> Result<Self, MultihashError> {
+        let (code, rest) = read_varint(bytes)?;
+        if code != BLAKE3_MULTIHASH_CODE {
+            return Err(MultihashError::WrongCode { code });
+        }
+        let (len, digest) = read_varint(rest)?;
+        if len != OUT_LEN as u64 {
+            return Err(MultihashError::WrongLength { len });
+        }
+        let Some(hash) = digest.get(..OUT_LEN) else {
+            return Err(MultihashError::Truncated);
+        };
+        if digest.len() > OUT_LEN {
+            return Err(MultihashError::TrailingBytes(digest.len() - OUT_LEN));
+        }
+        Ok(Hash32(hash.try_into().unwrap()))
+    }
+
+    /// The multihash of this root as a multibase string in `base`.
+    pub fn to_multibase(&self, base: Base) -> String {
+        multibase::encode(base, self.to_multihash())
+    }
+
+    /// Parse a multibase string in any base holding a BLAKE3 multihash.
+    pub fn from_multibase(encoded: &str) -> Result<Self, MultihashError> {
+        let (_, bytes) = multibase::decode(encoded).map_err(MultihashError::Multibase)?;
+        Self::from_multihash(&bytes)
+    }
+}
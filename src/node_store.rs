@@ -0,0 +1,212 @@
+//! Pluggable storage for the nodes of a `GenericMerkleTree`.
+//!
+//! Trees address nodes by heap position (see `HeapIndex`), and only ever read
+//! positions they have written. A store therefore does not need to be contiguous:
+//! `VecStore`, the default, preallocates the whole heap, while a map- or
+//! database-backed store can hold just the real nodes. `SharedStore` keeps
+//! them in a persistent tree of its own, so versions share what they have in
+//! common node by node.
+
+use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::binary_merkle_tree::{parent_output, write_parent_output, HeapIndex, Output};
+
+/// The identity of a node in a store: its heap position in the tree.
+pub type NodeId = HeapIndex;
+
+/// Storage for tree nodes, keyed by heap position.
+///
+/// A tree with `n` leaves writes every real node at ids below
+/// `2 * n.next_power_of_two()` before reading it, and never reads the padding
+/// positions past the end of a level. `get` may panic for an id that was never
+/// `put`, as `Vec` indexing does.
+pub trait NodeStore {
+    /// The node stored at `id`.
+    fn get(&self, id: NodeId) -> Output;
+
+    /// Store `output` at `id`, replacing any previous node there.
+    fn put(&mut self, id: NodeId, output: Output);
+
+    /// The number of node positions the store holds.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The nodes at `ids`, in order. Override this when one round trip for many
+    /// nodes is cheaper than one per node.
+    fn get_batch(&self, ids: &[NodeId]) -> Vec<Output> {
+        ids.iter().map(|&id| self.get(id)).collect()
+    }
+
+    /// Store every `(id, output)` pair, in order.
+    fn put_batch(&mut self, nodes: &[(NodeId, Output)]) {
+        for &(id, output) in nodes {
+            self.put(id, output);
+        }
+    }
+
+    /// Store the parent of `left_child_cv` and `right_child_cv` at `id`, as
+    /// `put(id, parent_output(..))` does. Override this to hash the parent
+    /// straight into the stored slot.
+    fn put_parent(&mut self, id: NodeId, left_child_cv: [u32; 8], right_child_cv: [u32; 8], key_words: [u32; 8], flags: u32) {
+        self.put(id, parent_output(left_child_cv, right_child_cv, key_words, flags));
+    }
+}
+
+/// The default store: the whole heap in one `Vec`, shared copy-on-write, so
+/// cloning it (see `BinaryMerkleTree::snapshot`) is O(1) and the nodes are only
+/// copied the first time either copy is modified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VecStore {
+    nodes: Arc<Vec<Output>>,
+}
+
+impl VecStore {
+    /// A store of `len` positions, each holding `fill` until overwritten.
+    pub fn new(len: usize, fill: Output) -> Self {
+        Self::from_nodes(vec![fill; len])
+    }
+
+    pub(crate) fn from_nodes(nodes: Vec<Output>) -> Self {
+        VecStore { nodes: Arc::new(nodes) }
+    }
+
+    /// The number of nodes the store has room for without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.nodes.capacity()
+    }
+
+    /// Mutable access to the nodes, copying them first if they are shared.
+    pub(crate) fn nodes_mut(&mut self) -> &mut Vec<Output> {
+        Arc::make_mut(&mut self.nodes)
+    }
+}
+
+impl NodeStore for VecStore {
+    fn get(&self, id: NodeId) -> Output {
+        self.nodes[id.0]
+    }
+
+    fn put(&mut self, id: NodeId, output: Output) {
+        self.nodes_mut()[id.0] = output;
+    }
+
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn put_batch(&mut self, nodes: &[(NodeId, Output)]) {
+        // One copy-on-write check for the whole batch
+        let stored = self.nodes_mut();
+        for &(id, output) in nodes {
+            stored[id.0] = output;
+        }
+    }
+
+    fn put_parent(&mut self, id: NodeId, left_child_cv: [u32; 8], right_child_cv: [u32; 8], key_words: [u32; 8], flags: u32) {
+        write_parent_output(&mut self.nodes_mut()[id.0], left_child_cv, right_child_cv, key_words, flags);
+    }
+}
+
+/// A position in a `SharedStore` and the positions below it, mirroring the heap:
+/// the children of id `i` are `2 * i` and `2 * i + 1`.
+#[derive(Debug, Clone, Default)]
+struct SharedNode {
+    output: Option<Output>,
+    children: [Option<Arc<SharedNode>>; 2],
+}
+
+/// A persistent store: the heap as a tree of `Arc`-shared nodes, updated by path
+/// copying. Cloning it is O(1), and a `put` to a clone copies only the
+/// positions from the root down to `id` that it still shares, O(log n) of
+/// them, leaving every other subtree shared with the versions it came from.
+/// This is the store of `SnapshotTree`.
+///
+/// Heap ids start at 1, the root; id 0 is not a node position.
+#[derive(Debug, Clone, Default)]
+pub struct SharedStore {
+    root: Option<Arc<SharedNode>>,
+    len: usize,
+}
+
+/// The child to follow at each step from the root down to `id`: the bits of `id`
+/// below its leading one, most significant first.
+fn heap_path(id: NodeId) -> impl Iterator<Item = usize> {
+    (0..id.0.ilog2()).rev().map(move |shift| (id.0 >> shift) & 1)
+}
+
+impl SharedStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of distinct node allocations across `stores`, each shared one
+    /// counted once: the memory a set of versions takes, in nodes.
+    pub fn distinct_nodes<'a>(stores: impl IntoIterator<Item = &'a SharedStore>) -> usize {
+        let mut seen = BTreeSet::new();
+        let mut stack: Vec<&Arc<SharedNode>> = stores.into_iter().filter_map(|store| store.root.as_ref()).collect();
+        while let Some(node) = stack.pop() {
+            // A node seen before brings its whole subtree with it
+            if seen.insert(Arc::as_ptr(node)) {
+                stack.extend(node.children.iter().flatten());
+            }
+        }
+        seen.len()
+    }
+}
+
+impl NodeStore for SharedStore {
+    fn get(&self, id: NodeId) -> Output {
+        let mut node = self.root.as_deref();
+        for child in heap_path(id) {
+            node = node.and_then(|node| node.children[child].as_deref());
+        }
+        node.and_then(|node| node.output)
+            .unwrap_or_else(|| panic!("no node stored at heap index {}", id.0))
+    }
+
+    fn put(&mut self, id: NodeId, output: Output) {
+        assert!(id.0 > 0, "heap index 0 is not a node position");
+        // `make_mut` copies a position only while another version still shares it
+        let mut slot = &mut self.root;
+        for child in heap_path(id) {
+            slot = &mut Arc::make_mut(slot.get_or_insert_with(Default::default)).children[child];
+        }
+        let node = Arc::make_mut(slot.get_or_insert_with(Default::default));
+        if node.output.replace(output).is_none() {
+            self.len += 1;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Wipes the nodes in place if this store is their only owner. Otherwise a
+/// snapshot still uses them, so this store only drops its reference and takes
+/// zeroed nodes of its own.
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for VecStore {
+    fn zeroize(&mut self) {
+        let len = self.nodes.len();
+        match Arc::get_mut(&mut self.nodes) {
+            Some(nodes) => nodes.iter_mut().for_each(zeroize::Zeroize::zeroize),
+            None => {
+                let empty = Output {
+                    input_chaining_value: [0; 8],
+                    block_words: [0; 16],
+                    counter: 0,
+                    block_len: 0,
+                    flags: 0,
+                };
+                *self = VecStore::new(len, empty);
+            }
+        }
+    }
+}
@@ -0,0 +1,433 @@
+//! Inclusion proofs: a single leaf plus the sibling chaining values on its path to
+//! the root, enough to check that leaf against a trusted root hash.
+
+use alloc::vec::Vec;
+
+use crate::binary_merkle_tree::{
+    cv_from_bytes, cv_to_bytes, leaf_hash_output, parent_output, ChunkState, GenericMerkleTree, LeafIndex, Output, BLOCK_LEN,
+    CHUNK_LEN, OUT_LEN, ROOT,
+};
+use crate::node_store::NodeStore;
+
+/// The byte length of the `leaf_index` and `actual_leaves` header of `to_path_bytes`.
+const PATH_HEADER_LEN: usize = 16;
+
+/// The version byte of `to_path_bytes_with_params`.
+const PARAMS_VERSION: u8 = 1;
+
+/// The byte length of the version, flags and key that `to_path_bytes_with_params`
+/// puts before the path.
+const PARAMS_HEADER_LEN: usize = 1 + 4 + OUT_LEN;
+
+/// Proof that `leaf` is leaf `leaf_index` of a tree with `actual_leaves` leaves.
+///
+/// With the `serde` feature, proofs serialize with their chaining values as hex
+/// in human-readable formats. Nothing is checked on load: a deserialized proof
+/// is only as good as `verify` against a trusted root says.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MerkleProof {
+    pub leaf_index: usize,
+    pub actual_leaves: usize,
+    pub leaf: Output,
+    /// Sibling chaining values from the leaf level up, in the order
+    /// `update_path_siblings` returns them: levels where the path node is a lone
+    /// left child are promoted and have no entry.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::cvs"))]
+    pub siblings: Vec<[u32; 8]>,
+}
+
+impl MerkleProof {
+    /// Fold the leaf up through the siblings to the root node, without the ROOT
+    /// flag. Returns `None` if the proof has too few or too many siblings for its
+    /// position.
+    pub fn root_node(&self, key_words: [u32; 8], flags: u32) -> Option<Output> {
+        fold_path(self.leaf, None, self.leaf_index, self.actual_leaves, &self.siblings, key_words, flags)
+    }
+
+    /// Whether the proof folds to `root_hash`.
+    pub fn verify(&self, root_hash: &[u8; OUT_LEN], key_words: [u32; 8], flags: u32) -> bool {
+        verify_root(self.root_node(key_words, flags), self.leaf_index, root_hash)
+    }
+
+    /// The proof without its leaf, for sending to a verifier that hashes the chunk
+    /// itself: `leaf_index` then `actual_leaves` as little-endian u64s, followed by
+    /// one 32-byte chaining value per sibling (8 little-endian u32 words each).
+    pub fn to_path_bytes(&self) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(PATH_HEADER_LEN + self.siblings.len() * OUT_LEN);
+        encoded.extend_from_slice(&(self.leaf_index as u64).to_le_bytes());
+        encoded.extend_from_slice(&(self.actual_leaves as u64).to_le_bytes());
+        for &sibling in &self.siblings {
+            encoded.extend_from_slice(&cv_to_bytes(sibling));
+        }
+        encoded
+    }
+
+    /// Rebuild a proof from `to_path_bytes`, hashing `chunk` as its leaf. Returns
+    /// `None` if the bytes are malformed or the chunk is longer than `CHUNK_LEN`.
+    pub fn from_path_bytes(bytes: &[u8], chunk: &[u8], key_words: [u32; 8], flags: u32) -> Option<Self> {
+        if bytes.len() < PATH_HEADER_LEN || !(bytes.len() - PATH_HEADER_LEN).is_multiple_of(OUT_LEN) || chunk.len() > CHUNK_LEN {
+            return None;
+        }
+        let leaf_index = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        let actual_leaves = u64::from_le_bytes(bytes[8..PATH_HEADER_LEN].try_into().unwrap());
+        let mut chunk_state = ChunkState::new(key_words, leaf_index, flags);
+        chunk_state.update(chunk);
+        Some(MerkleProof {
+            leaf_index: leaf_index.try_into().ok()?,
+            actual_leaves: actual_leaves.try_into().ok()?,
+            leaf: chunk_state.output(),
+            siblings: bytes[PATH_HEADER_LEN..]
+                .chunks_exact(OUT_LEN)
+                .map(|sibling| cv_from_bytes(sibling.try_into().unwrap()))
+                .collect(),
+        })
+    }
+}
+
+impl MerkleProof {
+    /// `to_path_bytes` preceded by the tree's hashing parameters, so a verifier
+    /// needs only these bytes, the chunk and a trusted root hash: a version byte
+    /// (currently 1), `flags` as a little-endian u32, and the 8 key words as 32
+    /// little-endian bytes. The header is 37 bytes, so these never have the length
+    /// of a plain `to_path_bytes`.
+    ///
+    /// The embedded parameters are not trusted on their own: the root hash still
+    /// anchors the proof, and parameters other than the tree's fold to a different
+    /// root. What the verifier gives up is the choice of mode, so one that must
+    /// only accept, say, keyed trees should check the parameters returned by
+    /// `from_path_bytes_with_params`. The key travels in the clear; don't embed a
+    /// key that must stay secret.
+    pub fn to_path_bytes_with_params(&self, key_words: [u32; 8], flags: u32) -> Vec<u8> {
+        let path = self.to_path_bytes();
+        let mut encoded = Vec::with_capacity(PARAMS_HEADER_LEN + path.len());
+        encoded.push(PARAMS_VERSION);
+        encoded.extend_from_slice(&flags.to_le_bytes());
+        encoded.extend_from_slice(&cv_to_bytes(key_words));
+        encoded.extend_from_slice(&path);
+        encoded
+    }
+
+    /// Rebuild a proof from `to_path_bytes_with_params`, hashing `chunk` as its leaf
+    /// with the embedded parameters, and return it with those key words and flags.
+    /// Returns `None` for an unknown version, malformed bytes or a chunk longer
+    /// than `CHUNK_LEN`.
+    pub fn from_path_bytes_with_params(bytes: &[u8], chunk: &[u8]) -> Option<(Self, [u32; 8], u32)> {
+        if bytes.len() < PARAMS_HEADER_LEN || bytes[0] != PARAMS_VERSION {
+            return None;
+        }
+        let flags = u32::from_le_bytes(bytes[1..5].try_into().unwrap());
+        let key_words = cv_from_bytes(bytes[5..PARAMS_HEADER_LEN].try_into().unwrap());
+        let proof = Self::from_path_bytes(&bytes[PARAMS_HEADER_LEN..], chunk, key_words, flags)?;
+        Some((proof, key_words, flags))
+    }
+}
+
+/// Proof that `leaf_hash` is leaf `leaf_index` of a tree of `actual_leaves` leaf
+/// hashes, built by `BinaryMerkleTree::from_leaf_hashes`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LeafHashProof {
+    pub leaf_index: usize,
+    pub actual_leaves: usize,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::hash_bytes"))]
+    pub leaf_hash: [u8; OUT_LEN],
+    /// Sibling chaining values from the leaf level up, as in `MerkleProof`.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::cvs"))]
+    pub siblings: Vec<[u32; 8]>,
+}
+
+impl LeafHashProof {
+    /// Fold the leaf hash up through the siblings to the root node, without the
+    /// ROOT flag. Returns `None` if the proof has too few or too many siblings for
+    /// its position, or claims fewer than two leaves.
+    pub fn root_node(&self, key_words: [u32; 8], flags: u32) -> Option<Output> {
+        if self.actual_leaves < 2 {
+            return None;
+        }
+        let leaf_cv = cv_from_bytes(self.leaf_hash);
+        fold_path(leaf_hash_output(&self.leaf_hash, flags), Some(leaf_cv), self.leaf_index, self.actual_leaves, &self.siblings, key_words, flags)
+    }
+
+    /// Whether the proof folds to `root_hash`, for the `key_words` and `flags` the
+    /// tree was built with.
+    pub fn verify(&self, root_hash: &[u8; OUT_LEN], key_words: [u32; 8], flags: u32) -> bool {
+        verify_root(self.root_node(key_words, flags), self.leaf_index, root_hash)
+    }
+}
+
+/// Proof that a block of up to `BLOCK_LEN` bytes is block `block_index` of chunk
+/// `chunk_index`, made by `get_block_proof` and checked by `verify_block_proof`.
+///
+/// A chunk is a chain of block compressions, so the verifier resumes the chunk
+/// from `prefix_cv`, its chaining value after the blocks before this one, hashes
+/// the block and then `suffix`, the rest of the chunk, and folds the resulting
+/// leaf up `siblings` as a `MerkleProof` would. The prover must keep the bytes of
+/// the chunk holding the block, as the tree keeps only its leaf Output; proving
+/// the last block of a chunk sends no suffix and so the least data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockProof {
+    pub chunk_index: usize,
+    pub actual_leaves: usize,
+    pub block_index: usize,
+    /// The chunk's chaining value after its first `block_index` blocks: the key for
+    /// the first block.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::words"))]
+    pub prefix_cv: [u32; 8],
+    /// The bytes of the chunk after the block.
+    pub suffix: Vec<u8>,
+    /// Sibling chaining values of the chunk's leaf, as in `MerkleProof`.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::cvs"))]
+    pub siblings: Vec<[u32; 8]>,
+}
+
+/// Fold `leaf` up through `siblings` to the root node. `leaf_cv`, if given, is the
+/// leaf's chaining value in place of its compression.
+fn fold_path(
+    leaf: Output,
+    mut leaf_cv: Option<[u32; 8]>,
+    leaf_index: usize,
+    actual_leaves: usize,
+    siblings: &[[u32; 8]],
+    key_words: [u32; 8],
+    flags: u32,
+) -> Option<Output> {
+    if leaf_index >= actual_leaves {
+        return None;
+    }
+    let mut node = leaf;
+    let mut siblings = siblings.iter();
+    let mut position = leaf_index;
+    let mut nodes_in_level = actual_leaves;
+    while nodes_in_level > 1 {
+        if (position ^ 1) < nodes_in_level {
+            let sibling = *siblings.next()?;
+            // Until the first parent, the node is the leaf
+            let node_cv = leaf_cv.take().unwrap_or_else(|| node.chaining_value());
+            node = if position.is_multiple_of(2) {
+                parent_output(node_cv, sibling, key_words, flags)
+            } else {
+                parent_output(sibling, node_cv, key_words, flags)
+            };
+        }
+        position /= 2;
+        nodes_in_level = nodes_in_level.div_ceil(2);
+    }
+    siblings.next().is_none().then_some(node)
+}
+
+/// Whether `root`, the result of folding a proof for `leaf_index`, has the ROOT
+/// output `root_hash`.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn verify_root(root: Option<Output>, leaf_index: usize, root_hash: &[u8; OUT_LEN]) -> bool {
+    let Some(mut root) = root else {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(leaf_index, "proof has the wrong number of siblings");
+        return false;
+    };
+    root.flags |= ROOT;
+    let mut hash = [0; OUT_LEN];
+    root.root_output_bytes(&mut hash);
+    let verified = hash == *root_hash;
+    #[cfg(feature = "tracing")]
+    if !verified {
+        tracing::debug!(leaf_index, "proof does not lead to the root hash");
+    }
+    verified
+}
+
+impl<S: NodeStore> GenericMerkleTree<S> {
+    /// The inclusion proof for the leaf at `leaf_index`, or `None` if out of bounds
+    /// or the tree's leaves are leaf hashes (see `generate_leaf_hash_proof`).
+    pub fn generate_proof(&self, leaf_index: impl Into<LeafIndex>) -> Option<MerkleProof> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("generate_proof", leaf_index).entered();
+        if self.has_leaf_hashes() {
+            return None;
+        }
+        Some(MerkleProof {
+            leaf_index,
+            actual_leaves: self.actual_leaves(),
+            leaf: self.leaf(leaf_index)?,
+            siblings: self.update_path_siblings(leaf_index)?,
+        })
+    }
+
+    /// The inclusion proof for the hash at `leaf_index` of a tree of leaf hashes,
+    /// or `None` if out of bounds or the leaves are chunks.
+    pub fn generate_leaf_hash_proof(&self, leaf_index: impl Into<LeafIndex>) -> Option<LeafHashProof> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        Some(LeafHashProof {
+            leaf_index,
+            actual_leaves: self.actual_leaves(),
+            leaf_hash: self.leaf_hash(leaf_index)?.0,
+            siblings: self.update_path_siblings(leaf_index)?,
+        })
+    }
+
+    /// The proof that block `block_index` of chunk `chunk_index` is in the tree,
+    /// given the chunk's bytes (see `BlockProof`). Returns `None` if the chunk is
+    /// out of bounds, `chunk` is not its data (see `verify_chunk`), or the chunk
+    /// has no such block: it has `chunk.len().div_ceil(BLOCK_LEN)` blocks, and an
+    /// empty chunk one empty block.
+    pub fn get_block_proof(&self, chunk_index: impl Into<LeafIndex>, block_index: usize, chunk: &[u8]) -> Option<BlockProof> {
+        let LeafIndex(chunk_index) = chunk_index.into();
+        if !self.verify_chunk(chunk_index, chunk) || block_index >= chunk.len().div_ceil(BLOCK_LEN).max(1) {
+            return None;
+        }
+        let block_start = block_index * BLOCK_LEN;
+        let block_end = (block_start + BLOCK_LEN).min(chunk.len());
+        let mut chunk_state = ChunkState::new(self.key_words(), chunk_index as u64, self.flags());
+        if block_index > 0 {
+            // One byte past the prefix, so its last block is compressed rather than buffered
+            chunk_state.update(&chunk[..block_start + 1]);
+        }
+        Some(BlockProof {
+            chunk_index,
+            actual_leaves: self.actual_leaves(),
+            block_index,
+            prefix_cv: chunk_state.chaining_value,
+            suffix: chunk[block_end..].to_vec(),
+            siblings: self.update_path_siblings(chunk_index)?,
+        })
+    }
+
+    /// The proof for every leaf, in leaf order. Each node is read and turned into a
+    /// chaining value once, rather than once per leaf below it as calling
+    /// `generate_proof` for every leaf would, so the hashing is O(n); only copying
+    /// the siblings into the proofs remains O(n log n). Empty for a tree of leaf
+    /// hashes, as `generate_proof` is `None` for it.
+    pub fn all_proofs(&self) -> Vec<MerkleProof> {
+        if self.has_leaf_hashes() {
+            return Vec::new();
+        }
+        let leaves: Vec<Output> = (0..self.actual_leaves()).map(|leaf_index| self.leaf(leaf_index).unwrap()).collect();
+        // The chaining values of each level below the root, from the leaves up
+        let level_sizes = Self::level_sizes(self.actual_leaves());
+        let mut levels: Vec<Vec<[u32; 8]>> = Vec::with_capacity(level_sizes.len() - 1);
+        let mut level_start = self.num_leaves();
+        for (level, &size) in level_sizes[..level_sizes.len() - 1].iter().enumerate() {
+            levels.push(match level {
+                0 => leaves.iter().map(Output::chaining_value).collect(),
+                _ => (level_start..level_start + size).map(|index| self.node(index).chaining_value()).collect(),
+            });
+            level_start /= 2;
+        }
+
+        leaves
+            .into_iter()
+            .enumerate()
+            .map(|(leaf_index, leaf)| {
+                let mut siblings = Vec::with_capacity(levels.len());
+                let mut position = leaf_index;
+                for level in &levels {
+                    if let Some(&sibling) = level.get(position ^ 1) {
+                        siblings.push(sibling);
+                    }
+                    position /= 2;
+                }
+                MerkleProof {
+                    leaf_index,
+                    actual_leaves: self.actual_leaves(),
+                    leaf,
+                    siblings,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The node over `leaves`, a subtree of BLAKE3's tree shape: the left side holds
+/// the largest power of two of leaves below the total.
+pub(crate) fn subtree_node(leaves: &[Output], key_words: [u32; 8], flags: u32) -> Output {
+    if leaves.len() == 1 {
+        return leaves[0];
+    }
+    let (left, right) = leaves.split_at(leaves.len().div_ceil(2).next_power_of_two());
+    parent_output(
+        subtree_node(left, key_words, flags).chaining_value(),
+        subtree_node(right, key_words, flags).chaining_value(),
+        key_words,
+        flags,
+    )
+}
+
+/// Build the same proof as `BinaryMerkleTree::generate_proof` straight from the
+/// leaves, without materializing the tree: each sibling is recomputed from the
+/// leaves it covers, so this takes O(n) hashing but only O(log n) extra memory.
+/// Meant for servers that keep leaf Outputs but not internal nodes. Returns `None`
+/// if `leaf_index` is out of bounds.
+pub fn recompute_proof(leaf_index: usize, leaves: &[Output], key_words: [u32; 8], flags: u32) -> Option<MerkleProof> {
+    let leaf = *leaves.get(leaf_index)?;
+    let mut siblings = Vec::new();
+    let mut position = leaf_index;
+    let mut nodes_in_level = leaves.len();
+    let mut level = 0;
+    while nodes_in_level > 1 {
+        let sibling = position ^ 1;
+        if sibling < nodes_in_level {
+            let first = sibling << level;
+            let last = ((sibling + 1) << level).min(leaves.len());
+            siblings.push(subtree_node(&leaves[first..last], key_words, flags).chaining_value());
+        }
+        position /= 2;
+        nodes_in_level = nodes_in_level.div_ceil(2);
+        level += 1;
+    }
+    Some(MerkleProof {
+        leaf_index,
+        actual_leaves: leaves.len(),
+        leaf,
+        siblings,
+    })
+}
+
+/// Whether `proof` proves a chunk at `proof.leaf_index` of the input hashing to
+/// `root_hash`.
+///
+/// `MerkleProof::verify` takes `leaf_index` on trust. The siblings only fix the
+/// side each one joins on, and the same sides fold to the same root for other
+/// indices in trees of other sizes: the last of 5 leaves joins the root from the
+/// right with one sibling, just like leaf 1 of 2. A chunk is hashed with its
+/// position in the input as its counter, so the index the path leads to is
+/// rebuilt from the leaf and the proof is rejected unless it is `leaf_index`.
+/// Proofs whose leaf is a parent node, such as `GroupedTree::group_proof`, carry
+/// no position and are rejected; check them with `MerkleProof::verify`.
+pub fn verify_proof(proof: &MerkleProof, root_hash: &[u8; OUT_LEN], key_words: [u32; 8], flags: u32) -> bool {
+    if proof.leaf.is_parent() || proof.leaf.is_leaf_hash() || proof.leaf.counter != proof.leaf_index as u64 {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(leaf_index = proof.leaf_index, counter = proof.leaf.counter, "proof leaf is not the chunk at its index");
+        return false;
+    }
+    proof.verify(root_hash, key_words, flags)
+}
+
+/// Whether `block` is block `proof.block_index` of chunk `proof.chunk_index` of the
+/// input hashing to `root_hash`. Every block but the chunk's last is `BLOCK_LEN`
+/// bytes, so the block must be full unless the proof has no suffix, and the block
+/// and suffix must fill the rest of the chunk: all of it for every chunk but the
+/// last, which may be short.
+///
+/// Chunk hashing does not count the blocks compressed, so the block's position
+/// comes only from that length. For the last chunk, whose length the proof does
+/// not fix, check `block_index` against the input length if it matters.
+pub fn verify_block_proof(block: &[u8], proof: &BlockProof, root_hash: &[u8; OUT_LEN], key_words: [u32; 8], flags: u32) -> bool {
+    let chunk_len = proof.block_index * BLOCK_LEN + block.len() + proof.suffix.len();
+    let is_last_chunk = proof.chunk_index + 1 == proof.actual_leaves;
+    let fits = proof.block_index < CHUNK_LEN / BLOCK_LEN
+        && block.len() <= BLOCK_LEN
+        && (block.len() == BLOCK_LEN || proof.suffix.is_empty())
+        && (!block.is_empty() || proof.block_index == 0)
+        && (chunk_len == CHUNK_LEN || (is_last_chunk && chunk_len < CHUNK_LEN));
+    if !fits {
+        return false;
+    }
+    let mut chunk_state = ChunkState::new(proof.prefix_cv, proof.chunk_index as u64, flags);
+    chunk_state.blocks_compressed = proof.block_index as u8;
+    chunk_state.update(block);
+    chunk_state.update(&proof.suffix);
+    let root = fold_path(chunk_state.output(), None, proof.chunk_index, proof.actual_leaves, &proof.siblings, key_words, flags);
+    verify_root(root, proof.chunk_index, root_hash)
+}
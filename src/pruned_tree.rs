@@ -0,0 +1,340 @@
+//! Partial trees for light clients: the leaves a verifier cares about plus the
+//! sibling chaining values ("witnesses") needed to recompute the root from them.
+//! Its size grows with the number of kept leaves and the depth of the tree, not
+//! with the total number of leaves.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+use crate::binary_merkle_tree::{cv_to_bytes, parent_output, BinaryMerkleTree, ChunkState, Hash32, Output, CHUNK_LEN, OUT_LEN, ROOT};
+#[cfg(feature = "std")]
+use crate::binary_merkle_tree::{cv_from_bytes, OUTPUT_BYTES_LEN};
+use crate::error::MerkleError;
+use crate::le_bytes::push_words;
+#[cfg(feature = "std")]
+use crate::le_bytes::read_words;
+use crate::proof::MerkleProof;
+
+// Serialized pruned tree format: magic and version.
+const PRUNED_MAGIC: [u8; 4] = *b"B3PT";
+const PRUNED_VERSION: u32 = 1;
+
+/// A pruned copy of a `BinaryMerkleTree`, made with `BinaryMerkleTree::prune`.
+///
+/// Witnesses are keyed by their heap index in the full tree (1 is the root and the
+/// leaves start at the next power of two of the leaf count), so the pruned tree
+/// recomputes the root exactly as the full tree would, promotions included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrunedTree {
+    actual_leaves: usize,
+    key_words: [u32; 8],
+    flags: u32,
+    leaves: BTreeMap<usize, Output>,
+    witnesses: BTreeMap<usize, [u32; 8]>,
+}
+
+/// The heap indices of the nodes a pruned tree keeping `kept` (a non-empty set of
+/// leaf indices) must store to recompute the root: at each level, every sibling of
+/// a node on a kept leaf's path that is not itself on such a path. Lone left
+/// children are promoted and have no sibling.
+fn witness_indices(actual_leaves: usize, kept: &BTreeSet<usize>) -> Vec<usize> {
+    let mut witnesses = Vec::new();
+    let mut positions = kept.clone();
+    let mut level_start = actual_leaves.next_power_of_two();
+    let mut nodes_in_level = actual_leaves;
+    while nodes_in_level > 1 {
+        for &position in &positions {
+            let sibling = position ^ 1;
+            if sibling < nodes_in_level && !positions.contains(&sibling) {
+                witnesses.push(level_start + sibling);
+            }
+        }
+        positions = positions.iter().map(|position| position / 2).collect();
+        level_start /= 2;
+        nodes_in_level = nodes_in_level.div_ceil(2);
+    }
+    witnesses.sort_unstable();
+    witnesses
+}
+
+impl BinaryMerkleTree {
+    /// Keep only the leaves in `keep_leaves` and the witnesses needed to recompute
+    /// the root from them. Duplicates are ignored.
+    ///
+    /// Returns `MerkleError::NoLeaves` if `keep_leaves` is empty, or
+    /// `MerkleError::LeafIndexOutOfBounds` if it holds an out-of-bounds index.
+    pub fn prune(&self, keep_leaves: &[usize]) -> Result<PrunedTree, MerkleError> {
+        if keep_leaves.is_empty() {
+            return Err(MerkleError::NoLeaves);
+        }
+        let kept: BTreeSet<usize> = keep_leaves.iter().copied().collect();
+        let leaves = kept
+            .iter()
+            .map(|&leaf_index| {
+                let leaf = self.leaf(leaf_index).ok_or(MerkleError::LeafIndexOutOfBounds {
+                    leaf_index,
+                    actual_leaves: self.actual_leaves(),
+                })?;
+                Ok((leaf_index, leaf))
+            })
+            .collect::<Result<_, _>>()?;
+        let witnesses = witness_indices(self.actual_leaves(), &kept)
+            .into_iter()
+            .map(|index| (index, self.node(index).chaining_value()))
+            .collect();
+        Ok(PrunedTree {
+            actual_leaves: self.actual_leaves(),
+            key_words: self.key_words(),
+            flags: self.flags(),
+            leaves,
+            witnesses,
+        })
+    }
+
+    /// A partial tree to send to a client, keeping `leaf_indices`: the client can
+    /// check the kept chunks and hand out their proofs but knows nothing of the
+    /// other leaves. The same as `prune`. This returns a `Result` rather than a
+    /// bare `PrunedTree`, since an empty or out-of-bounds `leaf_indices` has no
+    /// pruned tree, with the same errors as `prune`.
+    pub fn prune_to(&self, leaf_indices: &[usize]) -> Result<PrunedTree, MerkleError> {
+        self.prune(leaf_indices)
+    }
+}
+
+impl PrunedTree {
+    /// The number of leaves in the full tree.
+    pub fn actual_leaves(&self) -> usize {
+        self.actual_leaves
+    }
+
+    /// The indices of the kept leaves, in increasing order.
+    pub fn kept_leaves(&self) -> impl Iterator<Item = usize> + '_ {
+        self.leaves.keys().copied()
+    }
+
+    /// The nodes on the kept leaves' paths at each level, keyed by position within
+    /// the level, from the kept leaves up to the root.
+    fn path_levels(&self) -> Vec<BTreeMap<usize, Output>> {
+        let mut levels = Vec::from([self.leaves.clone()]);
+        let mut level_start = self.actual_leaves.next_power_of_two();
+        let mut nodes_in_level = self.actual_leaves;
+        while nodes_in_level > 1 {
+            let level = &levels[levels.len() - 1];
+            let chaining_value = |position: usize| match level.get(&position) {
+                Some(node) => node.chaining_value(),
+                None => self.witnesses[&(level_start + position)],
+            };
+            let mut parents = BTreeMap::new();
+            for (&position, node) in level {
+                let left = position & !1;
+                if left + 1 >= nodes_in_level {
+                    parents.insert(position / 2, *node);
+                } else {
+                    parents
+                        .entry(position / 2)
+                        .or_insert_with(|| parent_output(chaining_value(left), chaining_value(left + 1), self.key_words, self.flags));
+                }
+            }
+            levels.push(parents);
+            level_start /= 2;
+            nodes_in_level = nodes_in_level.div_ceil(2);
+        }
+        levels
+    }
+
+    /// The root node of the full tree, recomputed from the kept leaves and witnesses,
+    /// without the ROOT flag.
+    fn root_node(&self) -> Output {
+        self.path_levels().pop().unwrap()[&0]
+    }
+
+    /// The non-root chaining value of the root node, as `root_cv_and_bytes` returns
+    /// it for the full tree.
+    pub fn root_cv(&self) -> [u32; 8] {
+        self.root_node().chaining_value()
+    }
+
+    /// The 32-byte root hash of the full tree. Compare it with a trusted hash before
+    /// relying on `verify_chunk`.
+    pub fn root_hash(&self) -> Hash32 {
+        let mut root = self.root_node();
+        root.flags |= ROOT;
+        let mut hash = [0; OUT_LEN];
+        root.root_output_bytes(&mut hash);
+        Hash32(hash)
+    }
+
+    /// Check `chunk_bytes` against kept leaf `chunk_index`, as
+    /// `BinaryMerkleTree::verify_chunk` does. Returns `MerkleError::LeafNotKept` for
+    /// any leaf that was pruned away, including indices past the end.
+    pub fn verify_chunk(&self, chunk_index: usize, chunk_bytes: &[u8]) -> Result<bool, MerkleError> {
+        let leaf = self.leaves.get(&chunk_index).ok_or(MerkleError::LeafNotKept { leaf_index: chunk_index })?;
+        let verified = chunk_bytes.len() <= CHUNK_LEN && {
+            let mut chunk_state = ChunkState::new(self.key_words, chunk_index as u64, self.flags);
+            chunk_state.update(chunk_bytes);
+            chunk_state.output().chaining_value() == leaf.chaining_value()
+        };
+        #[cfg(feature = "tracing")]
+        if !verified {
+            tracing::debug!(chunk_index, len = chunk_bytes.len(), "chunk failed verification");
+        }
+        Ok(verified)
+    }
+
+    /// The inclusion proof for kept leaf `leaf_index`, the same one
+    /// `BinaryMerkleTree::generate_proof` gives for the full tree. Siblings off the
+    /// kept paths are witnesses and the rest are recomputed from the kept leaves.
+    /// Returns `MerkleError::LeafNotKept` for any leaf that was pruned away.
+    pub fn generate_proof(&self, leaf_index: usize) -> Result<MerkleProof, MerkleError> {
+        let leaf = *self.leaves.get(&leaf_index).ok_or(MerkleError::LeafNotKept { leaf_index })?;
+        let levels = self.path_levels();
+        let mut siblings = Vec::new();
+        let mut position = leaf_index;
+        let mut level_start = self.actual_leaves.next_power_of_two();
+        let mut nodes_in_level = self.actual_leaves;
+        for level in &levels[..levels.len() - 1] {
+            let sibling = position ^ 1;
+            if sibling < nodes_in_level {
+                siblings.push(match level.get(&sibling) {
+                    Some(node) => node.chaining_value(),
+                    None => self.witnesses[&(level_start + sibling)],
+                });
+            }
+            position /= 2;
+            level_start /= 2;
+            nodes_in_level = nodes_in_level.div_ceil(2);
+        }
+        Ok(MerkleProof {
+            leaf_index,
+            actual_leaves: self.actual_leaves,
+            leaf,
+            siblings,
+        })
+    }
+
+    /// Serialize the pruned tree, little-endian throughout:
+    ///
+    /// | field                 | encoding                                        |
+    /// |-----------------------|-------------------------------------------------|
+    /// | magic                 | `b"B3PT"`                                       |
+    /// | version               | u32, currently 1                                |
+    /// | flags                 | u32                                             |
+    /// | key                   | 8 u32 words                                     |
+    /// | leaf count            | u64, of the full tree                           |
+    /// | kept leaf count       | u64                                             |
+    /// | kept leaves           | per leaf: index (u64), then its `Output` bytes  |
+    /// | witness count         | u64                                             |
+    /// | witnesses             | per witness: heap index (u64), then 8 u32 words |
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&PRUNED_MAGIC);
+        bytes.extend_from_slice(&PRUNED_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.flags.to_le_bytes());
+        push_words(&mut bytes, &self.key_words);
+        bytes.extend_from_slice(&(self.actual_leaves as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.leaves.len() as u64).to_le_bytes());
+        for (&leaf_index, leaf) in &self.leaves {
+            bytes.extend_from_slice(&(leaf_index as u64).to_le_bytes());
+            bytes.extend_from_slice(&leaf.to_bytes());
+        }
+        bytes.extend_from_slice(&(self.witnesses.len() as u64).to_le_bytes());
+        for (&index, &cv) in &self.witnesses {
+            bytes.extend_from_slice(&(index as u64).to_le_bytes());
+            bytes.extend_from_slice(&cv_to_bytes(cv));
+        }
+        bytes
+    }
+
+    /// Deserialize a pruned tree written by `to_bytes`. The structure is validated:
+    /// leaves must be in bounds, in increasing order and carry their own chunk
+    /// counter, and the witnesses must be exactly those the kept leaves need. The
+    /// hashes are not; compare `root_hash` with a trusted value.
+    ///
+    /// Malformed input is reported as `io::ErrorKind::InvalidData`, truncated input
+    /// as `UnexpectedEof`.
+    #[cfg(feature = "std")]
+    pub fn from_bytes(mut bytes: &[u8]) -> io::Result<Self> {
+        fn read_array<const N: usize>(reader: &mut &[u8]) -> io::Result<[u8; N]> {
+            let mut array = [0u8; N];
+            reader.read_exact(&mut array)?;
+            Ok(array)
+        }
+        fn read_u64(reader: &mut &[u8]) -> io::Result<u64> {
+            Ok(u64::from_le_bytes(read_array(reader)?))
+        }
+        // Every count is bounded by the bytes left, before anything is allocated for it
+        fn read_count(reader: &mut &[u8], record_len: usize) -> io::Result<usize> {
+            let count = read_u64(reader)?;
+            if count > (reader.len() / record_len) as u64 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("{} records do not fit in the remaining {} bytes", count, reader.len()),
+                ));
+            }
+            Ok(count as usize)
+        }
+
+        if read_array::<4>(&mut bytes)? != PRUNED_MAGIC {
+            return Err(invalid_data("not a serialized pruned tree".to_string()));
+        }
+        let version = u32::from_le_bytes(read_array(&mut bytes)?);
+        if version != PRUNED_VERSION {
+            return Err(invalid_data(format!("unsupported pruned tree version {}", version)));
+        }
+        let flags = u32::from_le_bytes(read_array(&mut bytes)?);
+        let key_words = read_words(&read_array::<OUT_LEN>(&mut bytes)?);
+        let actual_leaves = usize::try_from(read_u64(&mut bytes)?)
+            .ok()
+            .filter(|leaves| (1..=usize::MAX / 4).contains(leaves))
+            .ok_or_else(|| invalid_data("invalid leaf count".to_string()))?;
+
+        let leaf_count = read_count(&mut bytes, 8 + OUTPUT_BYTES_LEN)?;
+        if leaf_count == 0 {
+            return Err(invalid_data("a pruned tree must keep at least one leaf".to_string()));
+        }
+        let mut leaves = BTreeMap::new();
+        for _ in 0..leaf_count {
+            let leaf_index = read_u64(&mut bytes)?;
+            let leaf = Output::try_from(&read_array::<OUTPUT_BYTES_LEN>(&mut bytes)?[..])
+                .map_err(|e| invalid_data(e.to_string()))?;
+            let previous = leaves.keys().next_back().copied();
+            if leaf_index >= actual_leaves as u64 || previous.is_some_and(|previous| previous as u64 >= leaf_index) {
+                return Err(invalid_data(format!("kept leaf {} is out of order or out of bounds", leaf_index)));
+            }
+            if leaf.counter != leaf_index {
+                return Err(invalid_data(format!("leaf {} has counter {}", leaf_index, leaf.counter)));
+            }
+            leaves.insert(leaf_index as usize, leaf);
+        }
+
+        let witness_count = read_count(&mut bytes, 8 + OUT_LEN)?;
+        let mut witnesses = BTreeMap::new();
+        for _ in 0..witness_count {
+            let index = read_u64(&mut bytes)?;
+            let cv = cv_from_bytes(read_array(&mut bytes)?);
+            witnesses.insert(index as usize, cv);
+        }
+        if !bytes.is_empty() {
+            return Err(invalid_data(format!("{} trailing bytes after the pruned tree", bytes.len())));
+        }
+
+        let kept: BTreeSet<usize> = leaves.keys().copied().collect();
+        if witnesses.len() != witness_count || !witnesses.keys().copied().eq(witness_indices(actual_leaves, &kept)) {
+            return Err(invalid_data("witnesses do not match the kept leaves".to_string()));
+        }
+        Ok(PrunedTree {
+            actual_leaves,
+            key_words,
+            flags,
+            leaves,
+            witnesses,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,186 @@
+//! A self-test against BLAKE3's official test vectors, for proving at startup
+//! that the linked hashing code is intact as well as for CI.
+//!
+//! The vectors are embedded from `test_vectors/test_vectors.json`, in the
+//! upstream format: for each input length, the 131-byte extended output of the
+//! hash, keyed_hash and derive_key modes over input bytes `i % 251`. Every mode is
+//! checked through both `Blake3Hasher` and the root of `BinaryMerkleTree::from_input`.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde::Deserialize;
+
+use crate::binary_merkle_tree::{
+    BinaryMerkleTree, Blake3Hasher, DERIVE_KEY_CONTEXT, DERIVE_KEY_MATERIAL, FLAGS, IV, KEYED_HASH, KEY_LEN,
+};
+use crate::le_bytes::read_words;
+
+/// The embedded copy of the official `test_vectors.json`.
+pub const TEST_VECTORS_JSON: &str = include_str!("../test_vectors/test_vectors.json");
+
+#[derive(Deserialize)]
+struct TestVectors {
+    key: String,
+    context_string: String,
+    cases: Vec<TestCase>,
+}
+
+#[derive(Deserialize)]
+struct TestCase {
+    input_len: usize,
+    hash: String,
+    keyed_hash: String,
+    derive_key: String,
+}
+
+/// A BLAKE3 mode, displayed as its field name in the vectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Hash,
+    KeyedHash,
+    DeriveKey,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Hash => "hash",
+            Mode::KeyedHash => "keyed_hash",
+            Mode::DeriveKey => "derive_key",
+        })
+    }
+}
+
+/// The code under test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Implementation {
+    /// The streaming `Blake3Hasher`.
+    Hasher,
+    /// The root output of `BinaryMerkleTree::from_input`.
+    Tree,
+}
+
+impl fmt::Display for Implementation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Implementation::Hasher => "Blake3Hasher",
+            Implementation::Tree => "BinaryMerkleTree",
+        })
+    }
+}
+
+/// One output that differs from its test vector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The position of the vector in the file's `cases`.
+    pub vector: usize,
+    pub input_len: usize,
+    pub mode: Mode,
+    pub implementation: Implementation,
+    pub expected: Vec<u8>,
+    pub actual: Vec<u8>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "vector {} (input_len {}), {} mode, {}: ",
+            self.vector, self.input_len, self.mode, self.implementation
+        )?;
+        // Name the first differing byte rather than print 262 hex digits twice
+        match self.expected.iter().zip(&self.actual).position(|(expected, actual)| expected != actual) {
+            Some(offset) => write!(
+                f,
+                "output byte {} is {:02x}, expected {:02x}",
+                offset, self.actual[offset], self.expected[offset]
+            ),
+            None => write!(f, "output is {} bytes, expected {}", self.actual.len(), self.expected.len()),
+        }
+    }
+}
+
+/// The outcome of a self-test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// The number of test vectors run.
+    pub vectors: usize,
+    /// The number of outputs compared: one per mode and implementation per vector.
+    pub checks: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Run every embedded test vector. The embedded file is known to be well formed.
+pub fn self_test() -> SelfTestReport {
+    run_test_vectors(TEST_VECTORS_JSON).expect("embedded test vectors are malformed")
+}
+
+/// Run the vectors in `json`, in the format of `TEST_VECTORS_JSON`. Returns `None`
+/// if it is not in that format, the key is not 32 bytes, or an output is not hex.
+pub fn run_test_vectors(json: &str) -> Option<SelfTestReport> {
+    let vectors: TestVectors = serde_json::from_str(json).ok()?;
+    let key: &[u8; KEY_LEN] = vectors.key.as_bytes().try_into().ok()?;
+    let key_words = read_words(key);
+    let context_key = BinaryMerkleTree::from_input(vectors.context_string.as_bytes(), IV, DERIVE_KEY_CONTEXT).root_hash();
+    let context_key_words = read_words(context_key.as_bytes());
+
+    let mut report = SelfTestReport { vectors: vectors.cases.len(), checks: 0, mismatches: Vec::new() };
+    for (vector, case) in vectors.cases.iter().enumerate() {
+        let input: Vec<u8> = (0..case.input_len).map(|i| (i % 251) as u8).collect();
+        let modes = [
+            (Mode::Hash, &case.hash, Blake3Hasher::new(), IV, FLAGS),
+            (Mode::KeyedHash, &case.keyed_hash, Blake3Hasher::new_keyed(key), key_words, KEYED_HASH),
+            (
+                Mode::DeriveKey,
+                &case.derive_key,
+                Blake3Hasher::new_derive_key(&vectors.context_string),
+                context_key_words,
+                DERIVE_KEY_MATERIAL,
+            ),
+        ];
+        for (mode, expected_hex, mut hasher, key_words, flags) in modes {
+            let expected = from_hex(expected_hex)?;
+
+            hasher.update(&input);
+            let mut hasher_output = vec![0; expected.len()];
+            hasher.finalize(&mut hasher_output);
+
+            let mut tree_output = vec![0; expected.len()];
+            BinaryMerkleTree::from_input(&input, key_words, flags).output_reader().fill(&mut tree_output);
+
+            for (implementation, actual) in [(Implementation::Hasher, hasher_output), (Implementation::Tree, tree_output)] {
+                report.checks += 1;
+                if actual != expected {
+                    report.mismatches.push(Mismatch {
+                        vector,
+                        input_len: case.input_len,
+                        mode,
+                        implementation,
+                        expected: expected.clone(),
+                        actual,
+                    });
+                }
+            }
+        }
+    }
+    Some(report)
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,195 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::{SerializeTuple, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::binary_merkle_tree::{cv_to_bytes, leaf_hash_output, BinaryMerkleTree, Output, CHUNK_LEN, OUT_LEN};
+use crate::le_bytes::{push_words, read_words};
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex<E: de::Error>(hex: &str, expected_len: usize) -> Result<Vec<u8>, E> {
+    if hex.len() != 2 * expected_len || !hex.is_ascii() {
+        return Err(E::custom(format!("expected {} hex digits, got {:?}", 2 * expected_len, hex)));
+    }
+    (0..expected_len)
+        .map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| E::custom(format!("invalid hex {:?}", hex))))
+        .collect()
+}
+
+/// Word arrays (chaining values, block words) as a lowercase hex string of their
+/// little-endian bytes for human-readable formats, and as a tuple of u32s otherwise.
+pub(crate) mod words {
+    use super::*;
+
+    pub fn serialize<S: Serializer, const N: usize>(words: &[u32; N], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let mut bytes = Vec::with_capacity(4 * N);
+            push_words(&mut bytes, words);
+            serializer.serialize_str(&to_hex(&bytes))
+        } else {
+            let mut tuple = serializer.serialize_tuple(N)?;
+            for word in words {
+                tuple.serialize_element(word)?;
+            }
+            tuple.end()
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(deserializer: D) -> Result<[u32; N], D::Error> {
+        if deserializer.is_human_readable() {
+            let hex = String::deserialize(deserializer)?;
+            let bytes = from_hex::<D::Error>(&hex, 4 * N)?;
+            Ok(read_words(&bytes))
+        } else {
+            deserializer.deserialize_tuple(N, WordsVisitor::<N>)
+        }
+    }
+
+    struct WordsVisitor<const N: usize>;
+
+    impl<'de, const N: usize> Visitor<'de> for WordsVisitor<N> {
+        type Value = [u32; N];
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "{} u32 words", N)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<[u32; N], A::Error> {
+            let mut words = [0; N];
+            for (i, word) in words.iter_mut().enumerate() {
+                *word = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(i, &self))?;
+            }
+            Ok(words)
+        }
+    }
+}
+
+/// A list of chaining values, such as a proof's siblings, each as `words` writes it.
+pub(crate) mod cvs {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(transparent)]
+    struct Cv(#[serde(with = "words")] [u32; 8]);
+
+    pub fn serialize<S: Serializer>(cvs: &[[u32; 8]], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(cvs.iter().map(|&cv| Cv(cv)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<[u32; 8]>, D::Error> {
+        Ok(Vec::<Cv>::deserialize(deserializer)?.into_iter().map(|Cv(cv)| cv).collect())
+    }
+}
+
+/// A 32-byte hash as hex for human-readable formats, and as raw bytes otherwise.
+pub(crate) mod hash_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(hash: &[u8; OUT_LEN], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&to_hex(hash))
+        } else {
+            hash.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; OUT_LEN], D::Error> {
+        if deserializer.is_human_readable() {
+            let hex = String::deserialize(deserializer)?;
+            Ok(from_hex::<D::Error>(&hex, OUT_LEN)?.try_into().unwrap())
+        } else {
+            <[u8; OUT_LEN]>::deserialize(deserializer)
+        }
+    }
+}
+
+/// The logical content of a tree. Padding and indices are derived from it on load,
+/// and the root hash is stored so that tampered leaves are rejected.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "BinaryMerkleTree")]
+struct SerializedTree {
+    #[serde(with = "words")]
+    key_words: [u32; 8],
+    flags: u32,
+    input_len: Option<u64>,
+    leaves: Vec<Output>,
+    // Whether the leaves are leaf hashes (see `from_leaf_hashes`); absent means false
+    #[serde(default)]
+    leaf_hashes: bool,
+    #[serde(with = "hash_bytes")]
+    root_hash: [u8; OUT_LEN],
+}
+
+impl Serialize for BinaryMerkleTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedTree {
+            key_words: self.key_words(),
+            flags: self.flags(),
+            input_len: self.input_len(),
+            leaves: (0..self.actual_leaves()).filter_map(|leaf_index| self.leaf(leaf_index)).collect(),
+            leaf_hashes: self.has_leaf_hashes(),
+            root_hash: self.root_hash().0,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BinaryMerkleTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let serialized = SerializedTree::deserialize(deserializer)?;
+        if serialized.leaves.is_empty() {
+            return Err(de::Error::custom("a tree has at least one leaf"));
+        }
+        if serialized.leaf_hashes {
+            return deserialize_leaf_hashes(serialized);
+        }
+        for (leaf_index, leaf) in serialized.leaves.iter().enumerate() {
+            if leaf.counter != leaf_index as u64 {
+                return Err(de::Error::custom(format!("leaf {} has counter {}", leaf_index, leaf.counter)));
+            }
+        }
+        let leaf_count = serialized.leaves.len();
+        if let Some(input_len) = serialized.input_len {
+            if input_len.div_ceil(CHUNK_LEN as u64).max(1) != leaf_count as u64 {
+                return Err(de::Error::custom(format!("input length {} does not fit {} leaves", input_len, leaf_count)));
+            }
+        }
+
+        let mut tree = BinaryMerkleTree::new_from_leaves(serialized.leaves, serialized.key_words, serialized.flags);
+        if let Some(input_len) = serialized.input_len {
+            tree = tree.with_input_len(input_len);
+        }
+        if tree.root_hash() != serialized.root_hash {
+            return Err(de::Error::custom("leaves do not hash to the stored root"));
+        }
+        Ok(tree)
+    }
+}
+
+/// Rebuild a tree of leaf hashes through `from_leaf_hashes`. Such leaves have no
+/// chunk counter, but each must be exactly the Output that tree stores for a hash.
+fn deserialize_leaf_hashes<E: de::Error>(serialized: SerializedTree) -> Result<BinaryMerkleTree, E> {
+    if serialized.input_len.is_some() {
+        return Err(E::custom("a tree of leaf hashes has no input length"));
+    }
+    let mut hashes = Vec::with_capacity(serialized.leaves.len());
+    for (leaf_index, leaf) in serialized.leaves.iter().enumerate() {
+        let hash = cv_to_bytes(leaf.input_chaining_value);
+        if *leaf != leaf_hash_output(&hash, serialized.flags) {
+            return Err(E::custom(format!("leaf {} is not a leaf hash", leaf_index)));
+        }
+        hashes.push(hash);
+    }
+    let tree = BinaryMerkleTree::from_leaf_hashes(&hashes, serialized.key_words, serialized.flags).map_err(E::custom)?;
+    if tree.flags() != serialized.flags || tree.root_hash() != serialized.root_hash {
+        return Err(E::custom("leaves do not hash to the stored root"));
+    }
+    Ok(tree)
+}
@@ -0,0 +1,213 @@
+//! Trees with their lower levels dropped, for files that have been verified once
+//! and only need checking again a region at a time.
+//!
+//! Cutting a tree `cut_level` levels above the leaves leaves one node per region
+//! of `2^cut_level` chunks: regions start at chunk `region << cut_level`, and only
+//! the last may be shorter. Those nodes and everything above them are kept, so a
+//! re-downloaded region is checked by rehashing its chunks up to the region's
+//! node, and a region's proof is the path from that node to the root.
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+use crate::binary_merkle_tree::{chunk_count, BinaryMerkleTree, ChunkState, Hash32, Output, CHUNK_LEN};
+#[cfg(feature = "std")]
+use crate::binary_merkle_tree::{OUTPUT_BYTES_LEN, OUT_LEN};
+use crate::le_bytes::push_words;
+#[cfg(feature = "std")]
+use crate::le_bytes::read_words;
+use crate::proof::{subtree_node, MerkleProof};
+
+// Serialized shallow tree format: magic and version.
+const SHALLOW_MAGIC: [u8; 4] = *b"B3ST";
+const SHALLOW_VERSION: u32 = 1;
+
+/// The top levels of a `BinaryMerkleTree`, made with `BinaryMerkleTree::truncate_depth`.
+///
+/// The kept levels form a tree of their own whose leaves are the region nodes, so
+/// its root is the full tree's root and its proofs are the full tree's proofs
+/// with the levels below the cut left out.
+#[derive(Debug, Clone)]
+pub struct ShallowTree {
+    actual_leaves: usize,
+    cut_level: u32,
+    regions: BinaryMerkleTree,
+}
+
+impl BinaryMerkleTree {
+    /// Keep the top `keep_levels` levels and drop the rest. A tree has
+    /// `num_leaves().ilog2() + 1` levels, counting the leaves and the root: 1 keeps
+    /// only the root, and asking for more levels than there are keeps them all, one
+    /// region per chunk. For a tree of 2^20 chunks, 11 levels keep one node per
+    /// 1024 chunks.
+    pub fn truncate_depth(&self, keep_levels: usize) -> ShallowTree {
+        let levels = self.num_leaves().ilog2() as usize + 1;
+        let cut_level = (levels - keep_levels.clamp(1, levels)) as u32;
+        let level_start = self.num_leaves() >> cut_level;
+        let region_count = Self::level_sizes(self.actual_leaves())[cut_level as usize];
+        let regions = (level_start..level_start + region_count).map(|index| self.node(index)).collect();
+        ShallowTree::from_regions(self.actual_leaves(), cut_level, regions, self.key_words(), self.flags())
+    }
+}
+
+impl ShallowTree {
+    fn from_regions(actual_leaves: usize, cut_level: u32, regions: Vec<Output>, key_words: [u32; 8], flags: u32) -> Self {
+        ShallowTree {
+            actual_leaves,
+            cut_level,
+            regions: BinaryMerkleTree::new_from_leaves(regions, key_words, flags),
+        }
+    }
+
+    /// The number of leaves in the full tree.
+    pub fn actual_leaves(&self) -> usize {
+        self.actual_leaves
+    }
+
+    /// How many levels above the leaves the tree was cut: each region covers
+    /// `2^cut_level` chunks.
+    pub fn cut_level(&self) -> u32 {
+        self.cut_level
+    }
+
+    /// The number of regions, the last of which may be short.
+    pub fn region_count(&self) -> usize {
+        self.regions.actual_leaves()
+    }
+
+    /// The chunks region `region_index` covers, or `None` if there is no such region.
+    pub fn region_chunks(&self, region_index: usize) -> Option<core::ops::Range<usize>> {
+        if region_index >= self.region_count() {
+            return None;
+        }
+        let first = region_index << self.cut_level;
+        Some(first..self.actual_leaves.min((region_index + 1) << self.cut_level))
+    }
+
+    /// The non-root chaining value of region `region_index`'s node.
+    pub fn region_cv(&self, region_index: usize) -> Option<[u32; 8]> {
+        Some(self.regions.leaf(region_index)?.chaining_value())
+    }
+
+    /// The 32-byte root hash of the full tree.
+    pub fn root_hash(&self) -> Hash32 {
+        self.regions.root_hash()
+    }
+
+    /// Check `region_bytes` against region `region_index` by hashing its chunks at
+    /// their positions in the full input and folding them up to the region's node.
+    /// Returns false for a region that does not exist or bytes that do not split
+    /// into the region's number of chunks.
+    pub fn verify_region(&self, region_index: usize, region_bytes: &[u8]) -> bool {
+        let Some(chunks) = self.region_chunks(region_index) else {
+            return false;
+        };
+        if chunk_count(region_bytes.len()) != chunks.len() {
+            return false;
+        }
+        let (key_words, flags) = (self.regions.key_words(), self.regions.flags());
+        let leaves: Vec<Output> = chunks
+            .clone()
+            .map(|chunk_index| {
+                let start = (chunk_index - chunks.start) * CHUNK_LEN;
+                let mut chunk_state = ChunkState::new(key_words, chunk_index as u64, flags);
+                chunk_state.update(&region_bytes[start..(start + CHUNK_LEN).min(region_bytes.len())]);
+                chunk_state.output()
+            })
+            .collect();
+        let verified = Some(subtree_node(&leaves, key_words, flags).chaining_value()) == self.region_cv(region_index);
+        #[cfg(feature = "tracing")]
+        if !verified {
+            tracing::debug!(region_index, len = region_bytes.len(), "region failed verification");
+        }
+        verified
+    }
+
+    /// The proof that region `region_index`'s node is in the tree: a `MerkleProof`
+    /// over the regions, with `leaf_index` the region index, `actual_leaves` the
+    /// region count and `leaf` the region's node. It verifies against the full
+    /// tree's root hash with `MerkleProof::verify`.
+    pub fn region_proof(&self, region_index: usize) -> Option<MerkleProof> {
+        self.regions.generate_proof(region_index)
+    }
+
+    /// Serialize the shallow tree, little-endian throughout:
+    ///
+    /// | field        | encoding                                            |
+    /// |--------------|-----------------------------------------------------|
+    /// | magic        | `b"B3ST"`                                           |
+    /// | version      | u32, currently 1                                    |
+    /// | flags        | u32                                                 |
+    /// | key          | 8 u32 words                                         |
+    /// | leaf count   | u64, of the full tree                               |
+    /// | cut level    | u32                                                 |
+    /// | region nodes | one `Output` per region, as many as the cut implies |
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&SHALLOW_MAGIC);
+        bytes.extend_from_slice(&SHALLOW_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.regions.flags().to_le_bytes());
+        push_words(&mut bytes, &self.regions.key_words());
+        bytes.extend_from_slice(&(self.actual_leaves as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.cut_level.to_le_bytes());
+        for region_index in 0..self.region_count() {
+            bytes.extend_from_slice(&self.regions.leaf(region_index).unwrap().to_bytes());
+        }
+        bytes
+    }
+
+    /// Deserialize a shallow tree written by `to_bytes`. The structure is
+    /// validated, but not the hashes; compare `root_hash` with a trusted value.
+    ///
+    /// Malformed input is reported as `io::ErrorKind::InvalidData`, truncated input
+    /// as `UnexpectedEof`.
+    #[cfg(feature = "std")]
+    pub fn from_bytes(mut bytes: &[u8]) -> io::Result<Self> {
+        fn read_array<const N: usize>(reader: &mut &[u8]) -> io::Result<[u8; N]> {
+            let mut array = [0u8; N];
+            reader.read_exact(&mut array)?;
+            Ok(array)
+        }
+
+        if read_array::<4>(&mut bytes)? != SHALLOW_MAGIC {
+            return Err(invalid_data("not a serialized shallow tree".to_string()));
+        }
+        let version = u32::from_le_bytes(read_array(&mut bytes)?);
+        if version != SHALLOW_VERSION {
+            return Err(invalid_data(format!("unsupported shallow tree version {}", version)));
+        }
+        let flags = u32::from_le_bytes(read_array(&mut bytes)?);
+        let key_words = read_words(&read_array::<OUT_LEN>(&mut bytes)?);
+        let actual_leaves = usize::try_from(u64::from_le_bytes(read_array(&mut bytes)?))
+            .ok()
+            .filter(|leaves| (1..=usize::MAX / 4).contains(leaves))
+            .ok_or_else(|| invalid_data("invalid leaf count".to_string()))?;
+        let cut_level = u32::from_le_bytes(read_array(&mut bytes)?);
+        if cut_level > actual_leaves.next_power_of_two().ilog2() {
+            return Err(invalid_data(format!("cut level {} is above the root", cut_level)));
+        }
+
+        let region_count = BinaryMerkleTree::level_sizes(actual_leaves)[cut_level as usize];
+        if bytes.len() / OUTPUT_BYTES_LEN < region_count {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} regions do not fit in the remaining {} bytes", region_count, bytes.len()),
+            ));
+        }
+        let regions = bytes[..region_count * OUTPUT_BYTES_LEN]
+            .chunks_exact(OUTPUT_BYTES_LEN)
+            .map(|region| Output::try_from(region).map_err(|e| invalid_data(e.to_string())))
+            .collect::<io::Result<Vec<_>>>()?;
+        let trailing = bytes.len() - region_count * OUTPUT_BYTES_LEN;
+        if trailing != 0 {
+            return Err(invalid_data(format!("{} trailing bytes after the shallow tree", trailing)));
+        }
+        Ok(Self::from_regions(actual_leaves, cut_level, regions, key_words, flags))
+    }
+}
+
+#[cfg(feature = "std")]
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
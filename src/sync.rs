@@ -0,0 +1,84 @@
+//! Planning which chunks to fetch to turn one tree's input into another's.
+//!
+//! Like rsync, a client holding a local copy compares its tree with the remote's
+//! and fetches only the chunks that differ. Subtrees covering the same leaves
+//! with the same chaining value are skipped whole, so the descent visits
+//! `O(changed · log n)` nodes rather than every leaf.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::binary_merkle_tree::{GenericMerkleTree, CHUNK_LEN};
+use crate::node_store::NodeStore;
+
+/// The chunks a local copy must fetch to match a remote one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncPlan {
+    /// Indices of the remote's chunks to fetch, ascending: the changed ones, then
+    /// any the local copy is too short to have.
+    pub chunks: Vec<usize>,
+    /// The remote byte ranges those chunks cover, ascending, with adjacent ranges merged.
+    pub byte_ranges: Vec<Range<u64>>,
+    /// The remote input length the local copy should end up with. Taken from
+    /// `remote.input_len()`, or assuming whole chunks when that is unknown; a
+    /// longer local copy is truncated to it.
+    pub remote_len: u64,
+    /// The number of node pairs compared.
+    pub nodes_visited: usize,
+}
+
+/// Plan the fetches that bring `local`'s input in line with `remote`'s. Both trees
+/// must use the same key and flags; if they do not, every node differs and every
+/// chunk is fetched.
+///
+/// Nodes are compared where they cover the same run of leaves in both trees: every
+/// complete power-of-two run within the shorter tree, and, when the leaf counts are
+/// equal, the partial runs at the right edge too. Chunks past the end of `local`
+/// are always fetched.
+pub fn sync_plan<S: NodeStore, T: NodeStore>(local: &GenericMerkleTree<S>, remote: &GenericMerkleTree<T>) -> SyncPlan {
+    let common = local.actual_leaves().min(remote.actual_leaves());
+    let same_shape = local.actual_leaves() == remote.actual_leaves();
+    let mut chunks = Vec::new();
+    let mut nodes_visited = 0;
+
+    // (height, position) of nodes to compare, leftmost on top
+    let top = common.next_power_of_two().trailing_zeros();
+    let mut stack = Vec::from([(top, 0usize)]);
+    while let Some((height, position)) = stack.pop() {
+        let start = position << height;
+        let end = (position + 1) << height;
+        if end <= common || same_shape {
+            nodes_visited += 1;
+            let local_node = local.node((local.num_leaves() >> height) + position);
+            let remote_node = remote.node((remote.num_leaves() >> height) + position);
+            if local_node.chaining_value() == remote_node.chaining_value() {
+                continue;
+            }
+        }
+        if height == 0 {
+            chunks.push(start);
+            continue;
+        }
+        let right = (height - 1, 2 * position + 1);
+        if right.1 << right.0 < common {
+            stack.push(right);
+        }
+        stack.push((height - 1, 2 * position));
+    }
+    chunks.extend(common..remote.actual_leaves());
+
+    let remote_len = remote.input_len().unwrap_or((remote.actual_leaves() * CHUNK_LEN) as u64);
+    let mut byte_ranges: Vec<Range<u64>> = Vec::new();
+    for &chunk in &chunks {
+        let range = (chunk * CHUNK_LEN) as u64..(((chunk + 1) * CHUNK_LEN) as u64).min(remote_len);
+        // The single empty chunk of empty input has no bytes to fetch
+        if range.is_empty() {
+            continue;
+        }
+        match byte_ranges.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => byte_ranges.push(range),
+        }
+    }
+    SyncPlan { chunks, byte_ranges, remote_len, nodes_visited }
+}
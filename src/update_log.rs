@@ -0,0 +1,147 @@
+//! Write-ahead log of leaf updates, so a persisted tree can be brought up to date
+//! without re-serializing it after every change.
+//!
+//! The log is a sequence of fixed-size records, little-endian throughout: the
+//! leaf index (u64), the new leaf's `Output` bytes, and the non-root chaining
+//! value of the root after the update (8 u32 words). The root chaining value lets
+//! replay check every record against the state it was written from.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::binary_merkle_tree::{cv_from_bytes, BinaryMerkleTree, LeafIndex, Output, OUTPUT_BYTES_LEN, OUT_LEN};
+use crate::error::MerkleError;
+
+/// Size of one record in the update log.
+pub const UPDATE_RECORD_LEN: usize = 8 + OUTPUT_BYTES_LEN + OUT_LEN;
+
+/// Why `replay` stopped, and the tree as far as it got.
+#[derive(Debug)]
+pub struct ReplayError {
+    /// The record that could not be applied, counting from 0. Every record before
+    /// it was applied and checked; the log is intact up to byte
+    /// `record * UPDATE_RECORD_LEN`.
+    pub record: u64,
+    /// The base tree with exactly the records before `record` applied. After a
+    /// crash mid-append (`UnexpectedEof`), this is the recovered tree.
+    pub tree: Box<BinaryMerkleTree>,
+    /// The underlying cause: `UnexpectedEof` if the log ends partway through the
+    /// record, `InvalidData` for an out-of-bounds leaf, an invalid Output or a root
+    /// that does not match the logged one, or the reader's own error.
+    pub error: io::Error,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "update log replay stopped at record {}: {}", self.record, self.error)
+    }
+}
+
+impl std::error::Error for ReplayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<ReplayError> for io::Error {
+    fn from(e: ReplayError) -> Self {
+        io::Error::new(e.error.kind(), e)
+    }
+}
+
+/// Fill as much of `buf` as `reader` yields before EOF, returning the count.
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl BinaryMerkleTree {
+    /// Replace the leaf at `leaf_index` as `insert_leaf` does, and append the update
+    /// to `log` as one record in a single write.
+    ///
+    /// If writing the record fails, the update is undone before the error is
+    /// returned, so the tree never runs ahead of the log. An out-of-bounds
+    /// `leaf_index` is reported as `io::ErrorKind::InvalidInput` wrapping
+    /// `MerkleError::LeafIndexOutOfBounds`, and nothing is written.
+    pub fn apply_and_log<W: Write>(&mut self, leaf_index: impl Into<LeafIndex>, leaf_output: Output, mut log: W) -> io::Result<()> {
+        let LeafIndex(leaf_index) = leaf_index.into();
+        let previous_leaf = self.leaf(leaf_index).ok_or(MerkleError::LeafIndexOutOfBounds {
+            leaf_index,
+            actual_leaves: self.actual_leaves(),
+        })?;
+        let previous_input_len = self.input_len();
+        self.insert_leaf(leaf_index, leaf_output)?;
+
+        let mut record = Vec::with_capacity(UPDATE_RECORD_LEN);
+        record.extend_from_slice(&(leaf_index as u64).to_le_bytes());
+        record.extend_from_slice(&leaf_output.to_bytes());
+        record.extend_from_slice(&self.node(1).cv_bytes());
+        if let Err(e) = log.write_all(&record) {
+            self.insert_leaf(leaf_index, previous_leaf)?;
+            self.set_input_len(previous_input_len);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Apply every record of an update log written by `apply_and_log` to `base`,
+    /// checking after each one that the root matches the logged root.
+    ///
+    /// Stops at the first record that is incomplete, invalid or does not match,
+    /// returning the tree as of the record before it.
+    pub fn replay<R: Read>(base: Self, mut log: R) -> Result<Self, ReplayError> {
+        let mut tree = base;
+        let mut record_bytes = [0u8; UPDATE_RECORD_LEN];
+        for record in 0u64.. {
+            let result = read_up_to(&mut log, &mut record_bytes).and_then(|len| match len {
+                0 => Ok(false),
+                UPDATE_RECORD_LEN => tree.apply_record(&record_bytes).map(|_| true),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("log ends {} bytes into a {}-byte record", len, UPDATE_RECORD_LEN),
+                )),
+            });
+            match result {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(error) => return Err(ReplayError { record, tree: Box::new(tree), error }),
+            }
+        }
+        Ok(tree)
+    }
+
+    /// Apply one complete record, leaving the tree unchanged if it is rejected.
+    fn apply_record(&mut self, record: &[u8; UPDATE_RECORD_LEN]) -> io::Result<()> {
+        let leaf_index = u64::from_le_bytes(record[..8].try_into().unwrap());
+        let leaf_output = Output::try_from(&record[8..8 + OUTPUT_BYTES_LEN]).map_err(|e| invalid_data(e.to_string()))?;
+        let logged_root_cv = cv_from_bytes(record[8 + OUTPUT_BYTES_LEN..].try_into().unwrap());
+        let leaf_index = usize::try_from(leaf_index)
+            .ok()
+            .filter(|&leaf_index| leaf_index < self.actual_leaves())
+            .ok_or_else(|| invalid_data(format!("leaf {} is out of bounds for {} leaves", leaf_index, self.actual_leaves())))?;
+
+        // Undo by reinserting the old leaf rather than keeping a snapshot, which
+        // would make the update copy every node
+        let previous_leaf = self.leaf(leaf_index).unwrap();
+        let previous_input_len = self.input_len();
+        self.insert_leaf(leaf_index, leaf_output)?;
+        if self.node(1).chaining_value() != logged_root_cv {
+            self.insert_leaf(leaf_index, previous_leaf)?;
+            self.set_input_len(previous_input_len);
+            return Err(invalid_data(format!("root after updating leaf {} does not match the log", leaf_index)));
+        }
+        Ok(())
+    }
+}
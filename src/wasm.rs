@@ -0,0 +1,76 @@
+//! wasm-bindgen bindings for checking downloads in the browser against a
+//! published root hash: an incremental hasher, chunk proof verification, and bao
+//! slice verification. All use the regular hash mode (`IV` and `FLAGS`), except
+//! proofs that carry their own parameters.
+//!
+//! Nothing here panics on bad input: malformed arguments make `verify_proof`
+//! return false and the other functions return an error string as a `JsValue`.
+//! The feature needs only `alloc`, so build with
+//! `--no-default-features --features wasm` to leave out the std-only tree
+//! construction (readers, files, serialization and the bao encoders).
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use wasm_bindgen::prelude::*;
+
+use crate::bao;
+use crate::binary_merkle_tree::{Blake3Hasher, FLAGS, IV, OUT_LEN};
+use crate::proof::{self, MerkleProof};
+
+/// Incremental BLAKE3 hashing of data fed in pieces, such as a streamed download.
+#[wasm_bindgen]
+pub struct WasmHasher {
+    hasher: Blake3Hasher,
+}
+
+#[wasm_bindgen]
+impl WasmHasher {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmHasher {
+        WasmHasher { hasher: Blake3Hasher::new() }
+    }
+
+    pub fn update(&mut self, input: &[u8]) {
+        self.hasher.update(input);
+    }
+
+    /// The 32-byte hash of everything passed to `update` so far. The hasher can keep
+    /// being updated afterwards.
+    pub fn finalize(&self) -> Vec<u8> {
+        self.hasher.finalize_array().to_vec()
+    }
+}
+
+impl Default for WasmHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `chunk` is chunk `index` of the input with the 32-byte `root`, according
+/// to `proof` in the layout of `MerkleProof::to_path_bytes`, hashed in the regular
+/// mode, or of `MerkleProof::to_path_bytes_with_params`, hashed with the embedded
+/// key and flags. False for a proof of another index and for malformed arguments.
+#[wasm_bindgen]
+pub fn verify_proof(root: &[u8], proof: &[u8], chunk: &[u8], index: u32) -> bool {
+    let Ok(root) = <&[u8; OUT_LEN]>::try_from(root) else {
+        return false;
+    };
+    // The two layouts never share a length, so at most one parses
+    let parsed = MerkleProof::from_path_bytes_with_params(proof, chunk)
+        .or_else(|| Some((MerkleProof::from_path_bytes(proof, chunk, IV, FLAGS)?, IV, FLAGS)));
+    match parsed {
+        Some((proof, key_words, flags)) => proof.leaf_index == index as usize && proof::verify_proof(&proof, root, key_words, flags),
+        None => false,
+    }
+}
+
+/// Verify a bao slice for the bytes `start..end` against the 32-byte `root`, and
+/// return those bytes. See `bao::verify_slice`.
+#[wasm_bindgen]
+pub fn verify_slice(slice: &[u8], root: &[u8], start: u64, end: u64) -> Result<Vec<u8>, JsValue> {
+    let root = <&[u8; OUT_LEN]>::try_from(root)
+        .map_err(|_| JsValue::from_str("root hash must be 32 bytes"))?;
+    bao::verify_slice(slice, root, IV, FLAGS, start..end).map_err(|e| JsValue::from_str(&e.to_string()))
+}
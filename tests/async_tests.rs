@@ -0,0 +1,71 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, CHUNK_LEN, IV, FLAGS};
+use tokio::io::{AsyncWriteExt, DuplexStream};
+use common::random_input;
+
+const ASYNC_INPUT_SIZE: usize = 3 * 1024 * 1024 + 77; // ~3MB, ending in a partial chunk
+
+/// Spawn a task that writes `input` into one end of a duplex pipe in small pieces
+fn duplex_source(input: Vec<u8>, write_size: usize) -> DuplexStream {
+    let (mut writer, reader) = tokio::io::duplex(4096);
+    tokio::spawn(async move {
+        for piece in input.chunks(write_size) {
+            writer.write_all(piece).await.unwrap();
+        }
+        writer.shutdown().await.unwrap();
+    });
+    reader
+}
+
+/// Tests hashing an async stream with the incremental hasher
+/// Verifies the digest and byte count match the synchronous path
+/// Methods tested: Blake3Hasher::update_async_reader
+#[tokio::test]
+async fn test_update_async_reader_matches_sync() {
+    let input = random_input(ASYNC_INPUT_SIZE);
+    let mut hasher = Blake3Hasher::new();
+    let bytes_hashed = hasher.update_async_reader(duplex_source(input.clone(), 1000)).await.unwrap();
+    assert_eq!(bytes_hashed, input.len() as u64);
+
+    let mut sync_hasher = Blake3Hasher::new();
+    sync_hasher.update(&input);
+    assert_eq!(hasher.finalize_array(), sync_hasher.finalize_array());
+}
+
+/// Tests building a tree from an async stream, inline and with blocking offload
+/// Verifies that both roots equal BinaryMerkleTree::from_input over the same bytes
+/// Methods tested: BinaryMerkleTree::from_async_reader, BinaryMerkleTree::from_async_reader_offloaded
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_from_async_reader_matches_from_input() {
+    let input = random_input(ASYNC_INPUT_SIZE);
+    let expected = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+
+    let tree = BinaryMerkleTree::from_async_reader(duplex_source(input.clone(), 777), IV, FLAGS).await.unwrap();
+    assert_eq!(tree.actual_leaves(), expected.actual_leaves());
+    assert_eq!(tree.root_hash(), expected.root_hash());
+
+    // Batch sizes that are not chunk multiples get rounded up
+    for &batch_len in &[CHUNK_LEN, 100 * CHUNK_LEN + 1, 1 << 20] {
+        let tree = BinaryMerkleTree::from_async_reader_offloaded(duplex_source(input.clone(), 777), IV, FLAGS, batch_len)
+            .await
+            .unwrap();
+        assert_eq!(tree.root_hash(), expected.root_hash(), "Root mismatch with batch_len {}", batch_len);
+    }
+}
+
+/// Tests async tree construction on empty and exactly-one-batch inputs
+/// Methods tested: BinaryMerkleTree::from_async_reader, BinaryMerkleTree::from_async_reader_offloaded
+#[tokio::test]
+async fn test_from_async_reader_edge_sizes() {
+    for &size in &[0, 1, 4 * CHUNK_LEN] {
+        let input = random_input(size);
+        let expected = BinaryMerkleTree::from_input(&input, IV, FLAGS).root_hash();
+        let inline = BinaryMerkleTree::from_async_reader(&input[..], IV, FLAGS).await.unwrap();
+        let offloaded = BinaryMerkleTree::from_async_reader_offloaded(&input[..], IV, FLAGS, 4 * CHUNK_LEN).await.unwrap();
+        assert_eq!(inline.root_hash(), expected, "Inline root mismatch for size {}", size);
+        assert_eq!(offloaded.root_hash(), expected, "Offloaded root mismatch for size {}", size);
+    }
+}
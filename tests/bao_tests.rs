@@ -0,0 +1,330 @@
+mod common;
+
+use blake3::hazmat::HasherExt;
+use merkle_tree::bao::{decode_and_verify, encode_combined, encode_outboard_bao, extract_slice, verify_slice, SliceError, VerifiedDecoder};
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, CHUNK_LEN, IV, FLAGS};
+use merkle_tree::error::MerkleError;
+use rand::Rng;
+use std::io::{self, Read};
+use common::{random_input, SeededRng};
+
+const BAO_SIZES: [usize; 9] = [0, 1, CHUNK_LEN - 1, CHUNK_LEN, CHUNK_LEN + 1, 3 * CHUNK_LEN, 4 * CHUNK_LEN, 37 * CHUNK_LEN + 123, 64 * CHUNK_LEN];
+
+// Written by the bao crate (0.13.1) with `bao::encode::encode` and `bao::encode::outboard`,
+// over the test-vector pattern (byte i is i % 251)
+const BAO_CRATE_FIXTURES: [(usize, &[u8], &[u8]); 4] = [
+    (0, include_bytes!("fixtures/bao_combined_0.bin"), include_bytes!("fixtures/bao_outboard_0.bin")),
+    (1, include_bytes!("fixtures/bao_combined_1.bin"), include_bytes!("fixtures/bao_outboard_1.bin")),
+    (1024, include_bytes!("fixtures/bao_combined_1024.bin"), include_bytes!("fixtures/bao_outboard_1024.bin")),
+    (1025, include_bytes!("fixtures/bao_combined_1025.bin"), include_bytes!("fixtures/bao_outboard_1025.bin")),
+];
+
+fn encode(input: &[u8]) -> (Vec<u8>, [u8; 32]) {
+    let tree = BinaryMerkleTree::from_input(input, IV, FLAGS);
+    (tree.encode_bao(input).unwrap(), tree.root_hash().into())
+}
+
+/// The non-root chaining value of a subtree starting at `offset`, from the blake3 crate
+fn reference_cv(subtree: &[u8], offset: usize) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.set_input_offset(offset as u64);
+    hasher.update(subtree);
+    hasher.finalize_non_root()
+}
+
+/// Append the pre-order parent pairs of the subtree over `subtree`, which starts at `offset`,
+/// following the bao spec and using only the blake3 crate
+fn reference_outboard_parents(subtree: &[u8], offset: usize, outboard: &mut Vec<u8>) {
+    if subtree.len() <= CHUNK_LEN {
+        return;
+    }
+    // As in the bao spec: the largest power of two of the full chunks that leave a non-empty right subtree
+    let full_chunks = (subtree.len() - 1) / CHUNK_LEN;
+    let left_len = (1 << full_chunks.ilog2()) * CHUNK_LEN;
+    let (left, right) = subtree.split_at(left_len);
+    outboard.extend_from_slice(&reference_cv(left, offset));
+    outboard.extend_from_slice(&reference_cv(right, offset + left_len));
+    reference_outboard_parents(left, offset, outboard);
+    reference_outboard_parents(right, offset + left_len, outboard);
+}
+
+/// Append the pre-order combined encoding of `subtree`, which starts at `offset`, following the
+/// bao spec and using only the blake3 crate
+fn reference_combined(subtree: &[u8], offset: usize, encoded: &mut Vec<u8>) {
+    if subtree.len() <= CHUNK_LEN {
+        encoded.extend_from_slice(subtree);
+        return;
+    }
+    let full_chunks = (subtree.len() - 1) / CHUNK_LEN;
+    let left_len = (1 << full_chunks.ilog2()) * CHUNK_LEN;
+    let (left, right) = subtree.split_at(left_len);
+    encoded.extend_from_slice(&reference_cv(left, offset));
+    encoded.extend_from_slice(&reference_cv(right, offset + left_len));
+    reference_combined(left, offset, encoded);
+    reference_combined(right, offset + left_len, encoded);
+}
+
+/// Tests encoding and decoding the combined bao format
+/// Verifies the encoded size and that decoding against the root hash returns the input
+/// Methods tested: BinaryMerkleTree::encode_bao, decode_and_verify
+#[test]
+fn test_bao_round_trip() {
+    for &size in BAO_SIZES.iter() {
+        let input = random_input(size);
+        let (encoded, root_hash) = encode(&input);
+        let parents = size.div_ceil(CHUNK_LEN).max(1) - 1;
+        assert_eq!(encoded.len(), 8 + 64 * parents + size);
+        assert_eq!(root_hash, *blake3::hash(&input).as_bytes());
+
+        let decoded = decode_and_verify(&encoded, &root_hash, IV, FLAGS).unwrap();
+        assert_eq!(decoded, input, "Round trip mismatch for size {}", size);
+    }
+}
+
+/// Tests the byte layout of the encoding against chaining values from the blake3 crate
+/// Verifies the header and the pre-order placement of parents and chunks for three chunks.
+/// The bao crate itself is not a dependency, so the layout is checked against the spec.
+/// Methods tested: BinaryMerkleTree::encode_bao
+#[test]
+fn test_bao_layout_matches_spec() {
+    let input = random_input(3 * CHUNK_LEN);
+    let (encoded, _) = encode(&input);
+    let (c0, c1, c2) = (&input[..CHUNK_LEN], &input[CHUNK_LEN..2 * CHUNK_LEN], &input[2 * CHUNK_LEN..]);
+
+    let mut expected = (input.len() as u64).to_le_bytes().to_vec();
+    // Root: the two-chunk left subtree and the lone right chunk
+    expected.extend_from_slice(&reference_cv(&input[..2 * CHUNK_LEN], 0));
+    expected.extend_from_slice(&reference_cv(c2, 2 * CHUNK_LEN));
+    // Left subtree, then its chunks, then the right chunk
+    expected.extend_from_slice(&reference_cv(c0, 0));
+    expected.extend_from_slice(&reference_cv(c1, CHUNK_LEN));
+    expected.extend_from_slice(c0);
+    expected.extend_from_slice(c1);
+    expected.extend_from_slice(c2);
+    assert_eq!(encoded, expected);
+}
+
+/// Tests that decoding rejects corrupted, truncated and extended encodings and wrong roots
+/// Methods tested: decode_and_verify
+#[test]
+fn test_bao_decode_rejects_bad_encoding() {
+    let input = random_input(37 * CHUNK_LEN + 123);
+    let (encoded, root_hash) = encode(&input);
+
+    // A flipped bit in the root parent, in a deeper parent, and in the final chunk
+    for &position in &[8, 8 + 64 + 5, encoded.len() - 1] {
+        let mut corrupted = encoded.clone();
+        corrupted[position] ^= 0x01;
+        let err = decode_and_verify(&corrupted, &root_hash, IV, FLAGS).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "Corruption at byte {} not detected", position);
+    }
+
+    let err = decode_and_verify(&encoded[..encoded.len() - 1], &root_hash, IV, FLAGS).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+    let mut extended = encoded.clone();
+    extended.push(0);
+    assert_eq!(decode_and_verify(&extended, &root_hash, IV, FLAGS).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let mut wrong_root = root_hash;
+    wrong_root[0] ^= 0x01;
+    assert_eq!(decode_and_verify(&encoded, &wrong_root, IV, FLAGS).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+/// Tests the bao outboard encoding against a spec reference built on the blake3 crate
+/// Verifies empty, 1-byte, exact-chunk, chunk-plus-one and multi-MB inputs. The bao crate itself
+/// is not a dependency, so the reference follows the spec's pre-order layout instead.
+/// Methods tested: encode_outboard_bao, BinaryMerkleTree::to_bao_outboard
+#[test]
+fn test_bao_outboard_matches_spec() {
+    for &size in &[0, 1, CHUNK_LEN, CHUNK_LEN + 1, 2 * CHUNK_LEN + 1, 3 * CHUNK_LEN, 5 * CHUNK_LEN + 1, 3 * 1024 * 1024 + 4321] {
+        let input = random_input(size);
+        let (outboard, hash) = encode_outboard_bao(&input);
+        assert_eq!(hash, *blake3::hash(&input).as_bytes());
+
+        let mut expected = (size as u64).to_le_bytes().to_vec();
+        reference_outboard_parents(&input, 0, &mut expected);
+        assert_eq!(outboard, expected, "Outboard mismatch for size {}", size);
+
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        assert_eq!(tree.to_bao_outboard(size as u64).unwrap(), outboard);
+    }
+}
+
+/// Tests the combined encoding byte-for-byte against a spec reference built on the blake3 crate
+/// Verifies balanced sizes and unbalanced right edges (a lone final chunk, a partial final subtree,
+/// several levels of promotion), and that the free function and the tree method agree
+/// Methods tested: encode_combined, BinaryMerkleTree::encode_combined
+#[test]
+fn test_combined_encoding_matches_spec() {
+    for &size in &[0, 1, CHUNK_LEN, CHUNK_LEN + 1, 2 * CHUNK_LEN + 1, 5 * CHUNK_LEN, 7 * CHUNK_LEN - 1, 13 * CHUNK_LEN + 9, 2 * 1024 * 1024 + 3 * CHUNK_LEN + 1] {
+        let input = random_input(size);
+        let mut expected = (size as u64).to_le_bytes().to_vec();
+        reference_combined(&input, 0, &mut expected);
+
+        let encoded = encode_combined(&input);
+        assert_eq!(encoded, expected, "Combined encoding mismatch for size {}", size);
+        assert_eq!(BinaryMerkleTree::from_input(&input, IV, FLAGS).encode_combined(&input).unwrap(), encoded);
+        assert_eq!(decode_and_verify(&encoded, blake3::hash(&input).as_bytes(), IV, FLAGS).unwrap(), input);
+    }
+}
+
+/// Tests extracting and verifying slices for byte ranges
+/// Verifies that slices cut from the combined and the outboard encoding are identical and decode to
+/// exactly the requested bytes, for ranges inside one chunk, across chunk boundaries, empty, past the
+/// end, and covering the whole input (whose slice is the combined encoding itself)
+/// Methods tested: extract_slice, verify_slice
+#[test]
+fn test_slice_round_trip() {
+    let mut rng = SeededRng::new();
+    for &size in &[0, 1, CHUNK_LEN + 1, 13 * CHUNK_LEN + 9, 64 * CHUNK_LEN, 300 * CHUNK_LEN + 17] {
+        let input = random_input(size);
+        let combined = encode_combined(&input);
+        let (outboard, root_hash) = encode_outboard_bao(&input);
+        let len = size as u64;
+
+        let mut ranges = vec![0..0, 0..1, 1000..1030, CHUNK_LEN as u64..2 * CHUNK_LEN as u64, len.saturating_sub(1)..len + 100, len + 5..len + 10, 0..u64::MAX];
+        ranges.extend((0..10).map(|_| {
+            let start = rng.gen_range(0..=len);
+            start..rng.gen_range(start..=len)
+        }));
+        for range in ranges {
+            let slice = extract_slice(&combined, None, range.clone()).unwrap();
+            assert_eq!(extract_slice(&outboard, Some(&input), range.clone()).unwrap(), slice, "Outboard slice differs for {:?}", range);
+
+            let start = range.start.min(len) as usize;
+            let end = range.end.clamp(range.start.min(len), len) as usize;
+            let decoded = verify_slice(&slice, &root_hash, IV, FLAGS, range.clone()).unwrap();
+            assert_eq!(decoded, &input[start..end], "Wrong bytes for {:?} of {} bytes", range, size);
+        }
+        assert_eq!(extract_slice(&combined, None, 0..len).unwrap(), combined);
+    }
+
+    // One chunk of a 64-chunk input needs the header, the six parents above it, and the chunk
+    let input = random_input(64 * CHUNK_LEN);
+    let slice = extract_slice(&encode_combined(&input), None, 5000..5001).unwrap();
+    assert_eq!(slice.len(), 8 + 6 * 64 + CHUNK_LEN);
+}
+
+/// Tests that tampered slices are rejected before any bytes are returned
+/// Verifies reordered chunks, swapped sibling chaining values, truncation, trailing bytes, a
+/// mismatched range, a wrong root, and a slice from other data of the same length
+/// Methods tested: verify_slice, extract_slice
+#[test]
+fn test_slice_rejects_malicious_slices() {
+    let input = random_input(16 * CHUNK_LEN);
+    let (encoded, root_hash) = encode(&input);
+    let range = 2 * CHUNK_LEN as u64 + 10..4 * CHUNK_LEN as u64 - 10;
+    let slice = extract_slice(&encoded, None, range.clone()).unwrap();
+    assert_eq!(verify_slice(&slice, &root_hash, IV, FLAGS, range.clone()).unwrap(), &input[range.start as usize..range.end as usize]);
+
+    // Header, then the parents over chunks 0..16, 0..8, 0..4 and 2..4, then chunks 2 and 3
+    let chunks_start = 8 + 4 * 64;
+    let mut reordered = slice.clone();
+    reordered[chunks_start..].rotate_left(CHUNK_LEN);
+    assert_eq!(verify_slice(&reordered, &root_hash, IV, FLAGS, range.clone()), Err(SliceError::ChunkMismatch { chunk_index: 2 }));
+
+    let mut swapped = slice.clone();
+    swapped[8..8 + 64].rotate_left(32);
+    assert_eq!(verify_slice(&swapped, &root_hash, IV, FLAGS, range.clone()), Err(SliceError::ParentMismatch { first_chunk: 0, chunk_count: 16 }));
+
+    let mut swapped_lower = slice.clone();
+    swapped_lower[8 + 3 * 64..8 + 4 * 64].rotate_left(32);
+    assert_eq!(verify_slice(&swapped_lower, &root_hash, IV, FLAGS, range.clone()), Err(SliceError::ParentMismatch { first_chunk: 2, chunk_count: 2 }));
+
+    assert_eq!(verify_slice(&slice[..slice.len() - 1], &root_hash, IV, FLAGS, range.clone()), Err(SliceError::Truncated));
+    let mut extended = slice.clone();
+    extended.push(0);
+    assert_eq!(verify_slice(&extended, &root_hash, IV, FLAGS, range.clone()), Err(SliceError::TrailingBytes(1)));
+
+    // The same slice does not prove a range in another part of the input
+    assert!(verify_slice(&slice, &root_hash, IV, FLAGS, 9 * CHUNK_LEN as u64..9 * CHUNK_LEN as u64 + 1).is_err());
+
+    let wrong_root = blake3::hash(b"something else");
+    assert!(verify_slice(&slice, wrong_root.as_bytes(), IV, FLAGS, range.clone()).is_err());
+
+    let other = random_input(input.len());
+    let other_slice = extract_slice(&encode_combined(&other), None, range.clone()).unwrap();
+    assert!(verify_slice(&other_slice, &root_hash, IV, FLAGS, range).is_err());
+}
+
+/// Read `decoder` in small pieces until it fails or ends, returning what it released and the error
+fn drain(mut decoder: impl Read) -> (Vec<u8>, Option<io::Error>) {
+    let mut released = Vec::new();
+    let mut buf = [0u8; 100];
+    loop {
+        match decoder.read(&mut buf) {
+            Ok(0) => return (released, None),
+            Ok(n) => released.extend_from_slice(&buf[..n]),
+            Err(e) => return (released, Some(e)),
+        }
+    }
+}
+
+/// Tests decoding a combined encoding incrementally
+/// Verifies byte-identical plaintext for every size, and that a flipped bit in a parent or a chunk
+/// fails at that node's offset before any byte after the corruption is released
+/// Methods tested: VerifiedDecoder::new, VerifiedDecoder::read, VerifiedDecoder::input_len
+#[test]
+fn test_verified_decoder() {
+    for &size in &BAO_SIZES {
+        let input = random_input(size);
+        let (encoded, root_hash) = encode(&input);
+        let mut decoder = VerifiedDecoder::new(&encoded[..], root_hash, IV, FLAGS);
+        let (released, error) = drain(&mut decoder);
+        assert!(error.is_none(), "Valid encoding of {} bytes rejected: {:?}", size, error);
+        assert_eq!(released, input);
+        assert_eq!(decoder.input_len(), Some(size as u64));
+    }
+
+    let input = random_input(16 * CHUNK_LEN);
+    let (encoded, root_hash) = encode(&input);
+    // The parent over chunks 8..16 follows the header, the root and the left half of the tree
+    let parent_offset = 8 + 64 + 7 * 64 + 8 * CHUNK_LEN;
+    let mut corrupted = encoded.clone();
+    corrupted[parent_offset + 40] ^= 1;
+    let (released, error) = drain(VerifiedDecoder::new(&corrupted[..], root_hash, IV, FLAGS));
+    let error = error.expect("Corrupted parent not detected");
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains(&format!("byte {}", parent_offset)), "{}", error);
+    assert_eq!(released, &input[..8 * CHUNK_LEN]);
+
+    let mut corrupted = encoded.clone();
+    *corrupted.last_mut().unwrap() ^= 0x80;
+    let (released, error) = drain(VerifiedDecoder::new(&corrupted[..], root_hash, IV, FLAGS));
+    assert!(error.unwrap().to_string().contains(&format!("byte {}", encoded.len() - CHUNK_LEN)));
+    assert_eq!(released, &input[..15 * CHUNK_LEN]);
+
+    let (released, error) = drain(VerifiedDecoder::new(&encoded[..encoded.len() - 1], root_hash, IV, FLAGS));
+    assert_eq!(error.unwrap().kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(released, &input[..15 * CHUNK_LEN]);
+
+    let mut extended = encoded.clone();
+    extended.push(0);
+    let (released, error) = drain(VerifiedDecoder::new(&extended[..], root_hash, IV, FLAGS));
+    assert_eq!(error.unwrap().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(released, input);
+}
+
+/// Tests the encodings byte-for-byte against output of the bao crate itself
+/// Verifies the combined and outboard encodings of 0, 1, 1024 and 1025-byte inputs, and that
+/// encode_bao returns an error instead of panicking when handed input of the wrong length
+/// Methods tested: BinaryMerkleTree::encode_bao, BinaryMerkleTree::to_bao_outboard, encode_combined, encode_outboard_bao
+#[test]
+fn test_bao_matches_bao_crate_fixtures() {
+    for (size, combined, outboard) in BAO_CRATE_FIXTURES {
+        let input: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        assert_eq!(tree.encode_bao(&input).unwrap(), combined, "Combined encoding mismatch for size {}", size);
+        assert_eq!(encode_combined(&input), combined);
+        assert_eq!(tree.to_bao_outboard(size as u64).unwrap(), outboard, "Outboard mismatch for size {}", size);
+        assert_eq!(encode_outboard_bao(&input), (outboard.to_vec(), *blake3::hash(&input).as_bytes()));
+    }
+
+    let input: Vec<u8> = (0..1025).map(|i| (i % 251) as u8).collect();
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    assert_eq!(
+        tree.encode_bao(&input[..1024]).unwrap_err(),
+        MerkleError::InputLengthMismatch { input_len: 1024, actual_leaves: 2 }
+    );
+}
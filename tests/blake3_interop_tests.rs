@@ -0,0 +1,118 @@
+#![cfg(feature = "blake3-interop")]
+
+mod common;
+
+use blake3::hazmat::{hash_derive_key_context, HasherExt};
+use merkle_tree::binary_merkle_tree::{cv_from_bytes, BinaryMerkleTree, Blake3Hasher, Hash32, CHUNK_LEN, DERIVE_KEY_MATERIAL, IV, FLAGS, KEYED_HASH};
+use merkle_tree::blake3_interop::leaf_cvs;
+use rand::Rng;
+use common::random_input;
+
+// Empty, partial-chunk, exact-chunk and multi-chunk inputs
+const INPUT_SIZES: [usize; 6] = [0, 1, CHUNK_LEN - 1, CHUNK_LEN, 2 * CHUNK_LEN + 1, 31 * CHUNK_LEN + 500];
+
+const KEY: [u8; 32] = *b"whats the Elvish word for friend";
+const CONTEXT: &str = "merkle_tree 2024 interop tests";
+
+
+/// The key_words and flags of the regular, keyed and derive_key modes, with a
+/// blake3 hasher in the same mode.
+fn modes() -> [([u32; 8], u32, blake3::Hasher); 3] {
+    let context_key = hash_derive_key_context(CONTEXT);
+    [
+        (IV, FLAGS, blake3::Hasher::new()),
+        (cv_from_bytes(KEY), KEYED_HASH, blake3::Hasher::new_keyed(&KEY)),
+        (cv_from_bytes(context_key), DERIVE_KEY_MATERIAL, blake3::Hasher::new_from_context_key(&context_key)),
+    ]
+}
+
+/// Tests comparing tree roots directly with blake3::Hash
+/// Verifies that full and pruned tree roots equal blake3::hash of the same input, in both
+/// operand orders, and differ from the hash of other input
+/// Methods tested: Hash32 == blake3::Hash, blake3::Hash == Hash32, root_hash
+#[test]
+fn test_root_hash_equals_blake3_hash() {
+    for &size in INPUT_SIZES.iter() {
+        let input = random_input(size);
+        let expected = blake3::hash(&input);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        assert!(tree.root_hash() == expected, "Root mismatch for {} bytes", size);
+        assert!(expected == tree.root_hash());
+        assert_eq!(tree.root_hash(), expected);
+
+        assert_eq!(tree.prune(&[0]).unwrap().root_hash(), expected);
+
+        let mut other = input.clone();
+        other.push(0);
+        assert_ne!(tree.root_hash(), blake3::hash(&other));
+    }
+}
+
+/// Tests converting between Hash32 and blake3::Hash
+/// Verifies that conversions in both directions keep the bytes
+/// Methods tested: From<blake3::Hash> for Hash32, From<Hash32> for blake3::Hash
+#[test]
+fn test_hash32_blake3_conversions() {
+    let input = random_input(3 * CHUNK_LEN + 7);
+    let root = BinaryMerkleTree::from_input(&input, IV, FLAGS).root_hash();
+    let hash: blake3::Hash = root.into();
+    assert_eq!(hash, blake3::hash(&input));
+    assert_eq!(Hash32::from(hash), root);
+    assert_eq!(hash.as_bytes(), root.as_bytes());
+}
+
+/// Tests every hashing mode against the blake3 crate
+/// Verifies plain, keyed and derive_key roots and hasher digests match, and that the tree's
+/// extended output is blake3's XOF stream
+/// Methods tested: root_hash, output_reader, Blake3Hasher::new_keyed, Blake3Hasher::new_derive_key
+#[test]
+fn test_hashing_modes_match_blake3() {
+    for &size in INPUT_SIZES.iter() {
+        let input = random_input(size);
+        for (key_words, flags, mut reference) in modes() {
+            reference.update(&input);
+            let tree = BinaryMerkleTree::from_input(&input, key_words, flags);
+            assert_eq!(tree.root_hash(), reference.finalize(), "{} bytes, flags {}", size, flags);
+
+            let mut expected_xof = [0; 200];
+            reference.finalize_xof().fill(&mut expected_xof);
+            let mut xof = [0; 200];
+            tree.output_reader().fill(&mut xof);
+            assert_eq!(xof, expected_xof, "{} bytes, flags {}", size, flags);
+        }
+
+        let mut keyed = Blake3Hasher::new_keyed(&KEY);
+        keyed.update(&input);
+        assert_eq!(keyed.finalize_array(), *blake3::keyed_hash(&KEY, &input).as_bytes());
+        let mut derive = Blake3Hasher::new_derive_key(CONTEXT);
+        derive.update(&input);
+        assert_eq!(derive.finalize_array(), blake3::derive_key(CONTEXT, &input));
+        let mut plain = Blake3Hasher::new();
+        plain.update(&input);
+        let mut expected_prefix = [0; 100];
+        blake3::Hasher::new().update(&input).finalize_xof().fill(&mut expected_prefix);
+        assert_eq!(plain.finalize_n::<100>(), expected_prefix);
+    }
+}
+
+/// Tests hashing leaves with the blake3 crate
+/// Verifies the leaf chaining values equal the tree's leaves in every mode, and in a mode
+/// blake3 does not have, through the fallback
+/// Methods tested: leaf_cvs
+#[test]
+fn test_leaf_cvs_match_tree_leaves() {
+    for &size in INPUT_SIZES.iter() {
+        let input = random_input(size);
+        let mut key_flags: Vec<([u32; 8], u32)> = modes().iter().map(|(key_words, flags, _)| (*key_words, *flags)).collect();
+        // A custom key with no flags is not a blake3 mode
+        key_flags.push((cv_from_bytes(KEY), FLAGS));
+        for (key_words, flags) in key_flags {
+            let tree = BinaryMerkleTree::from_input(&input, key_words, flags);
+            let cvs = leaf_cvs(&input, key_words, flags);
+            assert_eq!(cvs.len(), tree.actual_leaves());
+            for (leaf_index, cv) in cvs.into_iter().enumerate() {
+                assert_eq!(Some(cv), tree.leaf_cv(leaf_index), "Leaf {} of {} bytes, flags {}", leaf_index, size, flags);
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use merkle_tree::binary_merkle_tree::{chunk_output, BinaryMerkleTree, Output, TreeLeafBuilder, CHUNK_LEN, FLAGS, IV};
+use merkle_tree::error::MerkleError;
+
+fn sample_input(chunks: usize) -> Vec<u8> {
+    (0..chunks * CHUNK_LEN).map(|i| (i % 251) as u8).collect()
+}
+
+fn chunk_leaf(leaf_index: usize, seed: u8) -> Output {
+    chunk_output(&[seed; CHUNK_LEN], leaf_index as u64, IV, FLAGS).unwrap()
+}
+
+/// Apply a mix of single inserts, bulk inserts, swaps and removals
+fn mutate(tree: &mut BinaryMerkleTree, seed: u8) {
+    let last = tree.actual_leaves() - 1;
+    tree.insert_leaf(last, chunk_leaf(last, seed)).unwrap();
+    tree.bulk_insert_leaves([0, 2, 3].into_iter(), [0, 2, 3].map(|i| chunk_leaf(i, seed)).into_iter()).unwrap();
+    tree.swap_leaves(1, last - 1).unwrap();
+    tree.remove_leaf(4).unwrap();
+}
+
+/// Tests rolling back a batch of updates
+/// Verifies the node array, root, input length, removal marks, content hashes and the
+/// recomputed-node count are bit-identical to the state at the checkpoint, for several shapes
+/// Methods tested: BinaryMerkleTree::checkpoint, BinaryMerkleTree::rollback
+#[test]
+fn test_rollback_restores_state() {
+    for chunks in [7, 8, 13] {
+        let mut builder = TreeLeafBuilder::new(IV, FLAGS).with_content_hashes();
+        builder.update(&sample_input(chunks));
+        let mut tree = builder.finalize_tree();
+        tree.remove_leaf(5).unwrap();
+        let before = tree.snapshot();
+
+        let checkpoint = tree.checkpoint();
+        mutate(&mut tree, 1);
+        assert_ne!(tree.root_hash(), before.root_hash());
+        assert_eq!((tree.input_len(), tree.content_hash(0)), (None, None));
+        tree.rollback(checkpoint).unwrap();
+
+        assert_eq!(tree.store(), before.store(), "Nodes differ for {} chunks", chunks);
+        assert_eq!(tree.to_bytes(), before.to_bytes());
+        assert_eq!(tree.root_hash(), before.root_hash());
+        assert_eq!(tree.input_len(), before.input_len());
+        assert_eq!(tree.content_hash(2), before.content_hash(2));
+        assert!(tree.is_removed(5) && !tree.is_removed(4));
+        assert_eq!(tree.nodes_recomputed(), before.nodes_recomputed());
+        assert_eq!(tree.open_checkpoints(), 0);
+        assert!(tree.verify_integrity());
+    }
+}
+
+/// Tests committing a batch of updates
+/// Verifies discard keeps the changes and closes the checkpoint, and that a checkpoint with no
+/// changes rolls back to the same tree
+/// Methods tested: BinaryMerkleTree::checkpoint, BinaryMerkleTree::discard
+#[test]
+fn test_discard_keeps_changes() {
+    let mut tree = BinaryMerkleTree::from_input(&sample_input(13), IV, FLAGS);
+    let mut expected = tree.snapshot();
+    mutate(&mut expected, 2);
+
+    let checkpoint = tree.checkpoint();
+    mutate(&mut tree, 2);
+    tree.discard(checkpoint).unwrap();
+    assert_eq!(tree.store(), expected.store());
+    assert_eq!(tree.open_checkpoints(), 0);
+
+    // An untouched checkpoint rolls back to the same tree
+    let checkpoint = tree.checkpoint();
+    tree.rollback(checkpoint).unwrap();
+    assert_eq!(tree.store(), expected.store());
+}
+
+/// Tests nested checkpoints
+/// Verifies an inner rollback returns to the inner state, a discarded inner checkpoint is still
+/// undone by the outer one, rolling back the outer one closes the inner one, and closed
+/// checkpoints are rejected
+/// Methods tested: BinaryMerkleTree::checkpoint, BinaryMerkleTree::rollback,
+/// BinaryMerkleTree::discard
+#[test]
+fn test_nested_checkpoints() {
+    let mut tree = BinaryMerkleTree::from_input(&sample_input(13), IV, FLAGS);
+    let original = tree.snapshot();
+
+    let outer = tree.checkpoint();
+    mutate(&mut tree, 3);
+    let middle = tree.snapshot();
+    let inner = tree.checkpoint();
+    mutate(&mut tree, 4);
+    tree.insert_leaf(7, chunk_leaf(7, 4)).unwrap();
+    tree.rollback(inner).unwrap();
+    assert_eq!(tree.store(), middle.store());
+    assert_eq!(tree.open_checkpoints(), 1);
+
+    let inner = tree.checkpoint();
+    tree.insert_leaf(9, chunk_leaf(9, 5)).unwrap();
+    tree.insert_leaf(0, chunk_leaf(0, 5)).unwrap();
+    tree.discard(inner).unwrap();
+    tree.rollback(outer).unwrap();
+    assert_eq!(tree.store(), original.store());
+    assert_eq!(tree.to_bytes(), original.to_bytes());
+
+    let outer = tree.checkpoint();
+    let inner = tree.checkpoint();
+    tree.insert_leaf(1, chunk_leaf(1, 6)).unwrap();
+    tree.rollback(outer).unwrap();
+    assert_eq!(tree.rollback(inner), Err(MerkleError::UnknownCheckpoint));
+    assert_eq!(tree.store(), original.store());
+
+    // Compaction rebuilds the tree and drops its checkpoints
+    let checkpoint = tree.checkpoint();
+    tree.remove_leaf(3).unwrap();
+    tree.compact(&sample_input(13)).unwrap();
+    assert_eq!(tree.rollback(checkpoint), Err(MerkleError::UnknownCheckpoint));
+}
+
+/// Tests that checkpoints dropped by compaction never match newer ones
+/// Verifies that rolling back a checkpoint taken before compact fails with UnknownCheckpoint and
+/// leaves a checkpoint taken after it open and the tree unchanged
+/// Methods tested: BinaryMerkleTree::checkpoint, BinaryMerkleTree::compact, BinaryMerkleTree::rollback
+#[test]
+fn test_compact_does_not_reuse_checkpoint_ids() {
+    let mut tree = BinaryMerkleTree::from_input(&sample_input(13), IV, FLAGS);
+    let old = tree.checkpoint();
+    tree.remove_leaf(3).unwrap();
+    tree.compact(&sample_input(13)).unwrap();
+    assert_eq!(tree.open_checkpoints(), 0);
+
+    let new = tree.checkpoint();
+    tree.insert_leaf(2, chunk_leaf(2, 7)).unwrap();
+    let changed = tree.snapshot();
+    assert_eq!(tree.rollback(old), Err(MerkleError::UnknownCheckpoint));
+    assert_eq!(tree.store(), changed.store());
+    assert_eq!(tree.open_checkpoints(), 1);
+    tree.rollback(new).unwrap();
+    assert_ne!(tree.store(), changed.store());
+}
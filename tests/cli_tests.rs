@@ -0,0 +1,91 @@
+#![cfg(feature = "cli")]
+
+use assert_cmd::Command;
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, CHUNK_LEN};
+use std::fs;
+use std::path::Path;
+
+fn b3mt() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_b3mt"))
+}
+
+fn patterned_input(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i % 251) as u8).collect()
+}
+
+fn path_arg(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+/// Tests `b3mt hash` on files and stdin
+/// Verifies that the printed root is blake3::hash of the input for empty, partial and
+/// multi-chunk files, that "-" reads stdin, and that a missing file exits with 2
+/// Methods tested: b3mt hash
+#[test]
+fn test_cli_hash() {
+    let dir = tempfile::tempdir().unwrap();
+    for &size in &[0, 1, CHUNK_LEN, 7 * CHUNK_LEN + 99] {
+        let input = patterned_input(size);
+        let file = dir.path().join(format!("input-{}", size));
+        fs::write(&file, &input).unwrap();
+        let expected = format!("{}\n", blake3::hash(&input).to_hex());
+        b3mt().args(["hash", path_arg(&file)]).assert().success().stdout(expected.clone());
+        b3mt().args(["hash", "-"]).write_stdin(input).assert().success().stdout(expected);
+    }
+    b3mt().args(["hash", path_arg(&dir.path().join("missing"))]).assert().code(2);
+}
+
+/// Tests the tree, prove and verify subcommands end to end
+/// Verifies that the written tree loads with from_bytes, that every chunk verifies with its
+/// proof (exit 0), and that a corrupted chunk, a proof for another chunk or a wrong root
+/// exits with 1 while bad arguments exit with 2
+/// Methods tested: b3mt tree, b3mt prove, b3mt verify
+#[test]
+fn test_cli_tree_prove_verify() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = patterned_input(5 * CHUNK_LEN + 300);
+    let file = dir.path().join("input");
+    let tree_file = dir.path().join("input.tree");
+    fs::write(&file, &input).unwrap();
+    b3mt().args(["tree", path_arg(&file), "-o", path_arg(&tree_file)]).assert().success();
+    let tree = BinaryMerkleTree::from_bytes(&fs::read(&tree_file).unwrap()).unwrap();
+    let root = blake3::hash(&input).to_hex().to_string();
+    assert_eq!(tree.root_hash(), *blake3::hash(&input).as_bytes());
+
+    let chunk_file = dir.path().join("chunk.bin");
+    for (chunk_index, chunk) in input.chunks(CHUNK_LEN).enumerate() {
+        let proof_file = dir.path().join(format!("proof-{}.bin", chunk_index));
+        b3mt()
+            .args(["prove", path_arg(&tree_file), "--chunk", &chunk_index.to_string(), "-o", path_arg(&proof_file)])
+            .assert()
+            .success();
+        fs::write(&chunk_file, chunk).unwrap();
+        b3mt()
+            .args(["verify", "--root", &root, "--proof", path_arg(&proof_file), "--chunk-data", path_arg(&chunk_file)])
+            .assert()
+            .success()
+            .stdout(format!("chunk {} OK\n", chunk_index));
+    }
+
+    let proof_file = dir.path().join("proof-2.bin");
+    let verify = |root: &str, proof: &Path| {
+        let mut command = b3mt();
+        command.args(["verify", "--root", root, "--proof", path_arg(proof), "--chunk-data", path_arg(&chunk_file)]);
+        command
+    };
+    let mut corrupted = input[2 * CHUNK_LEN..3 * CHUNK_LEN].to_vec();
+    corrupted[100] ^= 1;
+    fs::write(&chunk_file, &corrupted).unwrap();
+    verify(&root, &proof_file).assert().code(1);
+    fs::write(&chunk_file, &input[2 * CHUNK_LEN..3 * CHUNK_LEN]).unwrap();
+    verify(&root, &proof_file).assert().success();
+    verify(&root, &dir.path().join("proof-3.bin")).assert().code(1);
+    verify(&blake3::hash(b"other").to_hex(), &proof_file).assert().code(1);
+
+    verify("not hex", &proof_file).assert().code(2);
+    verify(&root, &chunk_file).assert().code(2);
+    verify(&root, &dir.path().join("missing")).assert().code(2);
+    b3mt().args(["prove", path_arg(&tree_file), "--chunk", "6", "-o", path_arg(&proof_file)]).assert().code(2);
+    b3mt().args(["prove", path_arg(&file), "--chunk", "0", "-o", path_arg(&proof_file)]).assert().code(2);
+    b3mt().args(["verify", "--root", &root]).assert().code(2);
+}
@@ -0,0 +1,95 @@
+//! Helpers shared by the integration tests.
+
+use merkle_tree::binary_merkle_tree::{Output, TreeLeafBuilder};
+use rand::rngs::StdRng;
+use rand::{Error, RngCore, SeedableRng};
+use std::cell::{Cell, RefCell};
+
+/// The environment variable that fixes the seed of every test.
+pub const SEED_VAR: &str = "MERKLE_TEST_SEED";
+
+/// A seeded random generator for tests, so the inputs of a failing test can be
+/// generated again.
+///
+/// Each test draws one seed, from `MERKLE_TEST_SEED` when it is set and at random
+/// otherwise, and prints it; the test harness shows the message only if the test
+/// fails. Rerunning a failed test with the printed seed, for example
+/// `MERKLE_TEST_SEED=1234 cargo test test_fuzz_single_mutation`, reproduces the
+/// exact inputs it failed on.
+pub struct SeededRng {
+    rng: StdRng,
+    seed: u64,
+}
+
+thread_local! {
+    /// The running test's seed and the number of generators drawn from it so far
+    static TEST_SEED: Cell<Option<(u64, u64)>> = const { Cell::new(None) };
+    /// The generator `random_input` draws from
+    static INPUT_RNG: RefCell<Option<SeededRng>> = const { RefCell::new(None) };
+}
+
+impl SeededRng {
+    /// The next generator of the running test. Each one draws a different stream
+    /// from the test's seed.
+    #[allow(dead_code)]
+    pub fn new() -> Self {
+        TEST_SEED.with(|test_seed| {
+            let (seed, streams) = test_seed.get().unwrap_or_else(|| {
+                let seed = match std::env::var(SEED_VAR) {
+                    Ok(seed) => seed.parse().unwrap_or_else(|_| panic!("{} must be a u64, not {:?}", SEED_VAR, seed)),
+                    Err(_) => rand::random(),
+                };
+                eprintln!("random seed {0}; rerun with {1}={0} to reproduce", seed, SEED_VAR);
+                (seed, 0)
+            });
+            test_seed.set(Some((seed, streams + 1)));
+            SeededRng { rng: StdRng::seed_from_u64(seed.wrapping_add(streams)), seed }
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn from_seed(seed: u64) -> Self {
+        SeededRng { rng: StdRng::seed_from_u64(seed), seed }
+    }
+
+    #[allow(dead_code)]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+/// The leaf Outputs of `input`, as `from_input` builds them
+#[allow(dead_code)]
+pub fn chunk_leaves(input: &[u8], key_words: [u32; 8], flags: u32) -> Vec<Output> {
+    let mut builder = TreeLeafBuilder::new(key_words, flags);
+    builder.update(input);
+    builder.finalize()
+}
+
+/// `size` random bytes, drawn from the running test's seed
+#[allow(dead_code)]
+pub fn random_input(size: usize) -> Vec<u8> {
+    INPUT_RNG.with(|rng| {
+        let mut input = vec![0; size];
+        rng.borrow_mut().get_or_insert_with(SeededRng::new).fill_bytes(&mut input);
+        input
+    })
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{chunk_output, BinaryMerkleTree, CHUNK_LEN, FLAGS, IV, KEYED_HASH};
+use merkle_tree::compact_tree::CompactMerkleTree;
+use merkle_tree::error::MerkleError;
+use common::{chunk_leaves, random_input};
+
+/// Tests that the compact representation describes the same tree
+/// Verifies roots and proofs equal the full tree's for empty, single-chunk, balanced and
+/// unbalanced inputs, keyed and unkeyed, and that every proof verifies against the root
+/// Methods tested: CompactMerkleTree::from_input, CompactMerkleTree::root_hash,
+/// CompactMerkleTree::generate_proof
+#[test]
+fn test_compact_tree_matches_full_tree() {
+    for size in [0, 1, CHUNK_LEN, 2 * CHUNK_LEN, 3 * CHUNK_LEN + 1, 13 * CHUNK_LEN - 7, 64 * CHUNK_LEN, 100 * CHUNK_LEN] {
+        let input = random_input(size);
+        for (key_words, flags) in [(IV, FLAGS), ([3; 8], KEYED_HASH)] {
+            let full = BinaryMerkleTree::from_input(&input, key_words, flags);
+            let compact = CompactMerkleTree::from_input(&input, key_words, flags);
+            assert_eq!(compact.root_hash(), full.root_hash(), "Root mismatch for {} bytes", size);
+            assert_eq!(compact.root(), full.root());
+            assert_eq!(compact.actual_leaves(), full.actual_leaves());
+
+            for leaf_index in 0..full.actual_leaves() {
+                let proof = compact.generate_proof(leaf_index).unwrap();
+                assert_eq!(Some(&proof), full.generate_proof(leaf_index).as_ref());
+                assert!(proof.verify(&compact.root_hash(), key_words, flags));
+            }
+            assert!(compact.generate_proof(full.actual_leaves()).is_none());
+        }
+    }
+    assert_eq!(CompactMerkleTree::new_from_leaves(Vec::new(), IV, FLAGS), Err(MerkleError::NoLeaves));
+}
+
+/// Tests replacing leaves in the compact representation
+/// Verifies that after each replacement, including of a promoted last leaf, the root and
+/// proofs match a full tree given the same replacements, and out-of-bounds leaves are rejected
+/// Methods tested: CompactMerkleTree::insert_leaf
+#[test]
+fn test_compact_tree_insert_leaf() {
+    for chunks in [1, 2, 5, 37] {
+        let input = random_input(chunks * CHUNK_LEN);
+        let mut full = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let mut compact = CompactMerkleTree::new_from_leaves(chunk_leaves(&input, IV, FLAGS), IV, FLAGS).unwrap();
+        for leaf_index in [0, chunks / 2, chunks - 1] {
+            let leaf = chunk_output(&random_input(CHUNK_LEN), leaf_index as u64, IV, FLAGS).unwrap();
+            full.insert_leaf(leaf_index, leaf).unwrap();
+            compact.insert_leaf(leaf_index, leaf).unwrap();
+            assert_eq!(compact.root_hash(), full.root_hash());
+            assert_eq!(compact.generate_proof(leaf_index), full.generate_proof(leaf_index));
+        }
+        assert_eq!(
+            compact.insert_leaf(chunks, full.leaf(0).unwrap()),
+            Err(MerkleError::LeafIndexOutOfBounds { leaf_index: chunks, actual_leaves: chunks })
+        );
+    }
+}
+
+/// Tests the memory saved by the compact representation
+/// Verifies a compact tree takes at most two thirds of the full tree's memory
+/// Methods tested: CompactMerkleTree::heap_size_bytes, BinaryMerkleTree::heap_size_bytes
+#[test]
+fn test_compact_tree_heap_size() {
+    let input = random_input(1024 * CHUNK_LEN);
+    let full = BinaryMerkleTree::from_input(&input, IV, FLAGS).heap_size_bytes();
+    let compact = CompactMerkleTree::from_input(&input, IV, FLAGS).heap_size_bytes();
+    assert!(3 * compact <= 2 * full, "compact {} bytes, full {} bytes", compact, full);
+}
@@ -0,0 +1,84 @@
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, Hash32, InvalidHex, CHUNK_LEN, FLAGS, IV};
+use merkle_tree::content_id::{ContentId, InvalidContentId};
+use std::collections::{BTreeSet, HashMap};
+
+/// Tests building content ids
+/// Verifies a tree, a hasher fed the same input and the raw parts agree, and a tree
+/// without a known length has none
+/// Methods tested: BinaryMerkleTree::content_id, ContentId::from_hasher, ContentId::new, Blake3Hasher::count
+#[test]
+fn test_content_id_constructors() {
+    let input: Vec<u8> = (0..3 * CHUNK_LEN + 17).map(|i| i as u8).collect();
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let id = tree.content_id().unwrap();
+    assert_eq!(id, ContentId::new(tree.root_hash(), input.len() as u64));
+
+    let mut hasher = Blake3Hasher::new();
+    assert_eq!(hasher.count(), 0);
+    for piece in input.chunks(500) {
+        hasher.update(piece);
+    }
+    assert_eq!(hasher.count(), input.len() as u64);
+    assert_eq!(ContentId::from_hasher(&hasher), id);
+
+    let leaves = (0..tree.actual_leaves()).map(|i| tree.leaf(i).unwrap()).collect();
+    assert_eq!(BinaryMerkleTree::new_from_leaves(leaves, IV, FLAGS).content_id(), None);
+}
+
+/// Tests the byte and string encodings
+/// Verifies round trips, the exact string form, and rejection of malformed strings
+/// Methods tested: ContentId::to_bytes, ContentId::from_bytes, Display, FromStr
+#[test]
+fn test_content_id_encodings() {
+    let id = ContentId::new(Hash32([0xab; 32]), 1025);
+    let string = id.to_string();
+    assert_eq!(string, format!("b3mt:{}:1025", "ab".repeat(32)));
+    assert_eq!(string.parse(), Ok(id));
+    assert_eq!(format!("b3mt:{}:1025", "AB".repeat(32)).parse(), Ok(id), "Either case of hex");
+    assert_eq!(ContentId::from_bytes(&id.to_bytes()), id);
+    assert_eq!(id.to_bytes()[32..], 1025u64.to_le_bytes());
+
+    let hex = "ab".repeat(32);
+    let parse = |s: String| s.parse::<ContentId>();
+    assert_eq!(parse(format!("b3:{}:1", hex)), Err(InvalidContentId::Prefix));
+    assert_eq!(parse(format!("b3mt:{}", hex)), Err(InvalidContentId::MissingLength));
+    assert_eq!(parse(format!("b3mt:{}:1", &hex[2..])), Err(InvalidContentId::Root(InvalidHex::Length(62))));
+    assert_eq!(parse(format!("b3mt:x{}:1", &hex[1..])), Err(InvalidContentId::Root(InvalidHex::Digit(0))));
+    for len in ["", "+1", "01", "-1", "1 ", "18446744073709551616"] {
+        assert_eq!(parse(format!("b3mt:{}:{}", hex, len)), Err(InvalidContentId::Length), "{:?}", len);
+    }
+    assert_eq!(parse(format!("b3mt:{}:0", hex)).unwrap().len, 0);
+    assert_eq!(parse(format!("b3mt:{}:{}", hex, u64::MAX)).unwrap().len, u64::MAX);
+}
+
+/// Tests ordering and hashing
+/// Verifies ids order by root then length, and work as HashMap and BTreeSet keys
+/// Methods tested: Ord, Hash
+#[test]
+fn test_content_id_ordering() {
+    let low = Hash32([0; 32]);
+    let high = Hash32([1; 32]);
+    let mut ids = vec![ContentId::new(high, 0), ContentId::new(low, 5), ContentId::new(low, 2)];
+    ids.sort();
+    assert_eq!(ids, [ContentId::new(low, 2), ContentId::new(low, 5), ContentId::new(high, 0)]);
+
+    let set: BTreeSet<ContentId> = ids.iter().copied().collect();
+    assert_eq!(set.len(), 3);
+    let map: HashMap<ContentId, usize> = ids.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+    assert_eq!(map[&ContentId::new(low, 5)], 1);
+}
+
+/// Tests that lengths are bound into the id
+/// Verifies all-zero inputs of different lengths, including ones within a chunk and
+/// across the empty input, give distinct ids
+/// Methods tested: BinaryMerkleTree::content_id
+#[test]
+fn test_content_id_distinct_zero_lengths() {
+    let lens = [0, 1, 2, 63, 64, CHUNK_LEN - 1, CHUNK_LEN, CHUNK_LEN + 1, 2 * CHUNK_LEN, 4 * CHUNK_LEN];
+    let ids: BTreeSet<ContentId> = lens
+        .iter()
+        .map(|&len| BinaryMerkleTree::from_input(&vec![0; len], IV, FLAGS).content_id().unwrap())
+        .collect();
+    assert_eq!(ids.len(), lens.len());
+    assert_eq!(ids.iter().map(|id| id.root).collect::<BTreeSet<_>>().len(), lens.len());
+}
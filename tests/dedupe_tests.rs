@@ -0,0 +1,103 @@
+use merkle_tree::binary_merkle_tree::{
+    chunk_digest, chunk_output, BinaryMerkleTree, TreeLeafBuilder, CHUNK_LEN, FLAGS, IV, KEYED_HASH,
+};
+use merkle_tree::dedupe::{dedupe_report, ChunkMatch, DedupeReport};
+
+/// Chunk `seed` of a synthetic file: a full chunk of bytes unique to the seed
+fn chunk(seed: u8) -> Vec<u8> {
+    (0..CHUNK_LEN).map(|i| (i % 251) as u8 ^ seed).collect()
+}
+
+fn file(chunks: &[Vec<u8>]) -> Vec<u8> {
+    chunks.concat()
+}
+
+fn tree(input: &[u8]) -> BinaryMerkleTree {
+    let mut builder = TreeLeafBuilder::new(IV, FLAGS).with_content_hashes();
+    builder.update(input);
+    builder.finalize_tree()
+}
+
+/// Tests the content hashes recorded by the builder
+/// Verifies each is the chunk's `chunk_digest`, the root is unchanged, and replacing a leaf
+/// forgets them
+/// Methods tested: TreeLeafBuilder::with_content_hashes, BinaryMerkleTree::content_hash
+#[test]
+fn test_content_hashes() {
+    let input = file(&[chunk(1), chunk(2), chunk(3)[..100].to_vec()]);
+    let mut tree = tree(&input);
+    assert_eq!(tree.root_hash(), BinaryMerkleTree::from_input(&input, IV, FLAGS).root_hash());
+    for (index, bytes) in input.chunks(CHUNK_LEN).enumerate() {
+        assert_eq!(tree.content_hash(index).unwrap(), chunk_digest(bytes).unwrap());
+    }
+    assert_eq!(tree.content_hash(3), None);
+    assert_eq!(BinaryMerkleTree::from_input(&input, IV, FLAGS).content_hash(0), None);
+
+    // Empty input has one empty chunk
+    assert_eq!(self::tree(&[]).content_hash(0).unwrap(), chunk_digest(&[]).unwrap());
+
+    // The same bytes hash alike whatever the position, key or flags
+    let mut keyed = TreeLeafBuilder::new([7; 8], KEYED_HASH).with_content_hashes();
+    keyed.update(&file(&[chunk(2), chunk(1)]));
+    let keyed = keyed.finalize_tree();
+    assert_eq!(keyed.content_hash(1), tree.content_hash(0));
+
+    tree.insert_leaf(0, chunk_output(&chunk(9), 0, IV, FLAGS).unwrap()).unwrap();
+    assert_eq!(tree.content_hash(1), None);
+}
+
+/// Tests matching against files sharing known chunks
+/// Verifies chunks shared at the same offset, at other offsets and in another file are found,
+/// the rest are unique, and reusable bytes count a short final chunk by its length
+/// Methods tested: dedupe_report
+#[test]
+fn test_dedupe_report() {
+    let first = tree(&file(&[chunk(1), chunk(2), chunk(3), chunk(4)]));
+    let second = tree(&file(&[chunk(5), chunk(6), chunk(3)[..300].to_vec()]));
+    // Chunk 2 moves from offset 1 to offset 0, chunk 1 stays at offset 1, chunk 3
+    // appears twice, and the short tail is the second file's tail
+    let new = tree(&file(&[chunk(2), chunk(1), chunk(7), chunk(3), chunk(6), chunk(3), chunk(3)[..300].to_vec()]));
+
+    let report = dedupe_report(&new, &[&first, &second]);
+    assert_eq!(
+        report,
+        DedupeReport {
+            chunks: vec![
+                ChunkMatch::Duplicate { file: 0, index: 1 },
+                ChunkMatch::Duplicate { file: 0, index: 0 },
+                ChunkMatch::Unique,
+                ChunkMatch::Duplicate { file: 0, index: 2 },
+                ChunkMatch::Duplicate { file: 1, index: 1 },
+                ChunkMatch::Duplicate { file: 0, index: 2 },
+                ChunkMatch::Duplicate { file: 1, index: 2 },
+            ],
+            reusable_bytes: 5 * CHUNK_LEN as u64 + 300,
+        }
+    );
+
+    // The earlier file wins; without it the second one is used
+    let report = dedupe_report(&new, &[&second]);
+    assert_eq!(report.chunks[4], ChunkMatch::Duplicate { file: 0, index: 1 });
+    assert_eq!(report.chunks[0], ChunkMatch::Unique);
+    assert_eq!(report.reusable_bytes, CHUNK_LEN as u64 + 300);
+}
+
+/// Tests trees that cannot be matched
+/// Verifies trees without content hashes and removed chunks match nothing
+/// Methods tested: dedupe_report, BinaryMerkleTree::remove_leaf
+#[test]
+fn test_dedupe_report_without_hashes() {
+    let input = file(&[chunk(1), chunk(2)]);
+    let plain = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let hashed = tree(&input);
+
+    let unique = DedupeReport { chunks: vec![ChunkMatch::Unique; 2], reusable_bytes: 0 };
+    assert_eq!(dedupe_report(&plain, &[&hashed]), unique);
+    assert_eq!(dedupe_report(&hashed, &[&plain]), unique);
+    assert_eq!(dedupe_report(&hashed, &[]), unique);
+
+    let mut removed = tree(&input);
+    removed.remove_leaf(0).unwrap();
+    let report = dedupe_report(&hashed, &[&removed, &hashed]);
+    assert_eq!(report.chunks, [ChunkMatch::Duplicate { file: 1, index: 0 }, ChunkMatch::Duplicate { file: 0, index: 1 }]);
+}
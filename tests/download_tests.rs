@@ -0,0 +1,120 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, CHUNK_LEN, FLAGS, IV};
+use merkle_tree::download::{ChunkStatus, DownloadVerifier};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::io::{self, Cursor, Seek, SeekFrom, Write};
+use common::{random_input, SeededRng};
+
+fn chunk(input: &[u8], chunk_index: usize) -> &[u8] {
+    &input[chunk_index * CHUNK_LEN..((chunk_index + 1) * CHUNK_LEN).min(input.len())]
+}
+
+/// Tests a download delivered out of order
+/// Verifies shuffled chunks, with corrupted, truncated, extended and duplicate copies mixed
+/// in, are accepted exactly once each, that bad copies are rejected without being written,
+/// that progress and the missing chunks track the accepted ones, and that the completed sink
+/// equals the original bytes
+/// Methods tested: DownloadVerifier::with_sink, DownloadVerifier::submit_chunk,
+/// DownloadVerifier::missing_chunks, DownloadVerifier::progress, DownloadVerifier::is_complete
+#[test]
+fn test_shuffled_download_with_bad_chunks() {
+    let mut rng = SeededRng::new();
+    for size in [0, 100, CHUNK_LEN, 37 * CHUNK_LEN + 500] {
+        let input = random_input(size);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let chunks = tree.actual_leaves();
+        let mut verifier = DownloadVerifier::with_sink(&tree, Cursor::new(Vec::new()));
+        assert_eq!(verifier.missing_chunks(), (0..chunks).collect::<Vec<_>>());
+        assert_eq!(verifier.progress(), 0.0);
+
+        let mut order: Vec<usize> = (0..chunks).collect();
+        order.shuffle(&mut rng);
+        for (delivered, &chunk_index) in order.iter().enumerate() {
+            let good = chunk(&input, chunk_index);
+            if !good.is_empty() {
+                let mut corrupted = good.to_vec();
+                corrupted[rng.gen_range(0..good.len())] ^= 1;
+                assert_eq!(verifier.submit_chunk(chunk_index, &corrupted).unwrap(), ChunkStatus::Rejected);
+                assert_eq!(verifier.submit_chunk(chunk_index, &good[..good.len() - 1]).unwrap(), ChunkStatus::Rejected);
+            }
+            let mut extended = good.to_vec();
+            extended.push(0);
+            assert_eq!(verifier.submit_chunk(chunk_index, &extended).unwrap(), ChunkStatus::Rejected);
+            if chunks > 1 {
+                // Another chunk's bytes, delivered under the wrong index
+                let other = (chunk_index + 1) % chunks;
+                assert_eq!(verifier.submit_chunk(chunk_index, chunk(&input, other)).unwrap(), ChunkStatus::Rejected);
+            }
+
+            assert_eq!(verifier.submit_chunk(chunk_index, good).unwrap(), ChunkStatus::Accepted);
+            assert_eq!(verifier.submit_chunk(chunk_index, good).unwrap(), ChunkStatus::Duplicate);
+            assert_eq!(verifier.progress(), (delivered + 1) as f64 / chunks as f64);
+            assert!(!verifier.missing_chunks().contains(&chunk_index));
+            assert_eq!(verifier.is_complete(), delivered + 1 == chunks);
+        }
+        assert!(verifier.missing_chunks().is_empty());
+        assert_eq!(verifier.bytes_received(), size as u64);
+        assert_eq!(verifier.into_sink().into_inner(), input);
+    }
+}
+
+/// A sink that fails every write past a byte limit
+struct LimitedSink {
+    inner: Cursor<Vec<u8>>,
+    limit: u64,
+}
+
+impl Write for LimitedSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.inner.position() + buf.len() as u64 > self.limit {
+            return Err(io::Error::other("sink full"));
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for LimitedSink {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Tests errors from a download
+/// Verifies chunk indices outside the tree and failed sink writes are errors that leave the
+/// chunk missing, so it can be submitted again, and that a verifier without a sink still
+/// tracks the chunks
+/// Methods tested: DownloadVerifier::submit_chunk, DownloadVerifier::new
+#[test]
+fn test_download_errors() {
+    let input = random_input(4 * CHUNK_LEN);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+
+    let mut verifier = DownloadVerifier::new(&tree);
+    let error = verifier.submit_chunk(4, &[]).unwrap_err();
+    assert_eq!((error.chunk_index, error.error.kind()), (4, io::ErrorKind::InvalidInput));
+    for chunk_index in 0..4 {
+        assert_eq!(verifier.submit_chunk(chunk_index, chunk(&input, chunk_index)).unwrap(), ChunkStatus::Accepted);
+    }
+    assert!(verifier.is_complete());
+
+    let sink = LimitedSink { inner: Cursor::new(Vec::new()), limit: 2 * CHUNK_LEN as u64 };
+    let mut verifier = DownloadVerifier::with_sink(&tree, sink);
+    assert_eq!(verifier.submit_chunk(1, chunk(&input, 1)).unwrap(), ChunkStatus::Accepted);
+    let error = verifier.submit_chunk(3, chunk(&input, 3)).unwrap_err();
+    assert_eq!((error.chunk_index, error.bytes_written), (3, CHUNK_LEN as u64));
+    assert_eq!(verifier.missing_chunks(), vec![0, 2, 3]);
+
+    let mut sink = verifier.into_sink();
+    sink.limit = u64::MAX;
+    let mut verifier = DownloadVerifier::with_sink(&tree, sink);
+    for chunk_index in [3, 0, 2, 1] {
+        assert_eq!(verifier.submit_chunk(chunk_index, chunk(&input, chunk_index)).unwrap(), ChunkStatus::Accepted);
+    }
+    assert_eq!(verifier.into_sink().inner.into_inner(), input);
+}
@@ -0,0 +1,73 @@
+//! Byte formats are little-endian on every host. These tests pin them to
+//! checked-in fixtures and hand-written bytes, so running them on a big-endian
+//! target (see `scripts/test-big-endian.sh`) catches any native-endian shortcut.
+
+use merkle_tree::bao::{encode_outboard_bao, extract_slice, verify_slice};
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Output, CHUNK_LEN, IV, FLAGS, OUTPUT_BYTES_LEN};
+use merkle_tree::io::write_outboard;
+
+// Four chunks over the test-vector pattern (byte i is i % 251), with the default key
+const FIXTURE_INPUT_LEN: usize = 3 * CHUNK_LEN + 100;
+const BAO_OUTBOARD_FIXTURE: &[u8] = include_bytes!("fixtures/bao_outboard_v1.bin");
+const OUTBOARD_FIXTURE: &[u8] = include_bytes!("fixtures/outboard_v1.bin");
+
+fn fixture_input() -> Vec<u8> {
+    (0..FIXTURE_INPUT_LEN).map(|i| (i % 251) as u8).collect()
+}
+
+/// Tests the Output encoding against bytes written out by hand
+/// Verifies each word and scalar field lands least significant byte first, in both directions
+/// Methods tested: Output::to_bytes, Output::try_from
+#[test]
+fn test_output_bytes_are_little_endian() {
+    let output = Output {
+        input_chaining_value: [0x03020100, 0x07060504, 0x0B0A0908, 0x0F0E0D0C, 0x13121110, 0x17161514, 0x1B1A1918, 0x1F1E1D1C],
+        block_words: [
+            0x23222120, 0x27262524, 0x2B2A2928, 0x2F2E2D2C, 0x33323130, 0x37363534, 0x3B3A3938, 0x3F3E3D3C,
+            0x43424140, 0x47464544, 0x4B4A4948, 0x4F4E4D4C, 0x53525150, 0x57565554, 0x5B5A5958, 0x5F5E5D5C,
+        ],
+        counter: 0x6766656463626160,
+        block_len: 0x00000040,
+        flags: 0x6F6E6D6C,
+    };
+    let mut expected = [0u8; OUTPUT_BYTES_LEN];
+    for (i, byte) in expected[..104].iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    expected[104..108].copy_from_slice(&[0x40, 0, 0, 0]);
+    expected[108..112].copy_from_slice(&[0x6C, 0x6D, 0x6E, 0x6F]);
+
+    assert_eq!(output.to_bytes(), expected);
+    assert_eq!(Output::try_from(&expected[..]), Ok(output));
+}
+
+/// Tests the bao outboard encoding against a checked-in fixture
+/// Verifies byte-for-byte equality, the length header's byte order, and that the fixture verifies
+/// against blake3's hash of the input through a slice
+/// Methods tested: encode_outboard_bao, extract_slice, verify_slice
+#[test]
+fn test_bao_outboard_golden_fixture() {
+    let input = fixture_input();
+    let (outboard, root_hash) = encode_outboard_bao(&input);
+    assert_eq!(BAO_OUTBOARD_FIXTURE[..8], [0x64, 0x0C, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(outboard, BAO_OUTBOARD_FIXTURE, "Bao outboard encoding drifted from the golden fixture");
+
+    let range = 0..FIXTURE_INPUT_LEN as u64;
+    let slice = extract_slice(BAO_OUTBOARD_FIXTURE, Some(&input), range.clone()).unwrap();
+    assert_eq!(root_hash, *blake3::hash(&input).as_bytes());
+    assert_eq!(verify_slice(&slice, &root_hash, IV, FLAGS, range).unwrap(), input);
+}
+
+/// Tests the post-order outboard format against a checked-in fixture
+/// Verifies byte-for-byte equality and that the fixture loads to the tree of the input
+/// Methods tested: write_outboard, BinaryMerkleTree::from_outboard
+#[test]
+fn test_outboard_golden_fixture() {
+    let input = fixture_input();
+    let mut outboard = Vec::new();
+    write_outboard(&input[..], &mut outboard, IV, FLAGS).unwrap();
+    assert_eq!(outboard, OUTBOARD_FIXTURE, "Outboard format drifted from the golden fixture");
+
+    let tree = BinaryMerkleTree::from_outboard(OUTBOARD_FIXTURE, FIXTURE_INPUT_LEN as u64, IV, FLAGS).unwrap();
+    assert_eq!(tree.root_hash(), *blake3::hash(&input).as_bytes());
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{chunk_chaining_value, check_leaf_count, BinaryMerkleTree, GenericMerkleTree, HeapIndex, Output, CHUNK_LEN, IV, FLAGS, MAX_CHUNKS};
+use merkle_tree::error::MerkleError;
+use merkle_tree::flat_tree::FlatTree;
+use merkle_tree::grouped_tree::GroupedTree;
+use merkle_tree::kary_merkle_tree::{kary_parent_output, KaryMerkleTree, MAX_FANOUT};
+use merkle_tree::node_store::VecStore;
+use std::io;
+use common::chunk_leaves;
+
+fn sample_tree(chunks: usize) -> (Vec<u8>, BinaryMerkleTree) {
+    let input: Vec<u8> = (0..chunks * CHUNK_LEN).map(|i| (i % 251) as u8).collect();
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    (input, tree)
+}
+
+/// Tests that every MerkleError variant is reachable from a public call
+/// Verifies each variant is returned for the bad argument it documents, and that the tree is
+/// left unchanged
+/// Methods tested: insert_leaf, remove_leaf, bulk_insert_leaves, compact, prune, open_in,
+/// to_bao_outboard, encode_combined, PrunedTree::verify_chunk, chunk_chaining_value,
+/// KaryMerkleTree::new_from_leaves, KaryMerkleTree::insert_leaf, kary_parent_output,
+/// FlatTree::from_flat_cvs
+#[test]
+fn test_every_error_variant_is_reachable() {
+    let (input, mut tree) = sample_tree(5);
+    let original_root = tree.root_hash();
+    let leaf = tree.leaf(0).unwrap();
+    let out_of_bounds = MerkleError::LeafIndexOutOfBounds { leaf_index: 5, actual_leaves: 5 };
+
+    assert_eq!(
+        tree.bulk_insert_leaves([0, 1].into_iter(), [leaf].into_iter()),
+        Err(MerkleError::LengthMismatch { indices: 2, hashes: 1 })
+    );
+    assert_eq!(tree.bulk_insert_leaves([1, 0].into_iter(), [leaf, leaf].into_iter()), Err(MerkleError::UnsortedIndices));
+    assert_eq!(tree.bulk_insert_leaves([0, 5].into_iter(), [leaf, leaf].into_iter()), Err(out_of_bounds.clone()));
+    assert_eq!(
+        tree.bulk_insert_leaves([usize::MAX].into_iter(), [leaf].into_iter()),
+        Err(MerkleError::LeafIndexOutOfBounds { leaf_index: usize::MAX, actual_leaves: 5 })
+    );
+    assert_eq!(tree.insert_leaf(5, leaf), Err(out_of_bounds.clone()));
+    assert_eq!(tree.remove_leaf(usize::MAX), Err(MerkleError::LeafIndexOutOfBounds { leaf_index: usize::MAX, actual_leaves: 5 }));
+    assert_eq!(tree.prune(&[1, 5]), Err(out_of_bounds.clone()));
+    assert_eq!(tree.prune(&[]), Err(MerkleError::NoLeaves));
+    assert_eq!(tree.root_hash(), original_root, "Rejected calls must leave the tree unchanged");
+
+    let input_mismatch = MerkleError::InputLengthMismatch { input_len: 4 * CHUNK_LEN as u64, actual_leaves: 5 };
+    assert_eq!(tree.compact(&input[..4 * CHUNK_LEN]), Err(input_mismatch.clone()));
+    assert_eq!(tree.encode_combined(&input[..4 * CHUNK_LEN]), Err(input_mismatch.clone()));
+    assert_eq!(tree.to_bao_outboard(4 * CHUNK_LEN as u64), Err(input_mismatch));
+    assert_eq!(tree.actual_leaves(), 5);
+
+    let pruned = tree.prune(&[1]).unwrap();
+    assert_eq!(pruned.verify_chunk(2, &input[2 * CHUNK_LEN..3 * CHUNK_LEN]), Err(MerkleError::LeafNotKept { leaf_index: 2 }));
+
+    let store = VecStore::new(2, leaf);
+    assert!(matches!(GenericMerkleTree::open_in(store.clone(), 0, IV, FLAGS), Err(MerkleError::NoLeaves)));
+    assert!(matches!(
+        GenericMerkleTree::open_in(store, usize::MAX, IV, FLAGS),
+        Err(MerkleError::TooManyChunks { chunks }) if chunks == usize::MAX as u64
+    ));
+
+    assert_eq!(chunk_chaining_value(&[0; CHUNK_LEN + 1], 0), Err(MerkleError::ChunkTooLong { len: CHUNK_LEN + 1 }));
+
+    let leaves = chunk_leaves(&input, IV, FLAGS);
+    assert!(matches!(KaryMerkleTree::new_from_leaves(leaves.clone(), 1, IV, FLAGS), Err(MerkleError::InvalidFanout { fanout: 1 })));
+    assert!(matches!(
+        KaryMerkleTree::from_input(&input, MAX_FANOUT + 1, IV, FLAGS),
+        Err(MerkleError::InvalidFanout { .. })
+    ));
+    assert!(matches!(KaryMerkleTree::new_from_leaves(Vec::new(), 4, IV, FLAGS), Err(MerkleError::NoLeaves)));
+    assert_eq!(kary_parent_output(&[[0; 8]], IV, FLAGS), Err(MerkleError::InvalidFanout { fanout: 1 }));
+    let mut kary = KaryMerkleTree::new_from_leaves(leaves, 4, IV, FLAGS).unwrap();
+    assert_eq!(kary.insert_leaf(5, leaf), Err(out_of_bounds));
+
+    let cvs = tree.to_flat_cvs();
+    assert_eq!(FlatTree::from_flat_cvs(&cvs[1..], 5, IV, FLAGS), Err(MerkleError::CvCountMismatch { cvs: 10, actual_leaves: 5 }));
+
+    let parent = tree.node_at(HeapIndex(1)).unwrap();
+    assert!(matches!(
+        BinaryMerkleTree::new_from_leaves_checked(vec![leaf, parent], IV, FLAGS),
+        Err(MerkleError::InvalidLeaf { leaf_index: 1, flags: 4 })
+    ));
+    let conflicting = Output { flags: 5, ..leaf };
+    assert_eq!(conflicting.validate_flags(), Err(MerkleError::InvalidFlags { flags: 5, conflicting: 5 }));
+
+    let outer = tree.checkpoint();
+    let inner = tree.checkpoint();
+    tree.rollback(outer).unwrap();
+    assert_eq!(tree.discard(inner), Err(MerkleError::UnknownCheckpoint));
+
+    assert_eq!(GroupedTree::from_input(&input, 3, IV, FLAGS).unwrap_err(), MerkleError::InvalidGroupChunks { group_chunks: 3 });
+    let mut grouped = GroupedTree::from_input(&input, 2, IV, FLAGS).unwrap();
+    assert_eq!(grouped.insert_group(0, &input[..CHUNK_LEN]), Err(MerkleError::GroupLengthMismatch { group_index: 0, len: CHUNK_LEN }));
+
+    assert_eq!(BinaryMerkleTree::from_leaf_hashes(&[[0; 32]], IV, FLAGS).unwrap_err(), MerkleError::TooFewLeafHashes { count: 1 });
+    assert_eq!(tree.insert_leaf_hash(0, [0; 32]), Err(MerkleError::WrongLeafKind { leaf_hashes: false }));
+}
+
+/// Tests the limit on the number of chunks, by arithmetic rather than allocation
+/// Verifies the largest BLAKE3 input has exactly MAX_CHUNKS chunks, that one more chunk is
+/// rejected, and that counts a target cannot address are rejected
+/// Methods tested: check_leaf_count, try_from_input
+#[test]
+fn test_leaf_count_limit() {
+    assert_eq!(u64::MAX.div_ceil(CHUNK_LEN as u64), MAX_CHUNKS);
+    assert_eq!(check_leaf_count(1), Ok(1));
+    assert_eq!(check_leaf_count(MAX_CHUNKS + 1), Err(MerkleError::TooManyChunks { chunks: MAX_CHUNKS + 1 }));
+    assert_eq!(check_leaf_count(u64::MAX), Err(MerkleError::TooManyChunks { chunks: u64::MAX }));
+    if cfg!(target_pointer_width = "64") {
+        assert_eq!(check_leaf_count(MAX_CHUNKS), Ok(MAX_CHUNKS as usize));
+    } else {
+        // The padded heap of 2^(usize::BITS - 1) leaves needs 2^usize::BITS nodes
+        let unaddressable = (usize::MAX / 2 + 2) as u64;
+        assert_eq!(check_leaf_count(unaddressable), Err(MerkleError::TooManyChunks { chunks: unaddressable }));
+    }
+
+    let input = [7; 3 * CHUNK_LEN];
+    let tree = BinaryMerkleTree::try_from_input(&input, IV, FLAGS).unwrap();
+    assert_eq!(tree.root_hash(), BinaryMerkleTree::from_input(&input, IV, FLAGS).root_hash());
+}
+
+/// Tests that APIs returning io::Result report bad arguments as InvalidInput
+/// Methods tested: BinaryMerkleTree::apply_and_log
+#[test]
+fn test_merkle_errors_become_invalid_input() {
+    let (_, mut tree) = sample_tree(3);
+    let leaf = tree.leaf(0).unwrap();
+    let mut log = Vec::new();
+    let err = tree.apply_and_log(3, leaf, &mut log).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.to_string(), "Leaf index 3 is out of bounds for tree with 3 leaves");
+    assert!(log.is_empty());
+}
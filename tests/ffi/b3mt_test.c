@@ -0,0 +1,88 @@
+/*
+ * Exercises the C API from tests/ffi_tests.rs. Hashes `argv[1]` bytes of the
+ * pattern i % 251, prints the root as hex, and checks proofs, leaf updates and
+ * error codes. Exits non-zero on the first failed check.
+ */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "b3mt.h"
+
+#define CHUNK_LEN 1024
+
+#define CHECK(cond)                                                        \
+    do {                                                                   \
+        if (!(cond)) {                                                     \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+            exit(1);                                                       \
+        }                                                                  \
+    } while (0)
+
+static void print_hex(const uint8_t *bytes, size_t len) {
+    for (size_t i = 0; i < len; i++) {
+        printf("%02x", bytes[i]);
+    }
+    printf("\n");
+}
+
+int main(int argc, char **argv) {
+    CHECK(argc == 2);
+    size_t len = (size_t)strtoul(argv[1], NULL, 10);
+    uint8_t *input = malloc(len + 1);
+    CHECK(input != NULL);
+    for (size_t i = 0; i < len; i++) {
+        input[i] = (uint8_t)(i % 251);
+    }
+    size_t leaves = len == 0 ? 1 : (len + CHUNK_LEN - 1) / CHUNK_LEN;
+
+    b3mt_tree_t *tree = NULL;
+    CHECK(b3mt_tree_new(input, len, &tree) == B3MT_OK);
+    CHECK(tree != NULL);
+    uint8_t root[32];
+    CHECK(b3mt_tree_root(tree, root) == B3MT_OK);
+    print_hex(root, sizeof root);
+
+    /* Proof for the last leaf: query the size, then fill */
+    size_t last = leaves - 1;
+    const uint8_t *chunk = input + last * CHUNK_LEN;
+    size_t chunk_len = len - last * CHUNK_LEN;
+    size_t proof_len = 0;
+    CHECK(b3mt_tree_proof(tree, last, NULL, 0, &proof_len) == B3MT_BUFFER_TOO_SMALL);
+    uint8_t *proof = malloc(proof_len);
+    CHECK(proof != NULL);
+    size_t written = 0;
+    CHECK(b3mt_tree_proof(tree, last, proof, proof_len, &written) == B3MT_OK);
+    CHECK(written == proof_len);
+    CHECK(b3mt_verify_proof(root, chunk, chunk_len, proof, proof_len) == B3MT_OK);
+    CHECK(b3mt_verify_proof(root, chunk, chunk_len, proof, proof_len - 1) == B3MT_INVALID_ARGUMENT);
+    if (chunk_len > 0) {
+        CHECK(b3mt_verify_proof(root, chunk, chunk_len - 1, proof, proof_len) == B3MT_PROOF_MISMATCH);
+    }
+
+    /* Updating the leaf changes the root; the old proof no longer verifies */
+    uint8_t new_chunk[CHUNK_LEN];
+    memset(new_chunk, 0xAB, sizeof new_chunk);
+    size_t new_len = chunk_len == 0 ? 1 : chunk_len;
+    CHECK(b3mt_tree_update_leaf(tree, last, new_chunk, new_len) == B3MT_OK);
+    uint8_t new_root[32];
+    CHECK(b3mt_tree_root(tree, new_root) == B3MT_OK);
+    CHECK(memcmp(root, new_root, sizeof root) != 0);
+    CHECK(b3mt_verify_proof(new_root, chunk, chunk_len, proof, proof_len) == B3MT_PROOF_MISMATCH);
+    CHECK(b3mt_tree_proof(tree, last, proof, proof_len, &written) == B3MT_OK);
+    CHECK(b3mt_verify_proof(new_root, new_chunk, new_len, proof, proof_len) == B3MT_OK);
+
+    /* Bad arguments are reported, not crashed on */
+    CHECK(b3mt_tree_update_leaf(tree, leaves, new_chunk, 1) == B3MT_INVALID_ARGUMENT);
+    CHECK(b3mt_tree_update_leaf(tree, 0, new_chunk, CHUNK_LEN + 1) == B3MT_INVALID_ARGUMENT);
+    CHECK(b3mt_tree_proof(tree, leaves, proof, proof_len, &written) == B3MT_INVALID_ARGUMENT);
+    CHECK(b3mt_tree_root(NULL, root) == B3MT_NULL_POINTER);
+    CHECK(b3mt_tree_new(NULL, 1, &tree) == B3MT_NULL_POINTER);
+    CHECK(b3mt_verify_proof(NULL, chunk, chunk_len, proof, proof_len) == B3MT_NULL_POINTER);
+
+    b3mt_tree_free(tree);
+    b3mt_tree_free(NULL);
+    free(proof);
+    free(input);
+    return 0;
+}
@@ -0,0 +1,66 @@
+#![cfg(all(feature = "ffi", unix))]
+
+use merkle_tree::binary_merkle_tree::CHUNK_LEN;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Empty, partial-chunk, exact-chunk and multi-chunk inputs
+const INPUT_SIZES: [usize; 7] = [0, 1, CHUNK_LEN - 1, CHUNK_LEN, CHUNK_LEN + 1, 5 * CHUNK_LEN + 17, 64 * CHUNK_LEN];
+
+/// Build the library as a cdylib with the `ffi` feature, in its own target directory
+/// so it does not wait on the lock held by the running `cargo test`. Returns the
+/// directory holding it.
+fn build_cdylib() -> PathBuf {
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+    let status = Command::new(env!("CARGO"))
+        .args(["rustc", "--lib", "--features", "ffi", "--crate-type", "cdylib", "--manifest-path"])
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .unwrap();
+    assert!(status.success(), "Building the cdylib failed");
+    target_dir.join("debug")
+}
+
+/// Compile tests/ffi/b3mt_test.c against the generated header and the cdylib.
+fn build_c_test(lib_dir: &Path) -> PathBuf {
+    let exe = Path::new(env!("CARGO_TARGET_TMPDIR")).join("b3mt_test");
+    let compiler = cc::Build::new()
+        .cargo_metadata(false)
+        .target(env!("B3MT_TARGET"))
+        .host(env!("B3MT_TARGET"))
+        .opt_level(0)
+        .debug(false)
+        .get_compiler();
+    let status = compiler
+        .to_command()
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ffi/b3mt_test.c"))
+        .arg(format!("-I{}", env!("OUT_DIR")))
+        .arg(format!("-L{}", lib_dir.display()))
+        .arg("-lmerkle_tree")
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-o")
+        .arg(&exe)
+        .status()
+        .unwrap();
+    assert!(status.success(), "Compiling the C test failed");
+    exe
+}
+
+/// Tests the C API from a C program
+/// Verifies that roots printed from C equal blake3::hash of the same input, and that the
+/// program's own proof, update and error-code checks pass
+/// Methods tested: b3mt_tree_new, b3mt_tree_root, b3mt_tree_update_leaf, b3mt_tree_proof,
+/// b3mt_verify_proof, b3mt_tree_free
+#[test]
+fn test_c_program_against_cdylib() {
+    let exe = build_c_test(&build_cdylib());
+    for &size in INPUT_SIZES.iter() {
+        let output = Command::new(&exe).arg(size.to_string()).output().unwrap();
+        assert!(output.status.success(), "C test failed for {} bytes: {}", size, String::from_utf8_lossy(&output.stderr));
+        let input: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        assert_eq!(String::from_utf8(output.stdout).unwrap().trim(), blake3::hash(&input).to_hex().as_str(),
+            "Root from C does not match blake3 for {} bytes", size);
+    }
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, GenericMerkleTree, CHUNK_LEN, IV, FLAGS};
+use merkle_tree::file_store::FileStore;
+use merkle_tree::node_store::NodeStore;
+use common::random_input;
+
+/// Tests building a tree into a file through a tiny cache and reopening it cold
+/// Verifies that the root matches an in-memory build, that the cache never grows past its
+/// capacity, and that the reopened tree serves proofs and chunk checks
+/// Methods tested: GenericMerkleTree::from_input_in, GenericMerkleTree::open_in,
+/// FileStore::create, FileStore::open, FileStore::flush, generate_proof, verify_chunk
+#[test]
+fn test_file_store_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    for &size in &[0, 100, CHUNK_LEN, 5 * CHUNK_LEN, 37 * CHUNK_LEN + 300] {
+        let input = random_input(size);
+        let path = dir.path().join(format!("nodes-{}", size));
+        let memory_tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+
+        let store = FileStore::create(&path, 4).unwrap();
+        let mut disk_tree = GenericMerkleTree::from_input_in(store, &input, IV, FLAGS);
+        assert_eq!(disk_tree.root_hash(), memory_tree.root_hash(), "Root mismatch for {} bytes", size);
+        assert!(disk_tree.store().cached_nodes() <= 4);
+        let actual_leaves = disk_tree.actual_leaves();
+
+        // Updates are written through to the file as well
+        let last = actual_leaves - 1;
+        let new_leaf = memory_tree.leaf(0).unwrap();
+        let mut memory_tree = memory_tree;
+        memory_tree.insert_leaf(last, new_leaf).unwrap();
+        disk_tree.insert_leaf(last, new_leaf).unwrap();
+        drop(disk_tree);
+
+        let store = FileStore::open(&path, 3).unwrap();
+        assert_eq!(store.cached_nodes(), 0);
+        let reopened = GenericMerkleTree::open_in(store, actual_leaves, IV, FLAGS).unwrap();
+        assert_eq!(reopened.root_hash(), memory_tree.root_hash());
+        assert!(reopened.verify_integrity());
+        for leaf_index in 0..actual_leaves {
+            let proof = reopened.generate_proof(leaf_index).unwrap();
+            assert!(proof.verify(&memory_tree.root_hash(), IV, FLAGS));
+            if leaf_index != last {
+                let chunk = &input[leaf_index * CHUNK_LEN..((leaf_index + 1) * CHUNK_LEN).min(size)];
+                assert!(reopened.verify_chunk(leaf_index, chunk));
+            }
+        }
+        assert!(reopened.store().cached_nodes() <= 3);
+    }
+}
+
+/// Tests reading a file-backed tree from several threads at once
+/// Verifies the store is `Sync`, and that threads sharing one tiny cache all get proofs that
+/// verify while the cache stays within its capacity
+/// Methods tested: generate_proof, FileStore::cached_nodes
+#[test]
+fn test_file_store_concurrent_reads() {
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<GenericMerkleTree<FileStore>>();
+
+    let dir = tempfile::tempdir().unwrap();
+    let input = random_input(50 * CHUNK_LEN);
+    let store = FileStore::create(dir.path().join("nodes"), 8).unwrap();
+    let tree = GenericMerkleTree::from_input_in(store, &input, IV, FLAGS);
+    let root_hash = tree.root_hash();
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            let tree = &tree;
+            scope.spawn(move || {
+                for leaf_index in (thread..tree.actual_leaves()).step_by(4) {
+                    assert!(tree.generate_proof(leaf_index).unwrap().verify(&root_hash, IV, FLAGS));
+                }
+            });
+        }
+    });
+    assert!(tree.store().cached_nodes() <= 8);
+}
+
+/// Tests that flush writes dirty nodes back so a second store sees them
+/// Methods tested: GenericMerkleTree::store_mut, FileStore::flush, FileStore::open, NodeStore::len
+#[test]
+fn test_file_store_flush() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nodes");
+    let input = random_input(9 * CHUNK_LEN);
+    let mut tree = GenericMerkleTree::from_input_in(FileStore::create(&path, 64).unwrap(), &input, IV, FLAGS);
+    let root_hash = tree.root_hash();
+
+    // Everything fits in the cache, so nothing has reached the file yet
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+    let mut store = FileStore::open(&path, 64).unwrap();
+    assert_eq!(store.len(), 0);
+
+    tree.store_mut().flush().unwrap();
+    store = FileStore::open(&path, 64).unwrap();
+    // 9 leaves sit at heap indices 16..25
+    assert_eq!(store.len(), 25);
+    assert_eq!(GenericMerkleTree::open_in(store, 9, IV, FLAGS).unwrap().root_hash(), root_hash);
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, CHUNK_LEN, IV, FLAGS};
+use merkle_tree::io::StreamingTreeBuilder;
+use rand::Rng;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use common::SeededRng;
+
+// Sizes covering empty, sub-chunk, exact-chunk and multi-chunk files
+const FILE_SIZES: [usize; 6] = [0, 1, CHUNK_LEN - 1, CHUNK_LEN, 3 * CHUNK_LEN + 17, 200 * CHUNK_LEN + 5];
+
+fn write_temp_file(contents: &[u8]) -> tempfile::NamedTempFile {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(contents).unwrap();
+    file.flush().unwrap();
+    file
+}
+
+/// Tests tree construction from a file on disk
+/// Verifies that the root matches a tree built from the same bytes in memory
+/// Methods tested: BinaryMerkleTree::from_file, BinaryMerkleTree::from_input
+#[test]
+fn test_from_file_matches_from_input() {
+    let mut rng = SeededRng::new();
+    for &size in FILE_SIZES.iter() {
+        let input: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+        let file = write_temp_file(&input);
+
+        let file_tree = BinaryMerkleTree::from_file(file.path(), IV, FLAGS).unwrap();
+        let input_tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        assert_eq!(file_tree.actual_leaves(), input_tree.actual_leaves());
+        assert_eq!(file_tree.root_hash(), input_tree.root_hash(),
+            "File root does not match input root for size {} bytes", size);
+    }
+}
+
+/// Tests that a missing file surfaces the underlying io::Error
+/// Methods tested: BinaryMerkleTree::from_file
+#[test]
+fn test_from_file_missing_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let result = BinaryMerkleTree::from_file(dir.path().join("does-not-exist"), IV, FLAGS);
+    assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+}
+
+/// Tests tree construction from a reader with a configurable buffer
+/// Verifies that roots agree across buffer sizes, including one rounded down to a chunk multiple
+/// and a reader yielding short reads, and that buffers smaller than a chunk are rejected
+/// Methods tested: BinaryMerkleTree::from_reader_buffered, BinaryMerkleTree::from_input
+#[test]
+fn test_from_reader_buffered_matches_from_input() {
+    let mut rng = SeededRng::new();
+    for &size in FILE_SIZES.iter() {
+        let input: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+        let expected = BinaryMerkleTree::from_input(&input, IV, FLAGS).root_hash();
+        for &buf_size in &[1024, 8192, 65536, 3000] {
+            let tree = BinaryMerkleTree::from_reader_buffered(&input[..], buf_size, IV, FLAGS).unwrap();
+            assert_eq!(tree.root_hash(), expected, "Root mismatch for {} bytes with a {}-byte buffer", size, buf_size);
+            let short_reads = BufReader::with_capacity(100, &input[..]);
+            let tree = BinaryMerkleTree::from_reader_buffered(short_reads, buf_size, IV, FLAGS).unwrap();
+            assert_eq!(tree.root_hash(), expected);
+        }
+    }
+
+    let result = BinaryMerkleTree::from_reader_buffered(&[0u8; 10][..], CHUNK_LEN - 1, IV, FLAGS);
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+/// Tests streaming a file into a tree builder through io::copy
+/// Verifies that odd read buffer sizes still yield the Blake3Hasher root and the from_input leaves
+/// Methods tested: StreamingTreeBuilder::write, StreamingTreeBuilder::finalize
+#[test]
+fn test_streaming_tree_builder_from_file() {
+    let mut rng = SeededRng::new();
+    for &size in FILE_SIZES.iter() {
+        let input: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+        let file = write_temp_file(&input);
+        let mut hasher = Blake3Hasher::new();
+        hasher.update(&input);
+        let expected_hash = hasher.finalize_array();
+
+        for &buffer_len in &[1, 13, 1000, CHUNK_LEN + 1, 7 * CHUNK_LEN - 3] {
+            let mut reader = BufReader::with_capacity(buffer_len, File::open(file.path()).unwrap());
+            let mut builder = StreamingTreeBuilder::new(IV, FLAGS);
+            let copied = io::copy(&mut reader, &mut builder).unwrap();
+            assert_eq!(copied, size as u64);
+
+            let tree = builder.finalize();
+            assert_eq!(tree.actual_leaves(), BinaryMerkleTree::from_input(&input, IV, FLAGS).actual_leaves());
+            assert_eq!(tree.root_hash(), expected_hash,
+                "Streamed root does not match hasher for size {} with buffer {}", size, buffer_len);
+        }
+    }
+}
+
+/// Tests memory-mapped tree construction
+/// Verifies that the mmap root matches from_file for sub-chunk, multi-chunk and empty files
+/// Methods tested: BinaryMerkleTree::from_file_mmap, BinaryMerkleTree::from_file
+#[cfg(feature = "mmap")]
+#[test]
+fn test_from_file_mmap_matches_from_file() {
+    let mut rng = SeededRng::new();
+    for &size in FILE_SIZES.iter() {
+        let input: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+        let file = write_temp_file(&input);
+
+        let mmap_tree = BinaryMerkleTree::from_file_mmap(file.path(), IV, FLAGS).unwrap();
+        let file_tree = BinaryMerkleTree::from_file(file.path(), IV, FLAGS).unwrap();
+        assert_eq!(mmap_tree.root_hash(), file_tree.root_hash(),
+            "Mmap root does not match from_file root for size {} bytes", size);
+    }
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{chunk_output, cv_from_bytes, BinaryMerkleTree, CHUNK_LEN, IV, FLAGS};
+use merkle_tree::error::MerkleError;
+use merkle_tree::flat_tree::FlatTree;
+use common::random_input;
+
+const KEY_WORDS: [u32; 8] = [
+    0x03020100, 0x07060504, 0x0B0A0908, 0x0F0E0D0C, 0x13121110, 0x17161514, 0x1B1A1918, 0x1F1E1D1C,
+];
+
+/// Tests rebuilding a tree from its flat chaining values
+/// Verifies that the rebuilt tree has the original root, and that proofs from it equal the
+/// original tree's proofs and verify against the original root, keyed and unkeyed
+/// Methods tested: to_flat_cvs, FlatTree::from_flat_cvs, FlatTree::root_hash,
+/// FlatTree::generate_proof, MerkleProof::verify
+#[test]
+fn test_flat_cvs_round_trip_proofs_verify() {
+    for &key_words in &[IV, KEY_WORDS] {
+        for &chunks in &[2usize, 3, 5, 8, 13, 64] {
+            let input = random_input(chunks * CHUNK_LEN - 100);
+            let tree = BinaryMerkleTree::from_input(&input, key_words, FLAGS);
+            let cvs = tree.to_flat_cvs();
+            let flat = FlatTree::from_flat_cvs(&cvs, tree.actual_leaves(), key_words, FLAGS).unwrap();
+            let root = tree.root_hash();
+            assert_eq!(flat.root_hash(), Some(root), "Root mismatch for {} chunks", chunks);
+
+            for (leaf_index, chunk) in input.chunks(CHUNK_LEN).enumerate() {
+                let proof = flat.generate_proof(leaf_index, chunk_output(chunk, leaf_index as u64, key_words, FLAGS).unwrap()).unwrap();
+                assert_eq!(Some(&proof), tree.generate_proof(leaf_index).as_ref());
+                assert!(proof.verify(&root, key_words, FLAGS), "Proof for leaf {} of {} failed", leaf_index, chunks);
+            }
+        }
+    }
+}
+
+/// Tests the flat layout itself
+/// Verifies that the array starts at the root, ends with the leaves in order, and holds
+/// one entry per real node
+/// Methods tested: to_flat_cvs, FlatTree::leaf_cv
+#[test]
+fn test_flat_cvs_layout() {
+    let tree = BinaryMerkleTree::from_input(&random_input(5 * CHUNK_LEN), IV, FLAGS);
+    let cvs = tree.to_flat_cvs();
+    // Levels of 5, 3, 2 and 1 nodes
+    assert_eq!(cvs.len(), 11);
+    assert_eq!(cv_from_bytes(cvs[0]), tree.root_cv_and_bytes().0);
+    let flat = FlatTree::from_flat_cvs(&cvs, 5, IV, FLAGS).unwrap();
+    for leaf_index in 0..5 {
+        assert_eq!(cv_from_bytes(cvs[6 + leaf_index]), tree.leaf(leaf_index).unwrap().chaining_value());
+        assert_eq!(flat.leaf_cv(leaf_index), Some(cv_from_bytes(cvs[6 + leaf_index])));
+    }
+    assert_eq!(flat.leaf_cv(5), None);
+}
+
+/// Tests what a flat tree cannot or must not do
+/// Verifies that a one-leaf tree has no root hash but still proves its leaf, that a
+/// mismatched leaf or a wrong chaining value count is rejected, and that a tampered
+/// chaining value changes the root
+/// Methods tested: FlatTree::from_flat_cvs, FlatTree::root_hash, FlatTree::generate_proof
+#[test]
+fn test_flat_tree_limits() {
+    let input = random_input(100);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let flat = FlatTree::from_flat_cvs(&tree.to_flat_cvs(), 1, IV, FLAGS).unwrap();
+    assert_eq!(flat.root_hash(), None);
+    let proof = flat.generate_proof(0, tree.leaf(0).unwrap()).unwrap();
+    assert!(proof.verify(&tree.root_hash(), IV, FLAGS));
+
+    let input = random_input(3 * CHUNK_LEN);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let mut cvs = tree.to_flat_cvs();
+    let flat = FlatTree::from_flat_cvs(&cvs, 3, IV, FLAGS).unwrap();
+    assert_eq!(flat.generate_proof(0, tree.leaf(1).unwrap()), None);
+    assert_eq!(flat.generate_proof(3, tree.leaf(0).unwrap()), None);
+
+    assert_eq!(FlatTree::from_flat_cvs(&cvs, 4, IV, FLAGS), Err(MerkleError::CvCountMismatch { cvs: 6, actual_leaves: 4 }));
+    assert_eq!(FlatTree::from_flat_cvs(&[], 0, IV, FLAGS), Err(MerkleError::NoLeaves));
+    cvs[1][0] ^= 1;
+    let tampered = FlatTree::from_flat_cvs(&cvs, 3, IV, FLAGS).unwrap();
+    assert_ne!(tampered.root_hash(), Some(tree.root_hash()));
+}
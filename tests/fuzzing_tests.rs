@@ -0,0 +1,87 @@
+#![cfg(feature = "arbitrary")]
+
+use arbitrary::{Arbitrary, Unstructured};
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, CHUNK_LEN};
+use merkle_tree::fuzzing::{check_ops, check_pruned_serialization, check_serialization, TreeOp};
+use merkle_tree::pruned_tree::PrunedTree;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Tests the shadow model on hand-written operations, valid and not
+/// Verifies each op keeps the tree equal to the hasher over the shadow input, and that
+/// bad indices are rejected with the predicted error
+/// Methods tested: ShadowModel::apply, check_ops
+#[test]
+fn test_shadow_model_ops() {
+    let ops = [
+        TreeOp::Insert { leaf_index: 1, bytes: vec![9; 10] },
+        TreeOp::Insert { leaf_index: 4, bytes: Vec::new() },
+        TreeOp::Insert { leaf_index: usize::MAX, bytes: vec![1] },
+        TreeOp::BulkInsert { leaf_indices: vec![0, 3], chunks: vec![vec![1; 5000], vec![2]] },
+        TreeOp::BulkInsert { leaf_indices: vec![3, 0], chunks: vec![vec![1], vec![2]] },
+        TreeOp::BulkInsert { leaf_indices: vec![0, 9], chunks: vec![vec![1], vec![2]] },
+        TreeOp::BulkInsert { leaf_indices: vec![0], chunks: Vec::new() },
+        TreeOp::ProofRoundTrip { leaf_index: 2 },
+        TreeOp::ProofRoundTrip { leaf_index: 5 },
+        TreeOp::Append { bytes: pattern(3 * CHUNK_LEN + 1) },
+        TreeOp::Truncate { len: 2 * CHUNK_LEN + 17 },
+        TreeOp::Truncate { len: 2 * CHUNK_LEN },
+        TreeOp::Truncate { len: 0 },
+        TreeOp::Insert { leaf_index: 0, bytes: Vec::new() },
+        TreeOp::ProofRoundTrip { leaf_index: 0 },
+    ];
+    let model = check_ops(&pattern(4 * CHUNK_LEN + 100), None, &ops).unwrap();
+    assert_eq!(model.input(), &[] as &[u8]);
+    assert_eq!(model.tree().actual_leaves(), 1);
+
+    // Leaf 1 is the last of two, so its 10 bytes stay 10 bytes
+    let keyed = check_ops(&pattern(2 * CHUNK_LEN), Some([7; 32]), &ops[..9]).unwrap();
+    assert_eq!(keyed.input().len(), CHUNK_LEN + 10);
+}
+
+/// Tests the shadow model on operations built from random bytes, as a fuzzer would
+/// Verifies no sequence of arbitrary operations makes the tree and shadow input disagree
+/// Methods tested: TreeOp::arbitrary, check_ops
+#[test]
+fn test_arbitrary_op_sequences() {
+    let mut rng = StdRng::seed_from_u64(0x6233_6d74);
+    for _ in 0..200 {
+        let input = pattern(rng.gen_range(0..12 * CHUNK_LEN));
+        let key = rng.gen::<bool>().then(|| rng.gen());
+        let mut data = vec![0u8; 4000];
+        rng.fill(&mut data[..]);
+        let mut u = Unstructured::new(&data);
+        let mut ops = Vec::new();
+        while ops.len() < 20 {
+            match TreeOp::arbitrary(&mut u) {
+                Ok(op) => ops.push(op),
+                Err(_) => break,
+            }
+        }
+        if let Err(divergence) = check_ops(&input, key, &ops) {
+            panic!("{}", divergence);
+        }
+    }
+}
+
+/// Tests serialization round trips of arbitrary trees and pruned trees
+/// Methods tested: BinaryMerkleTree::arbitrary, PrunedTree::arbitrary, check_serialization,
+/// check_pruned_serialization
+#[test]
+fn test_arbitrary_serialization_round_trips() {
+    let mut rng = StdRng::seed_from_u64(17);
+    for _ in 0..100 {
+        let mut data = vec![0u8; rng.gen_range(0..6000)];
+        rng.fill(&mut data[..]);
+        if let Ok(tree) = BinaryMerkleTree::arbitrary(&mut Unstructured::new(&data)) {
+            check_serialization(&tree).unwrap();
+        }
+        if let Ok(pruned) = PrunedTree::arbitrary(&mut Unstructured::new(&data)) {
+            check_pruned_serialization(&pruned).unwrap();
+        }
+    }
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, CHUNK_LEN, FLAGS, IV, KEYED_HASH};
+use merkle_tree::error::MerkleError;
+use merkle_tree::grouped_tree::GroupedTree;
+use std::io::Cursor;
+use common::random_input;
+
+const GROUP_SIZES: [usize; 3] = [1, 4, 16];
+
+/// Tests that grouping chunks does not change the root
+/// Verifies roots match the per-chunk tree and `Blake3Hasher` for group sizes 1, 4 and 16,
+/// with full and partial final groups and empty input, from slices and readers
+/// Methods tested: GroupedTree::from_input, GroupedTree::from_reader, GroupedTree::root_hash
+#[test]
+fn test_grouped_root_matches_plain_tree() {
+    for size in [0, 1, CHUNK_LEN, 16 * CHUNK_LEN, 37 * CHUNK_LEN + 123, 64 * CHUNK_LEN, 100 * CHUNK_LEN + 1] {
+        let input = random_input(size);
+        let plain = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let mut hasher = Blake3Hasher::new();
+        hasher.update(&input);
+        for group_chunks in GROUP_SIZES {
+            let grouped = GroupedTree::from_input(&input, group_chunks, IV, FLAGS).unwrap();
+            assert_eq!(grouped.root_hash(), plain.root_hash(), "{} bytes in groups of {}", size, group_chunks);
+            assert_eq!(grouped.root_hash(), hasher.finalize_array());
+            assert_eq!(grouped.actual_groups(), plain.actual_leaves().div_ceil(group_chunks));
+            assert_eq!(grouped.input_len(), size as u64);
+
+            let streamed = GroupedTree::from_reader(Cursor::new(&input), group_chunks, IV, FLAGS).unwrap();
+            assert_eq!(streamed.root_hash(), plain.root_hash());
+            assert_eq!(streamed.input_len(), size as u64);
+        }
+        let keyed = BinaryMerkleTree::from_input(&input, [5; 8], KEYED_HASH);
+        assert_eq!(GroupedTree::from_input(&input, 4, [5; 8], KEYED_HASH).unwrap().root_hash(), keyed.root_hash());
+    }
+}
+
+/// Tests verifying and replacing whole groups
+/// Verifies every group checks out, a flipped byte does not, group proofs verify, and replacing
+/// a middle group or the final partial group (including changing its length) gives the root of
+/// the changed input
+/// Methods tested: GroupedTree::verify_group, GroupedTree::insert_group, GroupedTree::group_proof
+#[test]
+fn test_group_updates() {
+    for group_chunks in GROUP_SIZES {
+        let mut input = random_input(37 * CHUNK_LEN + 123);
+        let mut grouped = GroupedTree::from_input(&input, group_chunks, IV, FLAGS).unwrap();
+        let group_len = group_chunks * CHUNK_LEN;
+        for (group_index, group) in input.chunks(group_len).enumerate() {
+            assert!(grouped.verify_group(group_index, group));
+            let mut flipped = group.to_vec();
+            flipped[group.len() / 2] ^= 1;
+            assert!(!grouped.verify_group(group_index, &flipped));
+            assert!(grouped.group_proof(group_index).unwrap().verify(&grouped.root_hash(), IV, FLAGS));
+        }
+
+        let middle = grouped.actual_groups() / 2;
+        let range = middle * group_len..(middle + 1) * group_len;
+        input[range.clone()].copy_from_slice(&random_input(group_len));
+        grouped.insert_group(middle, &input[range]).unwrap();
+        assert_eq!(grouped.root_hash(), BinaryMerkleTree::from_input(&input, IV, FLAGS).root_hash());
+
+        // The final group keeps its number of chunks but may change length within the last one
+        let last = grouped.actual_groups() - 1;
+        input.truncate(input.len() - 100);
+        grouped.insert_group(last, &input[last * group_len..]).unwrap();
+        assert_eq!(grouped.input_len(), input.len() as u64);
+        assert_eq!(grouped.root_hash(), BinaryMerkleTree::from_input(&input, IV, FLAGS).root_hash());
+        assert!(grouped.verify_group(last, &input[last * group_len..]));
+    }
+}
+
+/// Tests that updates must supply whole groups
+/// Verifies short and long groups, a final group with a different number of chunks, an
+/// out-of-bounds group and a group size that is not a power of two are rejected, leaving the
+/// tree unchanged
+/// Methods tested: GroupedTree::insert_group, GroupedTree::from_input
+#[test]
+fn test_group_update_validation() {
+    let input = random_input(10 * CHUNK_LEN + 5);
+    let mut grouped = GroupedTree::from_input(&input, 4, IV, FLAGS).unwrap();
+    let root = grouped.root_hash();
+
+    for len in [4 * CHUNK_LEN - 1, 4 * CHUNK_LEN + 1, CHUNK_LEN] {
+        assert_eq!(grouped.insert_group(0, &vec![0; len]), Err(MerkleError::GroupLengthMismatch { group_index: 0, len }));
+    }
+    // The final group has 3 chunks: 2 full and 5 bytes
+    assert!(grouped.insert_group(2, &[0; 2 * CHUNK_LEN]).is_err());
+    assert!(grouped.insert_group(2, &[0; 3 * CHUNK_LEN + 1]).is_err());
+    assert!(grouped.insert_group(2, &[0; 3 * CHUNK_LEN]).is_ok());
+    grouped.insert_group(2, &input[8 * CHUNK_LEN..]).unwrap();
+    assert_eq!(grouped.insert_group(3, &[]), Err(MerkleError::LeafIndexOutOfBounds { leaf_index: 3, actual_leaves: 3 }));
+    assert!(!grouped.verify_group(3, &[]));
+    assert_eq!(grouped.root_hash(), root);
+
+    for group_chunks in [0, 3, 12] {
+        assert!(matches!(GroupedTree::from_input(&input, group_chunks, IV, FLAGS), Err(MerkleError::InvalidGroupChunks { .. })));
+    }
+}
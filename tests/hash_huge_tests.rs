@@ -0,0 +1,89 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, CHUNK_LEN, IV, FLAGS};
+use merkle_tree::io::hash_huge;
+use rand::Rng;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use common::SeededRng;
+
+// Large enough that every level of pass two spans several read windows
+const HUGE_INPUT_SIZE: u64 = 300 * 1024 * 1024 + 12345;
+
+/// Yields `len` bytes of the test-vector pattern (byte i is i % 251) without holding them
+struct PatternReader {
+    position: u64,
+    len: u64,
+}
+
+impl Read for PatternReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let take = (self.len - self.position).min(buf.len() as u64) as usize;
+        for byte in &mut buf[..take] {
+            *byte = (self.position % 251) as u8;
+            self.position += 1;
+        }
+        Ok(take)
+    }
+}
+
+/// Tests two-pass hashing with an in-memory scratch buffer
+/// Verifies the root against from_input for single-chunk, level-boundary and multi-window inputs
+/// Methods tested: hash_huge
+#[test]
+fn test_hash_huge_matches_tree() {
+    let mut rng = SeededRng::new();
+    for &size in &[0, 1, CHUNK_LEN, CHUNK_LEN + 1, 3 * CHUNK_LEN, 8 * CHUNK_LEN, 4097 * CHUNK_LEN, 9000 * CHUNK_LEN + 5] {
+        let input: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+        let mut scratch = Cursor::new(Vec::new());
+        let hash = hash_huge(&input[..], &mut scratch, IV, FLAGS).unwrap();
+        assert_eq!(hash, BinaryMerkleTree::from_input(&input, IV, FLAGS).root_hash(),
+            "Two-pass root mismatch for size {} bytes", size);
+    }
+}
+
+/// Tests two-pass hashing into a scratch buffer that already holds data
+/// Verifies the root is unchanged when scratch starts mid-buffer, and that the bytes before
+/// the starting position are left alone
+/// Methods tested: hash_huge
+#[test]
+fn test_hash_huge_scratch_not_at_start() {
+    let mut rng = SeededRng::new();
+    let header = [0xAB; 100];
+    for &size in &[1, CHUNK_LEN + 1, 3 * CHUNK_LEN, 4097 * CHUNK_LEN + 5] {
+        let input: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+        let mut scratch = Cursor::new(Vec::new());
+        scratch.write_all(&header).unwrap();
+        let hash = hash_huge(&input[..], &mut scratch, IV, FLAGS).unwrap();
+        assert_eq!(hash, BinaryMerkleTree::from_input(&input, IV, FLAGS).root_hash(),
+            "Two-pass root mismatch for size {} bytes", size);
+        assert_eq!(scratch.get_ref()[..header.len()], header);
+
+        // Same through a file positioned past existing data
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&header).unwrap();
+        file.seek(SeekFrom::Start(header.len() as u64)).unwrap();
+        assert_eq!(hash_huge(&input[..], &mut file, IV, FLAGS).unwrap(), hash);
+    }
+}
+
+/// Tests two-pass hashing of a few hundred MB through a temporary file
+/// Verifies the root against Blake3Hasher over the same stream. Expensive, so run with --ignored
+/// Methods tested: hash_huge
+#[test]
+#[ignore = "expensive: hashes 300 MB twice"]
+fn test_hash_huge_large_input() {
+    let scratch = tempfile::tempfile().unwrap();
+    let hash = hash_huge(PatternReader { position: 0, len: HUGE_INPUT_SIZE }, scratch, IV, FLAGS).unwrap();
+
+    let mut hasher = Blake3Hasher::new();
+    let mut reader = PatternReader { position: 0, len: HUGE_INPUT_SIZE };
+    let mut buffer = vec![0u8; 64 * CHUNK_LEN];
+    loop {
+        let bytes_read = reader.read(&mut buffer).unwrap();
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+    assert_eq!(hash, hasher.finalize_array());
+}
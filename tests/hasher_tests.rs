@@ -0,0 +1,228 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{chunk_chaining_value, chunk_output, cv_from_bytes, BinaryMerkleTree, KEYED_HASH, Blake3BuildHasher, Blake3Hasher, Blake3StdHasher, CHUNK_LEN, FLAGS, IV};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use rand::Rng;
+use common::SeededRng;
+
+/// Tests fixed-size finalization through const generics
+/// Verifies that finalize_n::<32>() equals finalize_array() and finalize_n::<48>() is the XOF prefix
+/// Methods tested: Blake3Hasher::finalize_n, Blake3Hasher::finalize_array, Blake3Hasher::finalize
+#[test]
+fn test_finalize_n_matches_finalize() {
+    let mut rng = SeededRng::new();
+    let input: Vec<u8> = (0..5 * CHUNK_LEN + 3).map(|_| rng.gen()).collect();
+    let mut hasher = Blake3Hasher::new();
+    hasher.update(&input);
+
+    assert_eq!(hasher.finalize_n::<32>(), hasher.finalize_array());
+    assert_eq!(hasher.finalize_array(), *blake3::hash(&input).as_bytes());
+
+    // Longer outputs are a prefix of the extended output stream
+    let mut xof = [0; 128];
+    hasher.finalize(&mut xof);
+    assert_eq!(hasher.finalize_n::<48>()[..], xof[..48]);
+    assert_eq!(hasher.finalize_n::<0>(), [0u8; 0]);
+}
+
+/// Tests the heap-backed CV stack variant of the hasher
+/// Verifies identical digests to the fixed-array default across stack depths, including
+/// chunk counts whose binary representation needs many stacked subtrees
+/// Methods tested: Blake3Hasher::new_heap, Blake3Hasher::update, Blake3Hasher::finalize
+#[test]
+fn test_heap_cv_stack_matches_array_cv_stack() {
+    let mut rng = SeededRng::new();
+    for &chunks in &[0, 1, 2, 3, 127, 255, 1023] {
+        let input: Vec<u8> = (0..chunks * CHUNK_LEN + 11).map(|_| rng.gen()).collect();
+        let mut array_hasher = Blake3Hasher::new();
+        let mut heap_hasher = Blake3Hasher::new_heap();
+        // Feed in uneven pieces so both stacks see pushes and pops mid-update
+        for piece in input.chunks(3 * CHUNK_LEN + 5) {
+            array_hasher.update(piece);
+            heap_hasher.update(piece);
+        }
+        let mut heap_xof = [0; 100];
+        let mut array_xof = [0; 100];
+        heap_hasher.finalize(&mut heap_xof);
+        array_hasher.finalize(&mut array_xof);
+        assert_eq!(heap_xof, array_xof, "Digest mismatch for {} chunks", chunks);
+        assert_eq!(heap_hasher.finalize_array(), *blake3::hash(&input).as_bytes());
+    }
+
+    // The heap variant does not carry the 54-entry (1728-byte) inline stack
+    assert!(std::mem::size_of::<Blake3Hasher<Vec<[u32; 8]>>>() + 1600 < std::mem::size_of::<Blake3Hasher>());
+}
+
+/// Tests querying the hash mid-stream
+/// Verifies that interleaving update and finalize never disturbs the state: each finalize equals
+/// a fresh hasher over the prefix so far, including at chunk boundaries and for the empty prefix
+/// Methods tested: Blake3Hasher::update, Blake3Hasher::finalize, Blake3Hasher::finalize_array
+#[test]
+fn test_interleaved_update_and_finalize() {
+    let mut rng = SeededRng::new();
+    let input: Vec<u8> = (0..20 * CHUNK_LEN + 7).map(|_| rng.gen()).collect();
+    let mut hasher = Blake3Hasher::new();
+    let mut fed = 0;
+
+    let mut cut_points = vec![0, 1, CHUNK_LEN, CHUNK_LEN + 1, 2 * CHUNK_LEN, 4 * CHUNK_LEN, 16 * CHUNK_LEN, input.len()];
+    cut_points.extend((0..20).map(|_| rng.gen_range(0..=input.len())));
+    cut_points.sort();
+    for &cut in &cut_points {
+        hasher.update(&input[fed..cut]);
+        fed = cut;
+
+        let mut fresh = Blake3Hasher::new();
+        fresh.update(&input[..cut]);
+        // Finalize twice to check it is idempotent as well as non-mutating
+        let mut xof = [0; 80];
+        hasher.finalize(&mut xof);
+        assert_eq!(hasher.finalize_array(), fresh.finalize_array(), "Mid-stream digest mismatch after {} bytes", cut);
+        assert_eq!(xof, fresh.finalize_n::<80>());
+    }
+    assert_eq!(hasher.finalize_array(), *blake3::hash(&input).as_bytes());
+}
+
+/// Tests the hasher's peaks, the roots of the complete subtrees seen so far
+/// Verifies one peak per set bit of the complete chunk count (one after 4 chunks, two after 3),
+/// that a trailing partial chunk adds none, that each peak is the chaining value of its run of
+/// chunks, and that heap and inline stacks agree
+/// Methods tested: Blake3Hasher::peaks
+#[test]
+fn test_peaks_follow_chunk_count_bits() {
+    let mut rng = SeededRng::new();
+    let input: Vec<u8> = (0..13 * CHUNK_LEN).map(|_| rng.gen()).collect();
+    let run_cv = |first: usize, count: usize| -> [u32; 8] {
+        let run = &input[first * CHUNK_LEN..(first + count) * CHUNK_LEN];
+        if count == 1 {
+            return chunk_chaining_value(run, first as u64).unwrap();
+        }
+        // Leaves keep their counters from the whole input
+        let leaves: Vec<_> = (0..count)
+            .map(|i| {
+                chunk_output(&run[i * CHUNK_LEN..(i + 1) * CHUNK_LEN], (first + i) as u64, IV, FLAGS).unwrap()
+            })
+            .collect();
+        BinaryMerkleTree::new_from_leaves(leaves, IV, FLAGS).root_cv_and_bytes().0
+    };
+
+    for chunks in 0..=13usize {
+        for extra in [0, 1, CHUNK_LEN - 1] {
+            if chunks == 13 && extra > 0 {
+                continue;
+            }
+            let len = (chunks * CHUNK_LEN + extra).min(input.len());
+            let mut hasher = Blake3Hasher::new();
+            hasher.update(&input[..len]);
+            let peaks = hasher.peaks();
+            assert_eq!(peaks.len(), chunks.count_ones() as usize, "Wrong peak count for {} chunks + {} bytes", chunks, extra);
+
+            let mut first = 0;
+            for (peak, bit) in peaks.iter().zip((0..usize::BITS).rev().filter(|bit| chunks >> bit & 1 == 1)) {
+                assert_eq!(*peak, run_cv(first, 1 << bit));
+                first += 1 << bit;
+            }
+            let mut heap_hasher = Blake3Hasher::new_heap();
+            heap_hasher.update(&input[..len]);
+            assert_eq!(heap_hasher.peaks(), peaks);
+        }
+    }
+
+    let mut hasher = Blake3Hasher::new();
+    hasher.update(&input[..4 * CHUNK_LEN]);
+    assert_eq!(hasher.peaks().len(), 1);
+    let mut hasher = Blake3Hasher::new();
+    hasher.update(&input[..3 * CHUNK_LEN]);
+    assert_eq!(hasher.peaks().len(), 2);
+}
+
+/// Tests the keyed hash and key derivation modes of the hasher
+/// Verifies both match the official implementation, and that a keyed tree has the same root
+/// Methods tested: Blake3Hasher::new_keyed, Blake3Hasher::new_derive_key
+#[test]
+fn test_keyed_and_derive_key_hashers() {
+    let key = *b"whats the Elvish word for friend";
+    let context = "merkle_tree 2024 hasher test";
+    for &size in &[0, 1, CHUNK_LEN, CHUNK_LEN + 1, 7 * CHUNK_LEN + 3] {
+        let input: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+
+        let mut keyed = Blake3Hasher::new_keyed(&key);
+        keyed.update(&input);
+        assert_eq!(keyed.finalize_array(), *blake3::keyed_hash(&key, &input).as_bytes(), "size {}", size);
+        let key_words = cv_from_bytes(key);
+        let tree = BinaryMerkleTree::from_input(&input, key_words, KEYED_HASH);
+        assert_eq!(tree.root_hash(), keyed.finalize_array());
+
+        let mut derive = Blake3Hasher::new_derive_key(context);
+        derive.update(&input);
+        assert_eq!(derive.finalize_array(), blake3::derive_key(context, &input), "size {}", size);
+        assert_ne!(derive.finalize_array(), keyed.finalize_array());
+    }
+}
+
+/// Tests the hasher through the std Hasher and BuildHasher traits
+/// Verifies finish is the first 8 digest bytes, that keyed builders differ, and that a
+/// HashMap keyed with it stores and finds every key
+/// Methods tested: Blake3StdHasher::write, Blake3StdHasher::finish, Blake3BuildHasher
+#[test]
+fn test_std_hasher_and_hash_map() {
+    let mut std_hasher = Blake3StdHasher::default();
+    std_hasher.write(b"hello ");
+    std_hasher.write(b"world");
+    let digest = blake3::hash(b"hello world");
+    assert_eq!(std_hasher.finish(), u64::from_le_bytes(digest.as_bytes()[..8].try_into().unwrap()));
+    assert_eq!(std_hasher.finish(), std_hasher.finish(), "finish must not consume the state");
+
+    let plain = Blake3BuildHasher::new();
+    let keyed = Blake3BuildHasher::new_keyed(&[3; 32]);
+    assert_eq!(plain.hash_one("key"), Blake3BuildHasher::default().hash_one("key"));
+    assert_ne!(plain.hash_one("key"), keyed.hash_one("key"));
+    let mut keyed_hasher = keyed.build_hasher();
+    keyed_hasher.write(b"hello world");
+    let keyed_digest = blake3::keyed_hash(&[3; 32], b"hello world");
+    assert_eq!(keyed_hasher.finish(), u64::from_le_bytes(keyed_digest.as_bytes()[..8].try_into().unwrap()));
+
+    let mut map = HashMap::with_hasher(keyed);
+    for i in 0..1000u32 {
+        map.insert(format!("key {}", i), i);
+    }
+    assert_eq!(map.len(), 1000);
+    assert!((0..1000u32).all(|i| map[&format!("key {}", i)] == i));
+    assert_eq!(map.get("key 1000"), None);
+}
+
+/// Tests hashing strings and byte containers without converting them first
+/// Verifies update_typed on str, String, slices, arrays, Vec and references to them matches
+/// update on the same bytes, across chunk boundaries and with a heap CV stack
+/// Methods tested: Blake3Hasher::update_typed, Update::update_hasher
+#[test]
+fn test_update_typed_matches_update() {
+    let hash_bytes = |bytes: &[u8]| {
+        let mut hasher = Blake3Hasher::new();
+        hasher.update(bytes);
+        hasher.finalize_array()
+    };
+    let hash_typed = |value: &dyn Fn(&mut Blake3Hasher)| {
+        let mut hasher = Blake3Hasher::new();
+        value(&mut hasher);
+        hasher.finalize_array()
+    };
+
+    let abc = hash_bytes(b"abc");
+    assert_eq!(hash_typed(&|h| h.update_typed(&"abc")), abc);
+    assert_eq!(hash_typed(&|h| h.update_typed("abc")), abc);
+    assert_eq!(hash_typed(&|h| h.update_typed(&String::from("abc"))), abc);
+    assert_eq!(hash_typed(&|h| h.update_typed(b"abc")), abc);
+    assert_eq!(hash_typed(&|h| h.update_typed(&b"abc"[..])), abc);
+    assert_eq!(hash_typed(&|h| h.update_typed(&vec![b'a', b'b', b'c'])), abc);
+    assert_eq!(hash_typed(&|h| {
+        h.update_typed("ab");
+        h.update_typed(&b"c"[..]);
+    }), abc);
+
+    let text = "naïve café ".repeat(300);
+    assert!(text.len() > 3 * CHUNK_LEN);
+    let mut heap = Blake3Hasher::new_heap();
+    heap.update_typed(text.as_str());
+    assert_eq!(heap.finalize_array(), hash_bytes(text.as_bytes()));
+}
@@ -0,0 +1,435 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, CHUNK_LEN, IV, FLAGS};
+use merkle_tree::io::{copy_verified, HashingReader, HashingWriter, VerifiedReader, VerifiedWriter};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use common::random_input;
+
+const IO_INPUT_SIZE: usize = 37 * CHUNK_LEN + 123;
+
+/// A reader that returns at most `max_read` bytes per call, to exercise short reads
+struct ChunkedReader<'a> {
+    data: &'a [u8],
+    max_read: usize,
+}
+
+impl Read for ChunkedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let take = self.max_read.min(buf.len()).min(self.data.len());
+        buf[..take].copy_from_slice(&self.data[..take]);
+        self.data = &self.data[take..];
+        Ok(take)
+    }
+}
+
+/// A writer that accepts at most `max_write` bytes per call, to exercise partial writes
+struct ChunkedWriter {
+    data: Vec<u8>,
+    max_write: usize,
+}
+
+impl Write for ChunkedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let take = self.max_write.min(buf.len());
+        self.data.extend_from_slice(&buf[..take]);
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Tests hashing data as it is read through a HashingReader
+/// Verifies that draining the reader with io::copy yields the one-shot hash and the passthrough bytes
+/// Methods tested: HashingReader::new, HashingReader::finalize
+#[test]
+fn test_hashing_reader_matches_one_shot_hash() {
+    let input = random_input(IO_INPUT_SIZE);
+    for &max_read in &[1, 7, 64, 1000, CHUNK_LEN, 3 * CHUNK_LEN + 1] {
+        let mut reader = HashingReader::new(ChunkedReader { data: &input, max_read });
+        let mut copied = Vec::new();
+        io::copy(&mut reader, &mut copied).unwrap();
+        let (hash, _inner) = reader.finalize();
+
+        let mut hasher = Blake3Hasher::new();
+        hasher.update(&input);
+        assert_eq!(copied, input);
+        assert_eq!(hash, hasher.finalize_array(), "Hash mismatch with max_read {}", max_read);
+    }
+}
+
+/// Tests tree mode of HashingReader with interleaved zero-length reads
+/// Verifies that the collected tree equals BinaryMerkleTree::from_input over the same bytes
+/// Methods tested: HashingReader::new_tree, HashingReader::finalize_tree
+#[test]
+fn test_hashing_reader_tree_mode() {
+    let input = random_input(IO_INPUT_SIZE);
+    let mut reader = HashingReader::new_tree(ChunkedReader { data: &input, max_read: 333 }, IV, FLAGS);
+    let mut buf = [0u8; 500];
+    let mut copied = Vec::new();
+    loop {
+        // Zero-length reads must not disturb chunk boundaries
+        assert_eq!(reader.read(&mut buf[..0]).unwrap(), 0);
+        let n = reader.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        copied.extend_from_slice(&buf[..n]);
+    }
+    assert_eq!(copied, input);
+
+    let (tree, _inner) = reader.finalize_tree().expect("reader was created in tree mode");
+    let expected = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    assert_eq!(tree.actual_leaves(), expected.actual_leaves());
+    assert_eq!(tree.root_hash(), expected.root_hash());
+
+    // Plain mode has no tree to hand back
+    assert!(HashingReader::new(io::empty()).finalize_tree().is_none());
+}
+
+/// Tests hashing data as it is written through a HashingWriter
+/// Verifies that partial writes only hash the accepted bytes and the result matches the one-shot hash
+/// Methods tested: HashingWriter::new, HashingWriter::finalize
+#[test]
+fn test_hashing_writer_partial_writes() {
+    let input = random_input(IO_INPUT_SIZE);
+    let mut writer = HashingWriter::new(ChunkedWriter { data: Vec::new(), max_write: 100 });
+
+    // A single write call only gets 100 bytes through
+    assert_eq!(writer.write(&input[..1000]).unwrap(), 100);
+    writer.write_all(&input[100..]).unwrap();
+    writer.flush().unwrap();
+
+    let (hash, inner) = writer.finalize();
+    assert_eq!(inner.data, input);
+    assert_eq!(hash, *blake3::hash(&input).as_bytes());
+}
+
+/// Tests tree mode of HashingWriter
+/// Verifies that the tree of everything written equals BinaryMerkleTree::from_input
+/// Methods tested: HashingWriter::new_tree, HashingWriter::finalize_tree
+#[test]
+fn test_hashing_writer_tree_mode() {
+    let input = random_input(IO_INPUT_SIZE);
+    let mut writer = HashingWriter::new_tree(ChunkedWriter { data: Vec::new(), max_write: 100 }, IV, FLAGS);
+    io::copy(&mut ChunkedReader { data: &input, max_read: 777 }, &mut writer).unwrap();
+
+    let (tree, inner) = writer.finalize_tree().expect("writer was created in tree mode");
+    let expected = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    assert_eq!(inner.data, input);
+    assert_eq!(tree.actual_leaves(), expected.actual_leaves());
+    assert_eq!(tree.root_hash(), expected.root_hash());
+}
+
+/// Drain a reader with small reads, returning the bytes released before the first error
+fn drain_until_error<R: Read>(mut reader: R) -> (Vec<u8>, io::Result<()>) {
+    let mut released = Vec::new();
+    let mut buf = [0u8; 100];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return (released, Ok(())),
+            Ok(n) => released.extend_from_slice(&buf[..n]),
+            Err(e) => return (released, Err(e)),
+        }
+    }
+}
+
+/// Tests reading through a VerifiedReader over pristine data
+/// Verifies that every byte is released unchanged for empty, partial and multi-chunk inputs
+/// Methods tested: VerifiedReader::new, VerifiedReader::read
+#[test]
+fn test_verified_reader_clean_read() {
+    for &size in &[0, 1, CHUNK_LEN, IO_INPUT_SIZE] {
+        let input = random_input(size);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let (released, result) = drain_until_error(VerifiedReader::new(&tree, ChunkedReader { data: &input, max_read: 300 }));
+        result.unwrap();
+        assert_eq!(released, input, "Verified read mismatch for input size {}", size);
+    }
+}
+
+/// Tests that a corrupted chunk stops a VerifiedReader before any of its bytes are released
+/// Verifies the failure position for a middle chunk and for the final partial chunk
+/// Methods tested: VerifiedReader::read
+#[test]
+fn test_verified_reader_detects_corruption() {
+    let input = random_input(IO_INPUT_SIZE);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let last_chunk = tree.actual_leaves() - 1;
+
+    for &corrupted_chunk in &[17, last_chunk] {
+        let mut corrupted = input.clone();
+        corrupted[corrupted_chunk * CHUNK_LEN + 5] ^= 0x01;
+
+        let (released, result) = drain_until_error(VerifiedReader::new(&tree, &corrupted[..]));
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains(&format!("chunk {}", corrupted_chunk)), "Unexpected error: {}", err);
+        assert_eq!(released, input[..corrupted_chunk * CHUNK_LEN]);
+    }
+}
+
+/// Tests that a VerifiedReader rejects streams ending too early or running too long
+/// Methods tested: VerifiedReader::read
+#[test]
+fn test_verified_reader_rejects_wrong_length() {
+    let input = random_input(IO_INPUT_SIZE);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+
+    // Ending inside a middle chunk
+    let (released, result) = drain_until_error(VerifiedReader::new(&tree, &input[..10 * CHUNK_LEN + 1]));
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(released, input[..10 * CHUNK_LEN]);
+
+    // Ending one byte short in the final chunk fails its hash check
+    let (_, result) = drain_until_error(VerifiedReader::new(&tree, &input[..input.len() - 1]));
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    // Trailing data after the final chunk
+    let mut extended = input.clone();
+    extended.push(0);
+    let (released, result) = drain_until_error(VerifiedReader::new(&tree, &extended[..]));
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(released.len(), (tree.actual_leaves() - 1) * CHUNK_LEN);
+}
+
+/// A sink that fails every other write call until `failures_left` runs out,
+/// to exercise resuming after sink errors
+struct FlakyWriter {
+    data: Vec<u8>,
+    failures_left: usize,
+    fail_next: bool,
+}
+
+impl Write for FlakyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.fail_next = !self.fail_next && self.failures_left > 0;
+        if self.fail_next {
+            self.failures_left -= 1;
+            return Err(io::Error::other("transient sink failure"));
+        }
+        let take = buf.len().min(300);
+        self.data.extend_from_slice(&buf[..take]);
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Tests writing pristine data through a VerifiedWriter into a sink that accepts at most 100 bytes per call
+/// Methods tested: VerifiedWriter::new, VerifiedWriter::write, VerifiedWriter::finish
+#[test]
+fn test_verified_writer_clean_write() {
+    for &size in &[0, 1, CHUNK_LEN, 2 * CHUNK_LEN, IO_INPUT_SIZE] {
+        let input = random_input(size);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let mut writer = VerifiedWriter::new(&tree, ChunkedWriter { data: Vec::new(), max_write: 100 });
+        io::copy(&mut ChunkedReader { data: &input, max_read: 700 }, &mut writer).unwrap();
+        let sink = writer.finish().unwrap();
+        assert_eq!(sink.data, input, "Verified write mismatch for input size {}", size);
+    }
+}
+
+/// Tests that sink errors are propagated and the pending chunk is resumed rather than rewritten
+/// Methods tested: VerifiedWriter::write, VerifiedWriter::finish
+#[test]
+fn test_verified_writer_resumes_after_sink_error() {
+    let input = random_input(IO_INPUT_SIZE);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let mut writer = VerifiedWriter::new(&tree, FlakyWriter { data: Vec::new(), failures_left: 50, fail_next: false });
+    let mut remaining = &input[..];
+    let mut sink_errors = 0;
+    while !remaining.is_empty() {
+        match writer.write(remaining) {
+            Ok(n) => remaining = &remaining[n..],
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::Other);
+                sink_errors += 1;
+            }
+        }
+    }
+    let sink = writer.finish().unwrap();
+    assert!(sink_errors > 0);
+    assert_eq!(sink.data, input);
+}
+
+/// Tests that a corrupted chunk is never written and nothing after it reaches the sink
+/// Methods tested: VerifiedWriter::write
+#[test]
+fn test_verified_writer_stops_at_corrupted_chunk() {
+    let input = random_input(IO_INPUT_SIZE);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let mut corrupted = input.clone();
+    corrupted[9 * CHUNK_LEN + 1000] ^= 0x80;
+
+    let mut writer = VerifiedWriter::new(&tree, Vec::new());
+    let err = writer.write_all(&corrupted).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("chunk 9"), "Unexpected error: {}", err);
+
+    // Further writes are refused and the sink only holds the verified prefix
+    assert!(writer.write(&corrupted[10 * CHUNK_LEN..]).is_err());
+    assert_eq!(writer.get_ref()[..], input[..9 * CHUNK_LEN]);
+    assert!(writer.finish().is_err());
+}
+
+/// Tests that finish reports a truncated stream and rejects data past the end
+/// Methods tested: VerifiedWriter::finish, VerifiedWriter::write
+#[test]
+fn test_verified_writer_rejects_wrong_length() {
+    let input = random_input(IO_INPUT_SIZE);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+
+    let mut writer = VerifiedWriter::new(&tree, Vec::new());
+    writer.write_all(&input[..20 * CHUNK_LEN + 3]).unwrap();
+    let err = writer.finish().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(err.to_string().contains(&format!("chunks 20..{}", tree.actual_leaves())), "Unexpected error: {}", err);
+
+    // A final chunk that is one byte short fails its hash check in finish
+    let mut writer = VerifiedWriter::new(&tree, Vec::new());
+    writer.write_all(&input[..input.len() - 1]).unwrap();
+    assert_eq!(writer.finish().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    // Data past a full final chunk is refused
+    let exact = random_input(2 * CHUNK_LEN);
+    let tree = BinaryMerkleTree::from_input(&exact, IV, FLAGS);
+    let mut writer = VerifiedWriter::new(&tree, Vec::new());
+    writer.write_all(&exact).unwrap();
+    assert!(writer.write(&[0]).is_err());
+}
+
+/// Tests a verified copy over pristine data
+/// Verifies that the destination is byte-identical and the byte count is returned
+/// Methods tested: copy_verified
+#[test]
+fn test_copy_verified_clean_copy() {
+    for &size in &[0, 1, CHUNK_LEN, IO_INPUT_SIZE] {
+        let input = random_input(size);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let mut copied = Vec::new();
+        let bytes = copy_verified(ChunkedReader { data: &input, max_read: 300 }, &mut copied, &tree).unwrap();
+        assert_eq!(bytes, size as u64);
+        assert_eq!(copied, input, "Verified copy mismatch for input size {}", size);
+    }
+}
+
+/// Tests that a verified copy of a corrupted source stops at the corrupted chunk
+/// Verifies the reported chunk index and byte count, and that only verified chunks reach the destination
+/// Methods tested: copy_verified
+#[test]
+fn test_copy_verified_stops_at_corrupted_chunk() {
+    let input = random_input(IO_INPUT_SIZE);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let last_chunk = tree.actual_leaves() - 1;
+
+    for &corrupted_chunk in &[0, 17, last_chunk] {
+        let mut corrupted = input.clone();
+        corrupted[corrupted_chunk * CHUNK_LEN + 5] ^= 0x01;
+
+        let mut copied = Vec::new();
+        let err = copy_verified(&corrupted[..], &mut copied, &tree).unwrap_err();
+        assert_eq!(err.chunk_index, corrupted_chunk);
+        assert_eq!(err.bytes_written, (corrupted_chunk * CHUNK_LEN) as u64);
+        assert_eq!(err.error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(copied, input[..corrupted_chunk * CHUNK_LEN]);
+    }
+
+    // A truncated source is reported as UnexpectedEof at the chunk it ends in
+    let mut copied = Vec::new();
+    let err = copy_verified(&input[..10 * CHUNK_LEN + 1], &mut copied, &tree).unwrap_err();
+    assert_eq!((err.chunk_index, err.bytes_written), (10, (10 * CHUNK_LEN) as u64));
+    assert_eq!(io::Error::from(err).kind(), io::ErrorKind::UnexpectedEof);
+}
+
+/// A reader over an in-memory input that counts the bytes read and the seeks made
+struct CountingSource {
+    inner: Cursor<Vec<u8>>,
+    bytes_read: usize,
+    seeks: usize,
+}
+
+impl Read for CountingSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes_read += n;
+        Ok(n)
+    }
+}
+
+impl Seek for CountingSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.seeks += 1;
+        self.inner.seek(pos)
+    }
+}
+
+/// Tests verifying the members of a synthetic archive by their offset table
+/// Verifies that only the corrupted member fails, that chunks shared by members are read once,
+/// and that reads stay within the chunks the members cover
+/// Methods tested: BinaryMerkleTree::verify_regions
+#[test]
+fn test_verify_regions_of_archive() {
+    // Members of assorted sizes, several sharing chunks, with gaps left unverified
+    let member_lens = [100, 3000, 1, 5000, 2048, 700, 40 * CHUNK_LEN];
+    let mut members = Vec::new();
+    let mut offset = 0u64;
+    for (i, &len) in member_lens.iter().enumerate() {
+        members.push((offset, len as u64));
+        offset += len as u64 + if i % 2 == 0 { 0 } else { 20 * CHUNK_LEN as u64 };
+    }
+    let archive = random_input(offset as usize + 500);
+    let tree = BinaryMerkleTree::from_input(&archive, IV, FLAGS);
+
+    let covered_chunks = |regions: &[(u64, u64)]| {
+        let mut chunks: Vec<u64> = regions
+            .iter()
+            .flat_map(|&(offset, len)| offset / CHUNK_LEN as u64..(offset + len).div_ceil(CHUNK_LEN as u64))
+            .collect();
+        chunks.sort_unstable();
+        chunks.dedup();
+        chunks.len()
+    };
+
+    let mut source = CountingSource { inner: Cursor::new(archive.clone()), bytes_read: 0, seeks: 0 };
+    let results = tree.verify_regions(&mut source, &members);
+    assert!(results.iter().all(Result::is_ok));
+    assert!(source.bytes_read <= covered_chunks(&members) * CHUNK_LEN);
+    assert!(source.bytes_read < archive.len() / 2);
+    // One seek per run of adjacent chunks: the gaps split the members into four runs
+    assert_eq!(source.seeks, 4);
+
+    // Corrupt the middle of the 5000-byte member, away from the chunks it shares
+    let mut corrupted = archive.clone();
+    corrupted[(members[3].0 + 2500) as usize] ^= 0x01;
+    let results = tree.verify_regions(Cursor::new(corrupted), &members);
+    for (member_index, result) in results.iter().enumerate() {
+        assert_eq!(result.is_err(), member_index == 3, "member {}", member_index);
+    }
+    let err = results[3].as_ref().unwrap_err();
+    assert_eq!(err.chunk_index, (members[3].0 as usize + 2500) / CHUNK_LEN);
+    assert_eq!(err.bytes_written, (err.chunk_index * CHUNK_LEN) as u64 - members[3].0);
+    assert_eq!(err.error.kind(), io::ErrorKind::InvalidData);
+
+    // The same member listed twice, overlapping regions, and an empty region: each
+    // covered chunk is still read once
+    let regions = [members[1], members[1], (members[1].0 + 10, 2000), (members[2].0, 0)];
+    let mut source = CountingSource { inner: Cursor::new(archive.clone()), bytes_read: 0, seeks: 0 };
+    assert!(tree.verify_regions(&mut source, &regions).iter().all(Result::is_ok));
+    assert_eq!(source.bytes_read, covered_chunks(&regions[..3]) * CHUNK_LEN);
+
+    // Regions reaching past the end of the input fail at the final chunk
+    let input_len = archive.len() as u64;
+    let regions = [(input_len - 10, 10), (input_len - 10, 11), (input_len + 5000, 1)];
+    let results = tree.verify_regions(Cursor::new(archive), &regions);
+    assert!(results[0].is_ok());
+    for result in &results[1..] {
+        let err = result.as_ref().unwrap_err();
+        assert_eq!(err.chunk_index, tree.actual_leaves() - 1);
+        assert_eq!(err.error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+    assert_eq!(results[1].as_ref().unwrap_err().bytes_written, 10);
+}
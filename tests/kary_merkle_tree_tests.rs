@@ -0,0 +1,68 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{leaf_for_input_chunk, BinaryMerkleTree, CHUNK_LEN, IV, FLAGS, ROOT};
+use merkle_tree::kary_merkle_tree::{kary_parent_output, KaryMerkleTree};
+use rand::Rng;
+use common::{random_input, SeededRng};
+
+const FANOUT: usize = 4;
+
+/// Tests the shape and determinism of a fanout-4 tree
+/// Verifies a hand-folded root for five chunks (one full group plus a promoted leaf),
+/// that rebuilding gives the same root, and that the depth shrinks with the fanout
+/// Methods tested: KaryMerkleTree::from_input, KaryMerkleTree::root, KaryMerkleTree::depth
+#[test]
+fn test_kary_tree_root_is_deterministic() {
+    let input = random_input(4 * CHUNK_LEN + 10);
+    let tree = KaryMerkleTree::from_input(&input, FANOUT, IV, FLAGS).unwrap();
+    assert_eq!(tree.actual_leaves(), 5);
+    assert_eq!(tree.depth(), 3);
+
+    let leaf_cvs: Vec<[u32; 8]> = (0..5).map(|i| tree.leaf(i).unwrap().chaining_value()).collect();
+    let group = kary_parent_output(&leaf_cvs[..4], IV, FLAGS).unwrap();
+    let mut expected_root = kary_parent_output(&[group.chaining_value(), leaf_cvs[4]], IV, FLAGS).unwrap();
+    expected_root.flags |= ROOT;
+    assert_eq!(tree.root(), expected_root);
+
+    assert_eq!(KaryMerkleTree::from_input(&input, FANOUT, IV, FLAGS).unwrap().root_hash(), tree.root_hash());
+
+    let large = KaryMerkleTree::from_input(&random_input(300 * CHUNK_LEN), FANOUT, IV, FLAGS).unwrap();
+    assert_eq!(large.depth(), 6); // 300 -> 75 -> 19 -> 5 -> 2 -> 1
+}
+
+/// Tests that k-ary parents are domain-separated from BLAKE3 parents
+/// Verifies a fanout-2 tree differs from the binary tree, a single chunk is still its BLAKE3 hash,
+/// and the fanout changes the root
+/// Methods tested: KaryMerkleTree::root_hash
+#[test]
+fn test_kary_tree_diverges_from_blake3() {
+    let input = random_input(7 * CHUNK_LEN + 1);
+    let binary = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    assert_ne!(KaryMerkleTree::from_input(&input, 2, IV, FLAGS).unwrap().root_hash(), binary.root_hash());
+    assert_ne!(KaryMerkleTree::from_input(&input, 4, IV, FLAGS).unwrap().root_hash(), KaryMerkleTree::from_input(&input, 8, IV, FLAGS).unwrap().root_hash());
+
+    let single_chunk = random_input(CHUNK_LEN);
+    assert_eq!(KaryMerkleTree::from_input(&single_chunk, FANOUT, IV, FLAGS).unwrap().root_hash(), *blake3::hash(&single_chunk).as_bytes());
+}
+
+/// Tests single-leaf updates in a fanout-4 tree
+/// Verifies that after each random chunk mutation the root equals a tree rebuilt from the mutated input
+/// Methods tested: KaryMerkleTree::insert_leaf
+#[test]
+fn test_kary_tree_update_propagation() {
+    let mut rng = SeededRng::new();
+    for &chunks in &[1, 2, 4, 5, 16, 17, 63] {
+        let mut input = random_input(chunks * CHUNK_LEN - 3);
+        let mut tree = KaryMerkleTree::from_input(&input, FANOUT, IV, FLAGS).unwrap();
+        for _ in 0..10 {
+            let chunk_index = rng.gen_range(0..chunks);
+            let chunk_start = chunk_index * CHUNK_LEN;
+            let chunk_end = (chunk_start + CHUNK_LEN).min(input.len());
+            input[rng.gen_range(chunk_start..chunk_end)] ^= 0xFF;
+
+            tree.insert_leaf(chunk_index, leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap()).unwrap();
+            assert_eq!(tree.root_hash(), KaryMerkleTree::from_input(&input, FANOUT, IV, FLAGS).unwrap().root_hash(),
+                "Root mismatch after updating chunk {} of {}", chunk_index, chunks);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{
+    cv_from_bytes, parent_cv, parent_output, BinaryMerkleTree, Blake3Hasher, HeapIndex, LeafCv, NodeCv, ParentCv, DERIVE_KEY_MATERIAL, FLAGS, IV,
+    KEYED_HASH, OUT_LEN,
+};
+use merkle_tree::error::MerkleError;
+use rand::Rng;
+use common::SeededRng;
+
+fn random_hashes(count: usize) -> Vec<[u8; OUT_LEN]> {
+    let mut rng = SeededRng::new();
+    (0..count).map(|_| rng.gen()).collect()
+}
+
+
+/// The chaining value of the subtree over `cvs`, split as BLAKE3 splits chunks
+fn subtree_cv(cvs: &[[u32; 8]], key_words: [u32; 8], flags: u32) -> [u32; 8] {
+    if cvs.len() == 1 {
+        return cvs[0];
+    }
+    let (left, right) = cvs.split_at(cvs.len().div_ceil(2).next_power_of_two());
+    parent_cv(subtree_cv(left, key_words, flags), subtree_cv(right, key_words, flags), key_words, flags)
+}
+
+/// The root hash of a tree of leaf hashes, computed from `parent_cv` alone
+fn reference_root(hashes: &[[u8; OUT_LEN]], key_words: [u32; 8], flags: u32) -> [u8; OUT_LEN] {
+    let cvs: Vec<[u32; 8]> = hashes.iter().map(|&hash| cv_from_bytes(hash)).collect();
+    let (left, right) = cvs.split_at(cvs.len().div_ceil(2).next_power_of_two());
+    let mut root = [0; OUT_LEN];
+    parent_output(subtree_cv(left, key_words, flags), subtree_cv(right, key_words, flags), key_words, flags).root_output_bytes(&mut root);
+    root
+}
+
+/// Tests building a tree over application-defined leaf hashes
+/// Verifies the root of balanced and unbalanced counts matches folding the hashes with
+/// `parent_cv` in the regular, keyed and derive-key modes, that the modes give different roots,
+/// that leaf hashes read back, and that every proof verifies while a proof for another hash,
+/// key or mode does not
+/// Methods tested: BinaryMerkleTree::from_leaf_hashes, BinaryMerkleTree::leaf_hash,
+/// BinaryMerkleTree::generate_leaf_hash_proof, LeafHashProof::verify
+#[test]
+fn test_leaf_hash_tree_roots_and_proofs() {
+    for count in [2, 3, 5, 7, 8, 13, 100] {
+        let hashes = random_hashes(count);
+        let mut roots = Vec::new();
+        for (key_words, flags) in [(IV, FLAGS), ([7; 8], KEYED_HASH), ([7; 8], DERIVE_KEY_MATERIAL)] {
+            let tree = BinaryMerkleTree::from_leaf_hashes(&hashes, key_words, flags).unwrap();
+            let root_hash = tree.root_hash();
+            assert_eq!(root_hash, reference_root(&hashes, key_words, flags), "Root mismatch for {} leaves", count);
+            assert_eq!(tree.flags(), flags);
+            roots.push(root_hash);
+            assert!(tree.has_leaf_hashes());
+            assert!(tree.verify_integrity());
+
+            for (leaf_index, hash) in hashes.iter().enumerate() {
+                assert_eq!(tree.leaf_hash(leaf_index), Some((*hash).into()));
+                let proof = tree.generate_leaf_hash_proof(leaf_index).unwrap();
+                assert_eq!(proof.leaf_hash, *hash);
+                assert!(proof.verify(&root_hash, key_words, flags));
+                assert!(!proof.verify(&root_hash, [9; 8], flags));
+                assert!(!proof.verify(&root_hash, key_words, flags ^ KEYED_HASH));
+
+                let mut tampered = proof.clone();
+                tampered.leaf_hash[0] ^= 1;
+                assert!(!tampered.verify(&root_hash, key_words, flags));
+            }
+            assert!(tree.leaf_hash(count).is_none());
+            assert!(tree.generate_leaf_hash_proof(count).is_none());
+            // Chunk proofs do not apply to leaf hashes
+            assert!(tree.generate_proof(0).is_none());
+            assert!(tree.all_proofs().is_empty());
+        }
+        assert!(roots[0] != roots[1] && roots[1] != roots[2]);
+    }
+}
+
+/// Tests that leaf hashes, including copies promoted up the tree, are classified as leaves
+/// Verifies the last of 5 leaf hashes and its promoted copies are not parents, convert to
+/// LeafCv holding the hash, have no ParentCv, and that parent_cv skips the promoted copies
+/// Methods tested: Output::is_parent, LeafCv::from_output, ParentCv::from_output, NodeCv::from_output,
+/// BinaryMerkleTree::parent_cv
+#[test]
+fn test_leaf_hashes_are_leaves() {
+    let hashes = random_hashes(5);
+    let tree = BinaryMerkleTree::from_leaf_hashes(&hashes, IV, FLAGS).unwrap();
+    let hash_cv = cv_from_bytes(hashes[4]);
+    // Leaf 4 sits at heap index 12 and is promoted to 6 and then 3
+    for heap_index in [12, 6, 3] {
+        let node = tree.node_at(HeapIndex(heap_index)).unwrap();
+        assert!(!node.is_parent(), "Node {} is a promoted leaf hash", heap_index);
+        assert_eq!(LeafCv::from_output(&node), Some(LeafCv::assume_leaf(hash_cv)));
+        assert_eq!(ParentCv::from_output(&node), None);
+        assert_eq!(NodeCv::from_output(&node), NodeCv::Leaf(LeafCv::assume_leaf(hash_cv)));
+    }
+    assert_eq!(tree.parent_cv(HeapIndex(6)), None);
+    assert_eq!(tree.parent_cv(HeapIndex(3)), None);
+    assert_eq!(tree.leaf_cv(4), Some(LeafCv::assume_leaf(hash_cv)));
+
+    let parent = tree.node_at(HeapIndex(2)).unwrap();
+    assert!(parent.is_parent());
+    assert_eq!(tree.parent_cv(HeapIndex(2)), Some(ParentCv::assume_parent(parent.chaining_value())));
+    assert_eq!(LeafCv::from_output(&parent), None);
+}
+
+/// Tests replacing leaf hashes
+/// Verifies single and bulk replacements of the first, a middle and the lone last leaf give
+/// the root of a tree built from the new hashes, and their proofs verify
+/// Methods tested: BinaryMerkleTree::insert_leaf_hash, BinaryMerkleTree::bulk_insert_leaf_hashes
+#[test]
+fn test_leaf_hash_tree_updates() {
+    for count in [2, 5, 13, 64] {
+        let mut hashes = random_hashes(count);
+        let mut tree = BinaryMerkleTree::from_leaf_hashes(&hashes, IV, FLAGS).unwrap();
+
+        let replacement = random_hashes(1)[0];
+        hashes[count - 1] = replacement;
+        tree.insert_leaf_hash(count - 1, replacement).unwrap();
+        assert_eq!(tree.root_hash(), BinaryMerkleTree::from_leaf_hashes(&hashes, IV, FLAGS).unwrap().root_hash());
+
+        let indices = [0, count / 2, count - 1];
+        let replacements = random_hashes(indices.len());
+        for (&leaf_index, hash) in indices.iter().zip(&replacements) {
+            hashes[leaf_index] = *hash;
+        }
+        let mut indices = indices.to_vec();
+        indices.dedup();
+        let replacements: Vec<_> = indices.iter().map(|&leaf_index| hashes[leaf_index]).collect();
+        tree.bulk_insert_leaf_hashes(indices.iter().copied(), replacements.into_iter()).unwrap();
+        let rebuilt = BinaryMerkleTree::from_leaf_hashes(&hashes, IV, FLAGS).unwrap();
+        assert_eq!(tree.root_hash(), rebuilt.root_hash());
+        assert!(tree.deep_equal(&rebuilt, true));
+        for leaf_index in indices {
+            assert!(tree.generate_leaf_hash_proof(leaf_index).unwrap().verify(&tree.root_hash(), IV, FLAGS));
+        }
+        assert!(tree.insert_leaf_hash(count, [0; OUT_LEN]).is_err());
+    }
+}
+
+/// Tests mixing up chunk leaves and leaf hashes
+/// Verifies the leaf-hash methods fail on a tree of chunks and the chunk methods on a tree of
+/// leaf hashes, including compact, with WrongLeafKind or None, leaving both trees unchanged
+/// Methods tested: BinaryMerkleTree::insert_leaf_hash, BinaryMerkleTree::bulk_insert_leaf_hashes,
+/// BinaryMerkleTree::insert_leaf, BinaryMerkleTree::bulk_insert_leaves, generate_proof, generate_leaf_hash_proof,
+/// BinaryMerkleTree::compact
+#[test]
+fn test_wrong_leaf_kind_is_rejected() {
+    let mut chunk_tree = BinaryMerkleTree::from_input(&[5; 3000], IV, FLAGS);
+    let chunk_root = chunk_tree.root_hash();
+    let hash = random_hashes(1)[0];
+    assert_eq!(chunk_tree.insert_leaf_hash(1, hash), Err(MerkleError::WrongLeafKind { leaf_hashes: false }));
+    assert_eq!(chunk_tree.bulk_insert_leaf_hashes([1].into_iter(), [hash].into_iter()),
+        Err(MerkleError::WrongLeafKind { leaf_hashes: false }));
+    assert_eq!(chunk_tree.generate_leaf_hash_proof(1), None);
+    assert_eq!(chunk_tree.root_hash(), chunk_root);
+
+    let mut hash_tree = BinaryMerkleTree::from_leaf_hashes(&random_hashes(5), IV, FLAGS).unwrap();
+    let hash_root = hash_tree.root_hash();
+    let leaf = chunk_tree.leaf(1).unwrap();
+    assert_eq!(hash_tree.insert_leaf(1, leaf), Err(MerkleError::WrongLeafKind { leaf_hashes: true }));
+    assert_eq!(hash_tree.bulk_insert_leaves([1].into_iter(), [leaf].into_iter()),
+        Err(MerkleError::WrongLeafKind { leaf_hashes: true }));
+    assert_eq!(hash_tree.generate_proof(1), None);
+    assert_eq!(hash_tree.root_hash(), hash_root);
+
+    // Leaf hashes have no input to rehash
+    hash_tree.remove_leaf(2).unwrap();
+    assert_eq!(hash_tree.compact(&[5; 5 * 1024]), Err(MerkleError::WrongLeafKind { leaf_hashes: true }));
+    assert!(hash_tree.has_leaf_hashes());
+    assert_eq!(hash_tree.root_hash(), hash_root);
+
+    // Swapping moves leaves of the tree's own kind
+    hash_tree.swap_leaves(0, 4).unwrap();
+    assert_ne!(hash_tree.root_hash(), hash_root);
+}
+
+/// Tests that a tree of leaf hashes is a different domain from hashing bytes
+/// Verifies its root differs from the BLAKE3 hash of the concatenated leaf hashes, from a
+/// chunk tree over them, and from `from_leaf_data` over the same digests
+/// Methods tested: BinaryMerkleTree::from_leaf_hashes
+#[test]
+fn test_leaf_hash_root_is_not_a_byte_hash() {
+    for count in [2, 32, 33] {
+        let hashes = random_hashes(count);
+        let root = BinaryMerkleTree::from_leaf_hashes(&hashes, IV, FLAGS).unwrap().root_hash();
+
+        let concatenated = hashes.concat();
+        let mut hasher = Blake3Hasher::new();
+        hasher.update(&concatenated);
+        assert_ne!(root, hasher.finalize_array());
+        assert_ne!(root, BinaryMerkleTree::from_input(&concatenated, IV, FLAGS).root_hash());
+        assert_ne!(root, BinaryMerkleTree::from_leaf_data(&hashes, |&hash| cv_from_bytes(hash), IV, FLAGS).unwrap().root_hash());
+    }
+}
+
+/// Tests serializing a tree of leaf hashes
+/// Verifies the tree reloads as a tree of leaf hashes with the same root, leaves and proofs
+/// Methods tested: BinaryMerkleTree::to_bytes, BinaryMerkleTree::from_bytes
+#[test]
+fn test_leaf_hash_tree_serialization() {
+    let hashes = random_hashes(11);
+    let tree = BinaryMerkleTree::from_leaf_hashes(&hashes, [3; 8], KEYED_HASH).unwrap();
+    let loaded = BinaryMerkleTree::from_bytes(&tree.to_bytes()).unwrap();
+    assert!(loaded.has_leaf_hashes());
+    assert!(loaded.deep_equal(&tree, true));
+    assert_eq!(loaded.leaf_hash(10), Some(hashes[10].into()));
+    assert!(loaded.generate_leaf_hash_proof(4).unwrap().verify(&tree.root_hash(), [3; 8], KEYED_HASH));
+    assert!(!BinaryMerkleTree::from_input(&[0; 3000], IV, FLAGS).has_leaf_hashes());
+}
+
+/// Tests serde round trips of a tree of leaf hashes
+/// Verifies JSON and bincode reload it as a tree of leaf hashes with the same root and proofs,
+/// and that a leaf that is not a leaf hash or a wrong root is rejected
+/// Methods tested: BinaryMerkleTree::serialize, BinaryMerkleTree::deserialize
+#[cfg(feature = "serde")]
+#[test]
+fn test_leaf_hash_tree_serde_round_trip() {
+    for count in [2, 5, 33] {
+        let hashes = random_hashes(count);
+        let tree = BinaryMerkleTree::from_leaf_hashes(&hashes, [3; 8], KEYED_HASH).unwrap();
+        let json = serde_json::to_string(&tree).unwrap();
+        let from_json: BinaryMerkleTree = serde_json::from_str(&json).unwrap();
+        let from_bincode: BinaryMerkleTree = bincode::deserialize(&bincode::serialize(&tree).unwrap()).unwrap();
+        for loaded in [from_json, from_bincode] {
+            assert!(loaded.has_leaf_hashes());
+            assert!(loaded.deep_equal(&tree, true));
+            assert!(loaded.generate_leaf_hash_proof(count - 1).unwrap().verify(&tree.root_hash(), [3; 8], KEYED_HASH));
+        }
+
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["root_hash"] = serde_json::Value::String("00".repeat(OUT_LEN));
+        assert!(serde_json::from_value::<BinaryMerkleTree>(value).is_err());
+    }
+
+    let chunk_tree = BinaryMerkleTree::from_input(&[7; 3000], IV, FLAGS);
+    let mut value = serde_json::to_value(&chunk_tree).unwrap();
+    value["leaf_hashes"] = serde_json::Value::Bool(true);
+    assert!(serde_json::from_value::<BinaryMerkleTree>(value).is_err());
+}
@@ -0,0 +1,197 @@
+use merkle_tree::binary_merkle_tree::{Blake3Hasher, Hash32, CHUNK_LEN};
+use merkle_tree::manifest::{Manifest, ManifestEntry, MANIFEST_CONTEXT};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+fn write_file(dir: &Path, path: &str, contents: &[u8]) {
+    let path = dir.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+/// A directory with files at the top level and nested two deep
+fn sample_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    write_file(dir.path(), "b.txt", b"second");
+    write_file(dir.path(), "a.txt", b"first");
+    write_file(dir.path(), "sub/deep/c.bin", &vec![7; 3 * CHUNK_LEN + 5]);
+    write_file(dir.path(), "sub/empty", b"");
+    dir
+}
+
+/// Tests building a manifest from a directory
+/// Verifies entries are sorted `/`-separated relative paths with each file's BLAKE3
+/// hash and length, and the digest follows the documented derivation
+/// Methods tested: Manifest::from_dir, Manifest::entries, Manifest::entry, ManifestEntry::digest
+#[test]
+fn test_from_dir_entries() {
+    let dir = sample_dir();
+    let manifest = Manifest::from_dir(dir.path()).unwrap();
+    let paths: Vec<&str> = manifest.entries().iter().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(paths, ["a.txt", "b.txt", "sub/deep/c.bin", "sub/empty"]);
+
+    let entry = manifest.entry("sub/deep/c.bin").unwrap();
+    assert_eq!(entry.root, Hash32(*blake3::hash(&vec![7; 3 * CHUNK_LEN + 5]).as_bytes()));
+    assert_eq!(entry.len, 3 * CHUNK_LEN as u64 + 5);
+    assert_eq!(manifest.entry("sub/deep"), None);
+
+    let mut hasher = Blake3Hasher::new_derive_key(MANIFEST_CONTEXT);
+    hasher.update(&14u64.to_le_bytes());
+    hasher.update(b"sub/deep/c.bin");
+    hasher.update(entry.root.as_bytes());
+    hasher.update(&entry.len.to_le_bytes());
+    assert_eq!(entry.digest(), Hash32(hasher.finalize_array()));
+
+    assert_eq!(Manifest::from_dir(dir.path()).unwrap().root(), manifest.root(), "Building is deterministic");
+    let from_entries = Manifest::from_entries(manifest.entries().iter().rev().cloned().collect()).unwrap();
+    assert_eq!(from_entries.root(), manifest.root(), "Entry order does not matter");
+}
+
+/// Tests how changes to the directory move the root
+/// Verifies renaming, changing, adding and removing a file each change the root, and
+/// undoing the change restores it
+/// Methods tested: Manifest::from_dir, Manifest::root
+#[test]
+fn test_changes_change_root() {
+    let dir = sample_dir();
+    let original = Manifest::from_dir(dir.path()).unwrap().root();
+    let root = || Manifest::from_dir(dir.path()).unwrap().root();
+
+    fs::rename(dir.path().join("a.txt"), dir.path().join("z.txt")).unwrap();
+    let renamed = root();
+    assert_ne!(renamed, original);
+    fs::rename(dir.path().join("z.txt"), dir.path().join("a.txt")).unwrap();
+    assert_eq!(root(), original);
+
+    fs::rename(dir.path().join("sub/deep"), dir.path().join("sub/deeper")).unwrap();
+    assert_ne!(root(), original, "Renaming a directory renames its files");
+    fs::rename(dir.path().join("sub/deeper"), dir.path().join("sub/deep")).unwrap();
+
+    write_file(dir.path(), "b.txt", b"Second");
+    let changed = root();
+    assert_ne!(changed, original);
+    write_file(dir.path(), "b.txt", b"second");
+    assert_eq!(root(), original);
+
+    write_file(dir.path(), "sub/new", b"");
+    let added = root();
+    assert_ne!(added, original);
+    fs::create_dir(dir.path().join("empty_dir")).unwrap();
+    assert_eq!(root(), added, "Empty directories are not entries");
+    fs::remove_file(dir.path().join("sub/new")).unwrap();
+    assert_eq!(root(), original);
+
+    fs::remove_file(dir.path().join("sub/empty")).unwrap();
+    let removed = root();
+    assert_ne!(removed, original);
+
+    let roots = [original, renamed, changed, added, removed];
+    for (i, a) in roots.iter().enumerate() {
+        assert!(roots[i + 1..].iter().all(|b| a != b), "Root {} repeats", i);
+    }
+}
+
+/// Tests that the file root, length and path are all bound into an entry
+/// Verifies moving contents between files or changing a recorded length changes the root
+/// Methods tested: Manifest::from_entries, Manifest::root
+#[test]
+fn test_entry_fields_are_bound() {
+    let entry = |path: &str, contents: &[u8]| ManifestEntry {
+        path: path.to_string(),
+        root: Hash32(*blake3::hash(contents).as_bytes()),
+        len: contents.len() as u64,
+    };
+    let original = Manifest::from_entries(vec![entry("a", b"one"), entry("b", b"two")]).unwrap();
+    let swapped = Manifest::from_entries(vec![entry("a", b"two"), entry("b", b"one")]).unwrap();
+    assert_ne!(original.root(), swapped.root());
+
+    let mut wrong_len = original.entries().to_vec();
+    wrong_len[0].len += 1;
+    assert_ne!(Manifest::from_entries(wrong_len).unwrap().root(), original.root());
+
+    // The length prefix keeps a path from absorbing bytes of the root
+    let mut shifted = original.entries().to_vec();
+    shifted[0].path = "ab".to_string();
+    shifted[1].path = "c".to_string();
+    assert_ne!(Manifest::from_entries(shifted).unwrap().root(), original.root());
+
+    let duplicate = Manifest::from_entries(vec![entry("a", b"one"), entry("a", b"two")]).unwrap_err();
+    assert_eq!(duplicate.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(Manifest::from_entries(Vec::new()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(Manifest::from_dir(tempfile::tempdir().unwrap().path()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+/// Tests file proofs
+/// Verifies every file's proof verifies against the manifest root, and a wrong path,
+/// file root, length or manifest root is rejected
+/// Methods tested: Manifest::prove_file, Manifest::verify_file, FileProof::verify
+#[test]
+fn test_file_proofs() {
+    let dir = sample_dir();
+    let manifest = Manifest::from_dir(dir.path()).unwrap();
+    for entry in manifest.entries() {
+        let proof = manifest.prove_file(&entry.path).unwrap();
+        assert_eq!(proof.file_len, entry.len);
+        assert!(manifest.verify_file(&entry.path, &entry.root, &proof), "{}", entry.path);
+        assert!(proof.verify(&manifest.root(), &entry.path, &entry.root));
+    }
+    assert_eq!(manifest.prove_file("missing"), None);
+
+    let entry = manifest.entry("b.txt").unwrap();
+    let proof = manifest.prove_file("b.txt").unwrap();
+    assert!(!manifest.verify_file("a.txt", &entry.root, &proof));
+    assert!(!manifest.verify_file("b.txt", &manifest.entry("a.txt").unwrap().root, &proof));
+
+    let mut wrong_len = proof.clone();
+    wrong_len.file_len += 1;
+    assert!(!manifest.verify_file("b.txt", &entry.root, &wrong_len));
+
+    write_file(dir.path(), "b.txt", b"Second");
+    let changed = Manifest::from_dir(dir.path()).unwrap();
+    assert!(!proof.verify(&changed.root(), "b.txt", &entry.root));
+    let a_root = manifest.entry("a.txt").unwrap().root;
+    assert!(!manifest.prove_file("a.txt").unwrap().verify(&changed.root(), "a.txt", &a_root),
+        "An unchanged file's old proof has the old b.txt as its sibling");
+    assert!(changed.prove_file("a.txt").unwrap().verify(&changed.root(), "a.txt", &a_root));
+}
+
+/// Tests serializing a manifest
+/// Verifies a round trip keeps the entries and root, and truncated, reordered or
+/// padded bytes are rejected
+/// Methods tested: Manifest::to_bytes, Manifest::from_bytes
+#[test]
+fn test_manifest_serialization() {
+    let manifest = Manifest::from_dir(sample_dir().path()).unwrap();
+    let bytes = manifest.to_bytes();
+    let loaded = Manifest::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.entries(), manifest.entries());
+    assert_eq!(loaded.root(), manifest.root());
+
+    for len in [0, 4, 16, bytes.len() - 1] {
+        assert!(Manifest::from_bytes(&bytes[..len]).is_err(), "Truncated to {}", len);
+    }
+    let mut padded = bytes.clone();
+    padded.push(0);
+    assert_eq!(Manifest::from_bytes(&padded).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] ^= 1;
+    assert_eq!(Manifest::from_bytes(&bad_magic).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let mut huge_count = bytes.clone();
+    huge_count[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert_eq!(Manifest::from_bytes(&huge_count).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let mut reversed = manifest.entries().to_vec();
+    reversed.reverse();
+    let mut unsorted = bytes[..16].to_vec();
+    for entry in &reversed {
+        unsorted.extend_from_slice(&(entry.path.len() as u32).to_le_bytes());
+        unsorted.extend_from_slice(entry.path.as_bytes());
+        unsorted.extend_from_slice(entry.root.as_bytes());
+        unsorted.extend_from_slice(&entry.len.to_le_bytes());
+    }
+    assert_eq!(unsorted.len(), bytes.len());
+    assert_eq!(Manifest::from_bytes(&unsorted).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
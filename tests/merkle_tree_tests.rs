@@ -1,4 +1,13 @@
-use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, CHUNK_LEN, IV, FLAGS, ChunkState};
+// These tests predate the clippy gate and are kept in their original form
+#![allow(clippy::unwrap_or_default)]
+
+mod common;
+
+use merkle_tree::binary_merkle_tree::{chunk_count, chunk_output, cv_from_bytes, cv_to_bytes, expected_root_bytes, fold_leaf_path, leaf_for_input_chunk, Hash32, HeapIndex, InvalidHex, LeafCv, LeafIndex, NodeCv, parent_cv, parent_output, ParentCv, Output, ROOT, BinaryMerkleTree, Blake3Hasher, CHUNK_LEN, IV, FLAGS, ChunkState, TreeLeafBuilder};
+use merkle_tree::flat_tree::FlatTree;
+use merkle_tree::node_store::NodeStore;
+use merkle_tree::error::MerkleError;
+use common::{chunk_leaves, SeededRng};
 use rand::Rng;
 use std::time::Instant;
 use std::collections::HashMap;
@@ -15,7 +24,7 @@ const BULK_MUTATIONS: [usize; 6] = [5, 10, 50, 100, 500, 1000]; // Different num
 #[test]
 fn test_initial_hash_value_match() {
     // Generate random input
-    let mut rng = rand::thread_rng();
+    let mut rng = SeededRng::new();
     let input: Vec<u8> = (0..RAW_BYTES_SIZE).map(|_| rng.gen()).collect();
     
     // Get initial BLAKE3 hash
@@ -25,10 +34,7 @@ fn test_initial_hash_value_match() {
     hasher.finalize(&mut initial_hash);
     
     // Convert initial hash bytes to chaining value format (8 u32 values)
-    let mut initial_blake3_chaining_value = [0u32; 8];
-    for i in 0..8 {
-        initial_blake3_chaining_value[i] = u32::from_le_bytes(initial_hash[i*4..(i+1)*4].try_into().unwrap());
-    }
+    let initial_blake3_chaining_value = cv_from_bytes(initial_hash);
     
     // Process through Merkle tree
     let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
@@ -46,7 +52,7 @@ fn test_initial_hash_value_match() {
 #[test]
 fn test_single_mutation_hash_value_match() {
     // Generate random input
-    let mut rng = rand::thread_rng();
+    let mut rng = SeededRng::new();
     let mut input: Vec<u8> = (0..RAW_BYTES_SIZE).map(|_| rng.gen()).collect();
     
     // Process through Merkle tree initially
@@ -61,15 +67,11 @@ fn test_single_mutation_hash_value_match() {
     let chunk_index = mutation_index / CHUNK_LEN;
 
     // Create new Output for the mutated chunk
-    let mut chunk_state = ChunkState::new(IV, chunk_index as u64, FLAGS);
-    let chunk_start = chunk_index * CHUNK_LEN;
-    let chunk_end = std::cmp::min(chunk_start + CHUNK_LEN, input.len());
-    chunk_state.update(&input[chunk_start..chunk_end]);
-    let mutated_chunk_output = chunk_state.output();
+    let mutated_chunk_output = leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap();
 
     // Time the tree update operation
     let update_start = Instant::now();
-    tree.insert_leaf(chunk_index, mutated_chunk_output);
+    tree.insert_leaf(chunk_index, mutated_chunk_output).unwrap();
     let mutated_root = tree.root().chaining_value();
     let update_duration = update_start.elapsed();
     println!("Tree root computation in updated merkle tree took: {:?}", update_duration);
@@ -84,10 +86,7 @@ fn test_single_mutation_hash_value_match() {
     println!("BLAKE3 hash computation took: {:?}", blake3_duration);
     
     // Convert mutated hash bytes to chaining value format
-    let mut mutated_blake3_chaining_value = [0u32; 8];
-    for i in 0..8 {
-        mutated_blake3_chaining_value[i] = u32::from_le_bytes(mutated_hash[i*4..(i+1)*4].try_into().unwrap());
-    }
+    let mutated_blake3_chaining_value = cv_from_bytes(mutated_hash);
 
     // Assert that the mutated root matches the mutated BLAKE3 hash
     assert_eq!(mutated_root, mutated_blake3_chaining_value,
@@ -100,7 +99,7 @@ fn test_single_mutation_hash_value_match() {
 /// Methods tested: BinaryMerkleTree::insert_leaf, BinaryMerkleTree::root
 #[test]
 fn test_fuzz_single_mutation() {
-    let mut rng = rand::thread_rng();
+    let mut rng = SeededRng::new();
     
     for iteration in 0..FUZZ_ITERATIONS {
         // Generate random input for this iteration
@@ -118,14 +117,10 @@ fn test_fuzz_single_mutation() {
         let chunk_index = mutation_index / CHUNK_LEN;
 
         // Create new Output for the mutated chunk
-        let mut chunk_state = ChunkState::new(IV, chunk_index as u64, FLAGS);
-        let chunk_start = chunk_index * CHUNK_LEN;
-        let chunk_end = std::cmp::min(chunk_start + CHUNK_LEN, input.len());
-        chunk_state.update(&input[chunk_start..chunk_end]);
-        let mutated_chunk_output = chunk_state.output();
+        let mutated_chunk_output = leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap();
 
         // Update merkle tree and get new root
-        tree.insert_leaf(chunk_index, mutated_chunk_output);
+        tree.insert_leaf(chunk_index, mutated_chunk_output).unwrap();
         let mutated_root = tree.root().chaining_value();
 
         // Compute full BLAKE3 hash for comparison
@@ -135,10 +130,7 @@ fn test_fuzz_single_mutation() {
         hasher.finalize(&mut mutated_hash);
         
         // Convert hash to chaining value format
-        let mut mutated_blake3_chaining_value = [0u32; 8];
-        for i in 0..8 {
-            mutated_blake3_chaining_value[i] = u32::from_le_bytes(mutated_hash[i*4..(i+1)*4].try_into().unwrap());
-        }
+        let mutated_blake3_chaining_value = cv_from_bytes(mutated_hash);
 
         // Assert equality and print diagnostic info on failure
         assert_eq!(mutated_root, mutated_blake3_chaining_value,
@@ -153,7 +145,7 @@ fn test_fuzz_single_mutation() {
 /// Methods tested: BinaryMerkleTree::bulk_insert_leaves, BinaryMerkleTree::root
 #[test]
 fn test_bulk_mutations() {
-    let mut rng = rand::thread_rng();
+    let mut rng = SeededRng::new();
     
     for &num_mutations in BULK_MUTATIONS.iter() {
         println!("\nTesting with {} random mutations:", num_mutations);
@@ -199,20 +191,15 @@ fn test_bulk_mutations() {
         let mut chunk_outputs = Vec::with_capacity(chunk_updates.len());
         
         for &chunk_index in &sorted_chunk_indices {
-            let chunk_start = chunk_index * CHUNK_LEN;
-            let chunk_end = std::cmp::min(chunk_start + CHUNK_LEN, input.len());
-            
             // Calculate chunk output after all mutations in this chunk
-            let mut chunk_state = ChunkState::new(IV, chunk_index as u64, FLAGS);
-            chunk_state.update(&input[chunk_start..chunk_end]);
-            
             chunk_indices.push(chunk_index);
-            chunk_outputs.push(chunk_state.output());
+            chunk_outputs.push(leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap());
         }
         
         // Time the Merkle tree bulk update
         let merkle_start = Instant::now();
-        tree.bulk_insert_leaves(chunk_indices.into_iter(), chunk_outputs.into_iter());
+        tree.bulk_insert_leaves(chunk_indices.into_iter(), chunk_outputs.into_iter())
+            .expect("Bulk insert failed");
         let mutated_root = tree.root().chaining_value();
         let merkle_duration = merkle_start.elapsed();
         println!("Merkle tree bulk update + root computation took: {:?}", merkle_duration);
@@ -229,10 +216,7 @@ fn test_bulk_mutations() {
                  blake3_duration.as_nanos() as f64 / merkle_duration.as_nanos() as f64);
         
         // Convert hash to chaining value format and verify
-        let mut mutated_blake3_chaining_value = [0u32; 8];
-        for i in 0..8 {
-            mutated_blake3_chaining_value[i] = u32::from_le_bytes(mutated_hash[i*4..(i+1)*4].try_into().unwrap());
-        }
+        let mutated_blake3_chaining_value = cv_from_bytes(mutated_hash);
         
         assert_eq!(mutated_root, mutated_blake3_chaining_value,
             "Bulk mutation test failed with {} mutations.\nRoot hash: {:?}\nBLAKE3 hash: {:?}",
@@ -245,7 +229,7 @@ fn test_bulk_mutations() {
 /// Methods tested: BinaryMerkleTree::bulk_insert_leaves, BinaryMerkleTree::root
 #[test]
 fn test_fuzz_bulk_mutations() {
-    let mut rng = rand::thread_rng();
+    let mut rng = SeededRng::new();
     
     for iteration in 0..FUZZ_ITERATIONS {
         // Generate random input for this iteration
@@ -289,18 +273,13 @@ fn test_fuzz_bulk_mutations() {
         let mut chunk_outputs = Vec::with_capacity(chunk_updates.len());
         
         for &chunk_index in &sorted_chunk_indices {
-            let chunk_start = chunk_index * CHUNK_LEN;
-            let chunk_end = std::cmp::min(chunk_start + CHUNK_LEN, input.len());
-            
-            let mut chunk_state = ChunkState::new(IV, chunk_index as u64, FLAGS);
-            chunk_state.update(&input[chunk_start..chunk_end]);
-            
             chunk_indices.push(chunk_index);
-            chunk_outputs.push(chunk_state.output());
+            chunk_outputs.push(leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap());
         }
         
         // Update merkle tree with bulk mutations
-        tree.bulk_insert_leaves(chunk_indices.into_iter(), chunk_outputs.into_iter());
+        tree.bulk_insert_leaves(chunk_indices.into_iter(), chunk_outputs.into_iter())
+            .expect("Bulk insert failed");
         let mutated_root = tree.root().chaining_value();
         
         // Compute full BLAKE3 hash for comparison
@@ -310,10 +289,7 @@ fn test_fuzz_bulk_mutations() {
         hasher.finalize(&mut mutated_hash);
         
         // Convert hash to chaining value format
-        let mut mutated_blake3_chaining_value = [0u32; 8];
-        for i in 0..8 {
-            mutated_blake3_chaining_value[i] = u32::from_le_bytes(mutated_hash[i*4..(i+1)*4].try_into().unwrap());
-        }
+        let mutated_blake3_chaining_value = cv_from_bytes(mutated_hash);
         
         // Assert equality and print diagnostic info on failure
         assert_eq!(mutated_root, mutated_blake3_chaining_value,
@@ -325,4 +301,696 @@ fn test_fuzz_bulk_mutations() {
         }
     }
     println!("Successfully completed {} fuzz test iterations with random bulk mutations", FUZZ_ITERATIONS);
-}
\ No newline at end of file
+}
+/// Tests the ROOT-flagged digest of an empty input
+/// Verifies that the single empty-chunk tree produces the official BLAKE3 hash of b""
+/// Methods tested: BinaryMerkleTree::from_input, BinaryMerkleTree::root_hash
+#[test]
+fn test_empty_input_root_hash() {
+    let tree = BinaryMerkleTree::from_input(&[], IV, FLAGS);
+    assert_eq!(tree.actual_leaves(), 1);
+
+    // af1349b9... is the published BLAKE3 digest of the empty string
+    let expected = blake3::hash(b"");
+    assert_eq!(expected.to_hex().as_str(), "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262");
+    assert_eq!(tree.root_hash(), *expected.as_bytes(),
+        "Empty input root hash {:?} does not match BLAKE3 hash {:?}",
+        tree.root_hash(), expected.as_bytes());
+
+    // The reference hasher agrees on the same bytes
+    let hasher = Blake3Hasher::new();
+    let mut reference_hash = [0; 32];
+    hasher.finalize(&mut reference_hash);
+    assert_eq!(tree.root_hash(), reference_hash);
+}
+
+/// Tests the tagged uppercase hex form of the root hash
+/// Verifies the length, the uppercase digits after the prefix, and that the stripped digits parse
+/// back to the root hash, alongside the plain lowercase form
+/// Methods tested: BinaryMerkleTree::root_hash_tagged, Hash32::to_hex, Hash32::from_str
+#[test]
+fn test_root_hash_tagged() {
+    let tree = BinaryMerkleTree::from_input(&[], IV, FLAGS);
+    let tagged = tree.root_hash_tagged("blake3:");
+    assert_eq!(tagged.len(), "blake3:".len() + 64);
+    assert_eq!(tagged, "blake3:AF1349B9F5F9A1A6A0404DEA36DCC9499BCB25C9ADC112B7CC9A93CAE41F3262");
+    assert_eq!(tree.root_hash().to_hex(), "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262");
+
+    let digits = tagged.strip_prefix("blake3:").unwrap();
+    assert_eq!(digits.parse::<Hash32>(), Ok(tree.root_hash()));
+    assert_eq!(tree.root_hash().to_hex().parse::<Hash32>(), Ok(tree.root_hash()));
+    assert_eq!(tree.root_hash_tagged(""), digits);
+
+    assert_eq!(tagged.parse::<Hash32>(), Err(InvalidHex::Length(71)));
+    assert_eq!(digits.replacen('A', "G", 1).parse::<Hash32>(), Err(InvalidHex::Digit(0)));
+}
+
+/// Tests that root_hash agrees with the official BLAKE3 hash for non-empty inputs
+/// Methods tested: BinaryMerkleTree::from_input, BinaryMerkleTree::root_hash
+#[test]
+fn test_root_hash_matches_blake3() {
+    let mut rng = SeededRng::new();
+    for &size in &[1, CHUNK_LEN - 1, CHUNK_LEN, CHUNK_LEN + 1, 3 * CHUNK_LEN, 5 * CHUNK_LEN + 7] {
+        let input: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        assert_eq!(tree.root_hash(), *blake3::hash(&input).as_bytes(),
+            "Root hash mismatch for input size {} bytes", size);
+    }
+}
+
+/// Tests incremental leaf construction with TreeLeafBuilder
+/// Verifies that feeding random-sized pieces yields exactly the leaves of the one-shot chunking
+/// Methods tested: TreeLeafBuilder::update, TreeLeafBuilder::finalize
+#[test]
+fn test_tree_leaf_builder_matches_one_shot_chunking() {
+    let mut rng = SeededRng::new();
+    for &size in &[0, 1, CHUNK_LEN, CHUNK_LEN + 1, FUZZ_BYTES_SIZE, 50 * CHUNK_LEN + 999] {
+        let input: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+        let mut builder = TreeLeafBuilder::new(IV, FLAGS);
+        let mut remaining = &input[..];
+        while !remaining.is_empty() {
+            let take = rng.gen_range(0..=remaining.len().min(3 * CHUNK_LEN));
+            builder.update(&remaining[..take]);
+            remaining = &remaining[take..];
+            // Only full chunks that more input has followed are reported as completed
+            let consumed = input.len() - remaining.len();
+            assert_eq!(builder.completed_leaves().len(), consumed.saturating_sub(1) / CHUNK_LEN);
+        }
+
+        let leaves = builder.finalize();
+        assert_eq!(leaves, chunk_leaves(&input, IV, FLAGS),
+            "Leaf mismatch for input size {} bytes", size);
+
+        let tree = BinaryMerkleTree::new_from_leaves(leaves, IV, FLAGS);
+        assert_eq!(tree.root_hash(), *blake3::hash(&input).as_bytes());
+    }
+}
+
+/// Tests copy-on-write snapshots
+/// Verifies that mutating a tree after taking a snapshot leaves the snapshot's root untouched
+/// Methods tested: BinaryMerkleTree::snapshot, BinaryMerkleTree::insert_leaf, BinaryMerkleTree::root_hash
+#[test]
+fn test_snapshot_unchanged_after_mutation() {
+    let mut rng = SeededRng::new();
+    let input_size = rng.gen_range(CHUNK_LEN * 5..=CHUNK_LEN * 50);
+    let mut input: Vec<u8> = (0..input_size).map(|_| rng.gen()).collect();
+    let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let snapshot = tree.snapshot();
+    let original_hash = blake3::hash(&input);
+
+    // Flip the first byte of a random chunk in the original tree only
+    let chunk_index = rng.gen_range(0..tree.actual_leaves());
+    let chunk_start = chunk_index * CHUNK_LEN;
+    input[chunk_start] ^= 0xFF;
+    tree.insert_leaf(chunk_index, leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap()).unwrap();
+
+    assert_eq!(tree.root_hash(), *blake3::hash(&input).as_bytes());
+    assert_eq!(snapshot.root_hash(), *original_hash.as_bytes(),
+        "Snapshot root changed after mutating the original tree");
+    assert!(snapshot.verify_integrity());
+}
+
+/// Tests that parent hashing depends on child order
+/// Verifies that swapping distinct left and right CVs changes the parent CV, and that
+/// swapping two distinct chunks changes the root
+/// Methods tested: parent_cv, BinaryMerkleTree::from_input
+#[test]
+fn test_parent_hash_is_not_commutative() {
+    let mut rng = SeededRng::new();
+    for _ in 0..100 {
+        let left: [u32; 8] = rng.gen();
+        let right: [u32; 8] = rng.gen();
+        if left == right {
+            continue;
+        }
+        assert_ne!(parent_cv(left, right, IV, FLAGS), parent_cv(right, left, IV, FLAGS),
+            "parent_cv is commutative for {:?} and {:?}", left, right);
+    }
+
+    let first_chunk = vec![1u8; CHUNK_LEN];
+    let second_chunk = vec![2u8; CHUNK_LEN];
+    let forward = BinaryMerkleTree::from_input(&[first_chunk.clone(), second_chunk.clone()].concat(), IV, FLAGS);
+    let swapped = BinaryMerkleTree::from_input(&[second_chunk, first_chunk].concat(), IV, FLAGS);
+    assert_ne!(forward.root_hash(), swapped.root_hash());
+}
+
+/// Tests the reference root computation and the tree check built on it
+/// Verifies that expected_root_bytes equals blake3::hash, that trees of several sizes match it
+/// (including after a mutation), and that a mismatched input is caught
+/// Methods tested: expected_root_bytes, BinaryMerkleTree::assert_matches_reference
+#[test]
+fn test_assert_matches_reference() {
+    let mut rng = SeededRng::new();
+    for &size in &[0, 1, CHUNK_LEN - 1, CHUNK_LEN, CHUNK_LEN + 1, 3 * CHUNK_LEN, 17 * CHUNK_LEN + 5] {
+        let mut input: Vec<u8> = (0..size).map(|_| rng.gen()).collect();
+        assert_eq!(expected_root_bytes(&input, IV, FLAGS), *blake3::hash(&input).as_bytes());
+
+        let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        tree.assert_matches_reference(&input);
+
+        if size > 0 {
+            let chunk_index = tree.actual_leaves() - 1;
+            let chunk_start = chunk_index * CHUNK_LEN;
+            input[chunk_start] ^= 0xFF;
+            tree.insert_leaf(chunk_index, leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap()).unwrap();
+            tree.assert_matches_reference(&input);
+        }
+    }
+
+    let tree = BinaryMerkleTree::from_input(&[1, 2, 3], IV, FLAGS);
+    let result = std::panic::catch_unwind(|| tree.assert_matches_reference(&[1, 2, 4]));
+    assert!(result.is_err(), "Mismatched input was not detected");
+}
+
+/// Fold a leaf up to the root using only its update-path siblings, the way a remote client would
+fn fold_update_path(leaf_index: usize, actual_leaves: usize, leaf: Output, siblings: &[[u32; 8]]) -> Output {
+    let mut node = leaf;
+    let mut siblings = siblings.iter();
+    let mut position = leaf_index;
+    let mut nodes_in_level = actual_leaves;
+    while nodes_in_level > 1 {
+        if (position ^ 1) < nodes_in_level {
+            let sibling = *siblings.next().expect("Sibling path is too short");
+            node = if position.is_multiple_of(2) {
+                parent_output(node.chaining_value(), sibling, IV, FLAGS)
+            } else {
+                parent_output(sibling, node.chaining_value(), IV, FLAGS)
+            };
+        }
+        position /= 2;
+        nodes_in_level = nodes_in_level.div_ceil(2);
+    }
+    assert!(siblings.next().is_none(), "Sibling path is too long");
+    node.flags |= ROOT;
+    node
+}
+
+/// Tests the sibling path exposed for remote updates
+/// Verifies that folding a new leaf with the siblings taken before the update gives the root
+/// after insert_leaf, for every leaf of balanced and unbalanced trees
+/// Methods tested: BinaryMerkleTree::update_path_siblings, BinaryMerkleTree::insert_leaf
+#[test]
+fn test_update_path_siblings_fold_to_new_root() {
+    let mut rng = SeededRng::new();
+    for &chunks in &[1, 2, 3, 4, 5, 8, 13] {
+        let input: Vec<u8> = (0..chunks * CHUNK_LEN - 7).map(|_| rng.gen()).collect();
+        let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        assert_eq!(tree.update_path_siblings(chunks), None);
+
+        for leaf_index in 0..chunks {
+            let siblings = tree.update_path_siblings(leaf_index).unwrap();
+            let new_chunk: Vec<u8> = (0..CHUNK_LEN).map(|_| rng.gen()).collect();
+            let new_leaf = chunk_output(&new_chunk, leaf_index as u64, IV, FLAGS).unwrap();
+
+            let folded_root = fold_update_path(leaf_index, chunks, new_leaf, &siblings);
+            tree.insert_leaf(leaf_index, new_leaf).unwrap();
+            assert_eq!(folded_root, tree.root(),
+                "Folded root mismatch for leaf {} of {}", leaf_index, chunks);
+        }
+    }
+}
+
+/// Tests the relationship between the root's chaining value and its digest for a multi-chunk input
+/// Verifies the CV composes from the children like any parent CV, the bytes are the BLAKE3 hash,
+/// the two differ, and the ROOT-flagged CV is only the digest's first 8 words
+/// Methods tested: BinaryMerkleTree::root_cv_and_bytes
+#[test]
+fn test_root_cv_and_bytes() {
+    let mut rng = SeededRng::new();
+    let input: Vec<u8> = (0..3 * CHUNK_LEN + 100).map(|_| rng.gen()).collect();
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let (root_cv, root_bytes) = tree.root_cv_and_bytes();
+
+    let leaf_cv = |i: usize| tree.leaf(i).unwrap().chaining_value();
+    let composed = parent_cv(parent_cv(leaf_cv(0), leaf_cv(1), IV, FLAGS), parent_cv(leaf_cv(2), leaf_cv(3), IV, FLAGS), IV, FLAGS);
+    assert_eq!(root_cv, composed);
+    assert_eq!(root_bytes, *blake3::hash(&input).as_bytes());
+
+    assert_ne!(root_cv, cv_from_bytes(*root_bytes), "Non-root CV must differ from the ROOT digest");
+    assert_eq!(tree.root().cv_bytes(), *root_bytes);
+}
+
+/// Tests the node count reported after incremental updates
+/// Verifies one rewrite per level for a single leaf, including promoted lone nodes, and
+/// that a batch rewrites each shared ancestor once
+/// Methods tested: BinaryMerkleTree::nodes_recomputed, insert_leaf, bulk_insert_leaves
+#[test]
+fn test_nodes_recomputed() {
+    let input: Vec<u8> = (0..8 * CHUNK_LEN).map(|i| (i % 251) as u8).collect();
+    let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    assert_eq!(tree.nodes_recomputed(), 0);
+    let leaf = tree.leaf(0).unwrap();
+
+    tree.insert_leaf(3, leaf).unwrap();
+    assert_eq!(tree.nodes_recomputed(), 3);
+    tree.bulk_insert_leaves([0, 1].into_iter(), [leaf, leaf].into_iter()).unwrap();
+    assert_eq!(tree.nodes_recomputed(), 3);
+    tree.bulk_insert_leaves([0, 2, 4, 6].into_iter(), [leaf; 4].into_iter()).unwrap();
+    assert_eq!(tree.nodes_recomputed(), 7);
+    tree.bulk_insert_leaves([].into_iter(), [].into_iter()).unwrap();
+    assert_eq!(tree.nodes_recomputed(), 0);
+
+    // Leaf 4 of 5 is promoted twice before meeting a sibling
+    let mut five = BinaryMerkleTree::from_input(&input[..5 * CHUNK_LEN], IV, FLAGS);
+    five.insert_leaf(4, leaf).unwrap();
+    assert_eq!(five.nodes_recomputed(), 3);
+    assert!(five.insert_leaf(5, leaf).is_err());
+    assert_eq!(five.nodes_recomputed(), 3, "A rejected update keeps the last count");
+}
+
+/// Tests swapping two leaves
+/// Verifies swapping twice restores the root, the result matches a tree over the reordered
+/// Outputs, rehashing the two chunks at their new positions matches `from_input` over the
+/// reordered chunks, and bad indices leave the tree unchanged
+/// Methods tested: BinaryMerkleTree::swap_leaves, nodes_recomputed
+#[test]
+fn test_swap_leaves() {
+    let input: Vec<u8> = (0..7 * CHUNK_LEN).map(|i| (i % 251) as u8 ^ (i / CHUNK_LEN) as u8).collect();
+    let original = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let mut tree = original.clone();
+
+    tree.swap_leaves(5, 1).unwrap();
+    assert_ne!(tree.root_hash(), original.root_hash());
+    assert_eq!(tree.nodes_recomputed(), 5, "Two paths of three levels meet at the root");
+    let mut leaves: Vec<Output> = (0..7).map(|i| original.leaf(i).unwrap()).collect();
+    leaves.swap(1, 5);
+    assert_eq!(tree.root_hash(), BinaryMerkleTree::new_from_leaves(leaves, IV, FLAGS).root_hash());
+
+    let mut reordered = input.clone();
+    let (front, back) = reordered.split_at_mut(5 * CHUNK_LEN);
+    front[CHUNK_LEN..2 * CHUNK_LEN].swap_with_slice(&mut back[..CHUNK_LEN]);
+    let reordered_tree = BinaryMerkleTree::from_input(&reordered, IV, FLAGS);
+    assert_ne!(tree.root_hash(), reordered_tree.root_hash(), "Swapped Outputs keep their chunk counters");
+    let mut rehashed = tree.clone();
+    for leaf_index in [1, 5] {
+        rehashed.insert_leaf(leaf_index, leaf_for_input_chunk(&reordered, leaf_index, IV, FLAGS).unwrap()).unwrap();
+    }
+    assert_eq!(rehashed.root_hash(), reordered_tree.root_hash());
+
+    tree.swap_leaves(1, 5).unwrap();
+    assert_eq!(tree.root_hash(), original.root_hash());
+    tree.swap_leaves(3, 3).unwrap();
+    assert_eq!(tree.root_hash(), original.root_hash());
+    assert_eq!(tree.nodes_recomputed(), 0);
+
+    assert_eq!(
+        tree.swap_leaves(2, 7),
+        Err(MerkleError::LeafIndexOutOfBounds { leaf_index: 7, actual_leaves: 7 })
+    );
+    assert!(tree.swap_leaves(9, 0).is_err());
+    assert_eq!(tree.root_hash(), original.root_hash());
+    assert_eq!(tree.leaf(2), original.leaf(2));
+}
+
+/// Tests that bulk updates reject mismatched index and hash counts instead of silently zipping
+/// Verifies too few hashes, too many hashes, and unsorted indices all leave the tree unchanged
+/// Methods tested: BinaryMerkleTree::bulk_insert_leaves
+#[test]
+fn test_bulk_insert_length_mismatch() {
+    let mut rng = SeededRng::new();
+    let mut input: Vec<u8> = (0..8 * CHUNK_LEN).map(|_| rng.gen()).collect();
+    let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let original_root = tree.root_hash();
+
+    for &chunk_index in &[1, 4, 6] {
+        input[chunk_index * CHUNK_LEN] ^= 0xFF;
+    }
+    let outputs: Vec<Output> = [1, 4, 6].iter().map(|&chunk_index| {
+        leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap()
+    }).collect();
+
+    let too_few = tree.bulk_insert_leaves([1, 4, 6].into_iter(), outputs[..2].iter().copied());
+    assert_eq!(too_few, Err(MerkleError::LengthMismatch { indices: 3, hashes: 2 }));
+    let too_many = tree.bulk_insert_leaves([1, 4].into_iter(), outputs.iter().copied());
+    assert_eq!(too_many, Err(MerkleError::LengthMismatch { indices: 2, hashes: 3 }));
+    let unsorted = tree.bulk_insert_leaves([4, 1, 6].into_iter(), outputs.iter().copied());
+    assert_eq!(unsorted, Err(MerkleError::UnsortedIndices));
+    assert_eq!(tree.root_hash(), original_root, "A rejected bulk insert modified the tree");
+
+    assert_eq!(tree.bulk_insert_leaves(std::iter::empty(), std::iter::empty()), Ok(()));
+    tree.bulk_insert_leaves([1, 4, 6].into_iter(), outputs.into_iter()).unwrap();
+    assert_eq!(tree.root_hash(), *blake3::hash(&input).as_bytes());
+}
+
+/// Tests the chunk count helper against the leaves actually produced
+/// Verifies empty input yields one leaf and partial chunks round up
+/// Methods tested: chunk_count, TreeLeafBuilder::finalize
+#[test]
+fn test_chunk_count_matches_leaves() {
+    for (input_len, expected) in [(0, 1), (1, 1), (1024, 1), (1025, 2), (3072, 3)] {
+        let leaves = chunk_leaves(&vec![7u8; input_len], IV, FLAGS);
+        assert_eq!(chunk_count(input_len), expected, "Wrong chunk count for {} bytes", input_len);
+        assert_eq!(leaves.len(), expected);
+    }
+}
+
+/// Tests logical deletes followed by compaction
+/// Verifies that removing leaves leaves the root untouched, and that compacting yields the tree
+/// of the surviving chunks, with an old-to-new index mapping, when the partial final chunk is removed too
+/// Methods tested: BinaryMerkleTree::remove_leaf, BinaryMerkleTree::is_removed, BinaryMerkleTree::compact
+#[test]
+fn test_remove_and_compact() {
+    let mut rng = SeededRng::new();
+    let input: Vec<u8> = (0..6 * CHUNK_LEN + 300).map(|_| rng.gen()).collect();
+    let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let original_root = tree.root_hash();
+
+    assert!(tree.remove_leaf(2).unwrap());
+    assert!(tree.remove_leaf(6).unwrap());
+    assert!(!tree.remove_leaf(2).unwrap(), "A leaf can only be removed once");
+    assert!(tree.is_removed(6) && !tree.is_removed(5));
+    assert_eq!(tree.root_hash(), original_root, "Removing must not change the root before compaction");
+
+    let mapping = tree.compact(&input).unwrap();
+    assert_eq!(mapping, vec![Some(0), Some(1), None, Some(2), Some(3), Some(4), None]);
+
+    let remaining: Vec<u8> = [0, 1, 3, 4, 5].iter().flat_map(|&i| input[i * CHUNK_LEN..(i + 1) * CHUNK_LEN].to_vec()).collect();
+    let expected = BinaryMerkleTree::from_input(&remaining, IV, FLAGS);
+    assert_eq!(tree.root_hash(), expected.root_hash());
+    assert_eq!(tree.actual_leaves(), 5);
+    assert!(!tree.is_removed(2), "Compaction clears the removal marks");
+    tree.assert_matches_reference(&remaining);
+}
+
+/// Tests conversions between leaf and heap indices
+/// Verifies the leaf offset in both directions, that padding, internal nodes and out-of-bounds
+/// indices are rejected, that node_at walks real parents, and that typed and plain indices agree
+/// Methods tested: BinaryMerkleTree::heap_index, BinaryMerkleTree::leaf_index, BinaryMerkleTree::node_at, BinaryMerkleTree::leaf
+#[test]
+fn test_leaf_and_heap_index_conversions() {
+    let mut rng = SeededRng::new();
+    let input: Vec<u8> = (0..5 * CHUNK_LEN).map(|_| rng.gen()).collect();
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    assert_eq!(tree.num_leaves(), 8);
+
+    for leaf_index in 0..5 {
+        let heap_index = tree.heap_index(LeafIndex(leaf_index)).unwrap();
+        assert_eq!(heap_index, HeapIndex(8 + leaf_index));
+        assert_eq!(tree.leaf_index(heap_index), Some(LeafIndex(leaf_index)));
+        assert_eq!(tree.node_at(heap_index), tree.leaf(leaf_index));
+        assert_eq!(tree.leaf(LeafIndex(leaf_index)), tree.leaf(leaf_index));
+    }
+    assert_eq!(tree.heap_index(5), None);
+    assert_eq!(tree.leaf_index(HeapIndex(13)), None, "Padding is not a leaf");
+    assert_eq!(tree.leaf_index(HeapIndex(3)), None, "Internal nodes are not leaves");
+
+    assert_eq!(tree.node_at(HeapIndex(1)).map(|root| root.chaining_value()), Some(tree.root_cv_and_bytes().0));
+    let expected_parent = parent_output(tree.leaf(0).unwrap().chaining_value(), tree.leaf(1).unwrap().chaining_value(), IV, FLAGS);
+    assert_eq!(tree.node_at(HeapIndex(4)), Some(expected_parent));
+    // Level 1 has three real nodes (4, 5, 6), so 7 is padding; 0 and past the heap are invalid
+    assert_eq!(tree.node_at(HeapIndex(6)), tree.leaf(4), "Lone leaf is promoted");
+    for invalid in [0, 7, 13, 16] {
+        assert_eq!(tree.node_at(HeapIndex(invalid)), None, "Heap index {} is not a real node", invalid);
+    }
+}
+
+/// Tests the typed leaf and parent chaining values
+/// Verifies kinds follow the node flags (a promoted lone leaf stays a leaf), that join matches
+/// parent_cv, and that the escape hatches round-trip the raw words
+/// Methods tested: LeafCv::from_output, ParentCv::from_output, ParentCv::join, BinaryMerkleTree::leaf_cv, BinaryMerkleTree::parent_cv
+#[test]
+fn test_typed_chaining_values() {
+    let mut rng = SeededRng::new();
+    let input: Vec<u8> = (0..5 * CHUNK_LEN).map(|_| rng.gen()).collect();
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+
+    let leaf = tree.leaf(0).unwrap();
+    assert_eq!(LeafCv::from_output(&leaf), tree.leaf_cv(0));
+    assert_eq!(ParentCv::from_output(&leaf), None, "A chunk is not a parent");
+    assert_eq!(tree.leaf_cv(5), None);
+
+    let parent = tree.node_at(HeapIndex(4)).unwrap();
+    assert_eq!(LeafCv::from_output(&parent), None, "A parent is not a chunk");
+    assert_eq!(ParentCv::from_output(&parent), tree.parent_cv(HeapIndex(4)));
+    assert_eq!(NodeCv::from_output(&parent), NodeCv::Parent(tree.parent_cv(HeapIndex(4)).unwrap()));
+
+    let joined = ParentCv::join(tree.leaf_cv(0).unwrap(), tree.leaf_cv(1).unwrap(), IV, FLAGS);
+    assert_eq!(Some(joined), tree.parent_cv(HeapIndex(4)));
+    assert_eq!(joined.words(), parent_cv(leaf.chaining_value(), tree.leaf(1).unwrap().chaining_value(), IV, FLAGS));
+    // Leaves and parents mix as children
+    let upper = ParentCv::join(joined, tree.parent_cv(HeapIndex(5)).unwrap(), IV, FLAGS);
+    assert_eq!(Some(upper), tree.parent_cv(HeapIndex(2)));
+
+    assert_eq!(tree.parent_cv(HeapIndex(6)), None, "Lone leaf is promoted, not a parent");
+    assert_eq!(tree.parent_cv(HeapIndex(8)), None, "Leaves are not parents");
+    assert_eq!(tree.parent_cv(HeapIndex(7)), None, "Padding is not a node");
+
+    let words = leaf.chaining_value();
+    assert_eq!(LeafCv::assume_leaf(words).words(), words);
+    assert_eq!(<[u32; 8]>::from(ParentCv::assume_parent(words)), words);
+}
+
+/// Tests folding a typed leaf up its update path
+/// Verifies the folded root CV matches the tree after insert_leaf, that a one-leaf tree folds to
+/// the leaf itself, and that wrong path lengths and indices are rejected
+/// Methods tested: fold_leaf_path, BinaryMerkleTree::update_path_siblings
+#[test]
+fn test_fold_leaf_path() {
+    let mut rng = SeededRng::new();
+    for &chunks in &[1, 2, 3, 5, 8, 13] {
+        let input: Vec<u8> = (0..chunks * CHUNK_LEN).map(|_| rng.gen()).collect();
+        let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        for leaf_index in 0..chunks {
+            let siblings = tree.update_path_siblings(leaf_index).unwrap();
+            let new_leaf = chunk_output(&[leaf_index as u8; 100], leaf_index as u64, IV, FLAGS).unwrap();
+            let leaf_cv = LeafCv::from_output(&new_leaf).unwrap();
+
+            let folded = fold_leaf_path(leaf_cv, leaf_index, chunks, &siblings, IV, FLAGS).unwrap();
+            tree.insert_leaf(leaf_index, new_leaf).unwrap();
+            assert_eq!(folded.words(), tree.root_cv_and_bytes().0, "Leaf {} of {}", leaf_index, chunks);
+            if chunks == 1 {
+                assert_eq!(folded, NodeCv::Leaf(leaf_cv));
+            }
+
+            if !siblings.is_empty() {
+                assert_eq!(fold_leaf_path(leaf_cv, leaf_index, chunks, &siblings[1..], IV, FLAGS), None);
+            }
+            let mut too_long = siblings.clone();
+            too_long.push([0; 8]);
+            assert_eq!(fold_leaf_path(leaf_cv, leaf_index, chunks, &too_long, IV, FLAGS), None);
+        }
+        let any_leaf = tree.leaf_cv(0).unwrap();
+        assert_eq!(fold_leaf_path(any_leaf, chunks, chunks, &[], IV, FLAGS), None);
+    }
+}
+
+/// A record whose digest comes from outside the tree, as for `from_leaf_data`
+struct Record {
+    id: u32,
+    balance: u64,
+}
+
+fn record_digest(record: &Record) -> [u32; 8] {
+    let mut hasher = Blake3Hasher::new();
+    hasher.update(&record.id.to_le_bytes());
+    hasher.update(&record.balance.to_le_bytes());
+    let digest = hasher.finalize_array();
+    cv_from_bytes(digest)
+}
+
+/// Tests building trees over arbitrary items with a caller-supplied leaf digest
+/// Verifies that roots are deterministic, match a tree built from each digest hashed as its
+/// chunk, change with an item's content or position, and that proofs and verify_chunk accept
+/// the digest bytes; an empty item list is rejected
+/// Methods tested: BinaryMerkleTree::from_leaf_data, verify_chunk, generate_proof
+#[test]
+fn test_from_leaf_data_over_structs() {
+    let records: Vec<Record> = (0..37).map(|id| Record { id, balance: u64::from(id) * 1000 + 7 }).collect();
+    let tree = BinaryMerkleTree::from_leaf_data(&records, record_digest, IV, FLAGS).unwrap();
+    assert_eq!(tree.actual_leaves(), records.len());
+    assert_eq!(tree.root_hash(), BinaryMerkleTree::from_leaf_data(&records, record_digest, IV, FLAGS).unwrap().root_hash());
+
+    let digest_bytes = |record: &Record| cv_to_bytes(record_digest(record));
+    let leaves: Vec<Output> = records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            chunk_output(&digest_bytes(record), i as u64, IV, FLAGS).unwrap()
+        })
+        .collect();
+    assert_eq!(tree.root_hash(), BinaryMerkleTree::new_from_leaves(leaves, IV, FLAGS).root_hash());
+    for (i, record) in records.iter().enumerate() {
+        assert!(tree.verify_chunk(i, &digest_bytes(record)));
+        assert!(tree.generate_proof(i).unwrap().verify(&tree.root_hash(), IV, FLAGS));
+    }
+
+    let mut changed = records.iter().map(|r| Record { id: r.id, balance: r.balance }).collect::<Vec<_>>();
+    changed[20].balance += 1;
+    assert_ne!(BinaryMerkleTree::from_leaf_data(&changed, record_digest, IV, FLAGS).unwrap().root_hash(), tree.root_hash());
+    changed[20].balance -= 1;
+    changed.swap(3, 4);
+    assert_ne!(BinaryMerkleTree::from_leaf_data(&changed, record_digest, IV, FLAGS).unwrap().root_hash(), tree.root_hash(),
+        "Swapping two items must change the root");
+
+    let single = BinaryMerkleTree::from_leaf_data(&records[..1], record_digest, IV, FLAGS).unwrap();
+    assert!(single.verify_chunk(0, &digest_bytes(&records[0])));
+    assert!(matches!(BinaryMerkleTree::from_leaf_data(&[] as &[Record], record_digest, IV, FLAGS), Err(MerkleError::NoLeaves)));
+}
+
+/// Tests that a chunk fed in pieces hashes like the chunk fed at once
+/// Verifies the leaf Output and root hash of full and partial chunks, fed in
+/// 17-byte increments and in pieces that end exactly on or just past block boundaries,
+/// against a single update and the `blake3` crate
+/// Methods tested: ChunkState::update, ChunkState::output
+#[test]
+fn test_chunk_state_split_updates() {
+    let mut rng = SeededRng::new();
+    let chunk: Vec<u8> = (0..CHUNK_LEN).map(|_| rng.gen()).collect();
+    for len in [0, 1, 17, 63, 64, 65, 128, 1000, 1023, CHUNK_LEN] {
+        let data = &chunk[..len];
+        let mut whole = ChunkState::new(IV, 0, FLAGS);
+        whole.update(data);
+        for piece_len in [17, 1, 64, 65] {
+            let mut pieces = ChunkState::new(IV, 0, FLAGS);
+            for piece in data.chunks(piece_len) {
+                pieces.update(piece);
+            }
+            assert_eq!(pieces.len(), len);
+            assert_eq!(pieces.output(), whole.output(), "{} bytes in {}-byte pieces", len, piece_len);
+        }
+        let mut hash = [0; 32];
+        let mut root = whole.output();
+        root.flags |= ROOT;
+        root.root_output_bytes(&mut hash);
+        assert_eq!(hash, *blake3::hash(data).as_bytes(), "{} bytes", len);
+    }
+}
+
+/// Tests comparing trees by root and node by node
+/// Verifies equal inputs compare equal either way, different inputs, shapes or keys do not,
+/// and a tree whose leaf was overwritten in its store without updating the ancestors keeps
+/// its root but is told apart only by the full comparison
+/// Methods tested: BinaryMerkleTree::deep_equal
+#[test]
+fn test_deep_equal() {
+    let input: Vec<u8> = (0..5 * CHUNK_LEN + 9).map(|i| (i % 251) as u8).collect();
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let same = BinaryMerkleTree::new_from_leaves(chunk_leaves(&input, IV, FLAGS), IV, FLAGS);
+    assert!(tree.deep_equal(&same, false) && tree.deep_equal(&same, true));
+
+    let mut changed = input.clone();
+    changed[3 * CHUNK_LEN] ^= 1;
+    assert!(!tree.deep_equal(&BinaryMerkleTree::from_input(&changed, IV, FLAGS), false));
+    assert!(!tree.deep_equal(&BinaryMerkleTree::from_input(&input[..4 * CHUNK_LEN], IV, FLAGS), true));
+    assert!(!tree.deep_equal(&BinaryMerkleTree::from_input(&input, [1; 8], FLAGS), true));
+
+    let mut crafted = tree.snapshot();
+    let leaf_position = crafted.heap_index(2).unwrap();
+    crafted.store_mut().put(leaf_position, chunk_output(&[9; CHUNK_LEN], 2, IV, FLAGS).unwrap());
+    assert_eq!(crafted.root_hash(), tree.root_hash());
+    assert!(tree.deep_equal(&crafted, false));
+    assert!(!tree.deep_equal(&crafted, true));
+    assert!(!crafted.verify_integrity());
+}
+
+/// Tests the reported memory size of a tree
+/// Verifies it grows with the padded heap, about two Outputs per leaf, stays within the heap
+/// plus a small overhead, and is several times that of the same tree's flat chaining values
+/// Methods tested: BinaryMerkleTree::heap_size_bytes, FlatTree::heap_size_bytes
+#[test]
+fn test_heap_size_bytes() {
+    for chunks in [1, 2, 5, 64, 100, 1024] {
+        let tree = BinaryMerkleTree::from_input(&vec![7; chunks * CHUNK_LEN], IV, FLAGS);
+        let heap_bytes = 2 * tree.num_leaves() * std::mem::size_of::<Output>();
+        let size = tree.heap_size_bytes();
+        assert!(size >= heap_bytes, "{} bytes reported for a heap of {} bytes", size, heap_bytes);
+        assert!(size <= heap_bytes + 1024, "{} bytes reported for a heap of {} bytes", size, heap_bytes);
+
+        let flat = FlatTree::from_flat_cvs(&tree.to_flat_cvs(), chunks, IV, FLAGS).unwrap();
+        if chunks >= 64 {
+            assert!(flat.heap_size_bytes() * 3 < size);
+        }
+    }
+    let small = BinaryMerkleTree::from_input(&vec![7; 512 * CHUNK_LEN], IV, FLAGS).heap_size_bytes();
+    let large = BinaryMerkleTree::from_input(&vec![7; 1024 * CHUNK_LEN], IV, FLAGS).heap_size_bytes();
+    assert!((1.9..2.1).contains(&(large as f64 / small as f64)));
+}
+
+fn assert_sync<T: Sync>() {}
+
+/// Tests sharing a tree between threads
+/// Verifies the in-memory trees are `Sync`, and that proofs generated on several threads at
+/// once from one shared tree equal those generated on one thread and verify
+/// Methods tested: BinaryMerkleTree::generate_proof, BinaryMerkleTree::root_hash
+#[test]
+fn test_concurrent_proof_generation() {
+    assert_sync::<BinaryMerkleTree>();
+    assert_sync::<merkle_tree::binary_merkle_tree::SnapshotTree>();
+
+    let input: Vec<u8> = (0..100 * CHUNK_LEN + 7).map(|i| (i % 253) as u8).collect();
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let expected: Vec<_> = (0..tree.actual_leaves()).map(|leaf_index| tree.generate_proof(leaf_index).unwrap()).collect();
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            let (tree, expected) = (&tree, &expected);
+            scope.spawn(move || {
+                for leaf_index in (thread..tree.actual_leaves()).step_by(4) {
+                    let proof = tree.generate_proof(leaf_index).unwrap();
+                    assert_eq!(proof, expected[leaf_index]);
+                    assert!(proof.verify(&tree.root_hash(), IV, FLAGS));
+                }
+            });
+        }
+    });
+}
+
+/// Tests the seeded generator the fuzz tests draw their inputs from
+/// Verifies that a seed reproduces the same inputs every time, and another seed does not
+/// Methods tested: SeededRng::new, SeededRng::from_seed
+#[test]
+fn test_seeded_rng_reproduces_inputs() {
+    let seed = SeededRng::new().seed();
+    let generate = |seed| {
+        let mut rng = SeededRng::from_seed(seed);
+        let input: Vec<u8> = (0..FUZZ_BYTES_SIZE).map(|_| rng.gen()).collect();
+        (rng.gen_range(0..input.len()), input)
+    };
+    let (mutation_index, input) = generate(seed);
+    assert_eq!(generate(seed), (mutation_index, input.clone()));
+    assert_ne!(generate(seed.wrapping_add(1)).1, input);
+}
+
+/// Tests building a large tree, whose parents are hashed straight into their slots
+/// Verifies that every node of a 100k-leaf tree equals the one parent_output folds,
+/// and reports how long each took
+/// Methods tested: BinaryMerkleTree::new_from_leaves, BinaryMerkleTree::node_at
+#[test]
+fn test_large_tree_parents_match_parent_output() {
+    const LEAVES: usize = 100_000;
+    let leaves: Vec<Output> = (0..LEAVES)
+        .map(|chunk_index| {
+            chunk_output(&(chunk_index as u32).to_le_bytes(), chunk_index as u64, IV, FLAGS).unwrap()
+        })
+        .collect();
+
+    let build_start = Instant::now();
+    let tree = BinaryMerkleTree::new_from_leaves(leaves.clone(), IV, FLAGS);
+    let build_duration = build_start.elapsed();
+
+    // Each level folded with parent_output, a lone left node promoted
+    let fold_start = Instant::now();
+    let mut levels = vec![leaves];
+    while levels.last().unwrap().len() > 1 {
+        let level = levels.last().unwrap();
+        let parents = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => parent_output(left.chaining_value(), right.chaining_value(), IV, FLAGS),
+                _ => pair[0],
+            })
+            .collect();
+        levels.push(parents);
+    }
+    let fold_duration = fold_start.elapsed();
+    println!("Building {} leaves in place took: {:?}, folding with parent_output took: {:?}",
+             LEAVES, build_duration, fold_duration);
+
+    let mut level_start = tree.num_leaves();
+    for level in &levels {
+        for (i, node) in level.iter().enumerate() {
+            assert_eq!(tree.node_at(HeapIndex(level_start + i)), Some(*node), "node {} differs", level_start + i);
+        }
+        level_start /= 2;
+    }
+    assert_eq!(level_start, 0);
+}
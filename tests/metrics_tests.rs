@@ -0,0 +1,56 @@
+#![cfg(feature = "metrics")]
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, ChunkState, CHUNK_LEN, FLAGS, IV};
+use merkle_tree::metrics::{measure, snapshot, Metrics};
+
+fn sample_input(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Tests the work counted for building trees
+/// Verifies 15 block compressions per full chunk, one parent per pair of subtrees, and two
+/// chaining values computed per parent
+/// Methods tested: measure, BinaryMerkleTree::from_input
+#[test]
+fn test_build_counts() {
+    let input = sample_input(8 * CHUNK_LEN);
+    let (_, one_chunk) = measure(|| BinaryMerkleTree::from_input(&input[..CHUNK_LEN], IV, FLAGS));
+    assert_eq!(one_chunk, Metrics { compressions: 15, chunks: 1, parents: 0, bytes: 1024 });
+
+    let (tree, eight_chunks) = measure(|| BinaryMerkleTree::from_input(&input, IV, FLAGS));
+    assert_eq!(eight_chunks, Metrics { compressions: 8 * 15 + 7 * 2, chunks: 8, parents: 7, bytes: 8192 });
+
+    let (_, root) = measure(|| tree.root_hash());
+    assert_eq!(root, Metrics { compressions: 1, ..Metrics::default() });
+}
+
+/// Tests the work counted for incremental updates of an 8-leaf tree
+/// Verifies a single-leaf update recomputes exactly 3 parents, and a batch of two sibling
+/// leaves shares them
+/// Methods tested: measure, BinaryMerkleTree::insert_leaf, BinaryMerkleTree::bulk_insert_leaves
+#[test]
+fn test_update_counts() {
+    let mut tree = BinaryMerkleTree::from_input(&sample_input(8 * CHUNK_LEN), IV, FLAGS);
+    let leaf = ChunkState::new(IV, 3, FLAGS).output();
+
+    let (_, single) = measure(|| tree.insert_leaf(3, leaf).unwrap());
+    assert_eq!(single, Metrics { compressions: 6, parents: 3, ..Metrics::default() });
+
+    let (_, siblings) = measure(|| tree.bulk_insert_leaves([0, 1].into_iter(), [leaf, leaf].into_iter()).unwrap());
+    assert_eq!(siblings, Metrics { compressions: 6, parents: 3, ..Metrics::default() });
+
+    let (_, spread) = measure(|| tree.bulk_insert_leaves([0, 7].into_iter(), [leaf, leaf].into_iter()).unwrap());
+    assert_eq!(spread.parents, 5, "Two paths meet only at the root");
+}
+
+/// Tests that counters only grow and are kept per thread
+/// Methods tested: snapshot
+#[test]
+fn test_counters_are_per_thread() {
+    let before = snapshot();
+    std::thread::spawn(|| BinaryMerkleTree::from_input(&sample_input(4 * CHUNK_LEN), IV, FLAGS)).join().unwrap();
+    assert_eq!(snapshot(), before);
+
+    BinaryMerkleTree::from_input(&sample_input(100), IV, FLAGS);
+    assert_eq!(snapshot() - before, Metrics { compressions: 1, chunks: 1, parents: 0, bytes: 100 });
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, Hash32, LeafCv, CHUNK_LEN, FLAGS, IV, KEYED_HASH};
+use merkle_tree::mmr::{bag_peaks, StreamingRoot};
+use common::chunk_leaves;
+
+const LEAF_COUNTS: [usize; 9] = [1, 2, 3, 4, 5, 7, 8, 13, 33];
+
+fn sample_input(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Tests bagging the hasher's peaks
+/// Verifies the bag equals the root chaining value of the tree over the complete chunks,
+/// and that a trailing partial chunk is left out
+/// Methods tested: Blake3Hasher::mmr_bag, BinaryMerkleTree::mmr_peaks, bag_peaks
+#[test]
+fn test_mmr_bag_matches_tree_root() {
+    let mut hasher = Blake3Hasher::new();
+    assert_eq!(hasher.mmr_bag(), None);
+    hasher.update(&sample_input(CHUNK_LEN - 1));
+    assert_eq!(hasher.mmr_bag(), None, "A partial chunk is not a leaf yet");
+
+    for &chunks in &LEAF_COUNTS {
+        let input = sample_input(chunks * CHUNK_LEN);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let mut hasher = Blake3Hasher::new();
+        hasher.update(&input);
+        assert_eq!(hasher.mmr_bag(), Some(tree.root_cv_and_bytes().0), "{} chunks", chunks);
+        assert_eq!(tree.mmr_peaks(), hasher.peaks(), "{} chunks", chunks);
+        assert_eq!(tree.mmr_peaks().len(), chunks.count_ones() as usize);
+        assert_eq!(bag_peaks(&tree.mmr_peaks(), IV, FLAGS), hasher.mmr_bag());
+
+        hasher.update(&[1; 100]);
+        assert_eq!(hasher.mmr_bag(), Some(tree.root_cv_and_bytes().0), "{} chunks and a partial one", chunks);
+    }
+}
+
+/// Tests MMR proofs for every leaf
+/// Verifies each proof checks against the hasher's bag and the tree's root CV, with a
+/// sibling per level of the leaf's peak and every other peak
+/// Methods tested: BinaryMerkleTree::mmr_proof, MmrProof::verify
+#[test]
+fn test_mmr_proofs_verify() {
+    for &chunks in &LEAF_COUNTS {
+        let input = sample_input(chunks * CHUNK_LEN);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let mut hasher = Blake3Hasher::new();
+        hasher.update(&input);
+        let bag = hasher.mmr_bag().unwrap();
+
+        for leaf_index in 0..chunks {
+            let proof = tree.mmr_proof(leaf_index).unwrap();
+            assert!(proof.verify(bag, IV, FLAGS), "Leaf {} of {}", leaf_index, chunks);
+            assert_eq!(proof.bag(IV, FLAGS), Some(tree.root_cv_and_bytes().0));
+            assert_eq!(proof.other_peaks.len() + 1, chunks.count_ones() as usize);
+        }
+        assert_eq!(tree.mmr_proof(chunks), None);
+    }
+}
+
+/// Tests rejecting bad MMR proofs
+/// Verifies a different leaf, a moved index, a tampered peak and wrong path lengths fail
+/// Methods tested: MmrProof::verify, MmrProof::bag
+#[test]
+fn test_mmr_proof_rejects_tampering() {
+    let tree = BinaryMerkleTree::from_input(&sample_input(13 * CHUNK_LEN), IV, FLAGS);
+    let bag = tree.root_cv_and_bytes().0;
+    let proof = tree.mmr_proof(9).unwrap();
+    assert!(proof.verify(bag, IV, FLAGS));
+
+    let mut wrong_leaf = proof.clone();
+    wrong_leaf.leaf = tree.leaf_cv(8).unwrap();
+    assert!(!wrong_leaf.verify(bag, IV, FLAGS));
+
+    let mut moved = proof.clone();
+    moved.leaf_index = 8;
+    assert!(!moved.verify(bag, IV, FLAGS));
+
+    let mut tampered_peak = proof.clone();
+    tampered_peak.other_peaks[0][0] ^= 1;
+    assert!(!tampered_peak.verify(bag, IV, FLAGS));
+
+    let mut short = proof.clone();
+    short.siblings.pop();
+    assert_eq!(short.bag(IV, FLAGS), None);
+
+    let mut missing_peak = proof.clone();
+    missing_peak.other_peaks.pop();
+    assert_eq!(missing_peak.bag(IV, FLAGS), None);
+
+    let mut out_of_range = proof;
+    out_of_range.leaf_index = 13;
+    out_of_range.leaf = LeafCv::assume_leaf([0; 8]);
+    assert_eq!(out_of_range.bag(IV, FLAGS), None);
+}
+
+/// Tests the root of every prefix of a stream of leaves
+/// Verifies each root returned by push equals a fresh hasher's over the same chunks, including a
+/// short final chunk, and the tree root over them in keyed mode
+/// Methods tested: StreamingRoot::push, StreamingRoot::root, StreamingRoot::root_hash
+#[test]
+fn test_streaming_root_matches_prefix_hashes() {
+    let input = sample_input(40 * CHUNK_LEN + 100);
+    let leaves = chunk_leaves(&input, IV, FLAGS);
+    let mut streaming_root = StreamingRoot::new(IV, FLAGS);
+    assert_eq!(streaming_root.root(), None);
+    assert_eq!(streaming_root.root_hash(), None);
+
+    for (leaf_index, &leaf) in leaves.iter().enumerate() {
+        let prefix = &input[..((leaf_index + 1) * CHUNK_LEN).min(input.len())];
+        let mut hasher = Blake3Hasher::new();
+        hasher.update(prefix);
+        let mut expected = [0; 32];
+        hasher.finalize(&mut expected);
+        assert_eq!(streaming_root.push(leaf), Hash32(expected), "after {} leaves", leaf_index + 1);
+        assert_eq!(streaming_root.leaf_count(), leaf_index + 1);
+    }
+
+    let key_words = [7; 8];
+    let leaves = chunk_leaves(&input, key_words, KEYED_HASH);
+    let mut streaming_root = StreamingRoot::new(key_words, KEYED_HASH);
+    for leaf_count in 1..=leaves.len() {
+        streaming_root.push(leaves[leaf_count - 1]);
+        let tree = BinaryMerkleTree::new_from_leaves(leaves[..leaf_count].to_vec(), key_words, KEYED_HASH);
+        assert_eq!(streaming_root.root(), Some(tree.root()));
+    }
+}
@@ -0,0 +1,133 @@
+#![cfg(feature = "multihash")]
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Hash32, FLAGS, IV};
+use merkle_tree::multihash::{Base, MultihashError};
+
+// (input_len, multihash hex, base32 lower, base58btc) for BLAKE3 test vector
+// inputs, where byte i is i % 251. The digests are from BLAKE3's test_vectors.json;
+// the encodings were produced by hand from the multihash and multibase specs, as
+// go-multihash would write them.
+const FIXTURES: [(usize, &str, &str, &str); 3] = [
+    (
+        0,
+        "1e20af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+        "bdyqk6e2jxh27tingubae32rw3teutg6lexe23qisw7gjve6k4qpteyq",
+        "zgW9T5gSXhgQB5LaqmnJrLVVpHawhM7youzHnuY6HyGBDy3",
+    ),
+    (
+        1024,
+        "1e2042214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+        "bdyqeeikhhhyjljag6p6ihxvyrf2evqan7ay4cdnkkumjwxisdscvv5y",
+        "zgW27occS1LM7JtwDyRLVoLHLPQ2KGUXKpJtEVS6dDoBKm8",
+    ),
+    (
+        4097,
+        "1e209b4052b38f1c5fc8b1f9ff7ac7b27cd242487b3d890d15c96a1c25b8aa0fb995",
+        "bdyqjwqcswohryx6iwh4766whwj6neqsipm6ysdivzfvbyjnyvih3tfi",
+        "zgW87hMcAiKU3MjtvBxopXHxAdHgdeEh2LphDcUKJPhicWp",
+    ),
+];
+
+fn vector_input(input_len: usize) -> Vec<u8> {
+    (0..input_len).map(|i| (i % 251) as u8).collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sample_root() -> Hash32 {
+    BinaryMerkleTree::from_input(&vector_input(3000), IV, FLAGS).root_hash()
+}
+
+/// Tests encoding tree roots against reference multihash and multibase strings
+/// Verifies the multihash bytes and base32/base58btc strings match the fixtures
+/// Methods tested: to_multihash, to_multibase
+#[test]
+fn test_roots_encode_to_fixtures() {
+    for &(input_len, multihash, base32, base58) in FIXTURES.iter() {
+        let root = BinaryMerkleTree::from_input(&vector_input(input_len), IV, FLAGS).root_hash();
+        assert_eq!(hex(&root.to_multihash()), multihash, "input_len {}", input_len);
+        assert_eq!(root.to_multibase(Base::Base32Lower), base32, "input_len {}", input_len);
+        assert_eq!(root.to_multibase(Base::Base58Btc), base58, "input_len {}", input_len);
+    }
+}
+
+/// Tests decoding the reference strings
+/// Verifies each fixture decodes to the root of its input
+/// Methods tested: from_multibase
+#[test]
+fn test_fixtures_decode_to_roots() {
+    for &(input_len, _, base32, base58) in FIXTURES.iter() {
+        let root = BinaryMerkleTree::from_input(&vector_input(input_len), IV, FLAGS).root_hash();
+        assert_eq!(Hash32::from_multibase(base32), Ok(root));
+        assert_eq!(Hash32::from_multibase(base58), Ok(root));
+    }
+}
+
+/// Tests round trips through every encoding
+/// Verifies a root survives multihash bytes and several multibase bases
+/// Methods tested: to_multihash, from_multihash, to_multibase, from_multibase
+#[test]
+fn test_round_trips() {
+    let root = sample_root();
+    assert_eq!(Hash32::from_multihash(&root.to_multihash()), Ok(root));
+    for base in [Base::Base16Lower, Base::Base32Lower, Base::Base36Lower, Base::Base58Btc, Base::Base64Url] {
+        assert_eq!(Hash32::from_multibase(&root.to_multibase(base)), Ok(root), "{:?}", base);
+    }
+}
+
+/// Tests rejecting multihashes that are not a 32-byte BLAKE3 digest
+/// Verifies wrong codes and lengths, truncation and trailing bytes give their errors
+/// Methods tested: from_multihash
+#[test]
+fn test_invalid_multihashes() {
+    let encoded = sample_root().to_multihash();
+
+    // SHA2-256
+    let mut sha256 = encoded.clone();
+    sha256[0] = 0x12;
+    assert_eq!(Hash32::from_multihash(&sha256), Err(MultihashError::WrongCode { code: 0x12 }));
+
+    let mut short_digest = encoded.clone();
+    short_digest[1] = 16;
+    assert_eq!(Hash32::from_multihash(&short_digest), Err(MultihashError::WrongLength { len: 16 }));
+
+    assert_eq!(Hash32::from_multihash(&encoded[..20]), Err(MultihashError::Truncated));
+    assert_eq!(Hash32::from_multihash(&[]), Err(MultihashError::Truncated));
+
+    let mut trailing = encoded.clone();
+    trailing.extend_from_slice(&[0, 0]);
+    assert_eq!(Hash32::from_multihash(&trailing), Err(MultihashError::TrailingBytes(2)));
+}
+
+/// Tests varint validation
+/// Verifies truncated, non-minimal and overlong varints are rejected
+/// Methods tested: from_multihash
+#[test]
+fn test_invalid_varints() {
+    // The continuation bit is set on the last byte
+    assert_eq!(Hash32::from_multihash(&[0x9e]), Err(MultihashError::Truncated));
+    assert_eq!(Hash32::from_multihash(&[0x1e, 0xa0]), Err(MultihashError::Truncated));
+
+    // 0x1e padded to two bytes
+    let mut non_minimal = vec![0x9e, 0x00, 0x20];
+    non_minimal.extend_from_slice(sample_root().as_bytes());
+    assert_eq!(Hash32::from_multihash(&non_minimal), Err(MultihashError::InvalidVarint));
+
+    assert_eq!(Hash32::from_multihash(&[0xff; 12]), Err(MultihashError::InvalidVarint));
+}
+
+/// Tests rejecting malformed multibase strings
+/// Verifies unknown prefixes and bad characters give multibase errors
+/// Methods tested: from_multibase
+#[test]
+fn test_invalid_multibase() {
+    assert!(matches!(Hash32::from_multibase("!abc"), Err(MultihashError::Multibase(_))));
+    assert!(matches!(Hash32::from_multibase("z0OIl"), Err(MultihashError::Multibase(_))));
+    assert!(matches!(Hash32::from_multibase(""), Err(MultihashError::Multibase(_))));
+
+    // Valid base16 of a SHA2-256 multihash
+    let sha256 = format!("f1220{}", "07".repeat(32));
+    assert_eq!(Hash32::from_multibase(&sha256), Err(MultihashError::WrongCode { code: 0x12 }));
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use std::collections::HashMap;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, GenericMerkleTree, HeapIndex, Output, CHUNK_LEN, IV, FLAGS};
+use merkle_tree::node_store::{NodeId, NodeStore};
+use common::{chunk_leaves, random_input};
+
+/// A sparse store holding only the nodes that were written, as a database-backed
+/// store would.
+#[derive(Debug, Default)]
+struct HashMapStore {
+    nodes: HashMap<usize, Output>,
+}
+
+impl NodeStore for HashMapStore {
+    fn get(&self, id: NodeId) -> Output {
+        self.nodes[&id.0]
+    }
+
+    fn put(&mut self, id: NodeId, output: Output) {
+        self.nodes.insert(id.0, output);
+    }
+
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+/// Tests that the node store does not change what the tree computes
+/// Verifies that a HashMap-backed tree matches the Vec-backed tree in roots and proofs after
+/// construction, single inserts and bulk inserts, and that it only stores real nodes
+/// Methods tested: GenericMerkleTree::new_from_leaves_in, insert_leaf, bulk_insert_leaves,
+/// generate_proof, verify_integrity
+#[test]
+fn test_hash_map_store_matches_vec_store() {
+    for &chunks in &[1, 2, 3, 5, 8, 13, 37] {
+        let input = random_input(chunks * CHUNK_LEN - 7);
+        let leaves = chunk_leaves(&input, IV, FLAGS);
+        let mut vec_tree = BinaryMerkleTree::new_from_leaves(leaves.clone(), IV, FLAGS);
+        let mut map_tree = GenericMerkleTree::new_from_leaves_in(HashMapStore::default(), leaves, IV, FLAGS);
+
+        // Only real nodes are written: the leaves plus the parents of each level. A
+        // single leaf is also the root, at the same heap index
+        let mut real_nodes = chunks;
+        let mut nodes_in_level = chunks;
+        while nodes_in_level > 1 {
+            nodes_in_level = nodes_in_level.div_ceil(2);
+            real_nodes += nodes_in_level;
+        }
+        assert_eq!(map_tree.store().len(), real_nodes);
+
+        let check = |vec_tree: &BinaryMerkleTree, map_tree: &GenericMerkleTree<HashMapStore>| {
+            assert_eq!(vec_tree.root_hash(), map_tree.root_hash());
+            assert!(map_tree.verify_integrity());
+            for leaf_index in 0..chunks {
+                let proof = map_tree.generate_proof(leaf_index).unwrap();
+                assert_eq!(Some(&proof), vec_tree.generate_proof(leaf_index).as_ref());
+                assert!(proof.verify(&vec_tree.root_hash(), IV, FLAGS));
+            }
+        };
+        check(&vec_tree, &map_tree);
+
+        let new_leaves = chunk_leaves(&random_input(chunks * CHUNK_LEN), IV, FLAGS);
+        vec_tree.insert_leaf(chunks / 2, new_leaves[chunks / 2]).unwrap();
+        map_tree.insert_leaf(chunks / 2, new_leaves[chunks / 2]).unwrap();
+        check(&vec_tree, &map_tree);
+
+        let indices: Vec<usize> = (0..chunks).step_by(2).collect();
+        let outputs: Vec<Output> = indices.iter().map(|&i| new_leaves[i]).collect();
+        vec_tree.bulk_insert_leaves(indices.iter().copied(), outputs.iter().copied()).unwrap();
+        map_tree.bulk_insert_leaves(indices.iter().copied(), outputs.iter().copied()).unwrap();
+        check(&vec_tree, &map_tree);
+    }
+}
+
+/// A store counting batch reads, to check proofs use one round trip.
+struct CountingStore(HashMapStore, std::cell::Cell<usize>);
+
+impl NodeStore for CountingStore {
+    fn get(&self, id: NodeId) -> Output {
+        self.0.get(id)
+    }
+
+    fn put(&mut self, id: NodeId, output: Output) {
+        self.0.put(id, output)
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn get_batch(&self, ids: &[NodeId]) -> Vec<Output> {
+        self.1.set(self.1.get() + 1);
+        ids.iter().map(|&id| self.0.get(id)).collect()
+    }
+}
+
+/// Tests that a proof reads its siblings in a single batch
+/// Methods tested: GenericMerkleTree::generate_proof, NodeStore::get_batch
+#[test]
+fn test_proof_reads_siblings_in_one_batch() {
+    let leaves = chunk_leaves(&random_input(100 * CHUNK_LEN), IV, FLAGS);
+    let store = CountingStore(HashMapStore::default(), std::cell::Cell::new(0));
+    let tree = GenericMerkleTree::new_from_leaves_in(store, leaves, IV, FLAGS);
+    let proof = tree.generate_proof(41).unwrap();
+    assert_eq!(proof.siblings.len(), 7);
+    assert_eq!(tree.store().1.get(), 1);
+    assert_eq!(tree.store().get(HeapIndex(128 + 41)), proof.leaf);
+}
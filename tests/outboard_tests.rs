@@ -0,0 +1,104 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{cv_to_bytes, parent_cv, BinaryMerkleTree, CHUNK_END, CHUNK_LEN, IV, FLAGS};
+use merkle_tree::io::write_outboard;
+use std::io;
+use common::random_input;
+
+// Bytes per leaf record (full chunk Output) and per parent record (chaining value)
+const LEAF_RECORD_LEN: usize = 112;
+const PARENT_RECORD_LEN: usize = 32;
+
+const OUTBOARD_SIZES: [usize; 8] = [0, 1, CHUNK_LEN, CHUNK_LEN + 1, 3 * CHUNK_LEN, 8 * CHUNK_LEN, 37 * CHUNK_LEN + 123, 200 * CHUNK_LEN + 5];
+
+fn assert_same_tree(actual: &BinaryMerkleTree, expected: &BinaryMerkleTree) {
+    assert_eq!(actual.actual_leaves(), expected.actual_leaves());
+    for leaf_index in 0..expected.actual_leaves() {
+        assert_eq!(actual.leaf(leaf_index), expected.leaf(leaf_index), "Leaf {} differs", leaf_index);
+    }
+    assert_eq!(actual.root(), expected.root());
+    assert!(actual.verify_integrity());
+}
+
+/// Tests writing an outboard while streaming and reloading it without the input
+/// Verifies the returned root, the outboard size, and that the reloaded tree is identical to from_input
+/// Methods tested: write_outboard, BinaryMerkleTree::from_outboard
+#[test]
+fn test_outboard_round_trip() {
+    for &size in OUTBOARD_SIZES.iter() {
+        let input = random_input(size);
+        let expected = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+
+        let mut outboard = Vec::new();
+        let root_hash = write_outboard(&input[..], &mut outboard, IV, FLAGS).unwrap();
+        assert_eq!(root_hash, expected.root_hash(), "Outboard root mismatch for size {}", size);
+
+        let leaves = expected.actual_leaves();
+        assert_eq!(outboard.len(), leaves * LEAF_RECORD_LEN + (leaves - 1) * PARENT_RECORD_LEN);
+
+        let reloaded = BinaryMerkleTree::from_outboard(&outboard[..], size as u64, IV, FLAGS).unwrap();
+        assert_same_tree(&reloaded, &expected);
+    }
+}
+
+/// Tests the post-order record layout for a three-chunk input
+/// Verifies leaf 0, leaf 1, their parent, leaf 2, then the root parent
+/// Methods tested: write_outboard
+#[test]
+fn test_outboard_post_order() {
+    let input = random_input(3 * CHUNK_LEN);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let mut outboard = Vec::new();
+    write_outboard(&input[..], &mut outboard, IV, FLAGS).unwrap();
+
+    let first_parent_offset = 2 * LEAF_RECORD_LEN;
+    let root_offset = 3 * LEAF_RECORD_LEN + PARENT_RECORD_LEN;
+    let first_parent = parent_cv(tree.leaf(0).unwrap().chaining_value(), tree.leaf(1).unwrap().chaining_value(), IV, FLAGS);
+    // Parent records hold non-root chaining values, including the root's
+    let root = parent_cv(first_parent, tree.leaf(2).unwrap().chaining_value(), IV, FLAGS);
+    assert_eq!(outboard[first_parent_offset..first_parent_offset + PARENT_RECORD_LEN], cv_to_bytes(first_parent)[..]);
+    assert_eq!(outboard[root_offset..], cv_to_bytes(root)[..]);
+}
+
+/// Tests that reloading rejects tampered, truncated and mismatched outboards, including the
+/// largest input length, and a lone leaf that is not a final chunk
+/// Methods tested: BinaryMerkleTree::from_outboard
+#[test]
+fn test_outboard_rejects_bad_input() {
+    let input = random_input(37 * CHUNK_LEN + 123);
+    let mut outboard = Vec::new();
+    write_outboard(&input[..], &mut outboard, IV, FLAGS).unwrap();
+
+    // A flipped bit in the final (root) parent record
+    let mut tampered = outboard.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 0x01;
+    let err = BinaryMerkleTree::from_outboard(&tampered[..], input.len() as u64, IV, FLAGS).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // A flipped bit in the first leaf changes its CV, which no longer matches its parent
+    let mut tampered = outboard.clone();
+    tampered[0] ^= 0x01;
+    let err = BinaryMerkleTree::from_outboard(&tampered[..], input.len() as u64, IV, FLAGS).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let err = BinaryMerkleTree::from_outboard(&outboard[..outboard.len() - 1], input.len() as u64, IV, FLAGS).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+    // Same chunk count, different final chunk length
+    let err = BinaryMerkleTree::from_outboard(&outboard[..], input.len() as u64 - 100, IV, FLAGS).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // The largest input length allocates nothing up front and fails on the first
+    // missing record
+    let err = BinaryMerkleTree::from_outboard(&outboard[..0], u64::MAX, IV, FLAGS).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+    // A lone leaf has no parent to check it against, so its own fields must fit
+    let mut single = Vec::new();
+    write_outboard(&input[..100], &mut single, IV, FLAGS).unwrap();
+    assert!(BinaryMerkleTree::from_outboard(&single[..], 100, IV, FLAGS).is_ok());
+    single[108] &= !(CHUNK_END as u8);
+    let err = BinaryMerkleTree::from_outboard(&single[..], 100, IV, FLAGS).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,359 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{
+    chunk_output, cv_from_bytes, cv_to_bytes, leaf_for_input_chunk, parent_output, BinaryMerkleTree, Blake3Hasher, ChunkState, InvalidOutputBytes, Output, CHUNK_END, CHUNK_LEN, CHUNK_START,
+    DERIVE_KEY_CONTEXT, DERIVE_KEY_MATERIAL, FLAGS, IV, KEYED_HASH, OUTPUT_BYTES_LEN, PARENT, ROOT,
+};
+use blake3::hazmat::HasherExt;
+use merkle_tree::error::MerkleError;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use common::{chunk_leaves, random_input};
+
+/// Tests the canonical byte encoding of Output
+/// Verifies round trips for full, partial and empty chunks and the documented field offsets
+/// Methods tested: Output::to_bytes, Output::try_from
+#[test]
+fn test_output_bytes_round_trip() {
+    let input = random_input(3 * CHUNK_LEN + 77);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let empty_leaf = BinaryMerkleTree::from_input(&[], IV, FLAGS).leaf(0).unwrap();
+    let leaves = (0..tree.actual_leaves()).map(|i| tree.leaf(i).unwrap()).chain([empty_leaf, tree.root()]);
+
+    for output in leaves {
+        let bytes = output.to_bytes();
+        assert_eq!(bytes.len(), OUTPUT_BYTES_LEN);
+        assert_eq!(bytes[..4], output.input_chaining_value[0].to_le_bytes());
+        assert_eq!(bytes[32..36], output.block_words[0].to_le_bytes());
+        assert_eq!(bytes[96..104], output.counter.to_le_bytes());
+        assert_eq!(bytes[104..108], output.block_len.to_le_bytes());
+        assert_eq!(bytes[108..], output.flags.to_le_bytes());
+        assert_eq!(Output::try_from(&bytes[..]), Ok(output));
+    }
+}
+
+/// Tests that decoding rejects wrong lengths and impossible block lengths
+/// Methods tested: Output::try_from
+#[test]
+fn test_output_bytes_validation() {
+    let bytes = BinaryMerkleTree::from_input(&random_input(100), IV, FLAGS).leaf(0).unwrap().to_bytes();
+    assert_eq!(Output::try_from(&bytes[..OUTPUT_BYTES_LEN - 1]), Err(InvalidOutputBytes::Length(OUTPUT_BYTES_LEN - 1)));
+    assert_eq!(Output::try_from(&[bytes.as_slice(), &[0]].concat()[..]), Err(InvalidOutputBytes::Length(OUTPUT_BYTES_LEN + 1)));
+
+    let mut bad_block_len = bytes;
+    bad_block_len[104..108].copy_from_slice(&65u32.to_le_bytes());
+    assert_eq!(Output::try_from(&bad_block_len[..]), Err(InvalidOutputBytes::BlockLen(65)));
+}
+
+/// Tests shipping leaves as bytes between a chunk-hashing worker and the tree builder
+/// Verifies a decoded leaf keeps its chaining value and updates the tree like the original
+/// Methods tested: Output::to_bytes, Output::try_from, BinaryMerkleTree::insert_leaf
+#[test]
+fn test_decoded_output_in_insert_leaf() {
+    let mut input = random_input(9 * CHUNK_LEN + 5);
+    let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+
+    input[4 * CHUNK_LEN + 1] ^= 0xFF;
+    let leaf = leaf_for_input_chunk(&input, 4, IV, FLAGS).unwrap();
+
+    let shipped = Output::try_from(&leaf.to_bytes()[..]).unwrap();
+    assert_eq!(shipped.chaining_value(), leaf.chaining_value());
+    tree.insert_leaf(4, shipped).unwrap();
+    assert_eq!(tree.root_hash(), *blake3::hash(&input).as_bytes());
+}
+
+/// Tests streaming extended output from a built tree
+/// Verifies that 200 bytes read from the tree's output reader equal root_output_bytes over a
+/// 200-byte buffer and blake3's XOF, whether read at once, in uneven pieces, or after seeking
+/// Methods tested: BinaryMerkleTree::into_output_reader, BinaryMerkleTree::output_reader, OutputReader::fill, OutputReader::set_position
+#[test]
+fn test_output_reader_matches_root_output_bytes() {
+    let input = random_input(5 * CHUNK_LEN + 77);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let mut expected = [0u8; 200];
+    tree.root().root_output_bytes(&mut expected);
+    let mut xof = [0u8; 200];
+    blake3::Hasher::new().update(&input).finalize_xof().fill(&mut xof);
+    assert_eq!(expected, xof);
+
+    let mut pieces = Vec::new();
+    let mut reader = tree.output_reader();
+    for len in [1, 31, 32, 63, 64, 9] {
+        let mut piece = vec![0u8; len];
+        reader.fill(&mut piece);
+        pieces.extend(piece);
+    }
+    assert_eq!(pieces[..], expected[..]);
+    assert_eq!(reader.position(), 200);
+
+    reader.set_position(70);
+    let mut tail = [0u8; 130];
+    reader.fill(&mut tail);
+    assert_eq!(tail[..], expected[70..]);
+
+    let mut streamed = [0u8; 200];
+    tree.into_output_reader().read_exact(&mut streamed).unwrap();
+    assert_eq!(streamed, expected);
+}
+
+/// Tests flag validation on Outputs with hand-picked flag masks
+/// Verifies masks hashing can produce pass and conflicting or unknown bits are named
+/// Methods tested: Output::validate_flags
+#[test]
+fn test_validate_flags() {
+    let with_flags = |flags| Output { flags, ..ChunkState::new(IV, 0, FLAGS).output() };
+
+    for flags in [
+        0,
+        CHUNK_START,
+        CHUNK_END,
+        CHUNK_START | CHUNK_END | ROOT,
+        PARENT,
+        PARENT | ROOT | KEYED_HASH,
+        CHUNK_END | DERIVE_KEY_CONTEXT,
+        CHUNK_START | DERIVE_KEY_MATERIAL,
+    ] {
+        assert_eq!(with_flags(flags).validate_flags(), Ok(()), "Flags {:#x}", flags);
+    }
+
+    for (flags, conflicting) in [
+        (CHUNK_START | PARENT, CHUNK_START | PARENT),
+        (CHUNK_START | CHUNK_END | PARENT | ROOT, CHUNK_START | CHUNK_END | PARENT),
+        (CHUNK_END | KEYED_HASH | DERIVE_KEY_MATERIAL, KEYED_HASH | DERIVE_KEY_MATERIAL),
+        (PARENT | DERIVE_KEY_CONTEXT | DERIVE_KEY_MATERIAL, DERIVE_KEY_CONTEXT | DERIVE_KEY_MATERIAL),
+        (CHUNK_END | 1 << 7, 1 << 7),
+        (u32::MAX, !0x7f),
+    ] {
+        assert_eq!(
+            with_flags(flags).validate_flags(),
+            Err(MerkleError::InvalidFlags { flags, conflicting }),
+            "Flags {:#x}",
+            flags
+        );
+    }
+}
+
+/// Tests building a tree from leaves that may not be well formed
+/// Verifies good chunks give the same tree as new_from_leaves and bad leaves are rejected
+/// Methods tested: BinaryMerkleTree::new_from_leaves_checked
+#[test]
+fn test_new_from_leaves_checked() {
+    let input = random_input(3 * CHUNK_LEN + 7);
+    let leaves = chunk_leaves(&input, IV, FLAGS);
+    let tree = BinaryMerkleTree::new_from_leaves_checked(leaves.clone(), IV, FLAGS).unwrap();
+    assert_eq!(tree.root_hash(), BinaryMerkleTree::from_input(&input, IV, FLAGS).root_hash());
+
+    assert!(matches!(BinaryMerkleTree::new_from_leaves_checked(Vec::new(), IV, FLAGS), Err(MerkleError::NoLeaves)));
+
+    let mut conflicting = leaves.clone();
+    conflicting[1].flags |= 4;
+    assert!(matches!(
+        BinaryMerkleTree::new_from_leaves_checked(conflicting, IV, FLAGS),
+        Err(MerkleError::InvalidFlags { conflicting: 6, .. })
+    ));
+
+    let mut rooted = leaves.clone();
+    rooted[2].flags |= ROOT;
+    assert!(matches!(
+        BinaryMerkleTree::new_from_leaves_checked(rooted, IV, FLAGS),
+        Err(MerkleError::InvalidLeaf { leaf_index: 2, .. })
+    ));
+
+    assert!(matches!(
+        BinaryMerkleTree::new_from_leaves_checked(leaves, IV, KEYED_HASH),
+        Err(MerkleError::InvalidLeaf { leaf_index: 0, .. })
+    ));
+}
+
+/// Tests the flag helpers and the Debug output of Outputs
+/// Verifies each helper reads its flag, and that Debug names the flags and previews the words
+/// of a leaf, a parent and a root
+/// Methods tested: Output::is_root, Output::is_parent, Output::is_chunk_start, Output::is_chunk_end,
+/// Output::is_keyed, Output::is_derive_key, Output::flag_names, Output::fmt
+#[test]
+fn test_output_flags_and_debug() {
+    let leaf = chunk_output(b"abc", 0, IV, FLAGS).unwrap();
+    assert!(leaf.is_chunk_start() && leaf.is_chunk_end());
+    assert!(!leaf.is_parent() && !leaf.is_root() && !leaf.is_keyed() && !leaf.is_derive_key());
+    assert_eq!(leaf.flag_names().collect::<Vec<_>>(), ["CHUNK_START", "CHUNK_END"]);
+    assert_eq!(
+        format!("{:?}", leaf),
+        "Output { input_chaining_value: 67e6096a85ae67bb…, block_words: 6162630000000000…, \
+         counter: 0, block_len: 3, flags: CHUNK_START|CHUNK_END }"
+    );
+
+    let parent = parent_output([1; 8], [2; 8], IV, FLAGS);
+    assert!(parent.is_parent() && !parent.is_chunk_start() && !parent.is_chunk_end());
+    assert_eq!(
+        format!("{:?}", parent),
+        "Output { input_chaining_value: 67e6096a85ae67bb…, block_words: 0100000001000000…, \
+         counter: 0, block_len: 64, flags: PARENT }"
+    );
+
+    let mut root = parent_output([1; 8], [2; 8], IV, KEYED_HASH);
+    root.flags |= ROOT;
+    assert!(root.is_root() && root.is_parent() && root.is_keyed());
+    assert_eq!(
+        format!("{:?}", root),
+        "Output { input_chaining_value: 67e6096a85ae67bb…, block_words: 0100000001000000…, \
+         counter: 0, block_len: 64, flags: PARENT|ROOT|KEYED_HASH }"
+    );
+
+    // No flags, derive_key flags and bits BLAKE3 does not define
+    let with_flags = |flags| format!("{:?}", Output { flags, ..leaf });
+    assert!(with_flags(0).ends_with("flags: 0 }"));
+    assert!(Output { flags: DERIVE_KEY_MATERIAL, ..leaf }.is_derive_key());
+    assert!(with_flags(CHUNK_START | DERIVE_KEY_CONTEXT | 1 << 9).ends_with("flags: CHUNK_START|DERIVE_KEY_CONTEXT|0x200 }"));
+    assert_eq!(Output { flags: 1 << 9, ..leaf }.flag_names().count(), 0);
+}
+
+/// Tests converting chaining values to and from bytes
+/// Verifies the bytes match the blake3 crate's non-root chaining values for leaves and roots,
+/// that words and bytes round-trip, and that the root hash is not the root's chaining value
+/// Methods tested: Output::cv_bytes, cv_to_bytes, cv_from_bytes
+#[test]
+fn test_cv_byte_conversions() {
+    for size in [1, CHUNK_LEN, CHUNK_LEN + 1, 5 * CHUNK_LEN + 17] {
+        let input = random_input(size);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&input);
+        let non_root_cv = hasher.finalize_non_root();
+        let root_cv = tree.root_cv_and_bytes().0;
+        assert_eq!(cv_to_bytes(root_cv), non_root_cv, "size {}", size);
+        assert_eq!(cv_from_bytes(non_root_cv), root_cv);
+
+        let hash = *blake3::hash(&input).as_bytes();
+        assert_eq!(cv_to_bytes(cv_from_bytes(hash)), hash);
+        assert_eq!(tree.root_hash(), hash);
+        assert_ne!(cv_to_bytes(root_cv), hash);
+        assert_eq!(tree.root().cv_bytes(), hash);
+
+        let mut first_chunk = blake3::Hasher::new();
+        first_chunk.update(&input[..size.min(CHUNK_LEN)]);
+        assert_eq!(tree.leaf(0).unwrap().cv_bytes(), first_chunk.finalize_non_root());
+    }
+
+    // Words are little-endian, first word first
+    assert_eq!(cv_to_bytes(IV)[..4], [0x67, 0xe6, 0x09, 0x6a]);
+    assert_eq!(cv_from_bytes(cv_to_bytes(IV)), IV);
+}
+
+/// Tests generating extended output from an arbitrary byte offset
+/// Verifies the bytes equal a slice of output generated from 0 at aligned and unaligned offsets,
+/// and equal the blake3 crate's seeked XOF more than 2^32 blocks in
+/// Methods tested: Output::root_output_bytes_at
+#[test]
+fn test_root_output_bytes_at_offset() {
+    let input = random_input(3 * CHUNK_LEN + 5);
+    let root = BinaryMerkleTree::from_input(&input, IV, FLAGS).root();
+    let mut reference = [0u8; 400];
+    root.root_output_bytes(&mut reference);
+
+    for offset in [0, 1, 63, 64, 65, 127, 200] {
+        for len in [0, 1, 64, 130] {
+            let mut out = vec![0u8; len];
+            root.root_output_bytes_at(offset as u64, &mut out);
+            assert_eq!(out, reference[offset..offset + len], "{} bytes at {}", len, offset);
+        }
+    }
+
+    let mut xof = blake3::Hasher::new().update(&input).finalize_xof();
+    for offset in [(1u64 << 32) * 64, (1u64 << 32) * 64 + 5, (1 << 40) + 63] {
+        let (mut out, mut expected) = ([0u8; 150], [0u8; 150]);
+        root.root_output_bytes_at(offset, &mut out);
+        xof.set_position(offset);
+        xof.fill(&mut expected);
+        assert_eq!(out, expected, "offset {}", offset);
+    }
+}
+
+/// Tests the root reader as a seekable stream
+/// Verifies that bytes read after seeks equal blake3's XOF and the hasher's output at the
+/// same offsets, and that seeking from the end or before byte 0 fails
+/// Methods tested: BinaryMerkleTree::root_reader, RootReader::seek, RootReader::read
+#[test]
+fn test_root_reader_seeks_into_xof() {
+    let input = random_input(3 * CHUNK_LEN + 5);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let mut reader = tree.root_reader();
+
+    let mut expected = [0u8; 100];
+    let mut xof = blake3::Hasher::new().update(&input).finalize_xof();
+    xof.set_position(1_000_003);
+    xof.fill(&mut expected);
+    assert_eq!(reader.seek(SeekFrom::Start(1_000_003)).unwrap(), 1_000_003);
+    let mut streamed = [0u8; 100];
+    reader.read_exact(&mut streamed).unwrap();
+    assert_eq!(streamed, expected);
+    assert_eq!(reader.stream_position().unwrap(), 1_000_103);
+
+    let mut hashed = vec![0u8; 200];
+    let mut hasher = Blake3Hasher::new();
+    hasher.update(&input);
+    hasher.finalize(&mut hashed);
+    assert_eq!(reader.seek(SeekFrom::Current(-1_000_003)).unwrap(), 100);
+    let mut tail = [0u8; 100];
+    reader.read_exact(&mut tail).unwrap();
+    assert_eq!(tail[..], hashed[100..]);
+
+    assert_eq!(reader.seek(SeekFrom::End(0)).unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(reader.seek(SeekFrom::Current(-201)).unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(reader.stream_position().unwrap(), 200);
+}
+
+/// Tests reading at the very end of the output stream
+/// Verifies that a read from u64::MAX - 10 returns the last 10 bytes, equal to blake3's XOF
+/// there, without overflowing the position, and that further reads return nothing
+/// Methods tested: RootReader::seek, RootReader::read, OutputReader::fill, Output::root_output_bytes_at
+#[test]
+fn test_root_reader_end_of_stream() {
+    let input = random_input(2 * CHUNK_LEN + 7);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let mut reader = tree.root_reader();
+
+    let mut expected = [0u8; 10];
+    let mut xof = blake3::Hasher::new().update(&input).finalize_xof();
+    xof.set_position(u64::MAX - 10);
+    xof.fill(&mut expected);
+
+    assert_eq!(reader.seek(SeekFrom::Start(u64::MAX - 10)).unwrap(), u64::MAX - 10);
+    let mut buf = [0xAAu8; 64];
+    assert_eq!(reader.read(&mut buf).unwrap(), 10);
+    assert_eq!(buf[..10], expected);
+    assert!(buf[10..].iter().all(|&byte| byte == 0xAA));
+    assert_eq!(reader.stream_position().unwrap(), u64::MAX);
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    assert_eq!(reader.read_exact(&mut buf).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+    let mut at_end = [0u8; 64];
+    tree.root().root_output_bytes_at(u64::MAX - 10, &mut at_end);
+    assert_eq!(at_end[..10], expected);
+    assert!(at_end[10..].iter().all(|&byte| byte == 0));
+}
+
+/// Tests building leaves in one call
+/// Verifies chunk_output equals ChunkState hashing at the boundary lengths 0, 1, 64, 1023 and
+/// 1024 and rejects longer chunks, and that leaf_for_input_chunk slices every chunk, the
+/// partial tail included, as from_input does and rejects chunks past the end
+/// Methods tested: chunk_output, leaf_for_input_chunk
+#[test]
+fn test_chunk_output_and_leaf_for_input_chunk() {
+    let key_words = [1, 2, 3, 4, 5, 6, 7, 8];
+    for len in [0, 1, 64, 1023, 1024] {
+        let chunk = random_input(len);
+        let mut chunk_state = ChunkState::new(key_words, 3, KEYED_HASH);
+        chunk_state.update(&chunk);
+        assert_eq!(chunk_output(&chunk, 3, key_words, KEYED_HASH), Ok(chunk_state.output()), "{} bytes", len);
+
+        let input = random_input(2 * CHUNK_LEN + len);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        for chunk_index in 0..tree.actual_leaves() {
+            assert_eq!(leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).ok(), tree.leaf(chunk_index),
+                "chunk {} of {} bytes", chunk_index, input.len());
+        }
+        assert_eq!(leaf_for_input_chunk(&input, tree.actual_leaves(), IV, FLAGS),
+            Err(MerkleError::LeafIndexOutOfBounds { leaf_index: tree.actual_leaves(), actual_leaves: tree.actual_leaves() }));
+    }
+    assert_eq!(chunk_output(&[0; CHUNK_LEN + 1], 0, IV, FLAGS), Err(MerkleError::ChunkTooLong { len: CHUNK_LEN + 1 }));
+    assert_eq!(leaf_for_input_chunk(&[], 0, IV, FLAGS).ok(), BinaryMerkleTree::from_input(&[], IV, FLAGS).leaf(0));
+}
@@ -0,0 +1,218 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, BLOCK_LEN, CHUNK_LEN, IV, FLAGS, KEYED_HASH};
+use merkle_tree::grouped_tree::GroupedTree;
+use merkle_tree::proof::{recompute_proof, verify_block_proof, verify_proof, MerkleProof};
+use common::{chunk_leaves, random_input};
+
+/// Tests building proofs from the leaves alone
+/// Verifies that recompute_proof equals generate_proof for every leaf of balanced and unbalanced
+/// trees, that both verify against the root hash, and that out-of-bounds leaves yield None
+/// Methods tested: recompute_proof, BinaryMerkleTree::generate_proof, MerkleProof::verify
+#[test]
+fn test_recompute_proof_matches_generate_proof() {
+    for &chunks in &[1, 2, 3, 5, 8, 13, 37, 64] {
+        let input = random_input(chunks * CHUNK_LEN - 11);
+        let leaves = chunk_leaves(&input, IV, FLAGS);
+        let tree = BinaryMerkleTree::new_from_leaves(leaves.clone(), IV, FLAGS);
+        let root_hash = tree.root_hash();
+
+        for leaf_index in 0..chunks {
+            let proof = recompute_proof(leaf_index, &leaves, IV, FLAGS).unwrap();
+            assert_eq!(Some(&proof), tree.generate_proof(leaf_index).as_ref(), "Proof mismatch for leaf {} of {}", leaf_index, chunks);
+            assert!(proof.verify(&root_hash, IV, FLAGS));
+        }
+        assert!(recompute_proof(chunks, &leaves, IV, FLAGS).is_none());
+        assert!(tree.generate_proof(chunks).is_none());
+    }
+}
+
+/// Tests generating every proof in one pass
+/// Verifies all_proofs has one proof per leaf, matches generate_proof for the first, last and a
+/// few middle leaves, and that every proof verifies, for single-leaf, balanced and unbalanced trees
+/// Methods tested: BinaryMerkleTree::all_proofs, BinaryMerkleTree::generate_proof, MerkleProof::verify
+#[test]
+fn test_all_proofs_match_generate_proof() {
+    for &chunks in &[1, 2, 3, 7, 16, 37, 100] {
+        let tree = BinaryMerkleTree::from_input(&random_input(chunks * CHUNK_LEN - 5), IV, FLAGS);
+        let proofs = tree.all_proofs();
+        assert_eq!(proofs.len(), chunks);
+        for leaf_index in [0, chunks / 3, chunks / 2, chunks - 1] {
+            assert_eq!(Some(&proofs[leaf_index]), tree.generate_proof(leaf_index).as_ref(), "Proof mismatch for leaf {} of {}", leaf_index, chunks);
+        }
+        assert!(proofs.iter().all(|proof| proof.verify(&tree.root_hash(), IV, FLAGS)));
+    }
+}
+
+/// Tests that altered proofs are rejected
+/// Verifies a tampered sibling, a missing sibling, and a proof moved to another leaf index
+/// Methods tested: MerkleProof::verify, MerkleProof::root_node
+#[test]
+fn test_tampered_proof_fails() {
+    let tree = BinaryMerkleTree::from_input(&random_input(13 * CHUNK_LEN), IV, FLAGS);
+    let root_hash = tree.root_hash();
+    let proof = tree.generate_proof(6).unwrap();
+
+    let mut tampered = proof.clone();
+    tampered.siblings[1][0] ^= 1;
+    assert!(!tampered.verify(&root_hash, IV, FLAGS));
+
+    let mut short = proof.clone();
+    short.siblings.pop();
+    assert!(short.root_node(IV, FLAGS).is_none());
+    assert!(!short.verify(&root_hash, IV, FLAGS));
+
+    let mut moved = proof;
+    moved.leaf_index = 7;
+    assert!(!moved.verify(&root_hash, IV, FLAGS));
+}
+
+/// Tests proofs whose leaf index does not match where their leaf sits
+/// Verifies that the last of 5 leaves, relabelled as leaf 1 of 2, folds to the same root and passes
+/// verify but is rejected by verify_proof, and that verify_proof rejects a grouped tree's group proofs
+/// Methods tested: verify_proof, MerkleProof::verify
+#[test]
+fn test_proof_leaf_index_must_match_leaf() {
+    let tree = BinaryMerkleTree::from_input(&random_input(4 * CHUNK_LEN + 10), IV, FLAGS);
+    let root_hash = tree.root_hash();
+    let proof = tree.generate_proof(4).unwrap();
+    assert_eq!(proof.siblings.len(), 1);
+    assert!(proof.verify(&root_hash, IV, FLAGS));
+    assert!(verify_proof(&proof, &root_hash, IV, FLAGS));
+
+    let relabelled = MerkleProof { leaf_index: 1, actual_leaves: 2, ..proof.clone() };
+    assert_eq!(relabelled.root_node(IV, FLAGS), proof.root_node(IV, FLAGS));
+    assert!(relabelled.verify(&root_hash, IV, FLAGS));
+    assert!(!verify_proof(&relabelled, &root_hash, IV, FLAGS));
+
+    // A chunk whose counter was rewritten to match no longer folds to the root
+    let mut rehashed = relabelled;
+    rehashed.leaf.counter = 1;
+    assert!(!verify_proof(&rehashed, &root_hash, IV, FLAGS));
+
+    for leaf_index in 0..tree.actual_leaves() {
+        assert!(verify_proof(&tree.generate_proof(leaf_index).unwrap(), &root_hash, IV, FLAGS));
+    }
+
+    let input = random_input(4 * CHUNK_LEN + 10);
+    let grouped = GroupedTree::from_input(&input, 2, IV, FLAGS).unwrap();
+    let first_group = grouped.group_proof(0).unwrap();
+    assert!(first_group.verify(&grouped.root_hash(), IV, FLAGS));
+    assert!(!verify_proof(&first_group, &grouped.root_hash(), IV, FLAGS));
+}
+
+/// Tests sending a proof as path bytes and rebuilding it from the chunk
+/// Verifies that the rebuilt proof equals the original and verifies for every leaf, and that
+/// malformed bytes or an oversized chunk yield None
+/// Methods tested: MerkleProof::to_path_bytes, MerkleProof::from_path_bytes
+#[test]
+fn test_path_bytes_round_trip() {
+    for &chunks in &[1, 2, 5, 13] {
+        let input = random_input(chunks * CHUNK_LEN - 11);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        for (leaf_index, chunk) in input.chunks(CHUNK_LEN).enumerate() {
+            let proof = tree.generate_proof(leaf_index).unwrap();
+            let bytes = proof.to_path_bytes();
+            assert_eq!(bytes.len(), 16 + 32 * proof.siblings.len());
+            let rebuilt = MerkleProof::from_path_bytes(&bytes, chunk, IV, FLAGS).unwrap();
+            assert_eq!(rebuilt, proof);
+            assert!(rebuilt.verify(&tree.root_hash(), IV, FLAGS));
+        }
+    }
+
+    let tree = BinaryMerkleTree::from_input(&random_input(3 * CHUNK_LEN), IV, FLAGS);
+    let bytes = tree.generate_proof(0).unwrap().to_path_bytes();
+    assert_eq!(MerkleProof::from_path_bytes(&bytes[..bytes.len() - 1], &[], IV, FLAGS), None);
+    assert_eq!(MerkleProof::from_path_bytes(&bytes[..15], &[], IV, FLAGS), None);
+    assert_eq!(MerkleProof::from_path_bytes(&bytes, &[0; CHUNK_LEN + 1], IV, FLAGS), None);
+}
+
+/// Tests sending a proof together with the tree's key and flags
+/// Verifies that a keyed tree's proof verifies from its bytes, the chunk and the root hash
+/// alone, and that tampered parameters, an unknown version or plain path bytes do not
+/// Methods tested: MerkleProof::to_path_bytes_with_params, MerkleProof::from_path_bytes_with_params
+#[test]
+fn test_path_bytes_with_params_round_trip() {
+    let key_words = [0x0706_0504, 0x0b0a_0908, 3, 4, 5, 6, 7, 8];
+    let input = random_input(5 * CHUNK_LEN + 9);
+    let tree = BinaryMerkleTree::from_input(&input, key_words, KEYED_HASH);
+    let root_hash = tree.root_hash();
+    let chunk = &input[3 * CHUNK_LEN..4 * CHUNK_LEN];
+    let proof = tree.generate_proof(3).unwrap();
+    let bytes = proof.to_path_bytes_with_params(key_words, KEYED_HASH);
+    assert_eq!(bytes.len(), 37 + proof.to_path_bytes().len());
+
+    // The verifier knows nothing but the bytes, the chunk and the root hash
+    let (rebuilt, embedded_key, embedded_flags) = MerkleProof::from_path_bytes_with_params(&bytes, chunk).unwrap();
+    assert_eq!((rebuilt.clone(), embedded_key, embedded_flags), (proof, key_words, KEYED_HASH));
+    assert!(rebuilt.verify(&root_hash, embedded_key, embedded_flags));
+
+    let mut regular_mode = bytes.clone();
+    regular_mode[1..5].copy_from_slice(&FLAGS.to_le_bytes());
+    let (rebuilt, key, flags) = MerkleProof::from_path_bytes_with_params(&regular_mode, chunk).unwrap();
+    assert!(!rebuilt.verify(&root_hash, key, flags));
+    let mut other_key = bytes.clone();
+    other_key[5] ^= 1;
+    let (rebuilt, key, flags) = MerkleProof::from_path_bytes_with_params(&other_key, chunk).unwrap();
+    assert!(!rebuilt.verify(&root_hash, key, flags));
+
+    let mut future_version = bytes.clone();
+    future_version[0] = 2;
+    assert_eq!(MerkleProof::from_path_bytes_with_params(&future_version, chunk), None);
+    assert_eq!(MerkleProof::from_path_bytes_with_params(&tree.generate_proof(3).unwrap().to_path_bytes(), chunk), None);
+    assert_eq!(MerkleProof::from_path_bytes_with_params(&bytes[..36], chunk), None);
+}
+
+/// Tests proving a single block within a chunk
+/// Verifies proofs for the first, a middle and the last block of a full chunk, every block of
+/// a partial final chunk and the empty chunk of empty input, on single- and multi-chunk trees,
+/// and that altered blocks, suffixes and prefix states, and moved blocks of full chunks, are
+/// rejected
+/// Methods tested: BinaryMerkleTree::get_block_proof, verify_block_proof
+#[test]
+fn test_block_proofs() {
+    for size in [0, 100, CHUNK_LEN, 3 * CHUNK_LEN + 200, 8 * CHUNK_LEN] {
+        let input = random_input(size);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let root_hash = tree.root_hash();
+        for chunk_index in 0..tree.actual_leaves() {
+            let chunk = &input[chunk_index * CHUNK_LEN..((chunk_index + 1) * CHUNK_LEN).min(size)];
+            let blocks = chunk.len().div_ceil(BLOCK_LEN).max(1);
+            for block_index in [0, blocks / 2, blocks - 1] {
+                let block = &chunk[(block_index * BLOCK_LEN).min(chunk.len())..((block_index + 1) * BLOCK_LEN).min(chunk.len())];
+                let proof = tree.get_block_proof(chunk_index, block_index, chunk).unwrap();
+                assert_eq!(proof.suffix.len(), chunk.len() - (block_index * BLOCK_LEN + block.len()));
+                assert!(verify_block_proof(block, &proof, &root_hash, IV, FLAGS), "Block {} of chunk {} of {} bytes", block_index, chunk_index, size);
+
+                if !block.is_empty() {
+                    let mut flipped = block.to_vec();
+                    flipped[0] ^= 1;
+                    assert!(!verify_block_proof(&flipped, &proof, &root_hash, IV, FLAGS));
+                    assert!(!verify_block_proof(&block[1..], &proof, &root_hash, IV, FLAGS));
+                }
+                // Only the length of a chunk before the last fixes the block's position
+                if chunk_index + 1 < tree.actual_leaves() {
+                    let mut moved = proof.clone();
+                    moved.block_index ^= 1;
+                    assert!(!verify_block_proof(block, &moved, &root_hash, IV, FLAGS));
+                }
+                let mut wrong_prefix = proof.clone();
+                wrong_prefix.prefix_cv[0] ^= 1;
+                assert!(!verify_block_proof(block, &wrong_prefix, &root_hash, IV, FLAGS));
+                if let Some(byte) = proof.suffix.first() {
+                    let mut wrong_suffix = proof.clone();
+                    wrong_suffix.suffix[0] = byte ^ 1;
+                    assert!(!verify_block_proof(block, &wrong_suffix, &root_hash, IV, FLAGS));
+                }
+            }
+            assert!(tree.get_block_proof(chunk_index, blocks, chunk).is_none());
+        }
+        assert!(tree.get_block_proof(tree.actual_leaves(), 0, &[]).is_none());
+    }
+
+    // The chunk bytes must be the chunk's
+    let input = random_input(2 * CHUNK_LEN);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    assert!(tree.get_block_proof(0, 0, &input[CHUNK_LEN..]).is_none());
+    assert!(tree.get_block_proof(0, 0, &input[..CHUNK_LEN - 1]).is_none());
+}
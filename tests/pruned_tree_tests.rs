@@ -0,0 +1,135 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, CHUNK_LEN, IV, FLAGS};
+use merkle_tree::error::MerkleError;
+use merkle_tree::pruned_tree::PrunedTree;
+use std::io;
+use common::random_input;
+
+fn chunk(input: &[u8], chunk_index: usize) -> &[u8] {
+    &input[chunk_index * CHUNK_LEN..((chunk_index + 1) * CHUNK_LEN).min(input.len())]
+}
+
+/// Tests that a pruned tree verifies exactly like the full tree for the kept leaves
+/// Verifies the recomputed root CV and hash, genuine and tampered chunks, the error for pruned leaves,
+/// and a serialization round trip, for several tree shapes and kept sets
+/// Methods tested: BinaryMerkleTree::prune, PrunedTree::root_cv, PrunedTree::root_hash, PrunedTree::verify_chunk
+#[test]
+fn test_pruned_tree_matches_full_tree() {
+    let cases: [(usize, Vec<usize>); 5] = [
+        (1, vec![0]),
+        (5 * CHUNK_LEN + 7, vec![5]),
+        (13 * CHUNK_LEN, vec![0, 12, 12, 3]),
+        (37 * CHUNK_LEN + 123, vec![20, 21, 22, 36]),
+        (64 * CHUNK_LEN, (0..64).collect()),
+    ];
+    for (size, keep) in cases {
+        let input = random_input(size);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let pruned = tree.prune(&keep).unwrap();
+        assert_eq!((pruned.root_cv(), pruned.root_hash()), tree.root_cv_and_bytes(), "Root mismatch for {} bytes", size);
+
+        for chunk_index in 0..tree.actual_leaves() + 1 {
+            if !keep.contains(&chunk_index) {
+                assert_eq!(pruned.verify_chunk(chunk_index, &[]), Err(MerkleError::LeafNotKept { leaf_index: chunk_index }));
+                continue;
+            }
+            let bytes = chunk(&input, chunk_index);
+            assert_eq!(pruned.verify_chunk(chunk_index, bytes), Ok(tree.verify_chunk(chunk_index, bytes)));
+            assert_eq!(pruned.verify_chunk(chunk_index, bytes), Ok(true));
+            let mut tampered = bytes.to_vec();
+            tampered[0] ^= 1;
+            assert_eq!(pruned.verify_chunk(chunk_index, &tampered), Ok(false));
+        }
+
+        assert_eq!(PrunedTree::from_bytes(&pruned.to_bytes()).unwrap(), pruned);
+    }
+}
+
+/// Tests the serialized size of a pruned tree
+/// Verifies that it grows with the kept leaves and the depth, not with the total number of leaves
+/// Methods tested: BinaryMerkleTree::prune, PrunedTree::to_bytes
+#[test]
+fn test_pruned_tree_size() {
+    const HEADER_LEN: usize = 4 + 4 + 4 + 32 + 8 + 8 + 8;
+    const LEAF_RECORD_LEN: usize = 8 + 112;
+    const WITNESS_RECORD_LEN: usize = 8 + 32;
+    let keep: Vec<usize> = (1000..1100).collect();
+
+    for chunks in [2048, 16384] {
+        let tree = BinaryMerkleTree::from_input(&random_input(chunks * CHUNK_LEN), IV, FLAGS);
+        let bytes = tree.prune(&keep).unwrap().to_bytes();
+        // A contiguous range needs at most two witnesses per level
+        let depth = chunks.ilog2() as usize;
+        assert!(
+            bytes.len() <= HEADER_LEN + keep.len() * LEAF_RECORD_LEN + 2 * depth * WITNESS_RECORD_LEN,
+            "{} bytes for {} kept of {} leaves",
+            bytes.len(),
+            keep.len(),
+            chunks
+        );
+        assert!(bytes.len() < tree.to_bytes().len() / 10);
+    }
+}
+
+/// Tests that malformed serialized pruned trees are rejected
+/// Verifies truncation, trailing bytes, a dropped witness and a bad magic
+/// Methods tested: PrunedTree::from_bytes
+#[test]
+fn test_pruned_tree_rejects_malformed_bytes() {
+    let tree = BinaryMerkleTree::from_input(&random_input(9 * CHUNK_LEN), IV, FLAGS);
+    let bytes = tree.prune(&[4]).unwrap().to_bytes();
+
+    assert_eq!(PrunedTree::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    let mut extended = bytes.clone();
+    extended.push(0);
+    assert_eq!(PrunedTree::from_bytes(&extended).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    // Drop the last witness and decrement the witness count
+    let witness_count_at = bytes.len() - 4 * 40 - 8;
+    assert_eq!(bytes[witness_count_at], 4);
+    let mut missing_witness = bytes[..bytes.len() - 40].to_vec();
+    missing_witness[witness_count_at] = 3;
+    assert_eq!(PrunedTree::from_bytes(&missing_witness).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    assert_eq!(PrunedTree::from_bytes(&bad_magic).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+/// Tests proofs handed out by a pruned tree
+/// Verifies each kept leaf's proof equals the full tree's and verifies against the original root,
+/// including kept leaves that are each other's siblings, and that pruned leaves have no proof
+/// Methods tested: BinaryMerkleTree::prune_to, PrunedTree::generate_proof
+#[test]
+fn test_pruned_tree_proofs() {
+    let cases: [(usize, Vec<usize>); 4] = [
+        (1, vec![0]),
+        (13 * CHUNK_LEN, vec![0, 1, 12]),
+        (37 * CHUNK_LEN + 123, vec![20, 21, 22, 36]),
+        (16 * CHUNK_LEN, (0..16).collect()),
+    ];
+    for (size, keep) in cases {
+        let tree = BinaryMerkleTree::from_input(&random_input(size), IV, FLAGS);
+        let root_hash = tree.root_hash();
+        let pruned = tree.prune_to(&keep).unwrap();
+        for leaf_index in 0..tree.actual_leaves() {
+            if !keep.contains(&leaf_index) {
+                assert_eq!(pruned.generate_proof(leaf_index), Err(MerkleError::LeafNotKept { leaf_index }));
+                continue;
+            }
+            let proof = pruned.generate_proof(leaf_index).unwrap();
+            assert_eq!(Some(&proof), tree.generate_proof(leaf_index).as_ref(), "Proof mismatch for leaf {}", leaf_index);
+            assert!(proof.verify(&root_hash, IV, FLAGS));
+        }
+    }
+}
+
+/// Tests that prune_to rejects an empty keep list and leaves the tree does not have
+/// Methods tested: BinaryMerkleTree::prune_to
+#[test]
+fn test_prune_to_rejects_bad_indices() {
+    let tree = BinaryMerkleTree::from_input(&random_input(3 * CHUNK_LEN), IV, FLAGS);
+    assert_eq!(tree.prune_to(&[]).unwrap_err(), MerkleError::NoLeaves);
+    assert_eq!(tree.prune_to(&[0, 3]).unwrap_err(), MerkleError::LeafIndexOutOfBounds { leaf_index: 3, actual_leaves: 3 });
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "test-vectors")]
+
+use merkle_tree::self_test::{run_test_vectors, self_test, Implementation, Mode, TEST_VECTORS_JSON};
+
+/// Tests the built-in self-test on the embedded vectors
+/// Verifies every vector passes in every mode through both the hasher and the tree
+/// Methods tested: self_test
+#[test]
+fn test_self_test_passes() {
+    let report = self_test();
+    assert!(report.passed(), "{:?}", report.mismatches);
+    assert_eq!(report.vectors, 35);
+    assert_eq!(report.checks, 35 * 3 * 2);
+}
+
+/// Tests the report for a corrupted vector
+/// Verifies the mismatch names the vector index, input length, mode and implementation
+/// Methods tested: run_test_vectors
+#[test]
+fn test_corrupted_vector_is_reported() {
+    // The keyed_hash of vector 1 (input_len 1) starts with 6d78; flip its third byte
+    let corrupted = TEST_VECTORS_JSON.replacen("\"keyed_hash\": \"6d7878", "\"keyed_hash\": \"6d7879", 1);
+    assert_ne!(corrupted, TEST_VECTORS_JSON);
+
+    let report = run_test_vectors(&corrupted).unwrap();
+    assert!(!report.passed());
+    assert_eq!(report.mismatches.len(), 2);
+    for (mismatch, implementation) in report.mismatches.iter().zip([Implementation::Hasher, Implementation::Tree]) {
+        assert_eq!((mismatch.vector, mismatch.input_len, mismatch.mode), (1, 1, Mode::KeyedHash));
+        assert_eq!(mismatch.implementation, implementation);
+        assert_eq!(mismatch.expected[2], 0x79);
+        assert_eq!(mismatch.actual[2], 0x78);
+    }
+    assert_eq!(
+        report.mismatches[1].to_string(),
+        "vector 1 (input_len 1), keyed_hash mode, BinaryMerkleTree: output byte 2 is 78, expected 79"
+    );
+}
+
+/// Tests malformed vector files
+/// Verifies bad JSON, a wrong-length key and non-hex outputs are rejected
+/// Methods tested: run_test_vectors
+#[test]
+fn test_malformed_vectors() {
+    assert_eq!(run_test_vectors("{"), None);
+    assert_eq!(run_test_vectors(&TEST_VECTORS_JSON.replacen("\"key\": \"whats the Elvish", "\"key\": \"whats the", 1)), None);
+    assert_eq!(run_test_vectors(&TEST_VECTORS_JSON.replacen("\"hash\": \"af", "\"hash\": \"zz", 1)), None);
+}
@@ -0,0 +1,134 @@
+#![cfg(feature = "serde")]
+
+mod common;
+
+use merkle_tree::binary_merkle_tree::{cv_to_bytes, BinaryMerkleTree, Output, BLOCK_LEN, CHUNK_LEN, IV, FLAGS};
+use merkle_tree::proof::{verify_block_proof, BlockProof, LeafHashProof, MerkleProof};
+use common::random_input;
+
+const KEY_WORDS: [u32; 8] = [
+    0x03020100, 0x07060504, 0x0B0A0908, 0x0F0E0D0C, 0x13121110, 0x17161514, 0x1B1A1918, 0x1F1E1D1C,
+];
+
+fn assert_same_tree(actual: &BinaryMerkleTree, expected: &BinaryMerkleTree) {
+    assert_eq!(actual.actual_leaves(), expected.actual_leaves());
+    assert_eq!(actual.num_leaves(), expected.num_leaves());
+    assert_eq!((actual.key_words(), actual.flags(), actual.input_len()), (expected.key_words(), expected.flags(), expected.input_len()));
+    for leaf_index in 0..expected.actual_leaves() {
+        assert_eq!(actual.leaf(leaf_index), expected.leaf(leaf_index));
+    }
+    assert_eq!(actual.root(), expected.root());
+    assert!(actual.verify_integrity());
+}
+
+/// Tests serde round trips through a human-readable and a binary format
+/// Verifies keyed and unbalanced trees rebuild identically, and that CVs appear as hex in JSON
+/// Methods tested: Serialize/Deserialize for BinaryMerkleTree and Output
+#[test]
+fn test_serde_round_trip() {
+    for &size in &[0, 1, CHUNK_LEN, 3 * CHUNK_LEN + 17, 37 * CHUNK_LEN + 123] {
+        let input = random_input(size);
+        for key_words in [IV, KEY_WORDS] {
+            let tree = BinaryMerkleTree::from_input(&input, key_words, FLAGS);
+
+            let json = serde_json::to_string(&tree).unwrap();
+            assert_same_tree(&serde_json::from_str(&json).unwrap(), &tree);
+
+            let encoded = bincode::serialize(&tree).unwrap();
+            assert_same_tree(&bincode::deserialize(&encoded).unwrap(), &tree);
+        }
+    }
+
+    // A single-block chunk starts from the key, so its input CV is IV
+    let leaf = BinaryMerkleTree::from_input(&random_input(50), IV, FLAGS).leaf(0).unwrap();
+    let json = serde_json::to_value(leaf).unwrap();
+    let expected_cv: String = cv_to_bytes(IV).iter().map(|byte| format!("{:02x}", byte)).collect();
+    assert_eq!(json["input_chaining_value"], expected_cv);
+    assert_eq!(json["block_words"].as_str().unwrap().len(), 128);
+    assert_eq!(serde_json::from_value::<Output>(json).unwrap(), leaf);
+    assert_eq!(bincode::deserialize::<Output>(&bincode::serialize(&leaf).unwrap()).unwrap(), leaf);
+}
+
+/// Tests that inconsistent or tampered serialized trees are rejected with a serde error
+/// Methods tested: Deserialize for BinaryMerkleTree
+#[test]
+fn test_serde_rejects_tampered_json() {
+    let tree = BinaryMerkleTree::from_input(&random_input(5 * CHUNK_LEN + 9), IV, FLAGS);
+    let json = serde_json::to_value(&tree).unwrap();
+    let rejects = |value: serde_json::Value| serde_json::from_value::<BinaryMerkleTree>(value).is_err();
+    assert!(!rejects(json.clone()));
+
+    // A changed leaf no longer hashes to the stored root
+    let mut tampered = json.clone();
+    tampered["leaves"][2]["block_len"] = 63.into();
+    assert!(rejects(tampered));
+
+    let mut tampered = json.clone();
+    tampered["root_hash"] = "00".repeat(32).into();
+    assert!(rejects(tampered));
+
+    // Structural problems: leaves out of order, no leaves, wrong length, malformed hex
+    let mut tampered = json.clone();
+    tampered["leaves"].as_array_mut().unwrap().swap(0, 1);
+    assert!(rejects(tampered));
+
+    let mut tampered = json.clone();
+    tampered["leaves"] = serde_json::Value::Array(Vec::new());
+    assert!(rejects(tampered));
+
+    let mut tampered = json.clone();
+    tampered["input_len"] = (100 * CHUNK_LEN).into();
+    assert!(rejects(tampered));
+
+    let mut tampered = json.clone();
+    tampered["key_words"] = "zz".repeat(32).into();
+    assert!(rejects(tampered));
+}
+
+/// Tests serde round trips of the proof types
+/// Verifies chunk, leaf-hash and block proofs rebuild identically through JSON and bincode and
+/// still verify, that chaining values appear as hex in JSON, and that a tampered sibling fails
+/// verification and malformed hex fails to load
+/// Methods tested: Serialize/Deserialize for MerkleProof, LeafHashProof and BlockProof
+#[test]
+fn test_serde_proofs_round_trip() {
+    let input = random_input(5 * CHUNK_LEN + 9);
+    let tree = BinaryMerkleTree::from_input(&input, KEY_WORDS, FLAGS);
+    let root_hash = tree.root_hash();
+
+    let proof = tree.generate_proof(3).unwrap();
+    let json = serde_json::to_value(&proof).unwrap();
+    assert_eq!(json["siblings"][0], cv_to_bytes(proof.siblings[0]).iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+    let from_json: MerkleProof = serde_json::from_value(json.clone()).unwrap();
+    let from_bincode: MerkleProof = bincode::deserialize(&bincode::serialize(&proof).unwrap()).unwrap();
+    for loaded in [from_json, from_bincode] {
+        assert_eq!(loaded, proof);
+        assert!(loaded.verify(&root_hash, KEY_WORDS, FLAGS));
+    }
+
+    let mut tampered = json.clone();
+    tampered["siblings"][1] = "00".repeat(32).into();
+    assert!(!serde_json::from_value::<MerkleProof>(tampered).unwrap().verify(&root_hash, KEY_WORDS, FLAGS));
+    let mut tampered = json.clone();
+    tampered["siblings"][1] = "zz".repeat(32).into();
+    assert!(serde_json::from_value::<MerkleProof>(tampered).is_err());
+    let mut tampered = json;
+    tampered["siblings"][1] = "00".repeat(31).into();
+    assert!(serde_json::from_value::<MerkleProof>(tampered).is_err());
+
+    let block_proof = tree.get_block_proof(2, 5, &input[2 * CHUNK_LEN..3 * CHUNK_LEN]).unwrap();
+    let loaded: BlockProof = serde_json::from_str(&serde_json::to_string(&block_proof).unwrap()).unwrap();
+    assert_eq!(loaded, block_proof);
+    let block = &input[2 * CHUNK_LEN + 5 * BLOCK_LEN..2 * CHUNK_LEN + 6 * BLOCK_LEN];
+    assert!(verify_block_proof(block, &loaded, &root_hash, KEY_WORDS, FLAGS));
+
+    let hashes: Vec<[u8; 32]> = (0..5u8).map(|i| [i; 32]).collect();
+    let hash_tree = BinaryMerkleTree::from_leaf_hashes(&hashes, KEY_WORDS, FLAGS).unwrap();
+    let leaf_hash_proof = hash_tree.generate_leaf_hash_proof(4).unwrap();
+    let json = serde_json::to_value(&leaf_hash_proof).unwrap();
+    assert_eq!(json["leaf_hash"], "04".repeat(32));
+    let loaded: LeafHashProof = serde_json::from_value(json).unwrap();
+    assert_eq!(loaded, leaf_hash_proof);
+    let from_bincode: LeafHashProof = bincode::deserialize(&bincode::serialize(&leaf_hash_proof).unwrap()).unwrap();
+    assert!(from_bincode.verify(&hash_tree.root_hash(), KEY_WORDS, FLAGS));
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{leaf_for_input_chunk, BinaryMerkleTree, CHUNK_LEN, IV, FLAGS};
+use std::io;
+use common::random_input;
+
+// Three leaves over the test-vector pattern (byte i is i % 251), with the default key
+const GOLDEN_FIXTURE: &[u8] = include_bytes!("fixtures/tree_v1.bin");
+const GOLDEN_INPUT_LEN: usize = 2 * CHUNK_LEN + 100;
+
+const KEY_WORDS: [u32; 8] = [
+    0x03020100, 0x07060504, 0x0B0A0908, 0x0F0E0D0C, 0x13121110, 0x17161514, 0x1B1A1918, 0x1F1E1D1C,
+];
+
+fn assert_same_tree(actual: &BinaryMerkleTree, expected: &BinaryMerkleTree) {
+    assert_eq!(actual.actual_leaves(), expected.actual_leaves());
+    assert_eq!((actual.key_words(), actual.flags(), actual.input_len()), (expected.key_words(), expected.flags(), expected.input_len()));
+    for leaf_index in 0..expected.actual_leaves() {
+        assert_eq!(actual.leaf(leaf_index), expected.leaf(leaf_index), "Leaf {} differs", leaf_index);
+    }
+    assert_eq!(actual.root(), expected.root());
+    assert!(actual.verify_integrity());
+}
+
+/// Tests the serialized format against a checked-in fixture so it cannot drift silently
+/// Verifies the header fields, byte-for-byte equality, and that the fixture loads to the same tree
+/// Methods tested: BinaryMerkleTree::to_bytes, BinaryMerkleTree::from_bytes
+#[test]
+fn test_serialization_golden_fixture() {
+    let input: Vec<u8> = (0..GOLDEN_INPUT_LEN).map(|i| (i % 251) as u8).collect();
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+
+    assert_eq!(&GOLDEN_FIXTURE[..4], b"B3MT");
+    assert_eq!(GOLDEN_FIXTURE[4..8], 1u32.to_le_bytes(), "Format version changed");
+    assert_eq!(GOLDEN_FIXTURE[24..32], (GOLDEN_INPUT_LEN as u64).to_le_bytes());
+    assert_eq!(tree.to_bytes(), GOLDEN_FIXTURE, "Serialized format drifted from the golden fixture");
+
+    let loaded = BinaryMerkleTree::from_bytes(GOLDEN_FIXTURE).unwrap();
+    assert_same_tree(&loaded, &tree);
+    assert_eq!(loaded.root_hash(), *blake3::hash(&input).as_bytes());
+}
+
+/// Tests round trips for unbalanced, keyed, and leaf-built trees, through bytes and through a reader
+/// Methods tested: BinaryMerkleTree::to_bytes, BinaryMerkleTree::from_bytes, BinaryMerkleTree::write_to, BinaryMerkleTree::read_from
+#[test]
+fn test_serialization_round_trip() {
+    for &size in &[0, 1, CHUNK_LEN, 3 * CHUNK_LEN + 17, 37 * CHUNK_LEN + 123] {
+        let input = random_input(size);
+        for key_words in [IV, KEY_WORDS] {
+            let tree = BinaryMerkleTree::from_input(&input, key_words, FLAGS);
+            assert_eq!(tree.input_len(), Some(size as u64));
+            assert_same_tree(&BinaryMerkleTree::from_bytes(&tree.to_bytes()).unwrap(), &tree);
+
+            let mut written = Vec::new();
+            tree.write_to(&mut written).unwrap();
+            assert_same_tree(&BinaryMerkleTree::read_from(&written[..]).unwrap(), &tree);
+        }
+    }
+
+    // Trees built from leaves, or whose final leaf was replaced, have no known input length
+    let input = random_input(5 * CHUNK_LEN + 9);
+    let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    tree.insert_leaf(5, leaf_for_input_chunk(&input, 5, IV, FLAGS).unwrap()).unwrap();
+    assert_eq!(tree.input_len(), None);
+    assert_same_tree(&BinaryMerkleTree::from_bytes(&tree.to_bytes()).unwrap(), &tree);
+}
+
+/// Tests that structurally invalid data is rejected, and that a tampered node is caught by verify_integrity
+/// Methods tested: BinaryMerkleTree::from_bytes, BinaryMerkleTree::verify_integrity
+#[test]
+fn test_deserialization_rejects_invalid_data() {
+    let invalid = |bytes: &[u8]| BinaryMerkleTree::from_bytes(bytes).unwrap_err().kind();
+
+    let mut bad_magic = GOLDEN_FIXTURE.to_vec();
+    bad_magic[0] = b'X';
+    assert_eq!(invalid(&bad_magic), io::ErrorKind::InvalidData);
+
+    let mut bad_version = GOLDEN_FIXTURE.to_vec();
+    bad_version[4] = 2;
+    assert_eq!(invalid(&bad_version), io::ErrorKind::InvalidData);
+
+    // Four leaves need seven nodes, not the six that follow
+    let mut bad_leaf_count = GOLDEN_FIXTURE.to_vec();
+    bad_leaf_count[16] = 4;
+    bad_leaf_count[8] = 0;
+    assert_eq!(invalid(&bad_leaf_count), io::ErrorKind::InvalidData);
+
+    let mut bad_input_len = GOLDEN_FIXTURE.to_vec();
+    bad_input_len[26] = 0x10;
+    assert_eq!(invalid(&bad_input_len), io::ErrorKind::InvalidData);
+
+    assert_eq!(invalid(&GOLDEN_FIXTURE[..GOLDEN_FIXTURE.len() - 1]), io::ErrorKind::UnexpectedEof);
+    assert_eq!(invalid(&[GOLDEN_FIXTURE, &[0]].concat()), io::ErrorKind::InvalidData);
+
+    // A header claiming 2^50 leaves, and the nodes to match, must not allocate for them
+    let mut huge = GOLDEN_FIXTURE[..40].to_vec();
+    huge[8] = 0;
+    huge[16..24].copy_from_slice(&(1u64 << 50).to_le_bytes());
+    huge[32..40].copy_from_slice(&((1u64 << 51) - 1).to_le_bytes());
+    assert_eq!(invalid(&huge), io::ErrorKind::UnexpectedEof);
+    huge[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert_eq!(invalid(&huge), io::ErrorKind::InvalidData);
+
+    // Structurally valid but with a corrupted block word in the root node
+    let mut tampered = GOLDEN_FIXTURE.to_vec();
+    tampered[40 + 32] ^= 0x01;
+    let tree = BinaryMerkleTree::from_bytes(&tampered).unwrap();
+    assert!(!tree.verify_integrity(), "Tampered root node was not detected");
+}
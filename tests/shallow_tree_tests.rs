@@ -0,0 +1,88 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, CHUNK_LEN, FLAGS, IV, KEYED_HASH};
+use merkle_tree::shallow_tree::ShallowTree;
+use rand::Rng;
+use std::io;
+use common::{random_input, SeededRng};
+
+/// Tests verifying regions against a shallow tree
+/// Verifies every pristine region is accepted, a single flipped bit or a truncated region is
+/// rejected, and region proofs verify against the full tree's root, for several shapes and cuts
+/// Methods tested: BinaryMerkleTree::truncate_depth, ShallowTree::verify_region,
+/// ShallowTree::region_proof, ShallowTree::root_hash
+#[test]
+fn test_shallow_tree_verifies_regions() {
+    for (size, keep_levels) in [(0, 3), (5 * CHUNK_LEN + 7, 2), (37 * CHUNK_LEN + 123, 3), (64 * CHUNK_LEN, 4), (13 * CHUNK_LEN, 1), (13 * CHUNK_LEN, 10)] {
+        let input = random_input(size);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let shallow = tree.truncate_depth(keep_levels);
+        assert_eq!(shallow.root_hash(), tree.root_hash());
+        assert_eq!(shallow.actual_leaves(), tree.actual_leaves());
+
+        let region_len = CHUNK_LEN << shallow.cut_level();
+        let regions: Vec<&[u8]> = if input.is_empty() { vec![&[]] } else { input.chunks(region_len).collect() };
+        assert_eq!(shallow.region_count(), regions.len(), "{} bytes keeping {} levels", size, keep_levels);
+        for (region_index, region) in regions.iter().enumerate() {
+            assert!(shallow.verify_region(region_index, region), "Region {} of {} bytes", region_index, size);
+            let proof = shallow.region_proof(region_index).unwrap();
+            assert!(proof.verify(&tree.root_hash(), IV, FLAGS));
+            assert_eq!(Some(proof.leaf.chaining_value()), shallow.region_cv(region_index));
+
+            if !region.is_empty() {
+                let mut flipped = region.to_vec();
+                let bit = SeededRng::new().gen_range(0..8 * flipped.len());
+                flipped[bit / 8] ^= 1 << (bit % 8);
+                assert!(!shallow.verify_region(region_index, &flipped));
+                assert!(!shallow.verify_region(region_index, &region[..region.len() - 1]));
+            }
+        }
+        assert!(!shallow.verify_region(regions.len(), &[]));
+        assert!(shallow.region_proof(regions.len()).is_none());
+    }
+}
+
+/// Tests the cut level chosen for a number of kept levels
+/// Verifies one region per 2^k chunks, and the clamping of 0 and too many levels
+/// Methods tested: BinaryMerkleTree::truncate_depth, ShallowTree::cut_level, ShallowTree::region_chunks
+#[test]
+fn test_truncate_depth_levels() {
+    // 2048 leaves, 12 levels
+    let tree = BinaryMerkleTree::from_input(&random_input(2000 * CHUNK_LEN), IV, FLAGS);
+    let shallow = tree.truncate_depth(2);
+    assert_eq!((shallow.cut_level(), shallow.region_count()), (10, 2));
+    assert_eq!(shallow.region_chunks(1), Some(1024..2000));
+    assert_eq!(tree.truncate_depth(0).cut_level(), 11);
+    assert_eq!(tree.truncate_depth(12).cut_level(), 0);
+    assert_eq!(tree.truncate_depth(100).region_count(), 2000);
+}
+
+/// Tests the serialization of shallow trees
+/// Verifies a round trip keeps verification working, and that truncation, trailing bytes, a
+/// bad magic and a cut above the root are rejected
+/// Methods tested: ShallowTree::to_bytes, ShallowTree::from_bytes
+#[test]
+fn test_shallow_tree_serialization() {
+    let input = random_input(37 * CHUNK_LEN + 123);
+    let tree = BinaryMerkleTree::from_input(&input, [3; 8], KEYED_HASH);
+    let shallow = tree.truncate_depth(3);
+    let bytes = shallow.to_bytes();
+    let restored = ShallowTree::from_bytes(&bytes).unwrap();
+    assert_eq!(restored.root_hash(), tree.root_hash());
+    assert_eq!(restored.to_bytes(), bytes);
+    for (region_index, region) in input.chunks(CHUNK_LEN << restored.cut_level()).enumerate() {
+        assert!(restored.verify_region(region_index, region));
+    }
+
+    assert_eq!(ShallowTree::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    let mut extended = bytes.clone();
+    extended.push(0);
+    assert_eq!(ShallowTree::from_bytes(&extended).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    assert_eq!(ShallowTree::from_bytes(&bad_magic).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    // The cut level follows the magic, version, flags, key and leaf count
+    let mut above_root = bytes.clone();
+    above_root[4 + 4 + 4 + 32 + 8] = 7;
+    assert_eq!(ShallowTree::from_bytes(&above_root).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,42 @@
+#![cfg(feature = "simd")]
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, BLOCK_LEN, CHUNK_LEN, FLAGS, IV, KEYED_HASH};
+use merkle_tree::simd::LANES;
+
+fn sample_input(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Tests the batched extended output against the scalar one
+/// Verifies 512 bytes, a single batch of `LANES` blocks, match byte for byte for a chunk root
+/// and a parent root
+/// Methods tested: Output::root_output_bytes_batched, Output::root_output_bytes
+#[test]
+fn test_batched_matches_scalar() {
+    assert_eq!(LANES * BLOCK_LEN, 512);
+    for len in [100, 4 * CHUNK_LEN + 1] {
+        let tree = BinaryMerkleTree::from_input(&sample_input(len), IV, FLAGS);
+        let root = tree.root();
+        let mut scalar = [0u8; 512];
+        let mut batched = [0u8; 512];
+        root.root_output_bytes(&mut scalar);
+        root.root_output_bytes_batched(&mut batched);
+        assert_eq!(scalar, batched);
+    }
+}
+
+/// Tests batched output lengths that do not fill a batch, a block or a word
+/// Verifies every prefix length across two batches matches the scalar output, under the keyed mode
+/// Methods tested: Output::root_output_bytes_batched
+#[test]
+fn test_batched_partial_lengths() {
+    let tree = BinaryMerkleTree::from_input(&sample_input(3 * CHUNK_LEN), [7; 8], KEYED_HASH);
+    let root = tree.root();
+    let mut scalar = vec![0u8; 2 * LANES * BLOCK_LEN + 3];
+    root.root_output_bytes(&mut scalar);
+    for len in 0..=scalar.len() {
+        let mut batched = vec![0u8; len];
+        root.root_output_bytes_batched(&mut batched);
+        assert_eq!(batched, scalar[..len], "length {len}");
+    }
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{chunk_output, BinaryMerkleTree, Output, SnapshotTree, CHUNK_LEN, FLAGS, IV};
+use merkle_tree::node_store::{NodeStore, SharedStore};
+use common::chunk_leaves;
+
+fn sample_input(chunks: usize) -> Vec<u8> {
+    (0..chunks * CHUNK_LEN).map(|i| (i % 251) as u8).collect()
+}
+
+fn chunk_leaf(leaf_index: usize, seed: u8) -> Output {
+    chunk_output(&[seed; CHUNK_LEN], leaf_index as u64, IV, FLAGS).unwrap()
+}
+
+/// The number of non-padding nodes in a tree of `leaves` leaves
+fn real_nodes(leaves: usize) -> usize {
+    let (mut total, mut level) = (leaves, leaves);
+    while level > 1 {
+        level = level.div_ceil(2);
+        total += level;
+    }
+    total
+}
+
+/// Tests that the shared store does not change what the tree computes
+/// Verifies roots, proofs and integrity match the Vec-backed tree for several shapes, and that
+/// only real nodes are stored
+/// Methods tested: SnapshotTree::from_input, SnapshotTree::new_from_leaves, generate_proof,
+/// verify_chunk, verify_integrity
+#[test]
+fn test_snapshot_tree_matches_binary_tree() {
+    for chunks in [1, 2, 3, 5, 8, 13, 37] {
+        let input = sample_input(chunks);
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let snapshot_tree = SnapshotTree::from_input(&input, IV, FLAGS);
+        assert_eq!(snapshot_tree.root_hash(), tree.root_hash());
+        assert_eq!(snapshot_tree.input_len(), Some(input.len() as u64));
+        assert!(snapshot_tree.verify_integrity());
+        assert_eq!(snapshot_tree.store().len(), real_nodes(chunks));
+        for leaf_index in 0..chunks {
+            assert_eq!(snapshot_tree.generate_proof(leaf_index), tree.generate_proof(leaf_index));
+            assert!(snapshot_tree.verify_chunk(leaf_index, &input[leaf_index * CHUNK_LEN..][..CHUNK_LEN]));
+        }
+
+        let leaves = chunk_leaves(&input, IV, FLAGS);
+        assert_eq!(SnapshotTree::new_from_leaves(leaves, IV, FLAGS).root_hash(), tree.root_hash());
+    }
+}
+
+/// Tests keeping many versions of one tree
+/// Verifies each of 100 single-leaf updates gives the root the Vec-backed tree gets, leaves
+/// every earlier version unchanged, and adds exactly one path of nodes, counted as distinct
+/// allocations across all versions
+/// Methods tested: SnapshotTree::with_leaf, SnapshotTree::snapshot, SharedStore::distinct_nodes
+#[test]
+fn test_snapshot_versions_share_nodes() {
+    let chunks: usize = 1024;
+    let depth = chunks.ilog2() as usize;
+    let input = sample_input(chunks);
+    let mut expected = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let mut versions = vec![SnapshotTree::from_input(&input, IV, FLAGS)];
+    let mut roots = vec![expected.root_hash()];
+    assert_eq!(SharedStore::distinct_nodes([versions[0].store()]), 2 * chunks - 1);
+
+    for version in 1..=100 {
+        let leaf_index = version * 37 % chunks;
+        let leaf = chunk_leaf(leaf_index, version as u8);
+        let next = versions[version - 1].with_leaf(leaf_index, leaf).unwrap();
+        expected.insert_leaf(leaf_index, leaf).unwrap();
+        assert_eq!(next.root_hash(), expected.root_hash());
+        versions.push(next);
+        roots.push(expected.root_hash());
+    }
+
+    for (version, root) in versions.iter().zip(&roots) {
+        assert_eq!(version.root_hash(), *root);
+        assert!(version.verify_integrity());
+    }
+    let distinct = SharedStore::distinct_nodes(versions.iter().map(|version| version.store()));
+    assert_eq!(distinct, 2 * chunks - 1 + 100 * (depth + 1));
+
+    // A snapshot shares everything until it changes
+    let copy = versions[100].snapshot();
+    assert_eq!(SharedStore::distinct_nodes([versions[100].store(), copy.store()]), 2 * chunks - 1);
+    assert!(versions[0].with_leaf(chunks, chunk_leaf(0, 0)).is_err());
+}
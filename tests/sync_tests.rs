@@ -0,0 +1,95 @@
+use merkle_tree::binary_merkle_tree::{chunk_output, BinaryMerkleTree, Output, CHUNK_LEN, FLAGS, IV};
+use merkle_tree::sync::sync_plan;
+
+fn sample_input(chunks: usize) -> Vec<u8> {
+    (0..chunks * CHUNK_LEN).map(|i| (i % 251) as u8 ^ (i / CHUNK_LEN) as u8).collect()
+}
+
+fn tree(input: &[u8]) -> BinaryMerkleTree {
+    BinaryMerkleTree::from_input(input, IV, FLAGS)
+}
+
+/// Tests planning between trees of the same length
+/// Verifies identical trees need nothing after one comparison, and changed chunks are
+/// listed with their byte ranges, adjacent ones merged
+/// Methods tested: sync_plan
+#[test]
+fn test_sync_plan_changed_chunks() {
+    let local = sample_input(13);
+    let plan = sync_plan(&tree(&local), &tree(&local));
+    assert!(plan.chunks.is_empty() && plan.byte_ranges.is_empty());
+    assert_eq!(plan.nodes_visited, 1);
+    assert_eq!(plan.remote_len, local.len() as u64);
+
+    let mut remote = local.clone();
+    for chunk in [2, 3, 9, 12] {
+        remote[chunk * CHUNK_LEN + 7] ^= 1;
+    }
+    let plan = sync_plan(&tree(&local), &tree(&remote));
+    assert_eq!(plan.chunks, [2, 3, 9, 12]);
+    let ranges = [2 * CHUNK_LEN..4 * CHUNK_LEN, 9 * CHUNK_LEN..10 * CHUNK_LEN, 12 * CHUNK_LEN..13 * CHUNK_LEN];
+    assert_eq!(plan.byte_ranges, ranges.map(|r| r.start as u64..r.end as u64));
+
+    // Applying the plan makes the trees match
+    let mut synced = local.clone();
+    for range in &plan.byte_ranges {
+        let range = range.start as usize..range.end as usize;
+        synced[range.clone()].copy_from_slice(&remote[range]);
+    }
+    assert_eq!(synced, remote);
+}
+
+/// Tests planning between trees of different lengths
+/// Verifies a longer remote adds its tail and a changed partial last chunk, and a
+/// shorter remote only changes the boundary chunk and reports the length to truncate to
+/// Methods tested: sync_plan
+#[test]
+fn test_sync_plan_length_changes() {
+    let input = sample_input(20);
+    let local = &input[..9 * CHUNK_LEN + 100];
+
+    let grown = tree(&input[..17 * CHUNK_LEN + 5]);
+    let plan = sync_plan(&tree(local), &grown);
+    assert_eq!(plan.chunks, (9..18).collect::<Vec<_>>(), "Chunk 9 grew and the rest is new");
+    assert_eq!(plan.byte_ranges, vec![9 * CHUNK_LEN as u64..17 * CHUNK_LEN as u64 + 5]);
+    assert_eq!(plan.remote_len, 17 * CHUNK_LEN as u64 + 5);
+
+    let shrunk = tree(&input[..4 * CHUNK_LEN + 10]);
+    let plan = sync_plan(&tree(local), &shrunk);
+    assert_eq!(plan.chunks, [4]);
+    assert_eq!(plan.byte_ranges, vec![4 * CHUNK_LEN as u64..4 * CHUNK_LEN as u64 + 10]);
+    assert_eq!(plan.remote_len, 4 * CHUNK_LEN as u64 + 10);
+
+    let aligned = tree(&input[..8 * CHUNK_LEN]);
+    let plan = sync_plan(&tree(local), &aligned);
+    assert!(plan.chunks.is_empty(), "Only truncation is needed");
+    assert_eq!(plan.remote_len, 8 * CHUNK_LEN as u64);
+
+    let plan = sync_plan(&tree(&[]), &tree(local));
+    assert_eq!(plan.chunks, (0..10).collect::<Vec<_>>());
+    assert_eq!(plan.byte_ranges, vec![0..local.len() as u64]);
+    let plan = sync_plan(&tree(local), &tree(&[]));
+    assert_eq!(plan.chunks, [0]);
+    assert!(plan.byte_ranges.is_empty());
+    assert_eq!(plan.remote_len, 0);
+}
+
+/// Tests the cost of the descent
+/// Verifies changing 3 chunks of 100,000 visits a few hundred nodes, not every leaf
+/// Methods tested: sync_plan
+#[test]
+fn test_sync_plan_visits_few_nodes() {
+    const CHUNKS: usize = 100_000;
+    let leaf = |leaf_index: usize, byte: u8| -> Output {
+        chunk_output(&[byte; 64], leaf_index as u64, IV, FLAGS).unwrap()
+    };
+    let local = BinaryMerkleTree::new_from_leaves((0..CHUNKS).map(|i| leaf(i, 0)).collect(), IV, FLAGS);
+    let mut remote = local.clone();
+    let changed = [17, 50_000, 99_999];
+    remote.bulk_insert_leaves(changed.into_iter(), changed.map(|i| leaf(i, 1)).into_iter()).unwrap();
+
+    let plan = sync_plan(&local, &remote);
+    assert_eq!(plan.chunks, changed);
+    assert!(plan.nodes_visited < 300, "Visited {} nodes", plan.nodes_visited);
+    assert!(plan.nodes_visited > 3 * 17, "Each changed leaf is reached from the root");
+}
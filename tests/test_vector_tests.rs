@@ -0,0 +1,99 @@
+use merkle_tree::binary_merkle_tree::{chunk_chaining_value, cv_to_bytes, chunk_digest, parent_output, BinaryMerkleTree, CHUNK_LEN, FLAGS, IV};
+use merkle_tree::error::MerkleError;
+use blake3::hazmat::{HasherExt, Mode};
+
+// Entries from BLAKE3's test_vectors.json: (input_len, hash). Input byte i is i % 251.
+const TEST_VECTORS: [(usize, &str); 4] = [
+    (1024, "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7"),
+    (2048, "e776b6028c7cd22a4d0ba182a8bf62205d2ef576467e838ed6f2529b85fba24a"),
+    (3072, "b98cb0ff3623be03326b373de6b9095218513e64f1ee2edd2525c7ad1e5cffd2"),
+    (4097, "9b4052b38f1c5fc8b1f9ff7ac7b27cd242487b3d890d15c96a1c25b8aa0fb995"),
+];
+
+fn vector_input(input_len: usize) -> Vec<u8> {
+    (0..input_len).map(|i| (i % 251) as u8).collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Tests per-chunk chaining values against the official test vectors
+/// Verifies that folding chunk CVs with parent nodes by hand reproduces each vector's hash
+/// Methods tested: chunk_chaining_value, parent_output
+#[test]
+fn test_chunk_chaining_values_reproduce_test_vectors() {
+    for &(input_len, expected_hash) in TEST_VECTORS.iter() {
+        let input = vector_input(input_len);
+        let chunk_cvs: Vec<[u32; 8]> = input
+            .chunks(CHUNK_LEN)
+            .enumerate()
+            .map(|(i, chunk)| chunk_chaining_value(chunk, i as u64).unwrap())
+            .collect();
+
+        // Build the root the same way the tree does: pair up CVs level by level,
+        // promoting a lone right-edge node unchanged
+        let mut level = chunk_cvs.clone();
+        let mut root_output = None;
+        while level.len() > 1 {
+            let mut next_level = Vec::new();
+            for pair in level.chunks(2) {
+                if let [left, right] = pair {
+                    let output = parent_output(*left, *right, IV, FLAGS);
+                    root_output = Some(output);
+                    next_level.push(output.chaining_value());
+                } else {
+                    next_level.push(pair[0]);
+                }
+            }
+            level = next_level;
+        }
+
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        assert_eq!(hex(tree.root_hash().as_bytes()), expected_hash, "Tree root mismatch for input_len {}", input_len);
+        match root_output {
+            Some(output) => {
+                let mut hash = [0; 32];
+                output.root_output_bytes(&mut hash);
+                assert_eq!(hex(&hash), expected_hash, "Chunk CV fold mismatch for input_len {}", input_len);
+            }
+            // A single chunk is the root itself, and its non-root CV must differ from the hash
+            None => assert_ne!(hex(&cv_to_bytes(chunk_cvs[0])), expected_hash),
+        }
+    }
+}
+
+/// Tests chunk chaining values against the reference implementation's non-root finalization
+/// Methods tested: chunk_chaining_value
+#[test]
+fn test_chunk_chaining_value_matches_reference_non_root() {
+    let input = vector_input(8 * CHUNK_LEN + 100);
+    for (i, chunk) in input.chunks(CHUNK_LEN).enumerate() {
+        let expected = blake3::Hasher::new()
+            .set_input_offset((i * CHUNK_LEN) as u64)
+            .update(chunk)
+            .finalize_non_root();
+        assert_eq!(cv_to_bytes(chunk_chaining_value(chunk, i as u64).unwrap()), expected, "Chunk {} CV mismatch", i);
+    }
+
+    // Two chunk CVs merged as a root must equal the hash of the two-chunk input
+    let left = chunk_chaining_value(&input[..CHUNK_LEN], 0).unwrap();
+    let right = chunk_chaining_value(&input[CHUNK_LEN..2 * CHUNK_LEN], 1).unwrap();
+    let merged = blake3::hazmat::merge_subtrees_root(&cv_to_bytes(left), &cv_to_bytes(right), Mode::Hash);
+    assert_eq!(merged, blake3::hash(&input[..2 * CHUNK_LEN]));
+}
+
+/// Tests standalone digests of single chunks, as content-defined chunking produces
+/// Verifies every length up to a chunk matches blake3::hash and the tree's root, and
+/// that longer input is rejected
+/// Methods tested: chunk_digest
+#[test]
+fn test_chunk_digest_matches_full_hash() {
+    let input = vector_input(CHUNK_LEN + 1);
+    for len in [0, 1, 63, 64, 65, 500, CHUNK_LEN - 1, CHUNK_LEN] {
+        let digest = chunk_digest(&input[..len]).unwrap();
+        assert_eq!(digest, *blake3::hash(&input[..len]).as_bytes(), "Length {}", len);
+        assert_eq!(digest, BinaryMerkleTree::from_input(&input[..len], IV, FLAGS).root_hash().0, "Length {}", len);
+    }
+    assert_eq!(chunk_digest(&input), Err(MerkleError::ChunkTooLong { len: CHUNK_LEN + 1 }));
+}
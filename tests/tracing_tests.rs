@@ -0,0 +1,84 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, CHUNK_LEN, FLAGS, IV};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Collects everything the subscriber writes, for asserting on afterwards.
+#[derive(Clone, Default)]
+struct CapturedWriter(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for CapturedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturedWriter {
+    type Writer = CapturedWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// Run `f` with a debug-level subscriber that logs span creation, returning its output.
+fn capture(f: impl FnOnce()) -> String {
+    let writer = CapturedWriter::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(writer.clone())
+        .with_max_level(tracing::Level::DEBUG)
+        .with_span_events(FmtSpan::NEW)
+        .with_ansi(false)
+        .finish();
+    tracing::subscriber::with_default(subscriber, f);
+    let output = writer.0.lock().unwrap().clone();
+    String::from_utf8(output).unwrap()
+}
+
+fn sample_input(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Tests the spans and events the expensive operations emit
+/// Verifies building a tree opens spans carrying the byte and leaf counts, and that bulk
+/// inserts, proofs and failed verifications are reported with their fields
+/// Methods tested: BinaryMerkleTree::from_input, BinaryMerkleTree::bulk_insert_leaves,
+/// BinaryMerkleTree::generate_proof, BinaryMerkleTree::verify_chunk
+#[cfg(feature = "tracing")]
+#[test]
+fn test_operations_emit_spans_and_events() {
+    let input = sample_input(5 * CHUNK_LEN);
+    let output = capture(|| {
+        let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        let leaf = tree.leaf(0).unwrap();
+        tree.bulk_insert_leaves([1, 2].into_iter(), [leaf, leaf].into_iter()).unwrap();
+        tree.generate_proof(3).unwrap();
+        assert!(!tree.verify_chunk(4, &input[..CHUNK_LEN]));
+    });
+
+    assert!(output.contains(&format!("from_input{{bytes={}}}", 5 * CHUNK_LEN)), "{}", output);
+    assert!(output.contains("new_from_leaves{leaves=5}"), "{}", output);
+    assert!(output.contains("bulk insert done leaves=2 nodes_recomputed=4"), "{}", output);
+    assert!(output.contains("generate_proof{leaf_index=3}"), "{}", output);
+    assert!(output.contains("chunk failed verification chunk_index=4 len=1024"), "{}", output);
+}
+
+/// Tests that without the feature nothing reaches a subscriber
+/// Methods tested: BinaryMerkleTree::from_input, BinaryMerkleTree::verify_chunk
+#[cfg(not(feature = "tracing"))]
+#[test]
+fn test_no_events_without_feature() {
+    let input = sample_input(5 * CHUNK_LEN);
+    let output = capture(|| {
+        let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+        assert!(!tree.verify_chunk(4, &input[..CHUNK_LEN]));
+    });
+    assert_eq!(output, "");
+}
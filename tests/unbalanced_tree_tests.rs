@@ -1,4 +1,10 @@
-use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, CHUNK_LEN, IV, FLAGS, ChunkState};
+// These tests predate the clippy gate and are kept in their original form
+#![allow(clippy::unwrap_or_default, clippy::manual_div_ceil, clippy::needless_range_loop, clippy::assign_op_pattern)]
+
+mod common;
+
+use merkle_tree::binary_merkle_tree::{chunk_output, cv_from_bytes, leaf_for_input_chunk, BinaryMerkleTree, Blake3Hasher, CHUNK_LEN, IV, FLAGS, ChunkState, HeapIndex};
+use common::SeededRng;
 use rand::Rng;
 use std::collections::HashMap;
 
@@ -32,10 +38,7 @@ fn test_unbalanced_tree_creation() {
     hasher.finalize(&mut hash);
     
     // Convert hash to chaining value format
-    let mut blake3_chaining_value = [0u32; 8];
-    for i in 0..8 {
-        blake3_chaining_value[i] = u32::from_le_bytes(hash[i*4..(i+1)*4].try_into().unwrap());
-    }
+    let blake3_chaining_value = cv_from_bytes(hash);
     
     // Compare root chaining value with BLAKE3 hash
     let root = tree.root();
@@ -49,7 +52,7 @@ fn test_unbalanced_tree_insert() {
     println!("\n=== Starting unbalanced tree insert test ===\n");
     
     // Generate random input with size between lower and upper bound bytes
-    let mut rng = rand::thread_rng();
+    let mut rng = SeededRng::new();
     let input_size = rng.gen_range(BYTES_SIZE_LOW_BOUND..=BYTES_SIZE_HIGH_BOUND);
     let mut input: Vec<u8> = (0..input_size).map(|_| rng.gen()).collect();
     
@@ -60,10 +63,7 @@ fn test_unbalanced_tree_insert() {
     hasher.finalize(&mut initial_hash);
     
     // Convert initial hash bytes to chaining value format (8 u32 values)
-    let mut initial_blake3_chaining_value = [0u32; 8];
-    for i in 0..8 {
-        initial_blake3_chaining_value[i] = u32::from_le_bytes(initial_hash[i*4..(i+1)*4].try_into().unwrap());
-    }
+    let initial_blake3_chaining_value = cv_from_bytes(initial_hash);
     println!("BLAKE3 final root chaining value: {:?}", initial_blake3_chaining_value);
     
     // Process through UnbalancedMerkleTree initially
@@ -97,13 +97,11 @@ fn test_unbalanced_tree_insert() {
         input[i] = input[i] ^ 0xFF; // Flip all bits in the chunk
     }
     
-    // Create new chunk state for the mutated chunk
-    let mut chunk_state = ChunkState::new(IV, chunk_index as u64, FLAGS);
-    chunk_state.update(&input[chunk_start..chunk_end]);
-    let mutated_chunk_output = chunk_state.output();
+    // Create new Output for the mutated chunk
+    let mutated_chunk_output = leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap();
     
     // Update tree with mutated chunk
-    tree.insert_leaf(chunk_index, mutated_chunk_output);
+    tree.insert_leaf(chunk_index, mutated_chunk_output).unwrap();
     let mutated_root = tree.root().chaining_value();
     
     // Get mutated BLAKE3 hash
@@ -113,10 +111,7 @@ fn test_unbalanced_tree_insert() {
     hasher.finalize(&mut mutated_hash);
     
     // Convert hash to chaining value format and verify
-    let mut mutated_blake3_chaining_value = [0u32; 8];
-    for i in 0..8 {
-        mutated_blake3_chaining_value[i] = u32::from_le_bytes(mutated_hash[i*4..(i+1)*4].try_into().unwrap());
-    }
+    let mutated_blake3_chaining_value = cv_from_bytes(mutated_hash);
     
     // Verify mutated hash values match
     assert_eq!(mutated_root, mutated_blake3_chaining_value,
@@ -129,7 +124,7 @@ fn test_unbalanced_tree_insert() {
 fn test_fuzz_unbalanced_tree_insert() {
     println!("\n=== Starting fuzz test for unbalanced tree insert ===\n");
     let num_iterations = FUZZ_ITERATIONS;
-    let mut rng = rand::thread_rng();
+    let mut rng = SeededRng::new();
     
     for iteration in 0..num_iterations {
         // Generate random input with size between low and high bound bytes
@@ -143,10 +138,7 @@ fn test_fuzz_unbalanced_tree_insert() {
         hasher.finalize(&mut initial_hash);
         
         // Convert initial hash bytes to chaining value format (8 u32 values)
-        let mut initial_blake3_chaining_value = [0u32; 8];
-        for i in 0..8 {
-            initial_blake3_chaining_value[i] = u32::from_le_bytes(initial_hash[i*4..(i+1)*4].try_into().unwrap());
-        }
+        let initial_blake3_chaining_value = cv_from_bytes(initial_hash);
         
         // Process through UnbalancedMerkleTree initially
         let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
@@ -166,13 +158,11 @@ fn test_fuzz_unbalanced_tree_insert() {
             input[i] = input[i] ^ 0xFF; // Flip all bits in the chunk
         }
         
-        // Create new chunk state for the mutated chunk
-        let mut chunk_state = ChunkState::new(IV, chunk_index as u64, FLAGS);
-        chunk_state.update(&input[chunk_start..chunk_end]);
-        let mutated_chunk_output = chunk_state.output();
+        // Create new Output for the mutated chunk
+        let mutated_chunk_output = leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap();
         
         // Update tree with mutated chunk
-        tree.insert_leaf(chunk_index, mutated_chunk_output);
+        tree.insert_leaf(chunk_index, mutated_chunk_output).unwrap();
         let mutated_root = tree.root().chaining_value();
 
         
@@ -183,10 +173,7 @@ fn test_fuzz_unbalanced_tree_insert() {
         hasher.finalize(&mut mutated_hash);
         
         // Convert hash to chaining value format and verify
-        let mut mutated_blake3_chaining_value = [0u32; 8];
-        for i in 0..8 {
-            mutated_blake3_chaining_value[i] = u32::from_le_bytes(mutated_hash[i*4..(i+1)*4].try_into().unwrap());
-        }
+        let mutated_blake3_chaining_value = cv_from_bytes(mutated_hash);
         
         assert_eq!(mutated_root, mutated_blake3_chaining_value,
             "Mutated hash mismatch in iteration {} for input size {} bytes", iteration + 1, input_size);
@@ -200,7 +187,7 @@ fn test_unbalanced_tree_bulk_insert() {
     println!("\n=== Starting unbalanced tree bulk insert test ===\n");
     
     // Generate random input with size between lower and upper bound bytes
-    let mut rng = rand::thread_rng();
+    let mut rng = SeededRng::new();
     let input_size = rng.gen_range(BYTES_SIZE_LOW_BOUND..=BYTES_SIZE_HIGH_BOUND);
     let mut input: Vec<u8> = (0..input_size).map(|_| rng.gen()).collect();
     
@@ -211,10 +198,7 @@ fn test_unbalanced_tree_bulk_insert() {
     hasher.finalize(&mut initial_hash);
     
     // Convert initial hash bytes to chaining value format (8 u32 values)
-    let mut initial_blake3_chaining_value = [0u32; 8];
-    for i in 0..8 {
-        initial_blake3_chaining_value[i] = u32::from_le_bytes(initial_hash[i*4..(i+1)*4].try_into().unwrap());
-    }
+    let initial_blake3_chaining_value = cv_from_bytes(initial_hash);
     println!("BLAKE3 final root chaining value: {:?}", initial_blake3_chaining_value);
     
     // Process through UnbalancedMerkleTree initially
@@ -269,15 +253,9 @@ fn test_unbalanced_tree_bulk_insert() {
     let mut chunk_outputs = Vec::with_capacity(chunk_updates.len());
     
     for &chunk_index in &sorted_chunk_indices {
-        let chunk_start = chunk_index * CHUNK_LEN;
-        let chunk_end = std::cmp::min(chunk_start + CHUNK_LEN, input.len());
-        
         // Calculate chunk output after all mutations in this chunk
-        let mut chunk_state = ChunkState::new(IV, chunk_index as u64, FLAGS);
-        chunk_state.update(&input[chunk_start..chunk_end]);
-        
         chunk_indices.push(chunk_index);
-        chunk_outputs.push(chunk_state.output());
+        chunk_outputs.push(leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap());
     }
     
     // Update tree with bulk mutations
@@ -292,10 +270,7 @@ fn test_unbalanced_tree_bulk_insert() {
     hasher.finalize(&mut mutated_hash);
     
     // Convert hash to chaining value format
-    let mut mutated_blake3_chaining_value = [0u32; 8];
-    for i in 0..8 {
-        mutated_blake3_chaining_value[i] = u32::from_le_bytes(mutated_hash[i*4..(i+1)*4].try_into().unwrap());
-    }
+    let mutated_blake3_chaining_value = cv_from_bytes(mutated_hash);
     
     // Verify all hash values match
     assert_eq!(mutated_root, mutated_blake3_chaining_value,
@@ -308,7 +283,7 @@ fn test_unbalanced_tree_bulk_insert() {
 fn test_fuzz_unbalanced_tree_bulk_insert() {
     println!("\n=== Starting fuzz test for unbalanced tree bulk insert ===\n");
     let num_iterations = FUZZ_ITERATIONS;
-    let mut rng = rand::thread_rng();
+    let mut rng = SeededRng::new();
     
     for iteration in 0..num_iterations {
         // Generate random input with size between low and high bound bytes
@@ -322,10 +297,7 @@ fn test_fuzz_unbalanced_tree_bulk_insert() {
         hasher.finalize(&mut initial_hash);
         
         // Convert initial hash bytes to chaining value format (8 u32 values)
-        let mut initial_blake3_chaining_value = [0u32; 8];
-        for i in 0..8 {
-            initial_blake3_chaining_value[i] = u32::from_le_bytes(initial_hash[i*4..(i+1)*4].try_into().unwrap());
-        }
+        let initial_blake3_chaining_value = cv_from_bytes(initial_hash);
         
         // Process through UnbalancedMerkleTree initially
         let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
@@ -371,15 +343,9 @@ fn test_fuzz_unbalanced_tree_bulk_insert() {
         let mut chunk_outputs = Vec::with_capacity(chunk_updates.len());
         
         for &chunk_index in &sorted_chunk_indices {
-            let chunk_start = chunk_index * CHUNK_LEN;
-            let chunk_end = std::cmp::min(chunk_start + CHUNK_LEN, input.len());
-            
             // Calculate chunk output after all mutations in this chunk
-            let mut chunk_state = ChunkState::new(IV, chunk_index as u64, FLAGS);
-            chunk_state.update(&input[chunk_start..chunk_end]);
-            
             chunk_indices.push(chunk_index);
-            chunk_outputs.push(chunk_state.output());
+            chunk_outputs.push(leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap());
         }
         
         // Update tree with bulk mutations
@@ -394,10 +360,7 @@ fn test_fuzz_unbalanced_tree_bulk_insert() {
         hasher.finalize(&mut mutated_hash);
         
         // Convert hash to chaining value format
-        let mut mutated_blake3_chaining_value = [0u32; 8];
-        for i in 0..8 {
-            mutated_blake3_chaining_value[i] = u32::from_le_bytes(mutated_hash[i*4..(i+1)*4].try_into().unwrap());
-        }
+        let mutated_blake3_chaining_value = cv_from_bytes(mutated_hash);
         
         // Verify all hash values match
         assert_eq!(mutated_root, mutated_blake3_chaining_value,
@@ -423,10 +386,7 @@ fn test_unbalanced_tree_corner_cases() {
     let mut hash = [0; 32];
     hasher.finalize(&mut hash);
     
-    let mut blake3_chaining_value = [0u32; 8];
-    for i in 0..8 {
-        blake3_chaining_value[i] = u32::from_le_bytes(hash[i*4..(i+1)*4].try_into().unwrap());
-    }
+    let blake3_chaining_value = cv_from_bytes(hash);
     
     assert_eq!(root, blake3_chaining_value, "Empty input hash mismatch");
     println!("Empty input test passed ✓");
@@ -443,10 +403,7 @@ fn test_unbalanced_tree_corner_cases() {
     let mut hash = [0; 32];
     hasher.finalize(&mut hash);
     
-    let mut blake3_chaining_value = [0u32; 8];
-    for i in 0..8 {
-        blake3_chaining_value[i] = u32::from_le_bytes(hash[i*4..(i+1)*4].try_into().unwrap());
-    }
+    let blake3_chaining_value = cv_from_bytes(hash);
     
     assert_eq!(initial_root, blake3_chaining_value, "Very short input hash mismatch");
     println!("Very short input test passed ✓");
@@ -463,10 +420,7 @@ fn test_unbalanced_tree_corner_cases() {
     let mut hash = [0; 32];
     hasher.finalize(&mut hash);
     
-    let mut blake3_chaining_value = [0u32; 8];
-    for i in 0..8 {
-        blake3_chaining_value[i] = u32::from_le_bytes(hash[i*4..(i+1)*4].try_into().unwrap());
-    }
+    let blake3_chaining_value = cv_from_bytes(hash);
     
     assert_eq!(initial_root, blake3_chaining_value, "Exact chunk size input hash mismatch");
     println!("Exact chunk size input test passed ✓");
@@ -483,10 +437,7 @@ fn test_unbalanced_tree_corner_cases() {
     let mut hash = [0; 32];
     hasher.finalize(&mut hash);
     
-    let mut blake3_chaining_value = [0u32; 8];
-    for i in 0..8 {
-        blake3_chaining_value[i] = u32::from_le_bytes(hash[i*4..(i+1)*4].try_into().unwrap());
-    }
+    let blake3_chaining_value = cv_from_bytes(hash);
     
     assert_eq!(initial_root, blake3_chaining_value, "Multiple exact chunks hash mismatch");
     println!("Multiple exact chunks test passed ✓");
@@ -525,15 +476,9 @@ fn test_unbalanced_tree_corner_cases() {
     let mut chunk_outputs = Vec::with_capacity(chunk_updates.len());
     
     for &chunk_index in &sorted_chunk_indices {
-        let chunk_start = chunk_index * CHUNK_LEN;
-        let chunk_end = chunk_start + CHUNK_LEN;
-        
         // Calculate chunk output after all mutations in this chunk
-        let mut chunk_state = ChunkState::new(IV, chunk_index as u64, FLAGS);
-        chunk_state.update(&input[chunk_start..chunk_end]);
-        
         chunk_indices.push(chunk_index);
-        chunk_outputs.push(chunk_state.output());
+        chunk_outputs.push(leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap());
     }
     
     // Update tree with bulk mutations
@@ -547,14 +492,64 @@ fn test_unbalanced_tree_corner_cases() {
     let mut mutated_hash = [0; 32];
     hasher.finalize(&mut mutated_hash);
     
-    let mut mutated_blake3_chaining_value = [0u32; 8];
-    for i in 0..8 {
-        mutated_blake3_chaining_value[i] = u32::from_le_bytes(mutated_hash[i*4..(i+1)*4].try_into().unwrap());
-    }
+    let mutated_blake3_chaining_value = cv_from_bytes(mutated_hash);
     
     assert_eq!(mutated_root, mutated_blake3_chaining_value,
         "First/last byte mutation hash mismatch");
     println!("First/last byte mutation test passed ✓");
 
     println!("\n=== All corner cases tests completed successfully ===");
-} 
\ No newline at end of file
+} 
+#[test]
+fn test_unbalanced_tree_integrity_after_updates() {
+    let mut rng = SeededRng::new();
+    let input_size = rng.gen_range(BYTES_SIZE_LOW_BOUND..=BYTES_SIZE_HIGH_BOUND);
+    let mut input: Vec<u8> = (0..input_size).map(|_| rng.gen()).collect();
+    let mut tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    assert!(tree.verify_integrity(), "Freshly built tree failed integrity check");
+
+    // Mutate the last (possibly partial, possibly promoted) chunk and re-check
+    let chunk_index = tree.actual_leaves() - 1;
+    let chunk_start = chunk_index * CHUNK_LEN;
+    input[chunk_start] ^= 0xFF;
+    tree.insert_leaf(chunk_index, leaf_for_input_chunk(&input, chunk_index, IV, FLAGS).unwrap()).unwrap();
+    assert!(tree.verify_integrity(), "Tree failed integrity check after insert_leaf");
+}
+
+/// Tests that padding never reaches the root
+/// Verifies trees of 3 leaves (the smallest with padding) and 5 leaves built with
+/// new_from_leaves match BLAKE3, stay correct through updates and a serialization round
+/// trip, and expose only real nodes, each with flags hashing could produce
+/// Methods tested: BinaryMerkleTree::new_from_leaves, node_at, insert_leaf, bulk_insert_leaves
+#[test]
+fn test_padding_never_folded() {
+    // Real nodes per tree count the promoted lone children on every level
+    for (chunks, real_node_count) in [(3, 6), (5, 11)] {
+        let input: Vec<u8> = (0..chunks * CHUNK_LEN - 1).map(|i| (i % 251) as u8).collect();
+        let leaves = input
+            .chunks(CHUNK_LEN)
+            .enumerate()
+            .map(|(i, chunk)| {
+                chunk_output(chunk, i as u64, IV, FLAGS).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let mut tree = BinaryMerkleTree::new_from_leaves(leaves, IV, FLAGS);
+        assert_eq!(tree.root_hash().0, *blake3::hash(&input).as_bytes(), "{} chunks", chunks);
+
+        let real_nodes = (1..2 * tree.num_leaves()).filter_map(|i| tree.node_at(HeapIndex(i))).collect::<Vec<_>>();
+        assert_eq!(real_nodes.len(), real_node_count, "{} chunks", chunks);
+        assert!(real_nodes.iter().all(|node| node.validate_flags().is_ok()));
+        assert_eq!(tree.node_at(HeapIndex(2 * tree.num_leaves() - 1)), None);
+
+        let mut updated = input.clone();
+        updated[(chunks - 1) * CHUNK_LEN] ^= 1;
+        updated[CHUNK_LEN] ^= 1;
+        let expected = BinaryMerkleTree::from_input(&updated, IV, FLAGS);
+        tree.insert_leaf(chunks - 1, expected.leaf(chunks - 1).unwrap()).unwrap();
+        tree.bulk_insert_leaves([1].into_iter(), [expected.leaf(1).unwrap()].into_iter()).unwrap();
+        assert_eq!(tree.root(), expected.root());
+
+        let loaded = BinaryMerkleTree::from_bytes(&tree.to_bytes()).unwrap();
+        assert_eq!(loaded.root(), expected.root());
+    }
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use merkle_tree::binary_merkle_tree::{chunk_output, BinaryMerkleTree, Hash32, Output, CHUNK_LEN, IV, FLAGS};
+use merkle_tree::update_log::UPDATE_RECORD_LEN;
+use rand::Rng;
+use std::io;
+use common::SeededRng;
+
+const LEAVES: usize = 37;
+const UPDATES: usize = 20;
+
+fn random_leaf(leaf_index: usize) -> Output {
+    let mut rng = SeededRng::new();
+    let chunk: Vec<u8> = (0..CHUNK_LEN).map(|_| rng.gen()).collect();
+    chunk_output(&chunk, leaf_index as u64, IV, FLAGS).unwrap()
+}
+
+/// A base tree, the log of `UPDATES` random updates to it, and the root hash after each prefix of the log
+fn logged_updates() -> (BinaryMerkleTree, Vec<u8>, Vec<Hash32>) {
+    let mut rng = SeededRng::new();
+    let input: Vec<u8> = (0..LEAVES * CHUNK_LEN).map(|_| rng.gen()).collect();
+    let base = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let mut tree = base.snapshot();
+    let mut log = Vec::new();
+    let mut roots = vec![tree.root_hash()];
+    for _ in 0..UPDATES {
+        let leaf_index = rng.gen_range(0..LEAVES);
+        tree.apply_and_log(leaf_index, random_leaf(leaf_index), &mut log).unwrap();
+        roots.push(tree.root_hash());
+    }
+    assert_eq!(log.len(), UPDATES * UPDATE_RECORD_LEN);
+    (base, log, roots)
+}
+
+/// Tests replaying an update log after a crash at every possible point
+/// Verifies that a log cut at a record boundary replays fully, and that a log cut mid-record stops
+/// cleanly before that record with UnexpectedEof and the tree of every earlier record applied
+/// Methods tested: BinaryMerkleTree::apply_and_log, BinaryMerkleTree::replay
+#[test]
+fn test_replay_truncated_log() {
+    let (base, log, roots) = logged_updates();
+    for cut in 0..=log.len() {
+        let applied = cut / UPDATE_RECORD_LEN;
+        match BinaryMerkleTree::replay(base.snapshot(), &log[..cut]) {
+            Ok(tree) => {
+                assert_eq!(cut % UPDATE_RECORD_LEN, 0, "Replay accepted a torn record at byte {}", cut);
+                assert_eq!(tree.root_hash(), roots[applied]);
+            }
+            Err(e) => {
+                assert_ne!(cut % UPDATE_RECORD_LEN, 0, "Replay rejected a whole log of {} records", applied);
+                assert_eq!((e.record, e.error.kind()), (applied as u64, io::ErrorKind::UnexpectedEof));
+                assert_eq!(e.tree.root_hash(), roots[applied], "Recovered tree is not the one before record {}", applied);
+            }
+        }
+    }
+}
+
+/// Tests that corrupted records stop replay at the right record
+/// Verifies a flipped bit in a logged leaf, in a logged root, and an out-of-bounds leaf index
+/// Methods tested: BinaryMerkleTree::replay
+#[test]
+fn test_replay_corrupted_log() {
+    let (base, log, roots) = logged_updates();
+    let record_start = 7 * UPDATE_RECORD_LEN;
+    for (offset, description) in [(8 + 40, "leaf block word"), (8 + 112 + 5, "root chaining value")] {
+        let mut corrupted = log.clone();
+        corrupted[record_start + offset] ^= 1;
+        let e = BinaryMerkleTree::replay(base.snapshot(), &corrupted[..]).unwrap_err();
+        assert_eq!((e.record, e.error.kind()), (7, io::ErrorKind::InvalidData), "Corrupted {} not detected", description);
+        assert_eq!(e.tree.root_hash(), roots[7]);
+    }
+
+    let mut out_of_bounds = log.clone();
+    out_of_bounds[record_start..record_start + 8].copy_from_slice(&(LEAVES as u64).to_le_bytes());
+    let e = BinaryMerkleTree::replay(base.snapshot(), &out_of_bounds[..]).unwrap_err();
+    assert_eq!((e.record, e.error.kind()), (7, io::ErrorKind::InvalidData));
+}
+
+/// Tests that a failed log write leaves the tree unchanged
+/// Methods tested: BinaryMerkleTree::apply_and_log
+#[test]
+fn test_apply_and_log_undoes_failed_write() {
+    let (base, _, roots) = logged_updates();
+    let mut tree = base.snapshot();
+    let mut full_log = [0u8; UPDATE_RECORD_LEN - 1];
+    let err = tree.apply_and_log(LEAVES - 1, random_leaf(LEAVES - 1), &mut full_log[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+    assert_eq!(tree.root_hash(), roots[0]);
+    assert_eq!(tree.input_len(), base.input_len());
+}
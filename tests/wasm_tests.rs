@@ -0,0 +1,82 @@
+#![cfg(feature = "wasm")]
+//! Run under node with
+//! `wasm-pack test --node -- --no-default-features --features wasm --test wasm_tests`.
+//! Tests marked `unsupported = test` also run natively; the others build a `JsValue`,
+//! which only works on wasm.
+
+use merkle_tree::binary_merkle_tree::{chunk_chaining_value, cv_to_bytes, BinaryMerkleTree, CHUNK_LEN, IV, FLAGS, KEYED_HASH};
+use merkle_tree::wasm::{verify_proof, verify_slice, WasmHasher};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+fn patterned_input(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i % 251) as u8).collect()
+}
+
+/// Tests that hashing through the wasm bindings matches the native tree
+/// Verifies WasmHasher digests over empty, partial and multi-chunk inputs fed in uneven
+/// pieces, against BinaryMerkleTree::root_hash
+/// Methods tested: WasmHasher::update, WasmHasher::finalize
+#[wasm_bindgen_test(unsupported = test)]
+fn test_wasm_hasher_matches_native() {
+    for &size in &[0, 1, CHUNK_LEN, CHUNK_LEN + 1, 9 * CHUNK_LEN + 300] {
+        let input = patterned_input(size);
+        let mut hasher = WasmHasher::new();
+        for piece in input.chunks(700) {
+            hasher.update(piece);
+        }
+        assert_eq!(hasher.finalize(), BinaryMerkleTree::from_input(&input, IV, FLAGS).root_hash().to_vec(),
+            "Digest mismatch for {} bytes", size);
+    }
+}
+
+/// Tests chunk proof verification through the wasm bindings
+/// Verifies that a valid proof passes, and that a wrong chunk, wrong index, tampered or
+/// truncated proof, or short root fails instead of panicking, and that a proof carrying a
+/// keyed tree's parameters verifies against that tree's root only
+/// Methods tested: verify_proof, MerkleProof::to_path_bytes, MerkleProof::to_path_bytes_with_params
+#[wasm_bindgen_test(unsupported = test)]
+fn test_wasm_verify_proof() {
+    let input = patterned_input(6 * CHUNK_LEN + 10);
+    let tree = BinaryMerkleTree::from_input(&input, IV, FLAGS);
+    let root = tree.root_hash();
+    let chunk = &input[4 * CHUNK_LEN..5 * CHUNK_LEN];
+    let mut proof = tree.generate_proof(4).unwrap().to_path_bytes();
+    assert!(verify_proof(&root[..], &proof, chunk, 4));
+
+    assert!(!verify_proof(&root[..], &proof, &input[..CHUNK_LEN], 4));
+    assert!(!verify_proof(&root[..], &proof, chunk, 3));
+    assert!(!verify_proof(&root[..31], &proof, chunk, 4));
+    assert!(!verify_proof(&root[..], &proof[..proof.len() - 1], chunk, 4));
+    let last = proof.len() - 1;
+    proof[last] ^= 1;
+    assert!(!verify_proof(&root[..], &proof, chunk, 4));
+
+    let key_words = [9, 8, 7, 6, 5, 4, 3, 2];
+    let keyed = BinaryMerkleTree::from_input(&input, key_words, KEYED_HASH);
+    let proof = keyed.generate_proof(4).unwrap().to_path_bytes_with_params(key_words, KEYED_HASH);
+    assert!(verify_proof(&keyed.root_hash()[..], &proof, chunk, 4));
+    assert!(!verify_proof(&root[..], &proof, chunk, 4));
+}
+
+/// Tests bao slice verification through the wasm bindings
+/// Verifies that a two-chunk combined encoding, built by hand since the encoders need std,
+/// yields its input, and that a tampered encoding or short root is rejected with an error
+/// Methods tested: verify_slice
+#[wasm_bindgen_test]
+fn test_wasm_verify_slice() {
+    let input = patterned_input(2 * CHUNK_LEN - 100);
+    let root = BinaryMerkleTree::from_input(&input, IV, FLAGS).root_hash();
+    // Length header, the parent's two child CVs, then the chunks
+    let mut encoded = (input.len() as u64).to_le_bytes().to_vec();
+    for (counter, chunk) in input.chunks(CHUNK_LEN).enumerate() {
+        encoded.extend_from_slice(&cv_to_bytes(chunk_chaining_value(chunk, counter as u64).unwrap()));
+    }
+    encoded.extend_from_slice(&input);
+    let end = input.len() as u64;
+    assert_eq!(verify_slice(&encoded, &root[..], 0, end).unwrap(), input);
+
+    let last = encoded.len() - 1;
+    encoded[last] ^= 1;
+    assert!(verify_slice(&encoded, &root[..], 0, end).is_err());
+    assert!(verify_slice(&encoded, &root[..16], 0, end).is_err());
+}
@@ -0,0 +1,105 @@
+#![cfg(feature = "zeroize")]
+
+use std::mem::MaybeUninit;
+use std::ptr;
+
+use merkle_tree::binary_merkle_tree::{BinaryMerkleTree, Blake3Hasher, ChunkState, HeapIndex, Output, CHUNK_LEN, FLAGS, OUT_LEN};
+use zeroize::{Zeroize, Zeroizing};
+
+const KEY_WORDS: [u32; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+const ZERO_OUTPUT: Output = Output {
+    input_chaining_value: [0; 8],
+    block_words: [0; 16],
+    counter: 0,
+    block_len: 0,
+    flags: 0,
+};
+
+fn sample_input(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// The hash of empty input under an all-zero key, which is all a wiped hasher can produce.
+fn zero_key_empty_hash() -> [u8; OUT_LEN] {
+    let mut hash = [0; OUT_LEN];
+    ChunkState::new([0; 8], 0, 0).output().root_output_bytes(&mut hash);
+    hash
+}
+
+/// Tests wiping a hasher part way through its input
+/// Verifies the buffered chunk, the CV stack and the key are all gone, for both CV stacks
+/// Methods tested: Blake3Hasher::zeroize
+#[test]
+fn test_hasher_zeroize() {
+    let input = sample_input(5 * CHUNK_LEN + 100);
+
+    let mut hasher = Blake3Hasher::new();
+    hasher.update(&input);
+    assert!(!hasher.peaks().is_empty());
+    hasher.zeroize();
+    assert!(hasher.peaks().is_empty());
+    assert_eq!(hasher.finalize_array(), zero_key_empty_hash());
+
+    let mut heap_hasher = Blake3Hasher::new_heap();
+    heap_hasher.update(&input);
+    heap_hasher.zeroize();
+    assert!(heap_hasher.peaks().is_empty());
+    assert_eq!(heap_hasher.finalize_array(), zero_key_empty_hash());
+}
+
+/// Tests wiping a keyed tree
+/// Verifies the key words and every node are zeroed and the shape is kept
+/// Methods tested: BinaryMerkleTree::zeroize
+#[test]
+fn test_tree_zeroize() {
+    let mut tree = BinaryMerkleTree::from_input(&sample_input(5 * CHUNK_LEN), KEY_WORDS, FLAGS);
+    tree.zeroize();
+    assert_eq!(tree.key_words(), [0; 8]);
+    assert_eq!(tree.actual_leaves(), 5);
+    for heap_index in 1..2 * tree.num_leaves() {
+        if let Some(node) = tree.node_at(HeapIndex(heap_index)) {
+            assert_eq!(node, ZERO_OUTPUT, "Node {} was not wiped", heap_index);
+        }
+    }
+}
+
+/// Tests wiping a tree whose nodes a snapshot shares
+/// Verifies the snapshot keeps its nodes until it is wiped itself
+/// Methods tested: BinaryMerkleTree::zeroize, BinaryMerkleTree::snapshot
+#[test]
+fn test_tree_zeroize_leaves_snapshots() {
+    let mut tree = BinaryMerkleTree::from_input(&sample_input(3 * CHUNK_LEN), KEY_WORDS, FLAGS);
+    let mut snapshot = tree.snapshot();
+    let root = snapshot.root_hash();
+
+    tree.zeroize();
+    assert_eq!(tree.leaf(0), Some(ZERO_OUTPUT));
+    assert_eq!(snapshot.root_hash(), root);
+
+    snapshot.zeroize();
+    assert_eq!(snapshot.leaf(0), Some(ZERO_OUTPUT));
+    assert_eq!(snapshot.key_words(), [0; 8]);
+}
+
+/// Tests wiping on drop through `Zeroizing`
+/// Verifies the memory of a dropped chunk state holding keyed input reads back as zeros
+/// Methods tested: ChunkState::zeroize
+#[test]
+fn test_zeroizing_wipes_chunk_state_on_drop() {
+    let mut chunk_state = ChunkState::new(KEY_WORDS, 7, FLAGS);
+    chunk_state.update(&sample_input(200));
+
+    let mut slot = MaybeUninit::new(Zeroizing::new(chunk_state));
+    // SAFETY: `slot` was initialized above and is dropped only here. `Zeroizing` is
+    // `repr(transparent)` and `ChunkState` is `Copy`, so its fields can still be
+    // read from the slot afterwards.
+    let wiped = unsafe {
+        ptr::drop_in_place(slot.as_mut_ptr());
+        ptr::read(slot.as_ptr().cast::<ChunkState>())
+    };
+    assert_eq!(wiped.chaining_value, [0; 8]);
+    assert_eq!(wiped.block, [0; 64]);
+    assert_eq!(wiped.chunk_counter, 0);
+    assert_eq!((wiped.block_len, wiped.blocks_compressed, wiped.flags), (0, 0, 0));
+}